@@ -1,14 +1,15 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 
 #[path = "../src/prom/mod.rs"] // Here
+#[allow(dead_code, unused_imports)]
 mod prom;
 
 fn criterion_decode_labels(c: &mut Criterion) {
     c.bench_function("decode_labels", |b| {
         b.iter(|| {
-            prom::parser::decode_labels(&String::from(
+            prom::parser::decode_labels(black_box(&String::from(
                 "key1=\"value1\",key2=\"0\",key3=\"value3\",key4=\"value4\"",
-            ))
+            )))
         })
     });
 }
@@ -16,9 +17,9 @@ fn criterion_decode_labels(c: &mut Criterion) {
 fn criterion_decode_labels_with_rgx(c: &mut Criterion) {
     c.bench_function("decode_labels with rgx", |b| {
         b.iter(|| {
-            prom::parser::decode_labels_with_rgx(&String::from(
+            prom::parser::decode_labels_with_rgx(black_box(&String::from(
                 "key1=\"value1\",key2=\"0\",key3=\"value3\",key4=\"value4\"",
-            ))
+            )))
         })
     });
 }
@@ -26,9 +27,9 @@ fn criterion_decode_labels_with_rgx(c: &mut Criterion) {
 fn criterion_extract_labels(c: &mut Criterion) {
     c.bench_function("extract labels", |b| {
         b.iter(|| {
-            prom::parser::extract_labels(&String::from(
+            prom::parser::extract_labels(black_box(&String::from(
                 "metric_1{key1=\"value1\",key2=\"0\",key3=\"value3\",key4=\"value4\"} 10.000007",
-            ))
+            )))
         })
     });
 }
@@ -36,9 +37,9 @@ fn criterion_extract_labels(c: &mut Criterion) {
 fn criterion_extract_labels_with_rgx(c: &mut Criterion) {
     c.bench_function("extract labels with rgx", |b| {
         b.iter(|| {
-            prom::parser::extract_labels_with_rgx(&String::from(
+            prom::parser::extract_labels_with_rgx(black_box(&String::from(
                 "metric_1{key1=\"value1\",key2=\"0\",key3=\"value3\",key4=\"value4\"} 10.000007",
-            ))
+            )))
         })
     });
 }
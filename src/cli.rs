@@ -1,15 +1,130 @@
 use clap::Parser;
+use clap::Subcommand;
 use clap::ValueHint;
+use regex::Regex;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::interactive::{DisplayZone, Lang, ProfileName, TimeFormat};
+use crate::prom::pipeline::Selector;
+use crate::prom::{
+    ConflictPolicy, OverlapPolicy, RecordType, SinkFormat, StorageCapacity, StorageKind,
+};
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Config file
+    ///
+    /// TOML file with default settings, see the README for the format. Settings given as flags or env variables take
+    /// precedence. Defaults to ~/.config/promviz/config.toml if it exists.
+    #[arg(short, long, env="PROMVIZ_CONFIG", value_hint=ValueHint::FilePath)]
+    pub config: Option<PathBuf>,
+
     /// Prometheus endpoint to scrape
     ///
-    /// The Prometheus endpoint used to scrape metrics from.
+    /// The Prometheus endpoint used to scrape metrics from. A file:// URL reads the metrics from a local file.
     #[arg(short, long, env="PROM_ENDPOINT", value_hint=ValueHint::Url, default_value="http://localhost:8080/metrics")]
     pub endpoint: String,
 
+    /// Scrape one target per host in the file, replacing {host} in the endpoint
+    ///
+    /// E.g. --endpoint "http://{host}:9100/metrics" --hosts hosts.txt to check a fleet of node_exporters. The file
+    /// lists one host per line, # starts a comment. The series of every target get an instance label with its host.
+    #[arg(long, env="PROM_HOSTS", value_hint=ValueHint::FilePath, group="target_list")]
+    pub hosts: Option<PathBuf>,
+
+    /// Scrape the pods and services of a Kubernetes cluster annotated with prometheus.io/scrape: "true"
+    ///
+    /// The targets are discovered every --k8s-refresh seconds, on the port, path and scheme of their
+    /// prometheus.io/port, prometheus.io/path and prometheus.io/scheme annotations. Their series get an instance
+    /// label with their namespace and name. With --tabs they are discovered once at the start.
+    #[arg(long, env="PROM_K8S", group="target_list", conflicts_with_all=["file", "stdin", "import"])]
+    pub k8s: bool,
+
+    /// Kubernetes API server to discover the targets with, e.g. http://127.0.0.1:8001 of `kubectl proxy`
+    ///
+    /// The targets are then scraped through the proxy of the API server. Defaults to the API server of the cluster
+    /// promviz runs in, with the credentials of its service account.
+    #[arg(long, env="PROM_K8S_API", value_hint=ValueHint::Url, requires="k8s")]
+    pub k8s_api: Option<String>,
+
+    /// Kubernetes namespace to discover the targets in, all namespaces by default
+    #[arg(long, env = "PROM_K8S_NAMESPACE", requires = "k8s")]
+    pub k8s_namespace: Option<String>,
+
+    /// Label selector of the discovered Kubernetes pods and services, e.g. app=web
+    #[arg(long, env = "PROM_K8S_SELECTOR", requires = "k8s")]
+    pub k8s_selector: Option<String>,
+
+    /// Seconds between two discoveries of --k8s
+    #[arg(long, env="PROM_K8S_REFRESH", value_hint=ValueHint::Other, default_value="30", requires="k8s")]
+    pub k8s_refresh: u64,
+
+    /// Scrape the targets found in DNS, e.g. _metrics._tcp.example.com
+    ///
+    /// The name is looked up every --dns-sd-refresh seconds, adding and removing targets as the records change. The
+    /// host and port of every record replace those of --endpoint, which gives the scheme and path.
+    #[arg(long, env="PROM_DNS_SD", value_name="NAME", value_hint=ValueHint::Hostname,
+        conflicts_with_all=["hosts", "k8s", "file", "stdin", "import"])]
+    pub dns_sd: Option<String>,
+
+    /// Record type of --dns-sd, A and AAAA records are scraped on the port of --endpoint
+    #[arg(long, env="PROM_DNS_SD_TYPE", value_enum, default_value_t=RecordType::Srv, requires="dns_sd")]
+    pub dns_sd_type: RecordType,
+
+    /// Seconds between two lookups of --dns-sd
+    #[arg(long, env="PROM_DNS_SD_REFRESH", value_hint=ValueHint::Other, default_value="30", requires="dns_sd")]
+    pub dns_sd_refresh: u64,
+
+    /// How series of several targets with the same name and labels are told apart
+    ///
+    /// With label every series gets an instance label with its target, keeping an instance label of the target as
+    /// exported_instance. With suffix only the series exposed by several targets get the instance label, with merge
+    /// they are added up into one series.
+    #[arg(long, env="PROM_TARGET_CONFLICTS", value_enum, default_value_t=ConflictPolicy::Label)]
+    pub target_conflicts: ConflictPolicy,
+
+    /// Show every target of --hosts or --k8s in a tab of its own instead of merging their series
+    ///
+    /// Every tab has its own scraper, metric list, selection and history. The number keys switch between them.
+    #[arg(long, env = "PROM_TABS", requires = "target_list")]
+    pub tabs: bool,
+
+    /// Read metrics from a local file instead of an endpoint
+    ///
+    /// The file is re-read every scrape interval, e.g. a saved /metrics dump. Same as --endpoint file:///path.
+    #[arg(short, long, env="PROM_FILE", value_hint=ValueHint::FilePath, conflicts_with="endpoint")]
+    pub file: Option<PathBuf>,
+
+    /// Read metrics once from stdin instead of an endpoint
+    ///
+    /// Allows piping e.g. `curl .../metrics | promviz --stdin`. Same as --endpoint -.
+    #[arg(long, conflicts_with_all=["endpoint", "file"])]
+    pub stdin: bool,
+
+    /// Show exported query results instead of scraping
+    ///
+    /// JSON file with a Prometheus query API response (e.g. saved from /api/v1/query_range) or a Grafana snapshot.
+    #[arg(long, value_name="FILE", value_hint=ValueHint::FilePath, conflicts_with_all=["endpoint", "file", "stdin"])]
+    pub import: Option<PathBuf>,
+
+    /// Graph the history of a PromQL query read from the Prometheus server at --endpoint, e.g. http://prometheus:9090
+    ///
+    /// Instead of scraping an exporter, promviz calls the query_range API of the server every scrape interval, with
+    /// the scrape interval as step. The first call reads --query-range of history. Can be repeated.
+    #[arg(long, value_name="PROMQL", value_hint=ValueHint::Other,
+        conflicts_with_all=["target_list", "dns_sd", "file", "stdin", "import", "otlp_listen"])]
+    pub query: Vec<String>,
+
+    /// History read by the first call of --query, e.g. 30m, 12h or 7d
+    #[arg(long, env="PROM_QUERY_RANGE", value_name="DURATION", value_parser=parse_duration,
+        value_hint=ValueHint::Other, default_value="1h")]
+    pub query_range: Duration,
+
     /// Prometheus endpoint's port number
     ///
     /// The port number used in the default prometheus endpoint. Example: http://localhost:<PORT>/metrics
@@ -22,9 +137,399 @@ pub struct Cli {
     #[arg(short='i', long, env="PROM_SCRAPE_INTERVAL", value_hint=ValueHint::Other, default_value="10")]
     pub scrape_interval: u16,
 
+    /// Scrape every given milliseconds instead, for sub-second intervals
+    ///
+    /// Meant for watching load tests. The scrapes are stored in batches once per frame, so rendering and locking keep
+    /// up. Samples keep their timestamps in seconds. Changing the interval with the keys ends it.
+    #[arg(long, env="PROM_FAST_INTERVAL", value_name="MS", value_hint=ValueHint::Other,
+        value_parser=clap::value_parser!(u64).range(10..1000))]
+    pub fast_interval: Option<u64>,
+
+    /// Timeout of a single scrape in seconds
+    #[arg(long, env="PROM_SCRAPE_TIMEOUT", value_hint=ValueHint::Other, default_value="10")]
+    pub scrape_timeout: u16,
+
+    /// Number of retries of a failed scrape
+    ///
+    /// Failed scrapes are retried with an exponential backoff (1s, 2s, 4s, ...) before waiting for the next scrape interval.
+    /// A 429 or 503 response with a Retry-After header is not retried, the next scrape waits as long as asked instead.
+    #[arg(long, env="PROM_SCRAPE_RETRIES", value_hint=ValueHint::Other, default_value="3")]
+    pub scrape_retries: u32,
+
+    /// What to do when a scrape takes longer than the scrape interval
+    ///
+    /// Scrapes never run concurrently. With skip the missed ticks are dropped, with queue the next scrape starts
+    /// right after the slow one.
+    #[arg(long, env="PROM_OVERLAP_POLICY", value_enum, default_value_t=OverlapPolicy::Skip)]
+    pub overlap_policy: OverlapPolicy,
+
+    /// CA bundle used to verify the endpoint's TLS certificate
+    ///
+    /// PEM file with one or more CA certificates to trust in addition to the system roots.
+    /// Useful for endpoints signed by a private CA.
+    #[arg(long, env="PROM_TLS_CA", value_hint=ValueHint::FilePath)]
+    pub tls_ca: Option<PathBuf>,
+
+    /// Skip TLS certificate verification
+    ///
+    /// Accept any certificate presented by the endpoint, e.g. self-signed ones. Use with care.
+    #[arg(long, env = "PROM_INSECURE_SKIP_VERIFY")]
+    pub insecure_skip_verify: bool,
+
+    /// Extra HTTP header sent with every scrape request
+    ///
+    /// Given as "Name: value", e.g. "X-Scope-OrgID: tenant-1". Can be repeated to add several headers.
+    #[arg(short='H', long="header", value_name="NAME: VALUE", value_parser=parse_header, value_hint=ValueHint::Other)]
+    pub headers: Vec<(String, String)>,
+
+    /// Proxy to use for scraping
+    ///
+    /// Overrides the HTTP_PROXY/HTTPS_PROXY environment variables, which are used otherwise.
+    /// Hosts listed in NO_PROXY are still reached directly.
+    #[arg(long, value_hint=ValueHint::Url)]
+    pub proxy: Option<String>,
+
+    /// Proxy credentials
+    ///
+    /// Given as "user:password". Read from PROM_PROXY_AUTH to keep it out of the process list.
+    #[arg(
+        long,
+        env = "PROM_PROXY_AUTH",
+        value_name = "USER:PASSWORD",
+        hide_env_values = true
+    )]
+    pub proxy_auth: Option<String>,
+
+    /// Shell command printing the credentials for the endpoint, e.g. "pass show prom/api"
+    ///
+    /// The first line of the output is sent as bearer token, or as password if a username is set in the config file. It is run again after --auth-refresh seconds and when the endpoint rejects the credentials.
+    #[arg(long, env="PROM_AUTH_COMMAND", value_hint=ValueHint::CommandString)]
+    pub auth_command: Option<String>,
+
+    /// Seconds the credentials of --auth-command are reused
+    #[arg(long, env="PROM_AUTH_REFRESH", value_hint=ValueHint::Other, default_value="300")]
+    pub auth_refresh: u64,
+
+    /// Receive OpenTelemetry metrics instead of scraping
+    ///
+    /// Listen on the given address (e.g. 127.0.0.1:4318) for metrics pushed with OTLP/HTTP using the JSON encoding,
+    /// i.e. OTEL_EXPORTER_OTLP_PROTOCOL=http/json. The endpoint is ignored in this mode.
+    #[arg(long, env="PROM_OTLP_LISTEN", value_name="ADDR", value_hint=ValueHint::Other)]
+    pub otlp_listen: Option<String>,
+
+    /// Forward every scrape to a Prometheus remote-write URL
+    ///
+    /// Sends the samples of each scrape as a remote-write request, e.g. to http://prometheus:9090/api/v1/write of a
+    /// Prometheus with --web.enable-remote-write-receiver, or to Mimir or VictoriaMetrics. The headers and credentials
+    /// of the endpoint are not sent along.
+    #[arg(long, env="PROM_REMOTE_WRITE", value_name="URL", value_hint=ValueHint::Url)]
+    pub remote_write: Option<String>,
+
+    /// Push the latest values to a Pushgateway
+    ///
+    /// Replaces the group of --push-job and --push-instance at the given Pushgateway, e.g. http://pushgateway:9091,
+    /// with the values of every scrape. Useful when the target is only reachable from here, but Prometheus can reach
+    /// the gateway.
+    #[arg(long, env="PROM_PUSH", value_name="URL", value_hint=ValueHint::Url)]
+    pub push: Option<String>,
+
+    /// Job label of the group pushed with --push
+    #[arg(long, env="PROM_PUSH_JOB", value_hint=ValueHint::Other, default_value="promviz")]
+    pub push_job: String,
+
+    /// Instance label of the group pushed with --push, by default the host and port of the endpoint
+    #[arg(long, env="PROM_PUSH_INSTANCE", value_hint=ValueHint::Other)]
+    pub push_instance: Option<String>,
+
+    /// Serve the control API on the given address, e.g. 127.0.0.1:9091
+    ///
+    /// Lets test scripts and editor plugins drive the session over HTTP: select a metric, trigger a scrape, add a
+    /// favorite or export. POST requests must be sent as application/json, and requests with an Origin header or
+    /// a Host other than localhost or an IP address are refused, so web pages can't drive it. There is no
+    /// authentication otherwise, so only listen on a local address. An address without a host listens on all
+    /// interfaces.
+    #[arg(long, env="PROM_CONTROL_LISTEN", value_name="ADDR", value_hint=ValueHint::Other)]
+    pub control_listen: Option<String>,
+
+    /// Serve a web dashboard of the history on the given address, e.g. :8099
+    ///
+    /// A page charting the series and a JSON API under /api/v1, so others can see what the session sees from a
+    /// browser. It is read-only but has no authentication. An address without a host listens on all interfaces.
+    #[arg(long, env="PROM_WEB", value_name="ADDR", value_hint=ValueHint::Other)]
+    pub web: Option<String>,
+
+    /// Serve the metrics of promviz itself at /metrics on the given address, e.g. :9099
+    ///
+    /// Scrape counts and duration, parse errors, the number of metrics and series, and the memory used, so long
+    /// running sessions, e.g. with --headless, can be monitored. An address without a host listens on all interfaces.
+    #[arg(long, env="PROM_SELF_METRICS", value_name="ADDR", value_hint=ValueHint::Other)]
+    pub self_metrics: Option<String>,
+
+    /// Check a gRPC service and scrape its metrics port
+    ///
+    /// Calls the standard gRPC health service (grpc.health.v1) at the given address, e.g. http://localhost:50051,
+    /// and scrapes the metrics port mapped to its gRPC port with --grpc-metrics-port instead of --endpoint.
+    #[arg(long, env="PROM_GRPC", value_name="ADDR", value_hint=ValueHint::Url)]
+    pub grpc: Option<String>,
+
+    /// Metrics port of a gRPC port, given as "GRPC_PORT=METRICS_PORT"
+    ///
+    /// Used with --grpc to find the Prometheus endpoint of the service. Can be repeated.
+    #[arg(long, value_name="GRPC_PORT=METRICS_PORT", value_parser=parse_port_mapping)]
+    pub grpc_metrics_port: Vec<(u16, u16)>,
+
+    /// Only store metric families whose name matches the regex
+    ///
+    /// Keeps memory small on exporters with many metrics. The regex is not anchored, e.g. "^http_". Can be repeated
+    /// to keep the metrics matching any of them.
+    #[arg(long, value_name = "REGEX")]
+    pub filter: Vec<Regex>,
+
+    /// Drop metric families whose name matches the regex
+    ///
+    /// Applied after --filter. Can be repeated.
+    #[arg(long, value_name = "REGEX")]
+    pub exclude: Vec<Regex>,
+
+    /// Only store the series matching the selector, e.g. 'http_requests_total{method="GET"}'
+    ///
+    /// Keeps sessions on giant endpoints focused and light. Labels are matched with = or !=, and metric families not
+    /// named by any selector are dropped. Applied after --filter and --exclude. Can be repeated to keep the series
+    /// matching any of them.
+    #[arg(long, value_name = "SELECTOR", value_hint=ValueHint::Other)]
+    pub selector: Vec<Selector>,
+
+    /// Storage backend for the scraped history
+    ///
+    /// The default memory storage keeps every sample, so long sessions grow without limit unless --storage bounded,
+    /// --max-samples or --retention is given. Both backends keep the history in memory; SQLite and memory-mapped
+    /// file backends are deferred and not available yet.
+    #[arg(long, env="PROM_STORAGE", value_enum, default_value_t=StorageKind::Memory)]
+    pub storage: StorageKind,
+
+    /// Size of the ring buffers of the bounded storage
+    ///
+    /// Either samples per time series, e.g. 1000, or bytes for the whole history with a unit (B, KiB, MiB or GiB),
+    /// e.g. 256MiB, shared equally by all series.
+    #[arg(long, env="PROM_STORAGE_CAPACITY", value_hint=ValueHint::Other, default_value="1000")]
+    pub storage_capacity: StorageCapacity,
+
+    /// Samples kept per time series, older ones are dropped
+    ///
+    /// Shorthand for --storage bounded --storage-capacity N. By default all samples are kept.
+    #[arg(long, env="PROM_MAX_SAMPLES", value_hint=ValueHint::Other, conflicts_with_all=["storage", "storage_capacity"])]
+    pub max_samples: Option<usize>,
+
+    /// Drop samples older than this, e.g. 30m, 12h or 7d
+    ///
+    /// The age is relative to the newest sample of the series. A number without unit is in seconds. By default
+    /// all samples are kept.
+    #[arg(long, env="PROM_RETENTION", value_name="DURATION", value_parser=parse_duration, value_hint=ValueHint::Other)]
+    pub retention: Option<Duration>,
+
+    /// Remove series missing from this many scrapes of their metric in a row
+    ///
+    /// E.g. the series of a restarted pod. Until then, series missing from the latest scrape are greyed out in the
+    /// labels list. By default they are kept.
+    #[arg(long, env="PROM_EXPIRE_AFTER", value_name="SCRAPES", value_hint=ValueHint::Other)]
+    pub expire_after: Option<u32>,
+
+    /// Spill the oldest samples to a temporary file when promviz uses more memory than this
+    ///
+    /// In MiB. Checked every 10s, each time moving the older half of every series to disk while keeping at least
+    /// the latest 100 samples. The :restore-history command loads them back. Only available on Linux.
+    #[arg(long, env="PROM_MEMORY_LIMIT", value_name="MIB", value_hint=ValueHint::Other)]
+    pub memory_limit: Option<u64>,
+
+    /// Run without the UI, writing every scrape to a sink
+    ///
+    /// Writes the samples of every scrape as CSV (the default) or JSON lines to --output, or only sends them with
+    /// --remote-write. Runs until interrupted or until --scrapes scrapes are done, e.g. in scripts and cron jobs.
+    #[arg(long, env="PROM_HEADLESS", value_name="SINK", num_args=0..=1, default_missing_value="csv",
+        conflicts_with="tabs")]
+    pub headless: Option<SinkFormat>,
+
+    /// File the samples of --headless are written to, instead of stdout
+    #[arg(short, long, env="PROM_OUTPUT", value_name="FILE", value_hint=ValueHint::FilePath, requires="headless")]
+    pub output: Option<PathBuf>,
+
+    /// Stop --headless after this many scrapes, successful or not
+    #[arg(long, env="PROM_SCRAPES", value_name="COUNT", value_hint=ValueHint::Other, requires="headless")]
+    pub scrapes: Option<u64>,
+
+    /// Print a session summary on exit
+    ///
+    /// Lists the duration, source, scrape counts, the most changed series and exported files, so the terminal
+    /// scrollback documents the session.
+    #[arg(long, env = "PROM_SUMMARY")]
+    pub summary: bool,
+
+    /// Save CPU and bandwidth, e.g. on a laptop or over mosh/ssh
+    ///
+    /// Only redraws after input or a scrape (and every 10s), and disables animations.
+    #[arg(long, env = "PROM_LOW_POWER")]
+    pub low_power: bool,
+
+    /// Send desktop notifications when a series goes above its alert threshold or the scrape keeps failing
+    ///
+    /// Uses notify-send, or osascript on macOS, so alerts are noticed while the terminal is in the background.
+    #[arg(long, env = "PROM_NOTIFY")]
+    pub notify: bool,
+
+    /// Settings for the metrics of a well-known exporter, auto picks one from the scraped metric names
+    ///
+    /// Hides noisy metrics, pins the important ones, sets units and fills the dashboard if the config file defines
+    /// no panels. The units of the config file take precedence.
+    #[arg(long, env = "PROM_PROFILE", value_enum)]
+    pub profile: Option<ProfileName>,
+
+    /// Start on the dashboard defined in the config file instead of the metric list
+    #[arg(long, env = "PROM_DASHBOARD")]
+    pub dashboard: bool,
+
+    /// Hide the footer line with the keys of the focused pane
+    #[arg(long, env = "PROM_HIDE_FOOTER")]
+    pub hide_footer: bool,
+
+    /// Disable animations
+    ///
+    /// Scrape errors are shown in steady bold text instead of flashing and pulsing, and the prompt cursor doesn't
+    /// blink.
+    #[arg(long, env = "PROM_REDUCED_MOTION")]
+    pub reduced_motion: bool,
+
+    /// Restore a session saved with :save-session
+    ///
+    /// Scrapes the saved endpoint, unless another one is given, and restores pinned and hidden metrics, filters, units and other UI state.
+    #[arg(long, env = "PROM_SESSION", value_hint=ValueHint::Other)]
+    pub session: Option<String>,
+
+    /// Autosave the UI state every SECONDS, 0 disables it
+    ///
+    /// Saves to the session named autosave, so a crash or kill loses at most the last interval. If the last run didn't
+    /// exit cleanly, its autosave is moved to the session autosave-crashed, and promviz tells at the next start that
+    /// :load-session autosave-crashed recovers its state.
+    #[arg(long, env="PROM_AUTOSAVE", value_name="SECONDS", value_hint=ValueHint::Other, default_value="30")]
+    pub autosave: u64,
+
+    /// Format of timestamps in tables and graph axes
+    ///
+    /// One of rfc2822, rfc3339, relative (e.g. "42s ago") or a strftime pattern like "%Y-%m-%d %H:%M:%S".
+    #[arg(long, env="PROM_TIME_FORMAT", value_hint=ValueHint::Other, default_value="%H:%M:%S")]
+    pub time_format: TimeFormat,
+
+    /// Language of the UI
+    ///
+    /// Strings can also be translated one by one in the [strings] section of the config file.
+    #[arg(long, env="PROM_LANG", value_enum, default_value_t=Lang::En)]
+    pub lang: Lang,
+
+    /// Time zone of timestamps in tables and graph axes
+    ///
+    /// UTC, Local, an offset like +05:30 or an IANA time zone like America/New_York, e.g. to share the same times
+    /// with a distributed team.
+    #[arg(long, env="PROM_TIMEZONE", value_hint=ValueHint::Other, default_value="Local")]
+    pub timezone: DisplayZone,
+
     /// Set the logging level
     ///
-    /// Set the logging level to use when logging to the app.log file
+    /// Set the logging level to use when logging to the log file
     #[arg(short, long, env="LOG_LEVEL", value_hint=ValueHint::Other, default_value="INFO")]
     pub loglevel: log::LevelFilter,
+
+    /// Log file, instead of a new one per session in the log directory
+    ///
+    /// By default every session logs to promviz-<start time>-<pid>.log in ~/.local/state/promviz (or $XDG_STATE_HOME),
+    /// ~/Library/Logs/promviz on macOS or %LOCALAPPDATA%\promviz\logs on Windows, and only the logs of the latest 10
+    /// sessions are kept. Files are rotated at 10 MiB.
+    #[arg(long, env="PROM_LOG_FILE", value_hint=ValueHint::FilePath)]
+    pub log_file: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Check the metrics for exposition format problems and print a report
+    ///
+    /// Reports duplicate series, missing HELP and TYPE, invalid metric and label names and counters not following
+    /// the naming conventions. Exits with status 1 if any errors are found.
+    Lint {
+        /// Endpoint URL, file or - for stdin, defaults to --endpoint
+        #[arg(value_hint=ValueHint::AnyPath)]
+        source: Option<String>,
+    },
+}
+
+fn parse_header(header: &str) -> Result<(String, String), String> {
+    match header.split_once(':') {
+        Some((name, value)) if !name.trim().is_empty() => {
+            Ok((name.trim().to_string(), value.trim().to_string()))
+        }
+        _ => Err(format!("expected \"Name: value\", got \"{}\"", header)),
+    }
+}
+
+/// Parse a duration like "90", "30s", "15m", "2h" or "7d"
+pub fn parse_duration(duration: &str) -> Result<Duration, String> {
+    let duration = duration.trim();
+    let (number, unit) = duration.split_at(
+        duration
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(duration.len()),
+    );
+    let seconds = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => 0,
+    };
+    match number.parse::<u64>() {
+        Ok(number) if seconds > 0 => Ok(Duration::from_secs(number * seconds)),
+        _ => Err(format!(
+            "expected a duration like 30s, 15m, 2h or 7d, got \"{}\"",
+            duration
+        )),
+    }
+}
+
+fn parse_port_mapping(mapping: &str) -> Result<(u16, u16), String> {
+    let ports = mapping.split_once('=').and_then(|(grpc, metrics)| {
+        Some((grpc.trim().parse().ok()?, metrics.trim().parse().ok()?))
+    });
+    ports.ok_or_else(|| format!("expected \"GRPC_PORT=METRICS_PORT\", got \"{}\"", mapping))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_header() {
+        assert_eq!(
+            parse_header("X-Scope-OrgID: tenant-1"),
+            Ok(("X-Scope-OrgID".to_string(), "tenant-1".to_string()))
+        );
+        assert_eq!(
+            parse_header("Authorization:Bearer a:b"),
+            Ok(("Authorization".to_string(), "Bearer a:b".to_string()))
+        );
+        assert!(parse_header("no-separator").is_err());
+        assert!(parse_header(": value").is_err());
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("15m"), Ok(Duration::from_secs(900)));
+        assert_eq!(parse_duration("7d"), Ok(Duration::from_secs(604_800)));
+        assert!(parse_duration("2w").is_err());
+        assert!(parse_duration("h").is_err());
+    }
+
+    #[test]
+    fn test_parse_port_mapping() {
+        assert_eq!(parse_port_mapping("50051=9090"), Ok((50051, 9090)));
+        assert!(parse_port_mapping("50051").is_err());
+        assert!(parse_port_mapping("50051=metrics").is_err());
+    }
 }
@@ -0,0 +1,443 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::Context;
+use clap::{parser::ValueSource, ArgMatches};
+use ratatui::style::Color;
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::cli::{parse_duration, Cli};
+use crate::interactive::{
+    Dashboard, DisplayZone, KeyBindings, Lang, ProfileName, Theme, TimeFormat,
+};
+use crate::prom::pipeline::{DropLabels, Rename, Select, Selector, Sum};
+use crate::prom::{MetricFilter, Pipeline, StorageCapacity, StorageKind};
+
+/// Settings read from the TOML config file. Every value is optional and
+/// only used when the corresponding CLI flag or env variable is not given.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub endpoint: Option<String>,
+    pub scrape_interval: Option<u16>,
+    pub scrape_timeout: Option<u16>,
+    pub scrape_retries: Option<u32>,
+    /// In MiB
+    pub memory_limit: Option<u64>,
+    pub storage: Option<StorageKind>,
+    /// Samples per series, or bytes with a unit like "256MiB"
+    pub storage_capacity: Option<StorageCapacity>,
+    pub max_samples: Option<usize>,
+    /// e.g. "12h", see `cli::parse_duration`
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub retention: Option<Duration>,
+    pub expire_after: Option<u32>,
+    pub tls_ca: Option<PathBuf>,
+    pub insecure_skip_verify: Option<bool>,
+    pub reduced_motion: Option<bool>,
+    pub hide_footer: Option<bool>,
+    pub notify: Option<bool>,
+    pub proxy: Option<String>,
+    pub remote_write: Option<String>,
+    pub push: Option<String>,
+    pub push_job: Option<String>,
+    pub time_format: Option<TimeFormat>,
+    pub timezone: Option<DisplayZone>,
+    pub lang: Option<Lang>,
+    pub profile: Option<ProfileName>,
+    /// Translation of single UI strings, keyed by their English text
+    pub strings: HashMap<String, String>,
+    pub headers: BTreeMap<String, String>,
+    pub auth: AuthConfig,
+    pub colors: ColorsConfig,
+    pub keys: KeyBindings,
+    /// Unit per metric name
+    pub units: HashMap<String, String>,
+    /// Alert threshold per metric name
+    pub thresholds: HashMap<String, f64>,
+    pub pipeline: PipelineConfig,
+    pub dashboard: Dashboard,
+}
+
+/// Built-in stages processing every scrape, applied after --filter and
+/// --exclude in the order listed here
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PipelineConfig {
+    pub rename: Vec<RenameConfig>,
+    /// Labels removed from all series
+    pub drop_labels: Vec<String>,
+    pub sum: Vec<SumConfig>,
+}
+
+/// Rename the metric families matching the regex `pattern`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RenameConfig {
+    pub pattern: String,
+    pub replacement: String,
+}
+
+/// Derive a gauge `name` with the sum of all series of `source`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SumConfig {
+    pub name: String,
+    pub source: String,
+}
+
+/// Credentials for the scraped endpoint
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub bearer_token: Option<String>,
+    /// Shell command printing the bearer token, or the password of `username`
+    pub command: Option<String>,
+}
+
+/// Color names (e.g. "lightgreen") or hex codes ("#00ff00")
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ColorsConfig {
+    pub focus: Option<String>,
+    pub graph: Option<String>,
+    pub error: Option<String>,
+}
+
+impl Config {
+    /// Load the config from `path`, or from the default location if no path is
+    /// given. A missing default config file is not an error.
+    pub fn load(path: Option<&Path>) -> anyhow::Result<Self> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => match default_config_path() {
+                Some(path) if path.exists() => path,
+                _ => return Ok(Config::default()),
+            },
+        };
+        log::info!("Reading config file: {}", path.display());
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("failed to read config file {}", path.display()))?;
+        toml::from_str(&content).with_context(|| format!("invalid config file {}", path.display()))
+    }
+
+    /// Fill the CLI settings that were left at their defaults with the values
+    /// from the config file
+    pub fn merge_into(&mut self, cli: &mut Cli, matches: &ArgMatches) {
+        let unset = |id: &str| {
+            !matches!(
+                matches.value_source(id),
+                Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+            )
+        };
+        if unset("endpoint") {
+            if let Some(endpoint) = self.endpoint.take() {
+                cli.endpoint = endpoint;
+            }
+        }
+        if unset("scrape_interval") {
+            if let Some(scrape_interval) = self.scrape_interval {
+                cli.scrape_interval = scrape_interval;
+            }
+        }
+        if unset("scrape_timeout") {
+            if let Some(scrape_timeout) = self.scrape_timeout {
+                cli.scrape_timeout = scrape_timeout;
+            }
+        }
+        if unset("scrape_retries") {
+            if let Some(scrape_retries) = self.scrape_retries {
+                cli.scrape_retries = scrape_retries;
+            }
+        }
+        if unset("memory_limit") && self.memory_limit.is_some() {
+            cli.memory_limit = self.memory_limit;
+        }
+        if unset("storage") {
+            if let Some(storage) = self.storage {
+                cli.storage = storage;
+            }
+        }
+        if unset("storage_capacity") {
+            if let Some(storage_capacity) = self.storage_capacity {
+                cli.storage_capacity = storage_capacity;
+            }
+        }
+        // a shorthand for the storage settings, which take precedence
+        if unset("max_samples")
+            && unset("storage")
+            && unset("storage_capacity")
+            && self.storage.is_none()
+            && self.storage_capacity.is_none()
+            && self.max_samples.is_some()
+        {
+            cli.max_samples = self.max_samples;
+        }
+        if unset("retention") && self.retention.is_some() {
+            cli.retention = self.retention;
+        }
+        if unset("expire_after") && self.expire_after.is_some() {
+            cli.expire_after = self.expire_after;
+        }
+        if unset("tls_ca") && self.tls_ca.is_some() {
+            cli.tls_ca = self.tls_ca.take();
+        }
+        if unset("insecure_skip_verify") {
+            if let Some(insecure_skip_verify) = self.insecure_skip_verify {
+                cli.insecure_skip_verify = insecure_skip_verify;
+            }
+        }
+        if unset("reduced_motion") {
+            if let Some(reduced_motion) = self.reduced_motion {
+                cli.reduced_motion = reduced_motion;
+            }
+        }
+        if unset("hide_footer") {
+            if let Some(hide_footer) = self.hide_footer {
+                cli.hide_footer = hide_footer;
+            }
+        }
+        if unset("notify") {
+            if let Some(notify) = self.notify {
+                cli.notify = notify;
+            }
+        }
+        if unset("proxy") && self.proxy.is_some() {
+            cli.proxy = self.proxy.take();
+        }
+        if unset("remote_write") && self.remote_write.is_some() {
+            cli.remote_write = self.remote_write.take();
+        }
+        if unset("push") && self.push.is_some() {
+            cli.push = self.push.take();
+        }
+        if unset("push_job") {
+            if let Some(push_job) = self.push_job.take() {
+                cli.push_job = push_job;
+            }
+        }
+        if unset("auth_command") && self.auth.command.is_some() {
+            cli.auth_command = self.auth.command.take();
+        }
+        if unset("time_format") {
+            if let Some(time_format) = self.time_format.take() {
+                cli.time_format = time_format;
+            }
+        }
+        if unset("timezone") {
+            if let Some(timezone) = self.timezone.take() {
+                cli.timezone = timezone;
+            }
+        }
+        if unset("lang") {
+            if let Some(lang) = self.lang {
+                cli.lang = lang;
+            }
+        }
+        if unset("profile") && self.profile.is_some() {
+            cli.profile = self.profile;
+        }
+        // Headers from the CLI replace config headers with the same name
+        let mut headers: Vec<(String, String)> = std::mem::take(&mut self.headers)
+            .into_iter()
+            .filter(|(name, _)| {
+                !cli.headers
+                    .iter()
+                    .any(|(cli_name, _)| cli_name.eq_ignore_ascii_case(name))
+            })
+            .collect();
+        headers.append(&mut cli.headers);
+        cli.headers = headers;
+    }
+
+    /// The scrape pipeline of `filter` and `selectors` followed by the
+    /// configured stages
+    pub fn pipeline(
+        &self,
+        filter: MetricFilter,
+        selectors: Vec<Selector>,
+    ) -> anyhow::Result<Pipeline> {
+        let mut pipeline = Pipeline::from(filter);
+        if !selectors.is_empty() {
+            pipeline = pipeline.with_stage(Select { selectors });
+        }
+        for rename in &self.pipeline.rename {
+            pipeline = pipeline.with_stage(Rename {
+                pattern: Regex::new(&rename.pattern)
+                    .with_context(|| format!("invalid rename pattern '{}'", rename.pattern))?,
+                replacement: rename.replacement.clone(),
+            });
+        }
+        if !self.pipeline.drop_labels.is_empty() {
+            pipeline = pipeline.with_stage(DropLabels {
+                labels: self.pipeline.drop_labels.clone(),
+            });
+        }
+        for sum in &self.pipeline.sum {
+            pipeline = pipeline.with_stage(Sum {
+                name: sum.name.clone(),
+                source: sum.source.clone(),
+            });
+        }
+        Ok(pipeline)
+    }
+
+    pub fn theme(&self) -> anyhow::Result<Theme> {
+        let parse = |name: &Option<String>, default: Color| match name {
+            Some(name) => {
+                Color::from_str(name).map_err(|_| anyhow::anyhow!("invalid color '{}'", name))
+            }
+            None => Ok(default),
+        };
+        let default = Theme::default();
+        Ok(Theme {
+            focus: parse(&self.colors.focus, default.focus)?,
+            graph: parse(&self.colors.graph, default.graph)?,
+            error: parse(&self.colors.error, default.error)?,
+            animate: default.animate,
+        })
+    }
+}
+
+/// `$XDG_CONFIG_HOME/promviz`, falling back to `~/.config`
+pub fn config_dir() -> Option<PathBuf> {
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_dir.join("promviz"))
+}
+
+fn default_config_path() -> Option<PathBuf> {
+    Some(config_dir()?.join("config.toml"))
+}
+
+fn deserialize_duration<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Duration>, D::Error> {
+    let duration = String::deserialize(deserializer)?;
+    parse_duration(&duration)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::{CommandFactory, FromArgMatches};
+
+    use super::*;
+
+    const CONFIG: &str = r##"
+        endpoint = "https://node1:9100/metrics"
+        scrape_interval = 5
+        storage = "bounded"
+        storage_capacity = "256MiB"
+        retention = "12h"
+        time_format = "rfc3339"
+        timezone = "UTC"
+        lang = "sv"
+        profile = "node"
+        remote_write = "http://localhost:9090/api/v1/write"
+        push_job = "node"
+        headers = { "X-Scope-OrgID" = "tenant-1", "X-Team" = "infra" }
+
+        [auth]
+        bearer_token = "secret"
+
+        [colors]
+        focus = "#00aaff"
+
+        [keys]
+        quit = "Q"
+
+        [units]
+        node_network_receive_bytes_total = "bytes"
+
+        [thresholds]
+        queue_size = 100
+
+        [dashboard]
+        columns = 3
+
+        [[dashboard.panels]]
+        metric = "node_load1"
+        chart = "value"
+    "##;
+
+    fn parse_cli(args: &[&str]) -> (Cli, ArgMatches) {
+        let matches = Cli::command().get_matches_from(args);
+        (Cli::from_arg_matches(&matches).unwrap(), matches)
+    }
+
+    #[test]
+    fn test_config_fills_defaults() {
+        let mut config: Config = toml::from_str(CONFIG).unwrap();
+        let (mut cli, matches) = parse_cli(&["promviz"]);
+        config.merge_into(&mut cli, &matches);
+        assert_eq!(cli.endpoint, "https://node1:9100/metrics");
+        assert_eq!(cli.scrape_interval, 5);
+        assert_eq!(cli.storage, StorageKind::Bounded);
+        assert_eq!(cli.storage_capacity, StorageCapacity::Bytes(256 << 20));
+        assert_eq!(cli.retention, Some(Duration::from_secs(12 * 60 * 60)));
+        assert_eq!(cli.time_format, TimeFormat::Rfc3339);
+        assert_eq!(cli.timezone, DisplayZone::Utc);
+        assert_eq!(cli.lang, Lang::Sv);
+        assert_eq!(cli.profile, Some(ProfileName::Node));
+        assert_eq!(
+            cli.remote_write.as_deref(),
+            Some("http://localhost:9090/api/v1/write")
+        );
+        assert_eq!(cli.push_job, "node");
+        assert_eq!(cli.headers.len(), 2);
+        assert_eq!(config.auth.bearer_token.as_deref(), Some("secret"));
+        assert_eq!(config.keys.quit, 'Q');
+        assert_eq!(config.keys.toggle_rate, 'r');
+        assert_eq!(config.units["node_network_receive_bytes_total"], "bytes");
+        assert_eq!(config.thresholds["queue_size"], 100.0);
+        assert_eq!(config.dashboard.columns, 3);
+        assert_eq!(config.dashboard.panels[0].metric, "node_load1");
+        assert_eq!(config.theme().unwrap().focus, Color::Rgb(0, 0xaa, 0xff));
+    }
+
+    #[test]
+    fn test_cli_takes_precedence() {
+        let mut config: Config = toml::from_str(CONFIG).unwrap();
+        let (mut cli, matches) = parse_cli(&[
+            "promviz",
+            "--endpoint",
+            "http://localhost:1234/metrics",
+            "--header",
+            "x-team: platform",
+        ]);
+        config.merge_into(&mut cli, &matches);
+        assert_eq!(cli.endpoint, "http://localhost:1234/metrics");
+        assert_eq!(cli.scrape_interval, 5);
+        assert_eq!(
+            cli.headers,
+            vec![
+                ("X-Scope-OrgID".to_string(), "tenant-1".to_string()),
+                ("x-team".to_string(), "platform".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        assert!(toml::from_str::<Config>("unknown_option = 1").is_err());
+        assert!(toml::from_str::<Config>("retention = \"1y\"").is_err());
+        assert!(toml::from_str::<Config>("storage = \"sqlite\"").is_err());
+        assert!(toml::from_str::<Config>("storage_capacity = \"1TB\"").is_err());
+        let config: Config = toml::from_str("storage_capacity = 500").unwrap();
+        assert_eq!(config.storage_capacity, Some(StorageCapacity::Samples(500)));
+        let config: Config = toml::from_str("[colors]\ngraph = \"no-such-color\"").unwrap();
+        assert!(config.theme().is_err());
+        let config: Config =
+            toml::from_str("[[pipeline.rename]]\npattern = \"(\"\nreplacement = \"\"").unwrap();
+        assert!(config.pipeline(MetricFilter::default(), vec![]).is_err());
+    }
+}
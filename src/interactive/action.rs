@@ -0,0 +1,211 @@
+use crossterm::event::{KeyCode, MouseButton, MouseEvent, MouseEventKind};
+
+use crate::interactive::app::CopyTarget;
+use crate::interactive::keys::KeyBindings;
+
+/// A user request, decoupled from the key or mouse event triggering it.
+/// Events are translated to actions, which are then applied by `App::dispatch`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Quit,
+    Down,
+    Up,
+    PageDown,
+    PageUp,
+    First,
+    Last,
+    /// First key of the `gg` chord, jumping to the first item when repeated
+    Goto,
+    SwitchFocus,
+    Escape,
+    ToggleRate,
+    ToggleMark,
+    HideMarked,
+    UnhideAll,
+    PinMarked,
+    ExportMarked,
+    ExportSelected,
+    ExportJson,
+    ToggleTopMovers,
+    TogglePayload,
+    ToggleDashboard,
+    ToggleTargets,
+    ToggleAggregate,
+    NextTimeWindow,
+    NextIncreaseWindow,
+    OpenExpression,
+    ShowErrorRatio,
+    OpenSearch,
+    OpenLabelFilter,
+    TogglePause,
+    OpenUnit,
+    OpenThreshold,
+    IncreaseInterval,
+    DecreaseInterval,
+    ToggleHelp,
+    NextBarOrientation,
+    ToggleBarLabels,
+    WiderBars,
+    NarrowerBars,
+    ScrollBarsLeft,
+    ScrollBarsRight,
+    ToggleCompare,
+    ToggleSummaryView,
+    NextCompareMode,
+    NextSortMode,
+    ToggleFavorite,
+    ToggleFavoritesTab,
+    IgnoreMarked,
+    ToggleMetadata,
+    ToggleComponents,
+    /// Show the tab at the index, see `App::tabs`
+    SwitchTab(usize),
+    Copy(CopyTarget),
+    /// Key typed while a prompt is open
+    PromptInput(KeyCode),
+    MouseDown {
+        column: u16,
+        row: u16,
+    },
+    MouseDrag {
+        column: u16,
+    },
+    MouseUp {
+        column: u16,
+    },
+    ScrollUp {
+        column: u16,
+        row: u16,
+    },
+    ScrollDown {
+        column: u16,
+        row: u16,
+    },
+}
+
+impl KeyBindings {
+    /// Action bound to `code`, if any
+    pub fn action(&self, code: KeyCode) -> Option<Action> {
+        let action = match code {
+            KeyCode::Down => Action::Down,
+            KeyCode::Up => Action::Up,
+            KeyCode::PageDown => Action::PageDown,
+            KeyCode::PageUp => Action::PageUp,
+            KeyCode::Home => Action::First,
+            KeyCode::End => Action::Last,
+            KeyCode::Tab | KeyCode::BackTab | KeyCode::Right | KeyCode::Left => Action::SwitchFocus,
+            KeyCode::Esc => Action::Escape,
+            KeyCode::Char(c) if c == self.quit => Action::Quit,
+            KeyCode::Char(c) if c == self.goto => Action::Goto,
+            KeyCode::Char(c) if c == self.last => Action::Last,
+            KeyCode::Char(c) if c == self.toggle_rate => Action::ToggleRate,
+            KeyCode::Char(c) if c == self.mark => Action::ToggleMark,
+            KeyCode::Char(c) if c == self.hide => Action::HideMarked,
+            KeyCode::Char(c) if c == self.unhide_all => Action::UnhideAll,
+            KeyCode::Char(c) if c == self.pin => Action::PinMarked,
+            KeyCode::Char(c) if c == self.export => Action::ExportMarked,
+            KeyCode::Char(c) if c == self.export_series => Action::ExportSelected,
+            KeyCode::Char(c) if c == self.export_json => Action::ExportJson,
+            KeyCode::Char(c) if c == self.top_movers => Action::ToggleTopMovers,
+            KeyCode::Char(c) if c == self.payload => Action::TogglePayload,
+            KeyCode::Char(c) if c == self.dashboard => Action::ToggleDashboard,
+            KeyCode::Char(c) if c == self.targets => Action::ToggleTargets,
+            KeyCode::Char(c) if c == self.aggregate => Action::ToggleAggregate,
+            KeyCode::Char(c) if c == self.time_window => Action::NextTimeWindow,
+            KeyCode::Char(c) if c == self.increase_window => Action::NextIncreaseWindow,
+            KeyCode::Char(c) if c == self.expression => Action::OpenExpression,
+            KeyCode::Char(c) if c == self.error_ratio => Action::ShowErrorRatio,
+            KeyCode::Char(c) if c == self.search => Action::OpenSearch,
+            KeyCode::Char(c) if c == self.label_filter => Action::OpenLabelFilter,
+            KeyCode::Char(c) if c == self.pause => Action::TogglePause,
+            KeyCode::Char(c) if c == self.unit => Action::OpenUnit,
+            KeyCode::Char(c) if c == self.threshold => Action::OpenThreshold,
+            KeyCode::Char(c) if c == self.increase_interval => Action::IncreaseInterval,
+            KeyCode::Char(c) if c == self.decrease_interval => Action::DecreaseInterval,
+            KeyCode::Char(c) if c == self.help => Action::ToggleHelp,
+            KeyCode::Char(c) if c == self.bar_orientation => Action::NextBarOrientation,
+            KeyCode::Char(c) if c == self.bar_labels => Action::ToggleBarLabels,
+            KeyCode::Char(c) if c == self.wider_bars => Action::WiderBars,
+            KeyCode::Char(c) if c == self.narrower_bars => Action::NarrowerBars,
+            KeyCode::Char(c) if c == self.scroll_bars_left => Action::ScrollBarsLeft,
+            KeyCode::Char(c) if c == self.scroll_bars_right => Action::ScrollBarsRight,
+            KeyCode::Char(c) if c == self.compare => Action::ToggleCompare,
+            KeyCode::Char(c) if c == self.summary_view => Action::ToggleSummaryView,
+            KeyCode::Char(c) if c == self.compare_mode => Action::NextCompareMode,
+            KeyCode::Char(c) if c == self.sort => Action::NextSortMode,
+            KeyCode::Char(c) if c == self.favorite => Action::ToggleFavorite,
+            KeyCode::Char(c) if c == self.favorites_tab => Action::ToggleFavoritesTab,
+            KeyCode::Char(c) if c == self.ignore => Action::IgnoreMarked,
+            KeyCode::Char(c) if c == self.metadata => Action::ToggleMetadata,
+            KeyCode::Char(c) if c == self.components => Action::ToggleComponents,
+            KeyCode::Char(c) if c == self.copy_name => Action::Copy(CopyTarget::MetricName),
+            KeyCode::Char(c) if c == self.copy_series => Action::Copy(CopyTarget::Series),
+            KeyCode::Char(c) if c == self.copy_value => Action::Copy(CopyTarget::LatestValue),
+            KeyCode::Char(c @ '1'..='9') => Action::SwitchTab(c as usize - '1' as usize),
+            _ => return None,
+        };
+        Some(action)
+    }
+}
+
+/// Action of a mouse event: left button clicks select, drags zoom the graph
+/// and the wheel scrolls
+pub fn mouse_action(event: &MouseEvent) -> Option<Action> {
+    let (column, row) = (event.column, event.row);
+    match event.kind {
+        MouseEventKind::Down(MouseButton::Left) => Some(Action::MouseDown { column, row }),
+        MouseEventKind::Drag(MouseButton::Left) => Some(Action::MouseDrag { column }),
+        MouseEventKind::Up(MouseButton::Left) => Some(Action::MouseUp { column }),
+        MouseEventKind::ScrollUp => Some(Action::ScrollUp { column, row }),
+        MouseEventKind::ScrollDown => Some(Action::ScrollDown { column, row }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::KeyModifiers;
+
+    #[test]
+    fn test_key_actions() {
+        let mut keys = KeyBindings::default();
+        assert_eq!(keys.action(KeyCode::Char('q')), Some(Action::Quit));
+        assert_eq!(keys.action(KeyCode::BackTab), Some(Action::SwitchFocus));
+        assert_eq!(keys.action(KeyCode::Char('/')), Some(Action::OpenSearch));
+        assert_eq!(keys.action(KeyCode::Char('z')), None);
+        assert_eq!(keys.action(KeyCode::End), Some(Action::Last));
+        assert_eq!(keys.action(KeyCode::Char('G')), Some(Action::Last));
+        assert_eq!(keys.action(KeyCode::Char('3')), Some(Action::SwitchTab(2)));
+
+        keys.quit = 'Q';
+        assert_eq!(keys.action(KeyCode::Char('q')), None);
+        assert_eq!(keys.action(KeyCode::Char('Q')), Some(Action::Quit));
+    }
+
+    #[test]
+    fn test_mouse_actions() {
+        let event = |kind| MouseEvent {
+            kind,
+            column: 12,
+            row: 3,
+            modifiers: KeyModifiers::NONE,
+        };
+        assert_eq!(
+            mouse_action(&event(MouseEventKind::Down(MouseButton::Left))),
+            Some(Action::MouseDown { column: 12, row: 3 })
+        );
+        assert_eq!(
+            mouse_action(&event(MouseEventKind::Up(MouseButton::Left))),
+            Some(Action::MouseUp { column: 12 })
+        );
+        assert_eq!(
+            mouse_action(&event(MouseEventKind::ScrollDown)),
+            Some(Action::ScrollDown { column: 12, row: 3 })
+        );
+        assert_eq!(
+            mouse_action(&event(MouseEventKind::Down(MouseButton::Right))),
+            None
+        );
+    }
+}
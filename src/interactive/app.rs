@@ -1,7 +1,29 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
 
-use crate::prom::MetricScraper;
-use ratatui::widgets::ListState;
+use crate::interactive::{
+    action::Action,
+    clipboard,
+    dashboard::Dashboard,
+    favorites::Favorites,
+    grouping::{self, group_values, in_group, section_rows, Group, SectionRow, TARGET_LABEL},
+    ignore_list::IgnoreList,
+    keys::KeyBindings,
+    profile::Profile,
+    session::Session,
+    ui::{components, detect_unit, Animation, SearchQuery},
+};
+use crate::prom::{
+    export,
+    expr::{self, Expr},
+    HistoryStore, Metric, MetricDetails, MetricScraper, Sample,
+};
+use crossterm::event::KeyCode;
+use ratatui::{layout::Rect, widgets::ListState};
 
 #[derive(Debug)]
 pub enum ElementInFocus {
@@ -9,48 +31,847 @@ pub enum ElementInFocus {
     LabelsView,
 }
 
+/// The screen shown below the info header
+#[derive(Debug, Default, PartialEq, Eq)]
+pub enum Screen {
+    /// Metric list with details of the selected metric
+    #[default]
+    Browse,
+    /// Counters with the highest rate across the endpoint
+    TopMovers,
+    /// Metric families by their bytes in the latest scrape
+    Payload,
+    /// Series computed by the entered expression
+    Expression,
+    /// Panels of the dashboard defined in the config file
+    Dashboard,
+    /// Health of every target of the endpoint
+    Targets,
+}
+
+/// Input line opened with a key and shown in the info header
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PromptKind {
+    Expression,
+    Search,
+    /// Filter of the labels pane
+    LabelFilter,
+    /// Unit of the selected metric
+    Unit,
+    /// Alert threshold of the selected metric
+    Threshold,
+}
+
+#[derive(Debug)]
+pub struct Prompt {
+    pub kind: PromptKind,
+    pub input: String,
+}
+
 #[derive(Debug)]
 enum Direction {
     Up,
     Down,
+    PageUp,
+    PageDown,
+    First,
+    Last,
+    /// Like Up and Down, but stopping at the ends of the list
+    ScrollUp,
+    ScrollDown,
+    /// The item at the index, if there is one
+    To(usize),
+}
+
+/// Items moved by PageUp and PageDown
+const PAGE_SIZE: usize = 10;
+
+/// Widest histogram bar the keys set
+const MAX_BAR_WIDTH: u16 = 40;
+
+/// How the values of a series are plotted
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ValueMode {
+    /// The values as scraped
+    #[default]
+    Raw,
+    /// Per-second rate of change between consecutive samples
+    Rate,
+}
+
+/// How the bars of histograms and summaries are laid out
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BarOrientation {
+    /// Vertical bars, or one row per bar when the labels don't fit
+    #[default]
+    Auto,
+    Vertical,
+    Horizontal,
+}
+
+impl BarOrientation {
+    pub fn next(self) -> Self {
+        match self {
+            BarOrientation::Auto => BarOrientation::Vertical,
+            BarOrientation::Vertical => BarOrientation::Horizontal,
+            BarOrientation::Horizontal => BarOrientation::Auto,
+        }
+    }
+}
+
+/// What the value labels of histogram bars show
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BarLabels {
+    /// Observations per bucket
+    #[default]
+    Count,
+    /// Share of all observations per bucket
+    Percent,
+}
+
+/// How the compared series is shown below the selected one
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CompareMode {
+    /// On a graph of its own
+    #[default]
+    Graphs,
+    /// As the selected series divided by the compared one, e.g. errors/requests
+    Ratio,
+    /// As the selected series minus the compared one, e.g. total-used memory
+    Difference,
+}
+
+/// What the copy keys put on the clipboard
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CopyTarget {
+    MetricName,
+    /// Name and labels, e.g. `http_requests_total{code="200"}`
+    Series,
+    /// Value of gauges and counters, count of histograms and summaries
+    LatestValue,
+}
+
+/// Order of the metric list, pinned metrics always come first
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SortMode {
+    #[default]
+    Name,
+    /// Largest latest value first
+    Value,
+    /// Fastest changing first
+    RateOfChange,
+}
+
+/// Scrape intervals (seconds) stepped through with the interval keys
+pub const SCRAPE_INTERVALS: [u64; 9] = [1, 2, 5, 10, 15, 30, 60, 120, 300];
+
+/// Time windows (seconds) the graphs are restricted to, `None` for the whole
+/// history
+pub const TIME_WINDOWS: [Option<u64>; 5] =
+    [None, Some(60), Some(5 * 60), Some(15 * 60), Some(3600)];
+
+/// Windows (seconds) the increase of counters is shown over, next to the graph
+pub const INCREASE_WINDOWS: [u64; 4] = [60, 5 * 60, 15 * 60, 3600];
+
+/// Zoom, mouse selection and display preferences of the graph
+#[derive(Debug, Default)]
+pub struct GraphView {
+    /// Time window (unix seconds) the graph is restricted to
+    pub zoom: Option<(f64, f64)>,
+    /// Columns of an in-progress mouse drag selection (start, current)
+    pub drag: Option<(u16, u16)>,
+    /// Plotting area of the last rendered graph
+    pub plot_area: Option<Rect>,
+    /// Time bounds (unix seconds) of the last rendered graph
+    pub x_bounds: [f64; 2],
+    /// Last used value mode per metric name
+    pub value_modes: HashMap<String, ValueMode>,
+    /// Plot the sum of all series of the metric instead of the selected one
+    pub aggregate: bool,
+    /// Plot the mean of the aggregated series and their range instead of the sum
+    pub spread: bool,
+    /// Series left out of the aggregate, per metric name
+    pub excluded_series: HashMap<String, HashSet<String>>,
+    /// Series plotted together with the selected one, per metric name
+    pub overlay_series: HashMap<String, Vec<String>>,
+    /// Index into `TIME_WINDOWS` of the window the graphs cover
+    pub time_window_index: usize,
+    /// Index into `INCREASE_WINDOWS` of the selected counter increase
+    pub increase_window_index: usize,
+    /// Unit per metric name, e.g. "bytes", shown on the axis and in the tables
+    pub units: HashMap<String, String>,
+    /// Alert threshold per metric name, series above it are drawn in red
+    pub thresholds: HashMap<String, f64>,
+    /// Area of the last rendered history table
+    pub table_area: Option<Rect>,
+    /// Rows the history table is scrolled back from the newest sample
+    pub history_scroll: usize,
+    pub bar_orientation: BarOrientation,
+    pub bar_labels: BarLabels,
+    /// Width of the histogram bars set with the keys, `None` to fit all
+    /// buckets in the pane
+    pub bar_width: Option<u16>,
+    /// Width fitting all buckets in the pane, as last drawn
+    pub fitted_bar_width: u16,
+    /// First histogram bucket shown when not all bars fit
+    pub bar_scroll: usize,
+    /// Series (metric name, labels) drawn below the graph of the selected one
+    pub compare: Option<(String, String)>,
+    pub compare_mode: CompareMode,
+    /// Plot the quantiles of summaries over time instead of the latest ones
+    pub quantiles_over_time: bool,
+}
+
+impl GraphView {
+    pub fn value_mode(&self, metric_name: &str) -> ValueMode {
+        self.value_modes
+            .get(metric_name)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn toggle_value_mode(&mut self, metric_name: &str) {
+        let mode = match self.value_mode(metric_name) {
+            ValueMode::Raw => ValueMode::Rate,
+            ValueMode::Rate => ValueMode::Raw,
+        };
+        self.value_modes.insert(metric_name.to_string(), mode);
+    }
+
+    /// Seconds before the newest sample that are plotted, `None` for all
+    pub fn time_window(&self) -> Option<u64> {
+        TIME_WINDOWS[self.time_window_index % TIME_WINDOWS.len()]
+    }
+
+    pub fn next_time_window(&mut self) {
+        self.time_window_index = (self.time_window_index + 1) % TIME_WINDOWS.len();
+    }
+
+    /// Seconds before the newest sample the selected counter increase covers
+    pub fn increase_window(&self) -> u64 {
+        INCREASE_WINDOWS[self.increase_window_index % INCREASE_WINDOWS.len()]
+    }
+
+    pub fn next_increase_window(&mut self) {
+        self.increase_window_index = (self.increase_window_index + 1) % INCREASE_WINDOWS.len();
+    }
+
+    /// Unit of the values plotted for the metric in `mode`, the one set by
+    /// the user or else the detected one
+    pub fn unit(&self, details: &MetricDetails, mode: ValueMode) -> Option<String> {
+        let unit = self
+            .units
+            .get(&details.name)
+            .cloned()
+            .or_else(|| detect_unit(details))?;
+        Some(match mode {
+            ValueMode::Raw => unit,
+            ValueMode::Rate => format!("{}/s", unit),
+        })
+    }
+
+    pub fn excluded_series(&self, metric_name: &str) -> HashSet<String> {
+        self.excluded_series
+            .get(metric_name)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Include or exclude a series from the aggregate of its metric
+    pub fn toggle_excluded_series(&mut self, metric_name: &str, labels: &str) {
+        let excluded = self
+            .excluded_series
+            .entry(metric_name.to_string())
+            .or_default();
+        if !excluded.remove(labels) {
+            excluded.insert(labels.to_string());
+        }
+    }
+
+    /// Add a series to the ones plotted with the selected series of its
+    /// metric, or remove it if it's already plotted
+    pub fn toggle_overlay_series(&mut self, metric_name: &str, labels: &str) {
+        let overlay = self
+            .overlay_series
+            .entry(metric_name.to_string())
+            .or_default();
+        match overlay.iter().position(|series| series == labels) {
+            Some(index) => {
+                overlay.remove(index);
+            }
+            None => overlay.push(labels.to_string()),
+        }
+    }
+
+    /// The series to plot, the selected one first followed by the overlaid
+    /// ones in the order they were added. Series not in `visible` are left out.
+    pub fn plotted_series(
+        &self,
+        metric_name: &str,
+        selected: &str,
+        visible: &[String],
+    ) -> Vec<String> {
+        let mut series = vec![selected.to_string()];
+        if let Some(overlay) = self.overlay_series.get(metric_name) {
+            series.extend(
+                overlay
+                    .iter()
+                    .filter(|labels| *labels != selected && visible.contains(labels))
+                    .cloned(),
+            );
+        }
+        series
+    }
+
+    fn column_to_time(&self, area: Rect, column: u16) -> f64 {
+        let offset = column.clamp(area.left(), area.right().saturating_sub(1)) - area.left();
+        let ratio = f64::from(offset) / f64::from(area.width.saturating_sub(1).max(1));
+        self.x_bounds[0] + ratio * (self.x_bounds[1] - self.x_bounds[0])
+    }
+
+    pub fn on_mouse_down(&mut self, column: u16, row: u16) {
+        self.drag = match self.plot_area {
+            Some(area) if area.contains((column, row).into()) => Some((column, column)),
+            _ => None,
+        };
+    }
+
+    pub fn on_mouse_drag(&mut self, column: u16) {
+        if let Some((start, _)) = self.drag {
+            self.drag = Some((start, column));
+        }
+    }
+
+    pub fn on_mouse_up(&mut self, column: u16) {
+        let (Some((start, _)), Some(area)) = (self.drag.take(), self.plot_area) else {
+            return;
+        };
+        // A plain click is not a selection
+        if start == column {
+            return;
+        }
+        let from = self.column_to_time(area, start.min(column));
+        let to = self.column_to_time(area, start.max(column));
+        self.zoom = Some((from, to));
+    }
+
+    /// Reset the zoom of the graph, and the width and scrolling of the
+    /// histogram bars
+    pub fn reset_zoom(&mut self) {
+        self.drag = None;
+        self.zoom = None;
+        self.bar_width = None;
+        self.bar_scroll = 0;
+    }
+}
+
+/// An endpoint scraped in a tab of its own, see `App::add_tab`
+#[derive(Debug)]
+pub struct Tab<'a> {
+    pub title: String,
+    /// State of the tab while another one is shown, `None` for the shown tab
+    parked: Option<TabState<'a>>,
+}
+
+/// The part of the app state kept per tab
+#[derive(Debug)]
+struct TabState<'a> {
+    endpoint: Cow<'a, str>,
+    metric_scraper: MetricScraper,
+    focus: ElementInFocus,
+    metric_list_state: ListState,
+    labels_list_state: ListState,
+    selected_metric: Option<String>,
+    selected_label: Option<String>,
+    graph_view: GraphView,
+}
+
+/// Short name of an endpoint for its tab, e.g. "node1:9100" for
+/// "http://node1:9100/metrics"
+fn tab_title(endpoint: &str) -> String {
+    let rest = endpoint
+        .split_once("://")
+        .map_or(endpoint, |(_, rest)| rest);
+    rest.split('/')
+        .next()
+        .filter(|host| !host.is_empty())
+        .unwrap_or(rest)
+        .to_string()
 }
 
 #[derive(Debug)]
 pub struct App<'a> {
-    pub endpoint: &'a str,
+    /// Source shown in the header, owned once switched by a loaded session
+    pub endpoint: Cow<'a, str>,
     pub scrape_interval: u64,
     pub metric_scraper: MetricScraper,
 
+    pub screen: Screen,
     pub focus: ElementInFocus,
     pub metric_list_state: ListState,
     pub labels_list_state: ListState,
+    /// Areas of the last rendered lists, for mouse clicks
+    pub metric_list_area: Option<Rect>,
+    pub labels_list_area: Option<Rect>,
     pub selected_metric: Option<String>,
     pub selected_label: Option<String>,
-    //TODO: Implement shutdown handling
-    #[allow(dead_code)]
+    pub graph_view: GraphView,
+    /// Metrics marked for a bulk action
+    pub marked_metrics: HashSet<String>,
+    pub hidden_metrics: HashSet<String>,
+    /// Prefixes of the metric names hidden by the profile
+    pub hidden_prefixes: Vec<&'static str>,
+    /// Pick the profile from the metric names once they are scraped
+    pub detect_profile: bool,
+    /// Metrics always listed first
+    pub pinned_metrics: HashSet<String>,
+    /// Metrics listed after the pinned ones, saved across sessions
+    pub favorite_metrics: HashSet<String>,
+    /// File the favorites are saved to, if any
+    favorites_path: Option<PathBuf>,
+    /// List only the favorites
+    pub show_favorites: bool,
+    /// Label whose values are listed before the metrics, see `grouping`
+    pub group_by: Option<String>,
+    /// The group of `group_by` whose metrics are listed
+    pub group: Option<Group>,
+    /// The groups are listed in place of the metrics until one is entered,
+    /// with a selection of their own so no metric is selected meanwhile
+    pub group_list_state: ListState,
+    pub selected_group: Option<Group>,
+    /// The section of the selected row while the metric list is split into a
+    /// section per target, see `shows_sections`
+    pub section: Option<Group>,
+    /// Sections whose metrics aren't listed
+    pub collapsed_sections: HashSet<Group>,
+    /// Metrics hidden in every session
+    pub ignore_list: IgnoreList,
+    /// File the ignore list is saved to, if any
+    ignore_list_path: Option<PathBuf>,
+    pub sort_mode: SortMode,
+    /// Outcome of the last user action, shown in the info header
+    pub status_msg: Option<String>,
+    /// Files written by exports during the session
+    pub exported_files: Vec<String>,
+    /// Open input line, if any
+    pub prompt: Option<Prompt>,
+    /// Filter of the metric and labels lists
+    pub search: SearchQuery,
+    /// Space separated parts of the series labels shown in the labels pane
+    pub label_filter: String,
+    /// Last entered expression and its parsed form
+    pub expression: Option<(String, Expr)>,
+    /// Set by the quit action, ending the event loop
     pub should_quit: bool,
+    pub keys: KeyBindings,
+    /// The keybindings overlay is open
+    pub show_help: bool,
+    /// Show the metadata of the selected metric above its labels
+    pub show_metadata: bool,
+    /// Show the raw `_bucket`, `_sum` and `_count` series of histograms and
+    /// summaries instead of their synthesized view
+    pub show_components: bool,
+    /// Show the most relevant keys in a footer line
+    pub show_footer: bool,
+    /// The first key of the `gg` chord was pressed
+    pending_goto: bool,
+    pub animation: Animation,
+    pub dashboard: Dashboard,
+    /// One tab per endpoint when scraping several of them, empty otherwise
+    pub tabs: Vec<Tab<'a>>,
+    /// Index into `tabs` of the shown tab
+    pub active_tab: usize,
 }
 
 impl<'a> App<'a> {
     pub fn new(endpoint: &'a str, scrape_interval: u64, metric_scraper: MetricScraper) -> App<'a> {
         App {
-            endpoint,
+            endpoint: Cow::Borrowed(endpoint),
             scrape_interval,
             metric_scraper,
+            screen: Screen::default(),
             focus: ElementInFocus::MetricHeaders,
             metric_list_state: ListState::default(),
             labels_list_state: ListState::default(),
+            metric_list_area: None,
+            labels_list_area: None,
             selected_metric: None,
             selected_label: None,
+            graph_view: GraphView::default(),
+            marked_metrics: HashSet::new(),
+            hidden_metrics: HashSet::new(),
+            hidden_prefixes: vec![],
+            detect_profile: false,
+            pinned_metrics: HashSet::new(),
+            favorite_metrics: HashSet::new(),
+            favorites_path: None,
+            show_favorites: false,
+            group_by: None,
+            group: None,
+            group_list_state: ListState::default(),
+            selected_group: None,
+            section: None,
+            collapsed_sections: HashSet::new(),
+            ignore_list: IgnoreList::default(),
+            ignore_list_path: None,
+            sort_mode: SortMode::default(),
+            status_msg: None,
+            exported_files: vec![],
+            prompt: None,
+            search: SearchQuery::default(),
+            label_filter: String::new(),
+            expression: None,
             should_quit: false,
+            keys: KeyBindings::default(),
+            show_help: false,
+            show_metadata: false,
+            show_components: false,
+            show_footer: false,
+            pending_goto: false,
+            animation: Animation::default(),
+            dashboard: Dashboard::default(),
+            tabs: vec![],
+            active_tab: 0,
         }
     }
 
-    fn change_selected_metric(&mut self, direction: Direction) -> Result<bool, Box<dyn Error>> {
-        let metrics_headers = self
+    /// Add a tab scraping `endpoint`, with its own metric list, selection and
+    /// history. The first call also turns the current endpoint into the first
+    /// tab.
+    pub fn add_tab(&mut self, endpoint: &'a str, metric_scraper: MetricScraper) {
+        if self.tabs.is_empty() {
+            self.tabs.push(Tab {
+                title: tab_title(&self.endpoint),
+                parked: None,
+            });
+        }
+        self.tabs.push(Tab {
+            title: tab_title(endpoint),
+            parked: Some(TabState {
+                endpoint: Cow::Borrowed(endpoint),
+                metric_scraper,
+                focus: ElementInFocus::MetricHeaders,
+                metric_list_state: ListState::default(),
+                labels_list_state: ListState::default(),
+                selected_metric: None,
+                selected_label: None,
+                graph_view: GraphView::default(),
+            }),
+        });
+    }
+
+    /// Show the tab at `index`, parking the state of the shown one
+    pub fn on_switch_tab(&mut self, index: usize) {
+        if index == self.active_tab {
+            return;
+        }
+        let Some(incoming) = self.tabs.get_mut(index).and_then(|tab| tab.parked.take()) else {
+            return;
+        };
+        let outgoing = self.swap_tab(incoming);
+        self.tabs[self.active_tab].parked = Some(outgoing);
+        self.active_tab = index;
+    }
+
+    /// Replace the state of the shown tab with `tab`, returning the old one
+    fn swap_tab(&mut self, mut tab: TabState<'a>) -> TabState<'a> {
+        std::mem::swap(&mut self.endpoint, &mut tab.endpoint);
+        std::mem::swap(&mut self.metric_scraper, &mut tab.metric_scraper);
+        std::mem::swap(&mut self.focus, &mut tab.focus);
+        std::mem::swap(&mut self.metric_list_state, &mut tab.metric_list_state);
+        std::mem::swap(&mut self.labels_list_state, &mut tab.labels_list_state);
+        std::mem::swap(&mut self.selected_metric, &mut tab.selected_metric);
+        std::mem::swap(&mut self.selected_label, &mut tab.selected_label);
+        std::mem::swap(&mut self.graph_view, &mut tab.graph_view);
+        // the units and thresholds of the config file apply to all tabs
+        std::mem::swap(&mut self.graph_view.units, &mut tab.graph_view.units);
+        std::mem::swap(
+            &mut self.graph_view.thresholds,
+            &mut tab.graph_view.thresholds,
+        );
+        tab
+    }
+
+    /// App over fixture scrapes taken at fixed unix seconds, see
+    /// `MetricScraper::with_fixture`
+    #[cfg(test)]
+    pub fn with_fixture(scrapes: &[(u64, &str)], error_msg: Option<&str>) -> App<'static> {
+        let store = crate::prom::test_data::memory_store();
+        let scraper = MetricScraper::with_fixture(scrapes, error_msg, store);
+        App::new("http://localhost:9100/metrics", 15, scraper)
+    }
+
+    /// Apply `action` to the state
+    pub fn dispatch(&mut self, action: Action) -> Result<(), Box<dyn Error>> {
+        let pending_goto = std::mem::take(&mut self.pending_goto);
+        match action {
+            Action::Quit => self.should_quit = true,
+            Action::Down => self.on_move(Direction::Down)?,
+            Action::Up => self.on_move(Direction::Up)?,
+            Action::PageDown => self.on_move(Direction::PageDown)?,
+            Action::PageUp => self.on_move(Direction::PageUp)?,
+            Action::First => self.on_move(Direction::First)?,
+            Action::Last => self.on_move(Direction::Last)?,
+            Action::Goto if pending_goto => self.on_move(Direction::First)?,
+            Action::Goto => self.pending_goto = true,
+            Action::SwitchFocus => self.on_tab()?,
+            Action::Escape => self.on_escape(),
+            Action::ToggleRate => self.on_toggle_rate(),
+            Action::ToggleMark => self.on_toggle_mark(),
+            Action::HideMarked => self.on_hide_marked(),
+            Action::UnhideAll => self.on_unhide_all(),
+            Action::PinMarked => self.on_pin_marked(),
+            Action::ExportMarked => self.on_export_marked()?,
+            Action::ExportSelected => self.on_export_selected()?,
+            Action::ExportJson => self.on_export_json()?,
+            Action::ToggleTopMovers => self.on_toggle_top_movers(),
+            Action::TogglePayload => self.on_toggle_payload(),
+            Action::ToggleTargets => self.on_toggle_targets(),
+            Action::ToggleDashboard => self.on_toggle_dashboard(),
+            Action::ToggleAggregate => self.on_toggle_aggregate(),
+            Action::NextTimeWindow => self.on_next_time_window(),
+            Action::NextIncreaseWindow => self.graph_view.next_increase_window(),
+            Action::OpenExpression => self.on_open_expression(),
+            Action::ShowErrorRatio => self.on_show_error_ratio()?,
+            Action::OpenSearch => self.on_open_search(),
+            Action::OpenLabelFilter => self.on_open_label_filter(),
+            Action::TogglePause => self.on_toggle_pause()?,
+            Action::OpenUnit => self.on_open_unit(),
+            Action::OpenThreshold => self.on_open_threshold(),
+            Action::IncreaseInterval => self.on_change_scrape_interval(true),
+            Action::DecreaseInterval => self.on_change_scrape_interval(false),
+            Action::ToggleHelp => self.show_help = !self.show_help,
+            Action::NextBarOrientation => self.on_next_bar_orientation(),
+            Action::ToggleBarLabels => self.on_toggle_bar_labels(),
+            Action::WiderBars => self.on_change_bar_width(true),
+            Action::NarrowerBars => self.on_change_bar_width(false),
+            Action::ScrollBarsLeft => self.on_scroll_bars(false),
+            Action::ScrollBarsRight => self.on_scroll_bars(true),
+            Action::ToggleCompare => self.on_toggle_compare(),
+            Action::ToggleSummaryView => self.on_toggle_summary_view(),
+            Action::NextCompareMode => self.on_next_compare_mode(),
+            Action::NextSortMode => self.on_next_sort_mode(),
+            Action::ToggleFavorite => self.on_toggle_favorite(),
+            Action::ToggleFavoritesTab => self.on_toggle_favorites_tab(),
+            Action::IgnoreMarked => self.on_ignore_marked(),
+            Action::ToggleMetadata => self.show_metadata = !self.show_metadata,
+            Action::ToggleComponents => self.on_toggle_components(),
+            Action::SwitchTab(index) => self.on_switch_tab(index),
+            Action::Copy(target) => self.on_copy(target)?,
+            Action::PromptInput(code) => self.on_prompt_key(code),
+            Action::MouseDown { column, row } => {
+                self.on_click(column, row)?;
+                self.graph_view.on_mouse_down(column, row);
+            }
+            Action::ScrollUp { column, row } => self.on_scroll(column, row, Direction::ScrollUp)?,
+            Action::ScrollDown { column, row } => {
+                self.on_scroll(column, row, Direction::ScrollDown)?
+            }
+            Action::MouseDrag { column } => self.graph_view.on_mouse_drag(column),
+            Action::MouseUp { column } => self.graph_view.on_mouse_up(column),
+        }
+        Ok(())
+    }
+
+    /// Names of the metrics not hidden, ignored or filtered out by the search
+    fn unhidden_metrics(&self, history: &dyn HistoryStore) -> Vec<String> {
+        history
+            .get_metrics_headers()
+            .into_iter()
+            .filter(|header| !self.hidden_metrics.contains(header))
+            .filter(|header| {
+                !self
+                    .hidden_prefixes
+                    .iter()
+                    .any(|prefix| header.starts_with(prefix))
+            })
+            .filter(|header| !self.ignore_list.matches(header))
+            .filter(|header| !self.show_favorites || self.favorite_metrics.contains(header))
+            .filter(|header| {
+                self.search.is_empty()
+                    || history
+                        .get_metric(header)
+                        .is_some_and(|metric| self.search.matches_metric(metric))
+            })
+            .collect()
+    }
+
+    /// Whether the groups are listed in place of the metrics
+    pub fn shows_groups(&self) -> bool {
+        self.group_by.is_some() && self.group.is_none()
+    }
+
+    /// The groups of the listed metrics while grouping, see `grouping`
+    pub fn get_groups(&self) -> Result<Vec<Group>, Box<dyn Error>> {
+        let Some(label) = &self.group_by else {
+            return Ok(vec![]);
+        };
+        let history = self.metric_scraper.get_history_lock()?;
+        let headers = self.unhidden_metrics(history.as_ref());
+        Ok(group_values(history.as_ref(), &headers, label))
+    }
+
+    /// Whether the metric list is split into a section per target, when
+    /// scraping several targets at once and not grouping by another label
+    pub fn shows_sections(&self) -> bool {
+        self.group_by.is_none()
+            && self
+                .metric_scraper
+                .get_status_read_guard()
+                .is_ok_and(|status| status.targets.len() > 1)
+    }
+
+    /// The rows of the metric list while it is split into sections, a
+    /// section per target first, see `grouping::section_rows`
+    pub fn get_section_rows(&self) -> Result<Vec<SectionRow>, Box<dyn Error>> {
+        let targets: Vec<Group> = self
             .metric_scraper
-            .get_history_lock()?
-            .get_metrics_headers();
+            .get_status_read_guard()?
+            .targets
+            .iter()
+            .map(|status| status.target.instance.clone())
+            .collect();
+        let headers = self.get_metrics_headers()?;
+        let history = self.metric_scraper.get_history_lock()?;
+        Ok(section_rows(
+            history.as_ref(),
+            &headers,
+            TARGET_LABEL,
+            targets,
+            &self.collapsed_sections,
+        ))
+    }
+
+    /// Whether `row` is the selected section heading or metric
+    fn is_selected_row(&self, row: &SectionRow) -> bool {
+        match row {
+            SectionRow::Heading(group, _) => {
+                self.selected_metric.is_none() && self.section.as_ref() == Some(group)
+            }
+            SectionRow::Metric(group, name) => {
+                self.section.as_ref() == Some(group) && self.selected_metric.as_ref() == Some(name)
+            }
+        }
+    }
+
+    /// The rows of the sections, with the selected one selected again as the
+    /// rows change, or the first one if it isn't listed anymore
+    pub fn sync_section_rows(&mut self) -> Result<Vec<SectionRow>, Box<dyn Error>> {
+        let rows = self.get_section_rows()?;
+        match rows.iter().position(|row| self.is_selected_row(row)) {
+            Some(index) => self.metric_list_state.select(Some(index)),
+            None if rows.is_empty() => {
+                self.section = None;
+                self.selected_metric = None;
+                self.metric_list_state.select(None);
+            }
+            None => {
+                self.change_selected_row(Direction::First)?;
+            }
+        }
+        Ok(rows)
+    }
+
+    /// Names of the metrics to list, pinned metrics first and without hidden ones
+    pub fn get_metrics_headers(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        let history = self.metric_scraper.get_history_lock()?;
+        let mut headers = self.unhidden_metrics(history.as_ref());
+        if let Some(label) = &self.group_by {
+            match &self.group {
+                // no metric is listed before a group is entered
+                None => return Ok(vec![]),
+                Some(group) => headers.retain(|header| {
+                    history
+                        .get_metric(header)
+                        .is_some_and(|metric| in_group(metric, label, group))
+                }),
+            }
+        }
+        let sort_key: Option<fn(&Metric) -> Option<f64>> = match self.sort_mode {
+            SortMode::Name => None,
+            SortMode::Value => Some(Metric::latest_value),
+            SortMode::RateOfChange => Some(Metric::rate_of_change),
+        };
+        if let Some(sort_key) = sort_key {
+            let mut keyed: Vec<(Option<f64>, String)> = headers
+                .into_iter()
+                .map(|header| (history.get_metric(&header).and_then(sort_key), header))
+                .collect();
+            // descending, metrics without a value last and by name
+            keyed.sort_by(|(a, _), (b, _)| match (a, b) {
+                (Some(a), Some(b)) => b.total_cmp(a),
+                (a, b) => a.is_none().cmp(&b.is_none()),
+            });
+            headers = keyed.into_iter().map(|(_, header)| header).collect();
+        }
+        headers.sort_by_key(|header| {
+            (
+                !self.pinned_metrics.contains(header),
+                !self.favorite_metrics.contains(header),
+            )
+        });
+        Ok(headers)
+    }
+
+    /// The raw series of a histogram or summary, while they are shown
+    pub fn components(&self, metric: &Metric) -> Option<Metric> {
+        self.show_components.then(|| components(metric)).flatten()
+    }
+
+    pub fn on_toggle_components(&mut self) {
+        self.show_components = !self.show_components;
+        // the first series gets selected on the next draw
+        self.selected_label = None;
+        self.labels_list_state.select(None);
+    }
+
+    /// Labels of the series of `metric` matching the search and the label filter
+    pub fn visible_labels(&self, metric: &Metric) -> Vec<String> {
+        if let Some(components) = self.components(metric) {
+            return self.visible_labels(&components);
+        }
+        metric
+            .get_labels()
+            .into_iter()
+            .filter(|labels| {
+                self.search
+                    .matches_series(&metric.time_series[*labels].labels)
+            })
+            .filter(|labels| {
+                self.label_filter
+                    .split_whitespace()
+                    .all(|term| labels.contains(term))
+            })
+            .filter(|labels| {
+                self.section.as_ref().is_none_or(|section| {
+                    grouping::series_group(&metric.time_series[*labels].labels, TARGET_LABEL)
+                        == section.as_deref()
+                })
+            })
+            .filter(|labels| {
+                self.group_by
+                    .as_ref()
+                    .zip(self.group.as_ref())
+                    .is_none_or(|(label, group)| {
+                        grouping::series_group(&metric.time_series[*labels].labels, label)
+                            == group.as_deref()
+                    })
+            })
+            .cloned()
+            .collect()
+    }
+
+    fn change_selected_metric(&mut self, direction: Direction) -> Result<bool, Box<dyn Error>> {
+        if self.shows_groups() {
+            return self.change_selected_group(direction);
+        }
+        if self.shows_sections() {
+            return self.change_selected_row(direction);
+        }
+        let metrics_headers = self.get_metrics_headers()?;
+        if metrics_headers.is_empty() {
+            return Ok(false);
+        }
         let metrics_headers_len = metrics_headers.len();
         update_list_state_with_direction(
             direction,
@@ -58,70 +879,195 @@ impl<'a> App<'a> {
             metrics_headers_len,
         );
         log::info!("C app: {self:?}");
-        let selected_index = self
-            .metric_list_state
-            .selected()
-            .expect("a selected metric item");
+        // nothing to move from, e.g. while no metric is selected
+        let Some(selected_index) = self.metric_list_state.selected() else {
+            return Ok(false);
+        };
         let next_selected_metric = metrics_headers.get(selected_index).cloned();
         let different = self.selected_metric != next_selected_metric;
         self.selected_metric = next_selected_metric;
+        if different {
+            self.graph_view.history_scroll = 0;
+            self.select_first_label()?;
+        }
+        Ok(different)
+    }
 
-        // reset labels state
+    /// Select the section heading or metric at the row moved to in the
+    /// sections of the targets
+    fn change_selected_row(&mut self, direction: Direction) -> Result<bool, Box<dyn Error>> {
+        let rows = self.get_section_rows()?;
+        if rows.is_empty() {
+            return Ok(false);
+        }
+        update_list_state_with_direction(direction, &mut self.metric_list_state, rows.len());
+        let Some(selected_index) = self.metric_list_state.selected() else {
+            return Ok(false);
+        };
+        let (section, metric) = match &rows[selected_index] {
+            // the heading is not a metric to act on
+            SectionRow::Heading(group, _) => (Some(group.clone()), None),
+            SectionRow::Metric(group, name) => (Some(group.clone()), Some(name.clone())),
+        };
+        let different = self.section != section || self.selected_metric != metric;
+        self.section = section;
+        self.selected_metric = metric;
         if different {
-            let selected_metric = self.selected_metric.clone().expect("metric to be selected");
-            if let Some(metric) = self
-                .metric_scraper
-                .get_history_lock()?
-                .get_metric(&selected_metric)
-            {
-                let labels: Vec<&String> = metric.get_labels();
-                self.selected_label = labels.first().map(|&s| s.clone());
-                self.labels_list_state.select(Some(0));
-            } else {
-                self.labels_list_state.select(Some(0));
-                self.selected_label = None
-            }
+            self.graph_view.history_scroll = 0;
+            self.select_first_label()?;
+        }
+        Ok(different)
+    }
+
+    /// Select the first series of the newly selected metric
+    fn select_first_label(&mut self) -> Result<(), Box<dyn Error>> {
+        let history = self.metric_scraper.get_history_lock()?;
+        let metric = self
+            .selected_metric
+            .as_ref()
+            .and_then(|selected_metric| history.get_metric(selected_metric));
+        if let Some(metric) = metric {
+            let labels = self.visible_labels(metric);
+            self.selected_label = labels.first().cloned();
+            self.labels_list_state
+                .select((!labels.is_empty()).then_some(0));
+        } else {
+            self.labels_list_state.select(None);
+            self.selected_label = None
+        }
+        Ok(())
+    }
+
+    fn change_selected_group(&mut self, direction: Direction) -> Result<bool, Box<dyn Error>> {
+        let groups = self.get_groups()?;
+        if groups.is_empty() {
+            return Ok(false);
         }
+        update_list_state_with_direction(direction, &mut self.group_list_state, groups.len());
+        let Some(selected_index) = self.group_list_state.selected() else {
+            return Ok(false);
+        };
+        let next_selected_group = groups.get(selected_index).cloned();
+        let different = self.selected_group != next_selected_group;
+        self.selected_group = next_selected_group;
         Ok(different)
     }
 
     fn change_selected_labels(&mut self, direction: Direction) -> Result<bool, Box<dyn Error>> {
-        let selected_metric = self.selected_metric.clone().expect("metric to be selected");
+        let Some(selected_metric) = self.selected_metric.clone() else {
+            return Ok(false);
+        };
         if let Some(metric) = self
             .metric_scraper
             .get_history_lock()?
             .get_metric(&selected_metric)
         {
-            let labels: Vec<&String> = metric.get_labels();
+            let labels = self.visible_labels(metric);
+            if labels.is_empty() {
+                return Ok(false);
+            }
             let labels_len = labels.len();
             update_list_state_with_direction(direction, &mut self.labels_list_state, labels_len);
-            let selected_index = self
-                .labels_list_state
-                .selected()
-                .expect("a selected labels item");
-            let next_selected_label = labels.get(selected_index).map(|&s| s.clone());
+            let Some(selected_index) = self.labels_list_state.selected() else {
+                return Ok(false);
+            };
+            let next_selected_label = labels.get(selected_index).cloned();
             let different = self.selected_label != next_selected_label;
             self.selected_label = next_selected_label;
+            if different {
+                self.graph_view.history_scroll = 0;
+            }
             return Ok(different);
         }
         Ok(false)
     }
 
-    pub fn on_down(&mut self) -> Result<(), Box<dyn Error>> {
-        let direction = Direction::Down;
-        match self.focus {
-            ElementInFocus::MetricHeaders => {
-                self.change_selected_metric(direction)?;
+    /// Select `metric` in the metric list, and the series with the labels
+    /// `series` if given, as if navigated to. Returns false if either isn't
+    /// listed.
+    pub fn select(&mut self, metric: &str, series: Option<&str>) -> Result<bool, Box<dyn Error>> {
+        let Some(index) = self.metric_index(metric)? else {
+            return Ok(false);
+        };
+        self.screen = Screen::Browse;
+        self.focus = ElementInFocus::MetricHeaders;
+        self.change_selected_metric(Direction::To(index))?;
+        let Some(series) = series else {
+            return Ok(true);
+        };
+        let labels = match self.metric_scraper.get_history_lock()?.get_metric(metric) {
+            Some(metric) => self.visible_labels(metric),
+            None => vec![],
+        };
+        let Some(index) = labels.iter().position(|labels| labels == series) else {
+            return Ok(false);
+        };
+        self.focus = ElementInFocus::LabelsView;
+        self.change_selected_labels(Direction::To(index))?;
+        Ok(true)
+    }
+
+    /// Row of `metric` in the metric list, in the first section listing it
+    /// while the list is split into sections
+    fn metric_index(&self, metric: &str) -> Result<Option<usize>, Box<dyn Error>> {
+        if self.shows_sections() {
+            let rows = self.get_section_rows()?;
+            return Ok(rows
+                .iter()
+                .position(|row| matches!(row, SectionRow::Metric(_, name) if name == metric)));
+        }
+        let headers = self.get_metrics_headers()?;
+        Ok(headers.iter().position(|header| header == metric))
+    }
+
+    /// Focus the clicked list and select the clicked item
+    fn on_click(&mut self, column: u16, row: u16) -> Result<(), Box<dyn Error>> {
+        let position = (column, row).into();
+        if let Some(area) = self.metric_list_area.filter(|area| area.contains(position)) {
+            self.focus = ElementInFocus::MetricHeaders;
+            let state = if self.shows_groups() {
+                &self.group_list_state
+            } else {
+                &self.metric_list_state
+            };
+            if let Some(index) = list_index(area, row, state) {
+                self.change_selected_metric(Direction::To(index))?;
             }
-            ElementInFocus::LabelsView => {
-                self.change_selected_labels(direction)?;
+        } else if let Some(area) = self.labels_list_area.filter(|area| area.contains(position)) {
+            self.focus = ElementInFocus::LabelsView;
+            if let Some(index) = list_index(area, row, &self.labels_list_state) {
+                self.change_selected_labels(Direction::To(index))?;
             }
         }
         Ok(())
     }
 
-    pub fn on_up(&mut self) -> Result<(), Box<dyn Error>> {
-        let direction = Direction::Up;
+    /// Move through the list or history table under the mouse pointer
+    fn on_scroll(
+        &mut self,
+        column: u16,
+        row: u16,
+        direction: Direction,
+    ) -> Result<(), Box<dyn Error>> {
+        let position = (column, row).into();
+        let over = |area: Option<Rect>| area.is_some_and(|area| area.contains(position));
+        if over(self.metric_list_area) {
+            self.change_selected_metric(direction)?;
+        } else if over(self.labels_list_area) {
+            self.change_selected_labels(direction)?;
+        } else if over(self.graph_view.table_area) {
+            // the newest sample is at the bottom, the drawing clamps the scroll
+            let scroll = &mut self.graph_view.history_scroll;
+            *scroll = match direction {
+                Direction::ScrollUp => scroll.saturating_add(1),
+                _ => scroll.saturating_sub(1),
+            };
+        }
+        Ok(())
+    }
+
+    /// Move the selection of the focused list
+    fn on_move(&mut self, direction: Direction) -> Result<(), Box<dyn Error>> {
         match self.focus {
             ElementInFocus::MetricHeaders => {
                 self.change_selected_metric(direction)?;
@@ -133,34 +1079,1634 @@ impl<'a> App<'a> {
         Ok(())
     }
 
-    pub fn on_tab(&mut self) -> Result<(), Box<dyn Error>> {
-        self.focus = match self.focus {
-            ElementInFocus::MetricHeaders => ElementInFocus::LabelsView,
-            ElementInFocus::LabelsView => ElementInFocus::MetricHeaders,
-        };
-        Ok(())
+    /// Clear the selection, so the first listed metric gets selected on the next draw
+    fn reset_selection(&mut self) {
+        self.section = None;
+        self.selected_metric = None;
+        self.selected_label = None;
+        self.metric_list_state.select(None);
+        self.labels_list_state.select(None);
     }
-}
 
-fn update_list_state_with_direction(direction: Direction, state: &mut ListState, list_len: usize) {
-    match direction {
-        Direction::Down => {
-            if let Some(selected) = state.selected() {
-                if selected >= list_len - 1 {
-                    state.select(Some(0));
+    /// Mark or unmark the selected metric for bulk actions. In the labels
+    /// list, overlay the selected series on the graph instead, or include or
+    /// exclude it from the aggregate while aggregating.
+    pub fn on_toggle_mark(&mut self) {
+        if matches!(self.focus, ElementInFocus::LabelsView) {
+            if let (Some(metric), Some(label)) = (&self.selected_metric, &self.selected_label) {
+                if self.graph_view.aggregate {
+                    self.graph_view.toggle_excluded_series(metric, label);
                 } else {
-                    state.select(Some(selected + 1));
+                    self.graph_view.toggle_overlay_series(metric, label);
                 }
             }
+            return;
         }
-        Direction::Up => {
-            if let Some(selected) = state.selected() {
-                if selected > 0 {
-                    state.select(Some(selected - 1));
-                } else {
-                    state.select(Some(list_len - 1));
-                }
+        if let Some(selected_metric) = &self.selected_metric {
+            if !self.marked_metrics.remove(selected_metric) {
+                self.marked_metrics.insert(selected_metric.clone());
             }
         }
     }
+
+    /// The marked metrics, or the selected one if nothing is marked
+    fn bulk_targets(&self) -> Vec<String> {
+        if self.marked_metrics.is_empty() {
+            self.selected_metric.iter().cloned().collect()
+        } else {
+            let mut targets: Vec<String> = self.marked_metrics.iter().cloned().collect();
+            targets.sort();
+            targets
+        }
+    }
+
+    pub fn on_hide_marked(&mut self) {
+        let targets = self.bulk_targets();
+        self.status_msg = Some(format!("Hid {} metric(s)", targets.len()));
+        self.hidden_metrics.extend(targets);
+        self.marked_metrics.clear();
+        self.reset_selection();
+    }
+
+    pub fn on_unhide_all(&mut self) {
+        self.status_msg = Some(format!(
+            "Showing {} hidden metric(s)",
+            self.hidden_metrics.len()
+        ));
+        self.hidden_metrics.clear();
+        self.hidden_prefixes.clear();
+        self.reset_selection();
+    }
+
+    /// Hide, pin and set the units of the metrics of `profile`, and fill an
+    /// empty dashboard with its panels. Units of the config file and the
+    /// session take precedence.
+    pub fn apply_profile(&mut self, profile: &Profile) {
+        self.hidden_prefixes.extend(profile.hidden);
+        for (name, unit) in profile.units {
+            self.graph_view
+                .units
+                .entry(name.to_string())
+                .or_insert_with(|| unit.to_string());
+        }
+        self.pinned_metrics
+            .extend(profile.pinned.iter().map(|name| name.to_string()));
+        if self.dashboard.panels.is_empty() {
+            self.dashboard.panels = profile.panels();
+        }
+    }
+
+    /// Apply the profile of the scraped exporter, once there are metrics
+    pub fn on_detect_profile(&mut self) -> Result<(), Box<dyn Error>> {
+        if !self.detect_profile {
+            return Ok(());
+        }
+        let headers = self
+            .metric_scraper
+            .get_history_lock()?
+            .get_metrics_headers();
+        if headers.is_empty() {
+            return Ok(());
+        }
+        self.detect_profile = false;
+        match Profile::detect(headers.iter().map(String::as_str)) {
+            Some(profile) => {
+                log::info!("Detected the {} profile", profile.name);
+                self.status_msg = Some(format!("Using the {} profile", profile.name));
+                self.apply_profile(profile);
+            }
+            None => log::info!("No profile matches the scraped metrics"),
+        }
+        Ok(())
+    }
+
+    /// Pin the marked metrics, or unpin them if they all are pinned already
+    pub fn on_pin_marked(&mut self) {
+        let targets = self.bulk_targets();
+        if targets.iter().all(|t| self.pinned_metrics.contains(t)) {
+            targets.iter().for_each(|t| {
+                self.pinned_metrics.remove(t);
+            });
+            self.status_msg = Some(format!("Unpinned {} metric(s)", targets.len()));
+        } else {
+            self.status_msg = Some(format!("Pinned {} metric(s)", targets.len()));
+            self.pinned_metrics.extend(targets);
+        }
+        self.marked_metrics.clear();
+    }
+
+    /// Read the saved favorites, which are written to `path` on every change
+    pub fn load_favorites(&mut self, path: PathBuf) {
+        match Favorites::load(&path) {
+            Ok(favorites) => self.favorite_metrics = favorites.metrics.into_iter().collect(),
+            Err(err) => log::error!("{:#}", err),
+        }
+        self.favorites_path = Some(path);
+    }
+
+    /// Add the marked metrics to the favorites, or remove them if they all
+    /// are favorites already
+    pub fn on_toggle_favorite(&mut self) {
+        let targets = self.bulk_targets();
+        if targets.iter().all(|t| self.favorite_metrics.contains(t)) {
+            targets.iter().for_each(|t| {
+                self.favorite_metrics.remove(t);
+            });
+            self.status_msg = Some(format!("Removed {} favorite(s)", targets.len()));
+        } else {
+            self.status_msg = Some(format!("Added {} favorite(s)", targets.len()));
+            self.favorite_metrics.extend(targets);
+        }
+        self.marked_metrics.clear();
+        self.save_favorites();
+    }
+
+    /// Add `metric` to the favorites, e.g. requested over the control API
+    pub fn add_favorite(&mut self, metric: &str) {
+        if self.favorite_metrics.insert(metric.to_string()) {
+            self.status_msg = Some(format!("Added {} to the favorites", metric));
+            self.save_favorites();
+        }
+    }
+
+    fn save_favorites(&mut self) {
+        if let Some(path) = &self.favorites_path {
+            let favorites = Favorites {
+                metrics: self.favorite_metrics.iter().cloned().collect(),
+            };
+            if let Err(err) = favorites.save(path) {
+                log::error!("{:#}", err);
+                self.status_msg = Some(format!("{:#}", err));
+            }
+        }
+    }
+
+    pub fn on_toggle_favorites_tab(&mut self) {
+        self.show_favorites = !self.show_favorites;
+        self.status_msg = Some(String::from(if self.show_favorites {
+            "Showing the favorites"
+        } else {
+            "Showing all metrics"
+        }));
+        self.reset_selection();
+    }
+
+    /// Read the saved ignore list, which is written to `path` on every change
+    pub fn load_ignore_list(&mut self, path: PathBuf) {
+        match IgnoreList::load(&path) {
+            Ok(ignore_list) => self.ignore_list = ignore_list,
+            Err(err) => log::error!("{:#}", err),
+        }
+        self.ignore_list_path = Some(path);
+    }
+
+    fn save_ignore_list(&self) -> anyhow::Result<()> {
+        match &self.ignore_list_path {
+            Some(path) => self.ignore_list.save(path),
+            None => Ok(()),
+        }
+    }
+
+    /// Hide the marked metrics now and in every later session
+    pub fn on_ignore_marked(&mut self) {
+        let targets = self.bulk_targets();
+        for target in &targets {
+            // names are plain metric names, never invalid patterns
+            let _ = self.ignore_list.add(&regex::escape(target));
+        }
+        self.status_msg = Some(match self.save_ignore_list() {
+            Ok(()) => format!(
+                "Ignoring {} metric(s), :unignore shows them again",
+                targets.len()
+            ),
+            Err(err) => {
+                log::error!("{:#}", err);
+                format!("{:#}", err)
+            }
+        });
+        self.marked_metrics.clear();
+        self.reset_selection();
+    }
+
+    /// Text copied for `target` from the selected metric or series, if any
+    pub fn copied_text(&self, target: CopyTarget) -> Result<Option<String>, Box<dyn Error>> {
+        let Some(metric_name) = &self.selected_metric else {
+            return Ok(None);
+        };
+        let label = self.selected_label.as_deref();
+        let text = match (target, label) {
+            (CopyTarget::MetricName, _) => Some(metric_name.clone()),
+            (CopyTarget::Series, Some(label)) => Some(series_name(metric_name, label)),
+            (CopyTarget::LatestValue, Some(label)) => {
+                let history = self.metric_scraper.get_history_lock()?;
+                history
+                    .get_metric(metric_name)
+                    .and_then(|metric| metric.time_series.get(label)?.samples.back())
+                    .map(|sample| match sample {
+                        Sample::GaugeSample(sample) | Sample::CounterSample(sample) => {
+                            sample.value.to_string()
+                        }
+                        Sample::HistogramSample(sample) => sample.count.to_string(),
+                        Sample::SummarySample(sample) => sample.count.to_string(),
+                    })
+            }
+            (_, None) => None,
+        };
+        Ok(text)
+    }
+
+    pub fn on_copy(&mut self, target: CopyTarget) -> Result<(), Box<dyn Error>> {
+        let Some(text) = self.copied_text(target)? else {
+            self.status_msg = Some("Nothing selected to copy".to_string());
+            return Ok(());
+        };
+        self.status_msg = Some(match clipboard::copy(&text) {
+            Ok(()) => format!("Copied {}", text),
+            Err(err) => {
+                log::error!("Failed to copy to the clipboard: {}", err);
+                format!("Copy failed: {}", err)
+            }
+        });
+        Ok(())
+    }
+
+    /// Write the history of the marked metrics to a CSV file in the current directory
+    pub fn on_export_marked(&mut self) -> Result<(), Box<dyn Error>> {
+        let targets = self.bulk_targets();
+        if targets.is_empty() {
+            return Ok(());
+        }
+        let file_name = format!(
+            "promviz-export-{}.csv",
+            chrono::Local::now().format("%Y%m%d-%H%M%S")
+        );
+        let history = self.metric_scraper.get_history_lock()?;
+        let metrics: Vec<&Metric> = targets
+            .iter()
+            .filter_map(|name| history.get_metric(name))
+            .collect();
+        let result = File::create(&file_name)
+            .and_then(|file| export::write_csv(&mut BufWriter::new(file), &metrics));
+        self.status_msg = Some(match result {
+            Ok(()) => {
+                self.exported_files.push(file_name.clone());
+                format!("Exported {} metric(s) to {}", metrics.len(), file_name)
+            }
+            Err(err) => {
+                log::error!("Failed to export to {}: {}", file_name, err);
+                format!("Export to {} failed: {}", file_name, err)
+            }
+        });
+        drop(history);
+        self.marked_metrics.clear();
+        Ok(())
+    }
+
+    /// Write the complete history of all metrics to a JSON file
+    pub fn on_export_json(&mut self) -> Result<(), Box<dyn Error>> {
+        let file_name = format!(
+            "promviz-history-{}.json",
+            chrono::Local::now().format("%Y%m%d-%H%M%S")
+        );
+        let history = self.metric_scraper.get_history_lock()?;
+        let headers = history.get_metrics_headers();
+        let metrics: Vec<&Metric> = headers
+            .iter()
+            .filter_map(|name| history.get_metric(name))
+            .collect();
+        let result = File::create(&file_name)
+            .and_then(|file| export::write_json(&mut BufWriter::new(file), &metrics));
+        self.status_msg = Some(match result {
+            Ok(()) => {
+                self.exported_files.push(file_name.clone());
+                format!("Exported {} metric(s) to {}", metrics.len(), file_name)
+            }
+            Err(err) => {
+                log::error!("Failed to export to {}: {}", file_name, err);
+                format!("Export to {} failed: {}", file_name, err)
+            }
+        });
+        Ok(())
+    }
+
+    /// Write the history of the selected metric and label to a CSV file
+    pub fn on_export_selected(&mut self) -> Result<(), Box<dyn Error>> {
+        let (Some(metric_name), Some(label)) = (&self.selected_metric, &self.selected_label) else {
+            return Ok(());
+        };
+        let history = self.metric_scraper.get_history_lock()?;
+        let Some(time_series) = history
+            .get_metric(metric_name)
+            .and_then(|metric| metric.time_series.get(label))
+        else {
+            return Ok(());
+        };
+        let file_name = format!(
+            "promviz-{}-{}.csv",
+            metric_name,
+            chrono::Local::now().format("%Y%m%d-%H%M%S")
+        );
+        let result = File::create(&file_name).and_then(|file| {
+            export::write_series_csv(&mut BufWriter::new(file), &time_series.samples)
+        });
+        self.status_msg = Some(match result {
+            Ok(()) => {
+                self.exported_files.push(file_name.clone());
+                format!(
+                    "Exported {} sample(s) to {}",
+                    time_series.samples.len(),
+                    file_name
+                )
+            }
+            Err(err) => {
+                log::error!("Failed to export to {}: {}", file_name, err);
+                format!("Export to {} failed: {}", file_name, err)
+            }
+        });
+        Ok(())
+    }
+
+    pub fn on_next_time_window(&mut self) {
+        self.graph_view.next_time_window();
+    }
+
+    pub fn on_next_bar_orientation(&mut self) {
+        let orientation = self.graph_view.bar_orientation.next();
+        self.graph_view.bar_orientation = orientation;
+        self.status_msg = Some(format!(
+            "Bars: {}",
+            match orientation {
+                BarOrientation::Auto => "auto",
+                BarOrientation::Vertical => "vertical",
+                BarOrientation::Horizontal => "horizontal",
+            }
+        ));
+    }
+
+    /// Make the histogram bars one column wider or narrower, starting from
+    /// the width fitting all buckets
+    pub fn on_change_bar_width(&mut self, wider: bool) {
+        let width = self
+            .graph_view
+            .bar_width
+            .unwrap_or(self.graph_view.fitted_bar_width)
+            .max(1);
+        let width = if wider {
+            (width + 1).min(MAX_BAR_WIDTH)
+        } else {
+            width.saturating_sub(1).max(1)
+        };
+        self.graph_view.bar_width = Some(width);
+        self.status_msg = Some(format!("Bar width: {}", width));
+    }
+
+    /// Scroll the histogram bars by one bucket, clamped when drawn
+    pub fn on_scroll_bars(&mut self, right: bool) {
+        let scroll = &mut self.graph_view.bar_scroll;
+        *scroll = if right {
+            scroll.saturating_add(1)
+        } else {
+            scroll.saturating_sub(1)
+        };
+    }
+
+    /// Switch the histogram bar labels between counts and percentages
+    pub fn on_toggle_bar_labels(&mut self) {
+        self.graph_view.bar_labels = match self.graph_view.bar_labels {
+            BarLabels::Count => BarLabels::Percent,
+            BarLabels::Percent => BarLabels::Count,
+        };
+        self.status_msg = Some(String::from(match self.graph_view.bar_labels {
+            BarLabels::Count => "Bar labels: counts",
+            BarLabels::Percent => "Bar labels: percentages",
+        }));
+    }
+
+    /// Keep the selected series on a second graph to compare it with the
+    /// series selected next, or close the comparison
+    pub fn on_toggle_compare(&mut self) {
+        if self.graph_view.compare.take().is_some() {
+            self.status_msg = Some("Comparison closed".to_string());
+            return;
+        }
+        if let (Some(metric), Some(label)) = (&self.selected_metric, &self.selected_label) {
+            self.status_msg = Some(format!(
+                "Comparing with {}, select another series",
+                series_name(metric, label)
+            ));
+            self.graph_view.compare = Some((metric.clone(), label.clone()));
+        }
+    }
+
+    pub fn on_next_compare_mode(&mut self) {
+        self.graph_view.compare_mode = match self.graph_view.compare_mode {
+            CompareMode::Graphs => CompareMode::Ratio,
+            CompareMode::Ratio => CompareMode::Difference,
+            CompareMode::Difference => CompareMode::Graphs,
+        };
+        self.status_msg = Some(String::from(match self.graph_view.compare_mode {
+            CompareMode::Graphs => "Comparison: separate graphs",
+            CompareMode::Ratio => "Comparison: ratio",
+            CompareMode::Difference => "Comparison: difference",
+        }));
+    }
+
+    pub fn on_next_sort_mode(&mut self) {
+        self.sort_mode = match self.sort_mode {
+            SortMode::Name => SortMode::Value,
+            SortMode::Value => SortMode::RateOfChange,
+            SortMode::RateOfChange => SortMode::Name,
+        };
+        self.status_msg = Some(String::from(match self.sort_mode {
+            SortMode::Name => "Sort: name",
+            SortMode::Value => "Sort: latest value",
+            SortMode::RateOfChange => "Sort: rate of change",
+        }));
+    }
+
+    /// Switch summaries between bars of the latest quantiles and a graph of
+    /// the quantiles over time
+    pub fn on_toggle_summary_view(&mut self) {
+        self.graph_view.quantiles_over_time = !self.graph_view.quantiles_over_time;
+        self.status_msg = Some(String::from(if self.graph_view.quantiles_over_time {
+            "Summaries: quantiles over time"
+        } else {
+            "Summaries: latest quantiles"
+        }));
+    }
+
+    /// Cycle the aggregate: off, sum, mean and range
+    pub fn on_toggle_aggregate(&mut self) {
+        let graph_view = &mut self.graph_view;
+        (graph_view.aggregate, graph_view.spread) = match (graph_view.aggregate, graph_view.spread)
+        {
+            (false, _) => (true, false),
+            (true, false) => (true, true),
+            (true, true) => (false, false),
+        };
+    }
+
+    pub fn on_toggle_top_movers(&mut self) {
+        self.screen = match self.screen {
+            Screen::TopMovers => Screen::Browse,
+            _ => Screen::TopMovers,
+        };
+    }
+
+    pub fn on_toggle_payload(&mut self) {
+        self.screen = match self.screen {
+            Screen::Payload => Screen::Browse,
+            _ => Screen::Payload,
+        };
+    }
+
+    pub fn on_toggle_targets(&mut self) {
+        self.screen = match self.screen {
+            Screen::Targets => Screen::Browse,
+            _ => Screen::Targets,
+        };
+    }
+
+    pub fn on_toggle_dashboard(&mut self) {
+        self.screen = match self.screen {
+            Screen::Dashboard => Screen::Browse,
+            _ => Screen::Dashboard,
+        };
+    }
+
+    /// Open the expression bar, starting with the last expression
+    pub fn on_open_expression(&mut self) {
+        let text = self.expression.as_ref().map(|(text, _)| text.clone());
+        self.prompt = Some(Prompt {
+            kind: PromptKind::Expression,
+            input: text.unwrap_or_default(),
+        });
+    }
+
+    /// Graph the ratio of the selected counter and its errors counter
+    pub fn on_show_error_ratio(&mut self) -> Result<(), Box<dyn Error>> {
+        let Some(metric_name) = &self.selected_metric else {
+            self.status_msg = Some("No metric selected".to_string());
+            return Ok(());
+        };
+        let names = self
+            .metric_scraper
+            .get_history_lock()?
+            .get_metrics_headers();
+        let Some(text) = error_ratio_expression(metric_name, &names) else {
+            self.status_msg = Some(format!(
+                "No _errors_total or _failures_total counter pairs with {}",
+                metric_name
+            ));
+            return Ok(());
+        };
+        let expr = expr::parse(&text)?;
+        self.expression = Some((text, expr));
+        self.screen = Screen::Expression;
+        self.status_msg = None;
+        Ok(())
+    }
+
+    /// Open the search bar, starting with the current search
+    pub fn on_open_search(&mut self) {
+        self.prompt = Some(Prompt {
+            kind: PromptKind::Search,
+            input: self.search.text.clone(),
+        });
+    }
+
+    /// Open the filter of the labels pane, starting with the current filter
+    pub fn on_open_label_filter(&mut self) {
+        self.prompt = Some(Prompt {
+            kind: PromptKind::LabelFilter,
+            input: self.label_filter.clone(),
+        });
+    }
+
+    /// Open the unit of the selected metric for editing
+    pub fn on_open_unit(&mut self) {
+        let Some(selected_metric) = &self.selected_metric else {
+            return;
+        };
+        let unit = self.graph_view.units.get(selected_metric).cloned();
+        self.prompt = Some(Prompt {
+            kind: PromptKind::Unit,
+            input: unit.unwrap_or_default(),
+        });
+    }
+
+    /// Open the alert threshold of the selected metric for editing
+    pub fn on_open_threshold(&mut self) {
+        let Some(selected_metric) = &self.selected_metric else {
+            return;
+        };
+        let threshold = self.graph_view.thresholds.get(selected_metric);
+        self.prompt = Some(Prompt {
+            kind: PromptKind::Threshold,
+            input: threshold.map(f64::to_string).unwrap_or_default(),
+        });
+    }
+
+    /// Handle a key typed into the open prompt
+    pub fn on_prompt_key(&mut self, code: KeyCode) {
+        let Some(prompt) = &mut self.prompt else {
+            return;
+        };
+        match code {
+            KeyCode::Char(c) => prompt.input.push(c),
+            KeyCode::Backspace => {
+                prompt.input.pop();
+            }
+            KeyCode::Esc => {
+                match prompt.kind {
+                    PromptKind::Search => self.apply_search(""),
+                    PromptKind::LabelFilter => self.apply_label_filter(""),
+                    PromptKind::Expression | PromptKind::Unit | PromptKind::Threshold => {}
+                }
+                self.prompt = None;
+                return;
+            }
+            KeyCode::Enter => {
+                let text = prompt.input.trim().to_string();
+                match prompt.kind {
+                    PromptKind::Search | PromptKind::LabelFilter => self.prompt = None,
+                    PromptKind::Unit => {
+                        if let Some(selected_metric) = self.selected_metric.clone() {
+                            if text.is_empty() {
+                                self.graph_view.units.remove(&selected_metric);
+                            } else {
+                                self.graph_view.units.insert(selected_metric, text);
+                            }
+                        }
+                        self.prompt = None;
+                    }
+                    PromptKind::Threshold => {
+                        let Some(selected_metric) = self.selected_metric.clone() else {
+                            self.prompt = None;
+                            return;
+                        };
+                        if text.is_empty() {
+                            self.graph_view.thresholds.remove(&selected_metric);
+                        } else {
+                            match text.parse::<f64>() {
+                                Ok(threshold) if threshold.is_finite() => {
+                                    self.graph_view
+                                        .thresholds
+                                        .insert(selected_metric, threshold);
+                                }
+                                _ => {
+                                    self.status_msg = Some(format!("Invalid threshold: {}", text));
+                                    return;
+                                }
+                            }
+                        }
+                        self.prompt = None;
+                    }
+                    PromptKind::Expression if is_command(&text) => {
+                        self.prompt = None;
+                        self.run_command(&text);
+                    }
+                    PromptKind::Expression => match expr::parse(&text) {
+                        Ok(expr) => {
+                            self.expression = Some((text, expr));
+                            self.prompt = None;
+                            self.screen = Screen::Expression;
+                            self.status_msg = None;
+                        }
+                        Err(err) => self.status_msg = Some(format!("Invalid expression: {}", err)),
+                    },
+                }
+                return;
+            }
+            _ => return,
+        }
+        // filters are applied while typing
+        let text = prompt.input.clone();
+        match prompt.kind {
+            PromptKind::Search => self.apply_search(&text),
+            PromptKind::LabelFilter => self.apply_label_filter(&text),
+            PromptKind::Expression | PromptKind::Unit | PromptKind::Threshold => {}
+        }
+    }
+
+    /// Run a command entered in the expression bar
+    fn run_command(&mut self, text: &str) {
+        let (command, name) = text.split_once(' ').unwrap_or((text, ""));
+        let name = name.trim();
+        let result = match command {
+            "save-session" => self
+                .session()
+                .save(name)
+                .map(|path| format!("Saved session {} to {}", name, path.display())),
+            "load-session" => Session::load(name).and_then(|session| {
+                let message = match &session.endpoint {
+                    Some(target) if self.metric_scraper.url() != Some(target.as_str()) => {
+                        self.switch_endpoint(target)?;
+                        format!("Loaded session {}, scraping {}", name, target)
+                    }
+                    _ => format!("Loaded session {}", name),
+                };
+                self.restore_session(session);
+                Ok(message)
+            }),
+            "ignore" => self
+                .ignore_list
+                .add(name)
+                .and_then(|_| self.save_ignore_list())
+                .map(|_| {
+                    self.reset_selection();
+                    format!("Ignoring metrics matching {}", name)
+                }),
+            "unignore" if self.ignore_list.remove(name) => self
+                .save_ignore_list()
+                .map(|_| format!("Stopped ignoring {}", name)),
+            "unignore" => Err(anyhow::anyhow!("'{}' is not ignored", name)),
+            "restore-history" => self
+                .metric_scraper
+                .restore_spilled()
+                .map(|count| format!("Restored {} spilled samples", count)),
+            "group" => {
+                self.group_by = (!name.is_empty()).then(|| name.to_string());
+                self.group = None;
+                self.selected_group = None;
+                self.group_list_state.select(None);
+                self.reset_selection();
+                Ok(match name {
+                    "" => "Stopped grouping metrics".to_string(),
+                    label => format!("Grouping metrics by {}", label),
+                })
+            }
+            _ => return,
+        };
+        self.status_msg = Some(result.unwrap_or_else(|err| {
+            log::error!("Command '{}' failed: {:#}", text, err);
+            format!("{:#}", err)
+        }));
+    }
+
+    /// Scrape `target` instead of the shown endpoint, starting over with an
+    /// empty history
+    fn switch_endpoint(&mut self, target: &str) -> anyhow::Result<()> {
+        if !self.metric_scraper.retarget(target)? {
+            anyhow::bail!(
+                "{} is not scraped, can't switch to {}",
+                self.endpoint,
+                target
+            );
+        }
+        self.endpoint = Cow::Owned(target.to_string());
+        if let Some(tab) = self.tabs.get_mut(self.active_tab) {
+            tab.title = tab_title(target);
+        }
+        self.reset_selection();
+        Ok(())
+    }
+
+    /// The target and UI state, to be saved as a named session
+    pub fn session(&self) -> Session {
+        Session {
+            endpoint: self.metric_scraper.url().map(String::from),
+            scrape_interval: self
+                .metric_scraper
+                .url()
+                .is_some()
+                .then_some(self.scrape_interval),
+            pinned: self.pinned_metrics.iter().cloned().collect(),
+            hidden: self.hidden_metrics.iter().cloned().collect(),
+            search: self.search.text.clone(),
+            label_filter: self.label_filter.clone(),
+            expression: self.expression.as_ref().map(|(text, _)| text.clone()),
+            units: self.graph_view.units.clone().into_iter().collect(),
+            thresholds: self.graph_view.thresholds.clone().into_iter().collect(),
+            rate: self
+                .graph_view
+                .value_modes
+                .iter()
+                .filter(|(_, mode)| **mode == ValueMode::Rate)
+                .map(|(name, _)| name.clone())
+                .collect(),
+            aggregate: self.graph_view.aggregate,
+            spread: self.graph_view.spread,
+            selected_metric: self.selected_metric.clone(),
+        }
+    }
+
+    /// Apply the UI state of a saved session. The target can't change while
+    /// running, but the scrape interval can.
+    pub fn restore_session(&mut self, session: Session) {
+        self.pinned_metrics = session.pinned.into_iter().collect();
+        self.hidden_metrics = session.hidden.into_iter().collect();
+        self.marked_metrics.clear();
+        self.apply_search(&session.search);
+        self.apply_label_filter(&session.label_filter);
+        self.expression = session
+            .expression
+            .and_then(|text| expr::parse(&text).ok().map(|expr| (text, expr)));
+        self.graph_view.units.extend(session.units);
+        self.graph_view.thresholds.extend(session.thresholds);
+        self.graph_view.value_modes = session
+            .rate
+            .into_iter()
+            .map(|name| (name, ValueMode::Rate))
+            .collect();
+        self.graph_view.aggregate = session.aggregate;
+        self.graph_view.spread = session.spread;
+        if let Some(scrape_interval) = session.scrape_interval {
+            if self.metric_scraper.set_scrape_interval(scrape_interval) {
+                self.scrape_interval = scrape_interval.max(1);
+            }
+        }
+        // metrics not scraped yet are left to the default selection
+        if let Some(selected) = session.selected_metric {
+            match self.metric_index(&selected) {
+                Ok(Some(index)) if self.shows_sections() => {
+                    let _ = self.change_selected_row(Direction::To(index));
+                }
+                Ok(Some(index)) => {
+                    self.metric_list_state.select(Some(index));
+                    self.selected_metric = Some(selected);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn apply_label_filter(&mut self, text: &str) {
+        self.label_filter = text.to_string();
+        // the first matching series gets selected on the next draw
+        self.selected_label = None;
+        self.labels_list_state.select(None);
+    }
+
+    fn apply_search(&mut self, text: &str) {
+        self.search = SearchQuery::parse(text);
+        self.reset_selection();
+    }
+
+    /// Close the expression graph, the payload sizes, the targets or the
+    /// dashboard, reset the zoom of the metric graph, go back to the groups
+    /// of the metric list, or collapse the section of the selected metric
+    pub fn on_escape(&mut self) {
+        if matches!(
+            self.screen,
+            Screen::Expression | Screen::Payload | Screen::Targets | Screen::Dashboard
+        ) {
+            self.screen = Screen::Browse;
+        } else if self.graph_view.zoom.is_none()
+            && matches!(self.focus, ElementInFocus::MetricHeaders)
+            && self.group.is_some()
+        {
+            self.leave_group();
+        } else if self.graph_view.zoom.is_none()
+            && matches!(self.focus, ElementInFocus::MetricHeaders)
+            && self.selected_metric.is_some()
+            && self.shows_sections()
+        {
+            self.collapse_section();
+        } else {
+            self.graph_view.reset_zoom();
+        }
+    }
+
+    /// Collapse or expand the section of the selected heading
+    fn toggle_section(&mut self) {
+        if let Some(section) = &self.section {
+            if !self.collapsed_sections.remove(section) {
+                self.collapsed_sections.insert(section.clone());
+            }
+        }
+    }
+
+    /// Collapse the section of the selected metric, with its heading selected
+    fn collapse_section(&mut self) {
+        let Some(section) = self.section.clone() else {
+            return;
+        };
+        self.collapsed_sections.insert(section);
+        self.selected_metric = None;
+        self.selected_label = None;
+        self.labels_list_state.select(None);
+        // selects the heading
+        let _ = self.sync_section_rows();
+    }
+
+    /// List the metrics of the selected group
+    fn enter_group(&mut self) {
+        self.group = self.selected_group.take();
+        self.group_list_state.select(None);
+        self.reset_selection();
+    }
+
+    /// List the groups again, with the one left selected
+    fn leave_group(&mut self) {
+        let group = self.group.take();
+        self.reset_selection();
+        let index = self.get_groups().ok().and_then(|groups| {
+            groups
+                .iter()
+                .position(|listed| Some(listed) == group.as_ref())
+        });
+        if index.is_some() {
+            self.group_list_state.select(index);
+            self.selected_group = group;
+        }
+    }
+
+    /// Step the scrape interval up or down through `SCRAPE_INTERVALS`
+    pub fn on_change_scrape_interval(&mut self, increase: bool) {
+        let current = self.scrape_interval;
+        let next = if increase {
+            SCRAPE_INTERVALS
+                .iter()
+                .find(|&&interval| interval > current)
+        } else {
+            SCRAPE_INTERVALS
+                .iter()
+                .rev()
+                .find(|&&interval| interval < current)
+        };
+        let Some(&next) = next else {
+            return;
+        };
+        if self.metric_scraper.set_scrape_interval(next) {
+            self.scrape_interval = next;
+            self.status_msg = Some(format!("Scraping every {}s", next));
+        } else {
+            self.status_msg = Some("The metrics are not scraped".to_string());
+        }
+    }
+
+    /// Stop or continue storing new samples, keeping the graphs still while inspecting them
+    pub fn on_toggle_pause(&mut self) -> Result<(), Box<dyn Error>> {
+        let paused = self.metric_scraper.toggle_pause()?;
+        self.status_msg = Some(if paused {
+            "Scraping paused".to_string()
+        } else {
+            "Scraping resumed".to_string()
+        });
+        Ok(())
+    }
+
+    /// Switch the selected metric between raw and rate display
+    pub fn on_toggle_rate(&mut self) {
+        if let Some(selected_metric) = &self.selected_metric {
+            self.graph_view.toggle_value_mode(selected_metric);
+        }
+    }
+
+    pub fn on_tab(&mut self) -> Result<(), Box<dyn Error>> {
+        // the groups have no labels to switch to, the metrics of the group
+        // are listed instead
+        if matches!(self.focus, ElementInFocus::MetricHeaders) && self.shows_groups() {
+            if self.selected_group.is_some() {
+                self.enter_group();
+            }
+            return Ok(());
+        }
+        // a heading has no labels either, its section is collapsed or expanded
+        if matches!(self.focus, ElementInFocus::MetricHeaders)
+            && self.selected_metric.is_none()
+            && self.shows_sections()
+        {
+            self.toggle_section();
+            return Ok(());
+        }
+        self.focus = match self.focus {
+            ElementInFocus::MetricHeaders => ElementInFocus::LabelsView,
+            ElementInFocus::LabelsView => ElementInFocus::MetricHeaders,
+        };
+        Ok(())
+    }
+}
+
+/// Commands accepted by the expression bar, followed by a session name
+const COMMANDS: [&str; 6] = [
+    "save-session",
+    "load-session",
+    "ignore",
+    "unignore",
+    "restore-history",
+    "group",
+];
+
+fn is_command(text: &str) -> bool {
+    let command = text.split_once(' ').map_or(text, |(command, _)| command);
+    COMMANDS.contains(&command)
+}
+
+/// Series in the exposition format, e.g. `queue_size{queue="a"}`, or the plain
+/// name for the key of a series without labels
+pub fn series_name(metric_name: &str, labels: &str) -> String {
+    if labels.contains('=') {
+        format!("{}{{{}}}", metric_name, labels)
+    } else {
+        metric_name.to_string()
+    }
+}
+
+/// Expression for the error ratio of a `X_total` counter and its
+/// `X_errors_total` or `X_failures_total` counterpart, starting from either
+/// of them
+fn error_ratio_expression(metric_name: &str, names: &[String]) -> Option<String> {
+    const ERROR_SUFFIXES: [&str; 2] = ["_errors_total", "_failures_total"];
+    let exists = |name: &String| names.contains(name);
+    let (errors, total) = ERROR_SUFFIXES
+        .iter()
+        .find_map(|suffix| {
+            let base = metric_name.strip_suffix(suffix)?;
+            Some((metric_name.to_string(), format!("{}_total", base)))
+        })
+        .filter(|(_, total)| exists(total))
+        .or_else(|| {
+            let base = metric_name.strip_suffix("_total")?;
+            ERROR_SUFFIXES
+                .iter()
+                .map(|suffix| format!("{}{}", base, suffix))
+                .find(exists)
+                .map(|errors| (errors, metric_name.to_string()))
+        })?;
+    Some(format!("sum(rate({})) / sum(rate({}))", errors, total))
+}
+
+/// Index of the list item drawn at `row` of the bordered list `area`
+fn list_index(area: Rect, row: u16, state: &ListState) -> Option<usize> {
+    if row <= area.top() || row + 1 >= area.bottom() {
+        return None;
+    }
+    Some(state.offset() + usize::from(row - area.top() - 1))
+}
+
+fn update_list_state_with_direction(direction: Direction, state: &mut ListState, list_len: usize) {
+    let Some(last) = list_len.checked_sub(1) else {
+        return;
+    };
+    let selected = match (direction, state.selected()) {
+        (Direction::First, _) => 0,
+        (Direction::Last, _) => last,
+        (Direction::To(index), _) if index > last => return,
+        (Direction::To(index), _) => index,
+        (_, None) => return,
+        (Direction::Down, Some(selected)) if selected >= last => 0,
+        (Direction::Down, Some(selected)) => selected + 1,
+        (Direction::Up, Some(0)) => last,
+        (Direction::Up, Some(selected)) => selected - 1,
+        (Direction::ScrollDown, Some(selected)) => (selected + 1).min(last),
+        (Direction::ScrollUp, Some(selected)) => selected.saturating_sub(1),
+        (Direction::PageDown, Some(selected)) => (selected + PAGE_SIZE).min(last),
+        (Direction::PageUp, Some(selected)) => selected.saturating_sub(PAGE_SIZE),
+    };
+    state.select(Some(selected));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prom::{
+        test_data::{memory_store, scrape_options, target_statuses},
+        HttpClientConfig, MetricType, Pipeline,
+    };
+
+    const METRICS: &str = "# HELP requests_total Handled requests
+# TYPE requests_total counter
+requests_total{code=\"200\"} 10
+requests_total{code=\"500\"} 2
+# HELP queue_size Queued jobs
+# TYPE queue_size gauge
+queue_size 3
+";
+
+    fn app(text: &str) -> App<'static> {
+        let scraper = MetricScraper::with_metrics_text(text, Pipeline::default(), memory_store());
+        App::new("-", 1, scraper)
+    }
+
+    #[test]
+    fn test_target_sections() -> Result<(), Box<dyn Error>> {
+        let text = "# TYPE up gauge
+up{instance=\"node1\"} 1
+up{instance=\"node2\"} 1
+# TYPE queue_size gauge
+queue_size{instance=\"node2\",queue=\"mail\"} 3
+queue_size{instance=\"node2\",queue=\"push\"} 2
+";
+        let mut app = app(text);
+        assert!(!app.shows_sections());
+        let (_, status) = app.metric_scraper.shared();
+        status.write().unwrap().targets = target_statuses(&[("node1", None), ("node2", None)]);
+        assert!(app.shows_sections());
+        let node = |name: &str| Some(name.to_string());
+
+        app.sync_section_rows()?;
+        // the heading is not a metric to act on
+        assert_eq!(app.section, Some(node("node1")));
+        assert_eq!(app.selected_metric, None);
+        app.dispatch(Action::Down)?;
+        app.dispatch(Action::Down)?;
+        app.dispatch(Action::Down)?;
+        assert_eq!(app.section, Some(node("node2")));
+        assert_eq!(app.selected_metric.as_deref(), Some("queue_size"));
+        // the labels pane lists the series of the target of the section only
+        let history = app.metric_scraper.get_history_lock()?;
+        let up = history.get_metric("up").unwrap();
+        assert_eq!(app.visible_labels(up).len(), 1);
+        drop(history);
+
+        // Esc collapses the section, Tab on its heading expands it again
+        app.dispatch(Action::Escape)?;
+        assert!(app.collapsed_sections.contains(&node("node2")));
+        assert_eq!(app.selected_metric, None);
+        assert_eq!(app.metric_list_state.selected(), Some(2));
+        assert_eq!(app.get_section_rows()?.len(), 3);
+        app.dispatch(Action::SwitchFocus)?;
+        assert!(app.collapsed_sections.is_empty());
+        assert_eq!(app.get_section_rows()?.len(), 5);
+
+        assert!(app.select("queue_size", None)?);
+        assert_eq!(app.metric_list_state.selected(), Some(3));
+        assert_eq!(app.section, Some(node("node2")));
+
+        // grouping by another label lists the metrics without sections
+        app.run_command("group queue");
+        assert!(!app.shows_sections());
+        assert_eq!(app.section, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_group_by_label() -> Result<(), Box<dyn Error>> {
+        let mut app = app(METRICS);
+        app.run_command("group code");
+        let group = |value: &str| Some(value.to_string());
+        assert_eq!(app.get_groups()?, vec![group("200"), group("500"), None]);
+        assert!(app.get_metrics_headers()?.is_empty());
+
+        app.dispatch(Action::First)?;
+        app.dispatch(Action::Down)?;
+        // the group is not a metric to act on
+        assert_eq!(app.selected_metric, None);
+        app.dispatch(Action::SwitchFocus)?;
+        assert_eq!(app.group, Some(group("500")));
+        assert!(matches!(app.focus, ElementInFocus::MetricHeaders));
+        assert_eq!(app.get_metrics_headers()?, vec!["requests_total"]);
+        let history = app.metric_scraper.get_history_lock()?;
+        let metric = history.get_metric("requests_total").expect("metric");
+        assert_eq!(app.visible_labels(metric), vec!["code=\"500\""]);
+        drop(history);
+
+        app.dispatch(Action::Escape)?;
+        assert_eq!(app.group, None);
+        assert_eq!(app.selected_group, Some(group("500")));
+        assert_eq!(app.group_list_state.selected(), Some(1));
+        assert_eq!(app.selected_metric, None);
+
+        app.run_command("group");
+        assert_eq!(app.group_by, None);
+        assert_eq!(
+            app.get_metrics_headers()?,
+            vec!["queue_size", "requests_total"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_move_in_empty_lists() -> Result<(), Box<dyn Error>> {
+        let mut empty = app("");
+        for focus in [ElementInFocus::MetricHeaders, ElementInFocus::LabelsView] {
+            empty.focus = focus;
+            for action in [Action::Down, Action::Up, Action::Last, Action::PageDown] {
+                empty.dispatch(action)?;
+            }
+        }
+        assert_eq!(empty.selected_metric, None);
+
+        // a labels filter matching nothing
+        let mut filtered = app(METRICS);
+        filtered.selected_metric = Some("requests_total".to_string());
+        filtered.label_filter = "nomatch".to_string();
+        filtered.focus = ElementInFocus::LabelsView;
+        filtered.dispatch(Action::Down)?;
+        assert_eq!(filtered.selected_label, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_dispatch_marks_and_hides_selected_metric() -> Result<(), Box<dyn Error>> {
+        let mut app = app(METRICS);
+        app.selected_metric = Some("queue_size".to_string());
+        app.dispatch(Action::ToggleMark)?;
+        assert!(app.marked_metrics.contains("queue_size"));
+
+        app.dispatch(Action::HideMarked)?;
+        assert!(app.marked_metrics.is_empty());
+        assert_eq!(app.get_metrics_headers()?, vec!["requests_total"]);
+        assert_eq!(app.selected_metric, None);
+
+        app.dispatch(Action::UnhideAll)?;
+        assert_eq!(app.get_metrics_headers()?.len(), 2);
+        app.selected_metric = Some("queue_size".to_string());
+        app.dispatch(Action::IgnoreMarked)?;
+        assert_eq!(app.get_metrics_headers()?, vec!["requests_total"]);
+        app.run_command("unignore queue_size");
+        app.run_command("ignore requests_.*");
+        assert_eq!(app.get_metrics_headers()?, vec!["queue_size"]);
+        app.run_command("unignore requests_.*");
+        assert_eq!(app.get_metrics_headers()?.len(), 2);
+        app.dispatch(Action::ToggleHelp)?;
+        assert!(app.show_help);
+        app.dispatch(Action::ToggleMetadata)?;
+        assert!(app.show_metadata);
+        app.dispatch(Action::ToggleComponents)?;
+        assert!(app.show_components);
+        app.dispatch(Action::ToggleDashboard)?;
+        assert_eq!(app.screen, Screen::Dashboard);
+        app.dispatch(Action::Escape)?;
+        assert_eq!(app.screen, Screen::Browse);
+        app.dispatch(Action::Last)?;
+        assert_eq!(app.metric_list_state.selected(), Some(1));
+        app.dispatch(Action::Goto)?;
+        assert_eq!(app.metric_list_state.selected(), Some(1));
+        app.dispatch(Action::Goto)?;
+        assert_eq!(app.metric_list_state.selected(), Some(0));
+        app.dispatch(Action::PageDown)?;
+        assert_eq!(app.metric_list_state.selected(), Some(1));
+        app.dispatch(Action::NextBarOrientation)?;
+        app.dispatch(Action::NextBarOrientation)?;
+        assert_eq!(app.graph_view.bar_orientation, BarOrientation::Horizontal);
+        assert_eq!(app.status_msg.as_deref(), Some("Bars: horizontal"));
+        app.dispatch(Action::ToggleBarLabels)?;
+        assert_eq!(app.graph_view.bar_labels, BarLabels::Percent);
+        app.dispatch(Action::ToggleSummaryView)?;
+        assert!(app.graph_view.quantiles_over_time);
+        app.selected_metric = Some("queue_size".to_string());
+        app.selected_label = Some("queue=\"a\"".to_string());
+        app.dispatch(Action::ToggleCompare)?;
+        assert_eq!(
+            app.graph_view.compare,
+            Some(("queue_size".to_string(), "queue=\"a\"".to_string()))
+        );
+        app.dispatch(Action::ToggleCompare)?;
+        assert_eq!(app.graph_view.compare, None);
+        app.dispatch(Action::NextCompareMode)?;
+        assert_eq!(app.graph_view.compare_mode, CompareMode::Ratio);
+        app.dispatch(Action::NextSortMode)?;
+        assert_eq!(app.status_msg.as_deref(), Some("Sort: latest value"));
+        assert_eq!(
+            app.get_metrics_headers()?,
+            vec!["requests_total", "queue_size"]
+        );
+        app.dispatch(Action::ToggleFavorite)?;
+        assert!(app.favorite_metrics.contains("queue_size"));
+        app.dispatch(Action::ToggleFavoritesTab)?;
+        assert_eq!(app.get_metrics_headers()?, vec!["queue_size"]);
+        assert_eq!(app.selected_metric, None);
+        app.dispatch(Action::ToggleFavoritesTab)?;
+        app.dispatch(Action::TogglePause)?;
+        assert!(app.metric_scraper.get_status_read_guard()?.paused);
+        // stdin input is not scraped
+        app.dispatch(Action::IncreaseInterval)?;
+        assert_eq!(app.scrape_interval, 1);
+        assert!(!app.should_quit);
+        app.dispatch(Action::Quit)?;
+        assert!(app.should_quit);
+        Ok(())
+    }
+
+    #[test]
+    fn test_mouse_selects_and_scrolls() -> Result<(), Box<dyn Error>> {
+        let mut app = app(METRICS);
+        app.metric_list_state.select(Some(0));
+        app.metric_list_area = Some(Rect::new(0, 5, 30, 10));
+        app.labels_list_area = Some(Rect::new(30, 5, 30, 10));
+        app.graph_view.table_area = Some(Rect::new(0, 15, 30, 10));
+        app.focus = ElementInFocus::LabelsView;
+
+        // the items start below the top border, row 7 is the second one
+        app.dispatch(Action::MouseDown { column: 3, row: 7 })?;
+        assert!(matches!(app.focus, ElementInFocus::MetricHeaders));
+        assert_eq!(app.selected_metric.as_deref(), Some("requests_total"));
+        app.dispatch(Action::MouseDown { column: 3, row: 12 })?;
+        assert_eq!(app.selected_metric.as_deref(), Some("requests_total"));
+
+        app.dispatch(Action::ScrollDown { column: 40, row: 8 })?;
+        assert_eq!(app.selected_label.as_deref(), Some("code=\"500\""));
+        app.dispatch(Action::ScrollDown { column: 40, row: 8 })?;
+        assert_eq!(app.labels_list_state.selected(), Some(1));
+
+        app.dispatch(Action::ScrollUp { column: 3, row: 20 })?;
+        assert_eq!(app.graph_view.history_scroll, 1);
+        // selecting another metric scrolls back to the newest sample
+        app.dispatch(Action::ScrollUp { column: 3, row: 8 })?;
+        assert_eq!(app.graph_view.history_scroll, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_dispatch_prompt_input() -> Result<(), Box<dyn Error>> {
+        let mut app = app(METRICS);
+        app.dispatch(Action::OpenSearch)?;
+        for c in "queue".chars() {
+            app.dispatch(Action::PromptInput(KeyCode::Char(c)))?;
+        }
+        assert_eq!(app.get_metrics_headers()?, vec!["queue_size"]);
+        app.dispatch(Action::PromptInput(KeyCode::Enter))?;
+        assert!(app.prompt.is_none());
+        assert_eq!(app.search.text, "queue");
+
+        app.dispatch(Action::OpenSearch)?;
+        app.dispatch(Action::PromptInput(KeyCode::Esc))?;
+        assert!(app.prompt.is_none());
+        assert_eq!(app.get_metrics_headers()?.len(), 2);
+
+        app.selected_metric = Some("queue_size".to_string());
+        app.dispatch(Action::OpenUnit)?;
+        app.dispatch(Action::PromptInput(KeyCode::Char('B')))?;
+        app.dispatch(Action::PromptInput(KeyCode::Enter))?;
+        assert_eq!(app.graph_view.units["queue_size"], "B");
+
+        app.dispatch(Action::OpenThreshold)?;
+        app.dispatch(Action::PromptInput(KeyCode::Char('x')))?;
+        app.dispatch(Action::PromptInput(KeyCode::Enter))?;
+        assert!(app.prompt.is_some());
+        assert!(app.graph_view.thresholds.is_empty());
+        app.dispatch(Action::PromptInput(KeyCode::Backspace))?;
+        app.dispatch(Action::PromptInput(KeyCode::Char('2')))?;
+        app.dispatch(Action::PromptInput(KeyCode::Enter))?;
+        assert!(app.prompt.is_none());
+        assert_eq!(app.graph_view.thresholds["queue_size"], 2.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_restore_session() -> Result<(), Box<dyn Error>> {
+        let mut saved = app(METRICS);
+        saved.pinned_metrics.insert("queue_size".to_string());
+        saved.graph_view.toggle_value_mode("requests_total");
+        saved.apply_label_filter("500");
+        let session = saved.session();
+        // stdin input has no endpoint to restore
+        assert_eq!(session.endpoint, None);
+        assert_eq!(session.scrape_interval, None);
+
+        let mut app = app(METRICS);
+        app.restore_session(Session {
+            selected_metric: Some("requests_total".to_string()),
+            ..session
+        });
+        assert!(app.pinned_metrics.contains("queue_size"));
+        assert_eq!(app.graph_view.value_mode("requests_total"), ValueMode::Rate);
+        assert_eq!(app.label_filter, "500");
+        assert_eq!(app.metric_list_state.selected(), Some(1));
+
+        app.dispatch(Action::OpenExpression)?;
+        for c in "load-session ../x".chars() {
+            app.dispatch(Action::PromptInput(KeyCode::Char(c)))?;
+        }
+        app.dispatch(Action::PromptInput(KeyCode::Enter))?;
+        assert!(app.prompt.is_none());
+        assert!(app
+            .status_msg
+            .as_deref()
+            .is_some_and(|msg| msg.starts_with("invalid session name")));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_switch_endpoint() -> Result<(), Box<dyn Error>> {
+        let dir = std::env::temp_dir().join(format!("promviz-switch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(dir.join("node1.txt"), METRICS)?;
+        std::fs::write(dir.join("node2.txt"), "# TYPE up gauge\nup 1\n")?;
+        let node1 = format!("file://{}/node1.txt", dir.display());
+        let node2 = format!("file://{}/node2.txt", dir.display());
+        let client = HttpClientConfig::default().build()?;
+        let scraper = MetricScraper::new(scrape_options(&node1), client, memory_store());
+        let mut switched = App::new(&node1, 3600, scraper);
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        switched.select("queue_size", None)?;
+
+        switched.switch_endpoint(&node2)?;
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        std::fs::remove_dir_all(&dir)?;
+        assert_eq!(switched.endpoint, node2);
+        assert_eq!(switched.metric_scraper.url(), Some(node2.as_str()));
+        assert_eq!(switched.selected_metric, None);
+        assert_eq!(switched.get_metrics_headers()?, vec!["up"]);
+
+        // piped metrics have no endpoint to replace
+        let mut piped = app(METRICS);
+        assert!(piped.switch_endpoint(&node1).is_err());
+        assert_eq!(piped.endpoint, "-");
+        Ok(())
+    }
+
+    #[test]
+    fn test_detect_profile() -> Result<(), Box<dyn Error>> {
+        let mut app = app(&format!(
+            "{}# TYPE go_goroutines gauge\ngo_goroutines 12\n\
+             # TYPE go_memstats_lookups_total counter\ngo_memstats_lookups_total 0\n",
+            METRICS
+        ));
+        app.graph_view
+            .units
+            .insert("go_threads".to_string(), "os threads".to_string());
+        app.detect_profile = true;
+        app.on_detect_profile()?;
+        assert!(!app.detect_profile);
+        assert_eq!(
+            app.get_metrics_headers()?,
+            vec!["go_goroutines", "queue_size", "requests_total"]
+        );
+        assert_eq!(app.graph_view.units["go_goroutines"], "goroutines");
+        assert_eq!(app.graph_view.units["go_threads"], "os threads");
+        assert_eq!(app.dashboard.panels[0].metric, "go_goroutines");
+
+        app.dispatch(Action::UnhideAll)?;
+        assert_eq!(app.get_metrics_headers()?.len(), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_visible_labels_applies_label_filter() -> Result<(), Box<dyn Error>> {
+        let mut app = app(METRICS);
+        app.dispatch(Action::OpenLabelFilter)?;
+        for c in "500".chars() {
+            app.dispatch(Action::PromptInput(KeyCode::Char(c)))?;
+        }
+        let history = app.metric_scraper.get_history_lock()?;
+        let metric = history.get_metric("requests_total").expect("metric");
+        assert_eq!(app.visible_labels(metric), vec!["code=\"500\""]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_graph_view_drag_selects_time_window() {
+        let mut view = GraphView {
+            plot_area: Some(Rect::new(10, 0, 101, 20)),
+            x_bounds: [1000.0, 2000.0],
+            ..Default::default()
+        };
+        view.on_mouse_down(60, 5);
+        view.on_mouse_drag(90);
+        view.on_mouse_up(35);
+        assert_eq!(view.zoom, Some((1250.0, 1500.0)));
+
+        view.reset_zoom();
+        assert_eq!(view.zoom, None);
+    }
+
+    #[test]
+    fn test_graph_view_ignores_clicks_outside_plot() {
+        let mut view = GraphView {
+            plot_area: Some(Rect::new(10, 0, 101, 20)),
+            x_bounds: [1000.0, 2000.0],
+            ..Default::default()
+        };
+        view.on_mouse_down(5, 5);
+        view.on_mouse_up(50);
+        assert_eq!(view.zoom, None);
+
+        view.on_mouse_down(50, 5);
+        view.on_mouse_up(50);
+        assert_eq!(view.zoom, None);
+    }
+
+    #[test]
+    fn test_value_mode_is_remembered_per_metric() {
+        let mut view = GraphView::default();
+        assert_eq!(view.value_mode("requests_total"), ValueMode::Raw);
+        view.toggle_value_mode("requests_total");
+        assert_eq!(view.value_mode("requests_total"), ValueMode::Rate);
+        assert_eq!(view.value_mode("errors_total"), ValueMode::Raw);
+        view.toggle_value_mode("requests_total");
+        assert_eq!(view.value_mode("requests_total"), ValueMode::Raw);
+    }
+
+    #[test]
+    fn test_time_window_cycles() {
+        let mut view = GraphView::default();
+        assert_eq!(view.time_window(), None);
+        view.next_time_window();
+        assert_eq!(view.time_window(), Some(60));
+        view.next_time_window();
+        view.next_time_window();
+        view.next_time_window();
+        assert_eq!(view.time_window(), Some(3600));
+        view.next_time_window();
+        assert_eq!(view.time_window(), None);
+
+        // the increase window is selected apart from the time window
+        assert_eq!(view.increase_window(), 60);
+        view.next_increase_window();
+        assert_eq!(view.increase_window(), 5 * 60);
+        assert_eq!(view.time_window(), None);
+    }
+
+    #[test]
+    fn test_unit_follows_value_mode() {
+        let mut view = GraphView::default();
+        let details = MetricDetails {
+            name: "sent_total".to_string(),
+            docstring: String::new(),
+            metric_type: MetricType::Counter,
+            unit: None,
+            mismatch: None,
+            payload_bytes: 0,
+        };
+        assert_eq!(view.unit(&details, ValueMode::Raw), None);
+        view.units
+            .insert("sent_total".to_string(), "bytes".to_string());
+        assert_eq!(
+            view.unit(&details, ValueMode::Rate).as_deref(),
+            Some("bytes/s")
+        );
+        view.units.clear();
+        let details = MetricDetails {
+            name: "sent_bytes_total".to_string(),
+            ..details
+        };
+        assert_eq!(
+            view.unit(&details, ValueMode::Raw).as_deref(),
+            Some("bytes")
+        );
+    }
+
+    #[test]
+    fn test_error_ratio_expression() -> Result<(), Box<dyn Error>> {
+        let names: Vec<String> = ["jobs_total", "jobs_failures_total", "http_requests_total"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        let expected = Some("sum(rate(jobs_failures_total)) / sum(rate(jobs_total))".to_string());
+        assert_eq!(error_ratio_expression("jobs_total", &names), expected);
+        assert_eq!(
+            error_ratio_expression("jobs_failures_total", &names),
+            expected
+        );
+        assert_eq!(error_ratio_expression("http_requests_total", &names), None);
+        assert_eq!(error_ratio_expression("queue_size", &names), None);
+
+        let mut app = app(&format!(
+            "{}# TYPE requests_errors_total counter\nrequests_errors_total 1\n",
+            METRICS
+        ));
+        app.selected_metric = Some("queue_size".to_string());
+        app.dispatch(Action::ShowErrorRatio)?;
+        assert_eq!(app.screen, Screen::Browse);
+        app.selected_metric = Some("requests_total".to_string());
+        app.dispatch(Action::ShowErrorRatio)?;
+        assert_eq!(app.screen, Screen::Expression);
+        assert_eq!(
+            app.expression.map(|(text, _)| text).as_deref(),
+            Some("sum(rate(requests_errors_total)) / sum(rate(requests_total))")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_switch_tab() -> Result<(), Box<dyn Error>> {
+        let other = app("# TYPE up gauge\nup 1\n");
+        let mut app = app(METRICS);
+        app.graph_view.units = HashMap::from([("queue_size".to_string(), "jobs".to_string())]);
+        app.add_tab("http://node2:9100/metrics", other.metric_scraper);
+        assert_eq!(app.tabs.len(), 2);
+        assert_eq!(app.tabs[0].title, "-");
+        assert_eq!(app.tabs[1].title, "node2:9100");
+
+        app.select("queue_size", None)?;
+        app.dispatch(Action::SwitchTab(1))?;
+        assert_eq!(app.active_tab, 1);
+        assert_eq!(app.endpoint, "http://node2:9100/metrics");
+        assert_eq!(app.get_metrics_headers()?, vec!["up"]);
+        assert_eq!(app.selected_metric, None);
+        assert!(app.graph_view.units.contains_key("queue_size"));
+
+        // a missing tab is ignored
+        app.dispatch(Action::SwitchTab(5))?;
+        assert_eq!(app.active_tab, 1);
+        app.dispatch(Action::SwitchTab(0))?;
+        assert_eq!(app.endpoint, "-");
+        assert_eq!(app.selected_metric.as_deref(), Some("queue_size"));
+        assert_eq!(app.get_metrics_headers()?.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_select() -> Result<(), Box<dyn Error>> {
+        let mut app = app(METRICS);
+        app.screen = Screen::TopMovers;
+        assert!(app.select("requests_total", Some("code=\"500\""))?);
+        assert_eq!(app.screen, Screen::Browse);
+        assert_eq!(app.selected_metric.as_deref(), Some("requests_total"));
+        assert_eq!(app.selected_label.as_deref(), Some("code=\"500\""));
+        assert!(matches!(app.focus, ElementInFocus::LabelsView));
+        assert!(!app.select("requests_total", Some("code=\"404\""))?);
+        assert!(!app.select("missing_total", None)?);
+
+        app.add_favorite("queue_size");
+        assert!(app.favorite_metrics.contains("queue_size"));
+        assert_eq!(app.get_metrics_headers()?[0], "queue_size");
+        Ok(())
+    }
+
+    #[test]
+    fn test_copied_text() -> Result<(), Box<dyn Error>> {
+        let mut app = app(METRICS);
+        assert_eq!(app.copied_text(CopyTarget::MetricName)?, None);
+        app.selected_metric = Some("requests_total".to_string());
+        assert_eq!(
+            app.copied_text(CopyTarget::MetricName)?.as_deref(),
+            Some("requests_total")
+        );
+        assert_eq!(app.copied_text(CopyTarget::Series)?, None);
+        app.selected_label = Some("code=\"500\"".to_string());
+        assert_eq!(
+            app.copied_text(CopyTarget::Series)?.as_deref(),
+            Some("requests_total{code=\"500\"}")
+        );
+        assert_eq!(
+            app.copied_text(CopyTarget::LatestValue)?.as_deref(),
+            Some("2")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_overlay_series() {
+        let mut view = GraphView::default();
+        let visible = vec![
+            "queue=\"a\"".to_string(),
+            "queue=\"b\"".to_string(),
+            "queue=\"c\"".to_string(),
+        ];
+        view.toggle_overlay_series("queue_size", "queue=\"c\"");
+        view.toggle_overlay_series("queue_size", "queue=\"a\"");
+        view.toggle_overlay_series("queue_size", "queue=\"b\"");
+        assert_eq!(
+            view.plotted_series("queue_size", "queue=\"a\"", &visible),
+            vec!["queue=\"a\"", "queue=\"c\"", "queue=\"b\""]
+        );
+        assert_eq!(
+            view.plotted_series("queue_size", "queue=\"a\"", &visible[..2]),
+            vec!["queue=\"a\"", "queue=\"b\""]
+        );
+        view.toggle_overlay_series("queue_size", "queue=\"c\"");
+        assert_eq!(
+            view.plotted_series("queue_size", "queue=\"b\"", &visible),
+            vec!["queue=\"b\"", "queue=\"a\""]
+        );
+        assert_eq!(view.plotted_series("errors_total", "", &visible), vec![""]);
+    }
+
+    #[test]
+    fn test_excluded_series_are_toggled_per_metric() {
+        let mut view = GraphView::default();
+        view.toggle_excluded_series("queue_size", "queue=\"a\"");
+        assert_eq!(
+            view.excluded_series("queue_size"),
+            HashSet::from(["queue=\"a\"".to_string()])
+        );
+        assert!(view.excluded_series("errors_total").is_empty());
+        view.toggle_excluded_series("queue_size", "queue=\"a\"");
+        assert!(view.excluded_series("queue_size").is_empty());
+    }
 }
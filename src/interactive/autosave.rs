@@ -0,0 +1,170 @@
+//! Periodic autosave of the session, so a crash or SIGKILL loses at most the
+//! last interval of UI state. Saves replace `sessions/autosave.toml`
+//! atomically, and a marker file with the pid exists while promviz runs, so a
+//! marker left behind by a process that is gone means the last run crashed.
+//! The session of a crashed run is then moved aside before it's overwritten.
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+
+use super::session::{session_path, write_atomically, Session};
+
+/// Session the autosaves are written to, restored with `:load-session autosave`
+pub const AUTOSAVE_SESSION: &str = "autosave";
+
+/// Session the last autosave of a crashed run is moved to, restored with
+/// `:load-session autosave-crashed`
+pub const CRASHED_SESSION: &str = "autosave-crashed";
+
+#[derive(Debug)]
+pub struct Autosave {
+    path: PathBuf,
+    marker: PathBuf,
+    interval: Duration,
+    last_save: Instant,
+    /// Session of the last save, to skip writing when nothing changed
+    saved: Option<Session>,
+}
+
+impl Autosave {
+    /// Start autosaving every `interval`, returning whether the previous run
+    /// crashed and left a session to recover in `CRASHED_SESSION`
+    pub fn start(interval: Duration) -> anyhow::Result<(Self, bool)> {
+        let path = session_path(AUTOSAVE_SESSION)?;
+        let marker = path.with_extension("running");
+        Self::start_at(path, marker, interval)
+    }
+
+    fn start_at(
+        path: PathBuf,
+        marker: PathBuf,
+        interval: Duration,
+    ) -> anyhow::Result<(Self, bool)> {
+        let crashed = match std::fs::read_to_string(&marker) {
+            Ok(pid) if is_running(pid.trim()) => {
+                anyhow::bail!("promviz with pid {} is autosaving already", pid.trim())
+            }
+            Ok(_) => path.exists(),
+            Err(_) => false,
+        };
+        if crashed {
+            // the autosaves of this run would overwrite the session to recover
+            let crashed_path = path.with_file_name(format!("{}.toml", CRASHED_SESSION));
+            std::fs::rename(&path, &crashed_path).with_context(|| {
+                format!(
+                    "failed to move {} to {}",
+                    path.display(),
+                    crashed_path.display()
+                )
+            })?;
+        }
+        if let Some(dir) = marker.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create {}", dir.display()))?;
+        }
+        std::fs::write(&marker, std::process::id().to_string())
+            .with_context(|| format!("failed to write {}", marker.display()))?;
+        let autosave = Autosave {
+            path,
+            marker,
+            interval,
+            last_save: Instant::now(),
+            saved: None,
+        };
+        Ok((autosave, crashed))
+    }
+
+    /// Save the session once the interval passed since the last save
+    pub fn tick(&mut self, session: impl FnOnce() -> Session) {
+        if self.last_save.elapsed() >= self.interval {
+            self.last_save = Instant::now();
+            self.save(session());
+        }
+    }
+
+    /// Save the final session and remove the marker on a clean exit
+    pub fn finish(mut self, session: Session) {
+        self.save(session);
+        if let Err(err) = std::fs::remove_file(&self.marker) {
+            log::error!("Failed to remove {}: {}", self.marker.display(), err);
+        }
+    }
+
+    fn save(&mut self, session: Session) {
+        if self.saved.as_ref() == Some(&session) {
+            return;
+        }
+        let result = toml::to_string(&session)
+            .map_err(anyhow::Error::from)
+            .and_then(|text| write_atomically(&self.path, &text));
+        match result {
+            Ok(()) => self.saved = Some(session),
+            Err(err) => log::error!("Autosave failed: {:#}", err),
+        }
+    }
+}
+
+/// Whether another process with the pid of a marker is running
+fn is_running(pid: &str) -> bool {
+    if pid == std::process::id().to_string() {
+        return false;
+    }
+    #[cfg(target_os = "linux")]
+    {
+        std::path::Path::new("/proc").join(pid).exists()
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_autosave_detects_crash() -> anyhow::Result<()> {
+        let dir = std::env::temp_dir().join(format!("promviz-autosave-{}", std::process::id()));
+        let path = dir.join("autosave.toml");
+        let marker = dir.join("autosave.running");
+        let session = || Session {
+            search: "requests".to_string(),
+            ..Default::default()
+        };
+
+        let (mut autosave, crashed) =
+            Autosave::start_at(path.clone(), marker.clone(), Duration::ZERO)?;
+        assert!(!crashed);
+        autosave.tick(session);
+        assert_eq!(
+            toml::from_str::<Session>(&std::fs::read_to_string(&path)?)?,
+            session()
+        );
+
+        // a marker of a process that is gone is left after a crash
+        std::fs::write(&marker, "0")?;
+        let (mut autosave, crashed) =
+            Autosave::start_at(path.clone(), marker.clone(), Duration::ZERO)?;
+        assert!(crashed);
+        // the session of the crashed run survives the saves of this one
+        autosave.tick(Session::default);
+        let crashed_path = dir.join("autosave-crashed.toml");
+        assert_eq!(
+            toml::from_str::<Session>(&std::fs::read_to_string(&crashed_path)?)?,
+            session()
+        );
+        autosave.finish(Session::default());
+        assert!(!marker.exists());
+        assert_eq!(
+            toml::from_str::<Session>(&std::fs::read_to_string(&path)?)?,
+            Session::default()
+        );
+        let (autosave, crashed) = Autosave::start_at(path, marker, Duration::ZERO)?;
+        assert!(!crashed);
+        autosave.finish(Session::default());
+        std::fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+}
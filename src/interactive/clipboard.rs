@@ -0,0 +1,26 @@
+//! Copying to the system clipboard through the terminal with the OSC 52
+//! escape sequence, which also works over SSH. Some terminals need it to be
+//! enabled, e.g. tmux with `set -g set-clipboard on`.
+use std::io::{self, Write};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+pub fn copy(text: &str) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    stdout.write_all(osc52(text).as_bytes())?;
+    stdout.flush()
+}
+
+fn osc52(text: &str) -> String {
+    format!("\x1b]52;c;{}\x07", STANDARD.encode(text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_osc52() {
+        assert_eq!(osc52("up{job=\"a\"}"), "\x1b]52;c;dXB7am9iPSJhIn0=\x07");
+    }
+}
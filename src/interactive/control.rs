@@ -0,0 +1,403 @@
+//! Local HTTP API driving the interactive session, e.g. from test scripts or
+//! editor plugins. Requests are answered by the render loop, so they see and
+//! change the same state as the keys.
+//!
+//! Web pages must not drive it: requests from browsers carry an Origin, or a
+//! Host that is a DNS name when the page rebinds its name to the API, and
+//! POST requests need a JSON content type, which a page can't send to another
+//! site without such an Origin.
+use std::net::IpAddr;
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::{
+    io::BufReader,
+    net::{TcpListener, TcpStream},
+    sync::{mpsc, oneshot},
+    task,
+};
+
+use crate::interactive::app::App;
+use crate::prom::http_server::{self, Request};
+
+/// Status line and JSON body of a response
+pub type Reply = (&'static str, Value);
+
+/// A command of the control API, with the channel its reply is sent to
+#[derive(Debug)]
+pub struct ControlRequest {
+    pub command: Command,
+    pub reply: oneshot::Sender<Reply>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    /// The screen, the selection and the scrape status
+    Status,
+    /// The names in the metric list
+    Metrics,
+    Select {
+        metric: String,
+        series: Option<String>,
+    },
+    Scrape,
+    Favorite {
+        metric: String,
+    },
+    Export {
+        format: ExportFormat,
+    },
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// The selected series, like the export series key
+    #[default]
+    Csv,
+    /// The history of all metrics, like the JSON export key
+    Json,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SelectBody {
+    metric: String,
+    /// The labels of the series as listed, e.g. `code="200"`
+    series: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FavoriteBody {
+    metric: String,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ExportBody {
+    format: ExportFormat,
+}
+
+/// Refuse requests sent by web pages, see the module docs, with the status
+/// line and message of the error
+pub fn check_headers(request: &Request) -> Result<(), (&'static str, String)> {
+    if let Some(origin) = &request.origin {
+        return Err((
+            "403 Forbidden",
+            format!("requests from web pages are refused, got origin {}", origin),
+        ));
+    }
+    if let Some(host) = request.host.as_deref().filter(|host| !is_direct_host(host)) {
+        return Err((
+            "403 Forbidden",
+            format!("host {} is refused, use localhost or an IP address", host),
+        ));
+    }
+    if request.method == "POST" && !request.content_type.starts_with("application/json") {
+        return Err((
+            "415 Unsupported Media Type",
+            format!(
+                "content type '{}' is refused, use application/json",
+                request.content_type
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Whether the Host header `host` is localhost or an IP address, with or
+/// without a port
+fn is_direct_host(host: &str) -> bool {
+    let name = match host.strip_prefix('[') {
+        Some(rest) => rest.split(']').next().unwrap_or_default(),
+        None => host.rsplit_once(':').map_or(host, |(name, _)| name),
+    };
+    name.eq_ignore_ascii_case("localhost") || name.parse::<IpAddr>().is_ok()
+}
+
+/// The command of a request, or the status line and message of the error
+pub fn parse(method: &str, path: &str, body: &[u8]) -> Result<Command, (&'static str, String)> {
+    fn json<'a, T: Deserialize<'a> + Default>(body: &'a [u8]) -> Result<T, serde_json::Error> {
+        match body.iter().all(u8::is_ascii_whitespace) {
+            true => Ok(T::default()),
+            false => serde_json::from_slice(body),
+        }
+    }
+    let bad_request = |err: serde_json::Error| ("400 Bad Request", err.to_string());
+    match (method, path) {
+        ("GET", "/v1/status") => Ok(Command::Status),
+        ("GET", "/v1/metrics") => Ok(Command::Metrics),
+        ("POST", "/v1/select") => {
+            let body: SelectBody = serde_json::from_slice(body).map_err(bad_request)?;
+            Ok(Command::Select {
+                metric: body.metric,
+                series: body.series,
+            })
+        }
+        ("POST", "/v1/scrape") => Ok(Command::Scrape),
+        ("POST", "/v1/favorites") => {
+            let body: FavoriteBody = serde_json::from_slice(body).map_err(bad_request)?;
+            Ok(Command::Favorite {
+                metric: body.metric,
+            })
+        }
+        ("POST", "/v1/export") => {
+            let body: ExportBody = json(body).map_err(bad_request)?;
+            Ok(Command::Export {
+                format: body.format,
+            })
+        }
+        (
+            _,
+            "/v1/status" | "/v1/metrics" | "/v1/select" | "/v1/scrape" | "/v1/favorites"
+            | "/v1/export",
+        ) => Err((
+            "405 Method Not Allowed",
+            format!("{} is not supported by {}", method, path),
+        )),
+        _ => Err(("404 Not Found", format!("no such endpoint {}", path))),
+    }
+}
+
+/// Run `command` against the session
+pub fn handle(app: &mut App, command: Command) -> Reply {
+    match run(app, command) {
+        Ok(reply) => reply,
+        Err(err) => {
+            log::error!("Control request failed: {}", err);
+            error("500 Internal Server Error", err.to_string())
+        }
+    }
+}
+
+fn run(app: &mut App, command: Command) -> Result<Reply, Box<dyn std::error::Error>> {
+    Ok(match command {
+        Command::Status => {
+            let status = app.metric_scraper.get_status_read_guard()?;
+            let body = json!({
+                "screen": format!("{:?}", app.screen).to_lowercase(),
+                "metric": app.selected_metric,
+                "series": app.selected_label,
+                "paused": status.paused,
+                "scrapes": status.scrape_count,
+                "error": status.error_msg,
+                "message": app.status_msg,
+            });
+            ("200 OK", body)
+        }
+        Command::Metrics => ("200 OK", json!({ "metrics": app.get_metrics_headers()? })),
+        Command::Select { metric, series } => {
+            if !app.select(&metric, series.as_deref())? {
+                let selector = format!("{}{}", metric, series.unwrap_or_default());
+                return Ok(error(
+                    "404 Not Found",
+                    format!("{} is not listed", selector),
+                ));
+            }
+            (
+                "200 OK",
+                json!({ "metric": metric, "series": app.selected_label }),
+            )
+        }
+        Command::Scrape if app.metric_scraper.scrape_now() => ("200 OK", json!({})),
+        Command::Scrape => error(
+            "409 Conflict",
+            "the metrics are not scraped from an endpoint".to_string(),
+        ),
+        Command::Favorite { metric } => {
+            app.add_favorite(&metric);
+            ("200 OK", json!({ "metric": metric }))
+        }
+        Command::Export { format } => {
+            let exported = app.exported_files.len();
+            match format {
+                ExportFormat::Csv => app.on_export_selected()?,
+                ExportFormat::Json => app.on_export_json()?,
+            }
+            match app.exported_files.get(exported) {
+                Some(file) => ("200 OK", json!({ "file": file })),
+                None => error(
+                    "409 Conflict",
+                    app.status_msg
+                        .clone()
+                        .unwrap_or_else(|| "no series selected".to_string()),
+                ),
+            }
+        }
+    })
+}
+
+fn error(status: &'static str, message: String) -> Reply {
+    (status, json!({ "error": message }))
+}
+
+/// Accept connections to the control API, passing the commands of their
+/// requests to the render loop as events
+pub async fn serve<E>(listener: TcpListener, events: mpsc::Sender<E>)
+where
+    E: From<ControlRequest> + Send + 'static,
+{
+    loop {
+        match listener.accept().await {
+            Ok((stream, peer)) => {
+                log::debug!("Control connection from {}", peer);
+                let events = events.clone();
+                task::spawn(async move {
+                    if let Err(err) = handle_connection(stream, &events).await {
+                        log::error!("Control connection from {} failed: {}", peer, err);
+                    }
+                });
+            }
+            Err(err) => log::error!("Failed to accept control connection: {}", err),
+        }
+    }
+}
+
+async fn handle_connection<E: From<ControlRequest>>(
+    stream: TcpStream,
+    events: &mpsc::Sender<E>,
+) -> anyhow::Result<()> {
+    let mut reader = BufReader::new(stream);
+    loop {
+        let Some(request) = http_server::read_request(&mut reader).await? else {
+            return Ok(());
+        };
+        let command = check_headers(&request)
+            .and_then(|_| parse(&request.method, &request.path, &request.body));
+        let (status, body) = match command {
+            Ok(command) => {
+                let (reply, response) = oneshot::channel();
+                events
+                    .send(ControlRequest { command, reply }.into())
+                    .await
+                    .map_err(|_| anyhow::anyhow!("promviz is shutting down"))?;
+                response.await?
+            }
+            Err((status, message)) => error(status, message),
+        };
+        http_server::write_response(reader.get_mut(), status, &body.to_string()).await?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("GET", "/v1/status", b""), Ok(Command::Status));
+        assert_eq!(
+            parse(
+                "POST",
+                "/v1/select",
+                br#"{"metric": "http_requests_total", "series": "code=\"200\""}"#
+            ),
+            Ok(Command::Select {
+                metric: "http_requests_total".to_string(),
+                series: Some("code=\"200\"".to_string()),
+            })
+        );
+        assert_eq!(
+            parse("POST", "/v1/export", b""),
+            Ok(Command::Export {
+                format: ExportFormat::Csv
+            })
+        );
+        assert_eq!(
+            parse("POST", "/v1/export", br#"{"format": "json"}"#),
+            Ok(Command::Export {
+                format: ExportFormat::Json
+            })
+        );
+        assert_eq!(
+            parse("POST", "/v1/select", b"{}").unwrap_err().0,
+            "400 Bad Request"
+        );
+        assert_eq!(
+            parse("GET", "/v1/scrape", b"").unwrap_err().0,
+            "405 Method Not Allowed"
+        );
+        assert_eq!(parse("GET", "/", b"").unwrap_err().0, "404 Not Found");
+    }
+
+    #[test]
+    fn test_check_headers() {
+        let request = |host: &str, origin: Option<&str>, content_type: &str| Request {
+            method: "POST".to_string(),
+            path: "/v1/scrape".to_string(),
+            content_type: content_type.to_string(),
+            host: Some(host.to_string()),
+            origin: origin.map(String::from),
+            body: vec![],
+        };
+        let json = "application/json";
+        for host in ["localhost:9091", "127.0.0.1:9091", "[::1]:9091", "10.0.0.2"] {
+            assert_eq!(
+                check_headers(&request(host, None, json)),
+                Ok(()),
+                "{}",
+                host
+            );
+        }
+        let status = |request| check_headers(&request).unwrap_err().0;
+        assert_eq!(
+            status(request("evil.example:9091", None, json)),
+            "403 Forbidden"
+        );
+        assert_eq!(
+            status(request(
+                "localhost:9091",
+                Some("https://evil.example"),
+                json
+            )),
+            "403 Forbidden"
+        );
+        assert_eq!(
+            status(request("localhost:9091", None, "text/plain")),
+            "415 Unsupported Media Type"
+        );
+        let get = Request {
+            method: "GET".to_string(),
+            ..request("localhost:9091", None, "")
+        };
+        assert_eq!(check_headers(&get), Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_serve() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (events, mut requests) = mpsc::channel::<ControlRequest>(1);
+        task::spawn(serve(listener, events));
+        task::spawn(async move {
+            while let Some(request) = requests.recv().await {
+                let reply = (
+                    "200 OK",
+                    json!({ "command": format!("{:?}", request.command) }),
+                );
+                request.reply.send(reply).unwrap();
+            }
+        });
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(
+                b"POST /v1/scrape HTTP/1.1\r\nHost: localhost\r\n\
+                  Content-Type: application/json\r\nContent-Length: 0\r\n\r\n",
+            )
+            .await
+            .unwrap();
+        let mut response = vec![0; 1024];
+        let read = stream.read(&mut response).await.unwrap();
+        let response = String::from_utf8_lossy(&response[..read]);
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+        assert!(
+            response.ends_with(r#"{"command":"Scrape"}"#),
+            "{}",
+            response
+        );
+    }
+}
@@ -0,0 +1,105 @@
+//! Grid of panels defined in the `[dashboard]` section of the config file,
+//! each graphing the series of one metric
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+
+use crate::prom::TimeSeries;
+
+/// Panels laid out row by row, `columns` per row
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Dashboard {
+    pub columns: u16,
+    pub panels: Vec<Panel>,
+}
+
+impl Default for Dashboard {
+    fn default() -> Self {
+        Self {
+            columns: 2,
+            panels: vec![],
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Panel {
+    /// Defaults to the metric name and the label selector
+    #[serde(default)]
+    pub title: Option<String>,
+    /// A metric name, or one of the `_bucket`, `_sum` and `_count` series of a
+    /// histogram or summary
+    pub metric: String,
+    /// Only series with all of these label values are shown
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    #[serde(default)]
+    pub chart: ChartType,
+    /// Graph the per-second rate instead of the values
+    #[serde(default)]
+    pub rate: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChartType {
+    /// The series over time
+    #[default]
+    Line,
+    /// The latest value of every series
+    Bars,
+    /// The sum of the latest values of the series as a single number
+    Value,
+}
+
+impl Panel {
+    pub fn title(&self) -> String {
+        if let Some(title) = &self.title {
+            return title.clone();
+        }
+        if self.labels.is_empty() {
+            return self.metric.clone();
+        }
+        let selector: Vec<String> = self
+            .labels
+            .iter()
+            .map(|(label, value)| format!("{}=\"{}\"", label, value))
+            .collect();
+        format!("{}{{{}}}", self.metric, selector.join(","))
+    }
+
+    /// Whether `series` has all the label values of the selector
+    pub fn matches(&self, series: &TimeSeries) -> bool {
+        self.labels
+            .iter()
+            .all(|(label, value)| series.labels.get(label) == Some(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, VecDeque};
+
+    use super::*;
+
+    #[test]
+    fn test_panel_selector() {
+        let panel: Panel =
+            toml::from_str("metric = \"http_requests_total\"\nlabels = { code = \"500\" }")
+                .unwrap();
+        assert_eq!(panel.chart, ChartType::Line);
+        assert_eq!(panel.title(), "http_requests_total{code=\"500\"}");
+        let series = |code: &str| TimeSeries {
+            labels: HashMap::from([
+                ("code".to_string(), code.to_string()),
+                ("method".to_string(), "GET".to_string()),
+            ]),
+            samples: VecDeque::new(),
+            missed_scrapes: 0,
+        };
+        assert!(panel.matches(&series("500")));
+        assert!(!panel.matches(&series("200")));
+    }
+}
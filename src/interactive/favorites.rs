@@ -0,0 +1,61 @@
+//! Favorite metrics, kept across sessions and endpoints in
+//! `~/.config/promviz/favorites.toml`
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::config::config_dir;
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Favorites {
+    pub metrics: BTreeSet<String>,
+}
+
+impl Favorites {
+    /// Location of the favorites, `None` without a config directory
+    pub fn default_path() -> Option<PathBuf> {
+        Some(config_dir()?.join("favorites.toml"))
+    }
+
+    /// Favorites saved at `path`, none if nothing was saved yet
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(content) => toml::from_str(&content)
+                .with_context(|| format!("invalid favorites {}", path.display())),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => {
+                Err(err).with_context(|| format!("failed to read favorites {}", path.display()))
+            }
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create {}", dir.display()))?;
+        }
+        std::fs::write(path, toml::to_string(self)?)
+            .with_context(|| format!("failed to write favorites {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_favorites_round_trip() {
+        let path =
+            std::env::temp_dir().join(format!("promviz-favorites-{}.toml", std::process::id()));
+        assert_eq!(Favorites::load(&path).unwrap(), Favorites::default());
+        let favorites = Favorites {
+            metrics: BTreeSet::from(["requests_total".to_string()]),
+        };
+        favorites.save(&path).unwrap();
+        assert_eq!(Favorites::load(&path).unwrap(), favorites);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
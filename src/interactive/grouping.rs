@@ -0,0 +1,207 @@
+//! Label-first navigation: the metric list first shows the values of a
+//! label, e.g. `subsystem`, and then the metrics with series of the chosen one.
+//! When scraping several targets, the metric list is split into a section per
+//! target instead.
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use crate::prom::{HistoryStore, Metric};
+
+/// Shown for the group of the series without the label
+pub const NO_GROUP: &str = "(none)";
+
+/// A value of the grouping label, `None` for the series without it, so a
+/// label value that reads like `NO_GROUP` is a group of its own
+pub type Group = Option<String>;
+
+/// Label telling the series of the targets of a scrape apart, see
+/// `Target::instance`
+pub const TARGET_LABEL: &str = "instance";
+
+/// A row of the metric list while it is split into sections
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SectionRow {
+    /// Heading of the section of a group and its number of metrics, followed
+    /// by the metrics unless it is collapsed
+    Heading(Group, usize),
+    Metric(Group, String),
+}
+
+/// The group of a series with the labels `labels`
+pub fn series_group<'a>(labels: &'a HashMap<String, String>, label: &str) -> Option<&'a str> {
+    labels
+        .get(label)
+        .map(String::as_str)
+        .filter(|value| !value.is_empty())
+}
+
+/// The name `group` is listed as
+pub fn group_name(group: &Group) -> &str {
+    group.as_deref().unwrap_or(NO_GROUP)
+}
+
+/// Whether `metric` has a series in `group`
+pub fn in_group(metric: &Metric, label: &str, group: &Group) -> bool {
+    metric
+        .time_series
+        .values()
+        .any(|series| series_group(&series.labels, label) == group.as_deref())
+}
+
+/// The groups of the series of `metrics`, sorted, with the one of the series
+/// without the label last
+pub fn group_values(history: &dyn HistoryStore, metrics: &[String], label: &str) -> Vec<Group> {
+    let groups: BTreeSet<Option<&str>> = metrics
+        .iter()
+        .filter_map(|name| history.get_metric(name))
+        .flat_map(|metric| metric.time_series.values())
+        .map(|series| series_group(&series.labels, label))
+        .collect();
+    let mut values: Vec<Group> = groups
+        .iter()
+        .flatten()
+        .map(|value| Some(value.to_string()))
+        .collect();
+    if groups.contains(&None) {
+        values.push(None);
+    }
+    values
+}
+
+/// The rows of `metrics` split into a section per group of `label`: the
+/// `first` groups in their order, e.g. every target even before it has
+/// series, then the other groups of the series. The metrics of a section keep
+/// their order, and only the headings of the `collapsed` sections are listed.
+pub fn section_rows(
+    history: &dyn HistoryStore,
+    metrics: &[String],
+    label: &str,
+    first: Vec<Group>,
+    collapsed: &HashSet<Group>,
+) -> Vec<SectionRow> {
+    let mut groups = first;
+    for group in group_values(history, metrics, label) {
+        if !groups.contains(&group) {
+            groups.push(group);
+        }
+    }
+    let mut rows = vec![];
+    for group in groups {
+        let section: Vec<&String> = metrics
+            .iter()
+            .filter(|name| {
+                history
+                    .get_metric(name)
+                    .is_some_and(|metric| in_group(metric, label, &group))
+            })
+            .collect();
+        rows.push(SectionRow::Heading(group.clone(), section.len()));
+        if !collapsed.contains(&group) {
+            rows.extend(
+                section
+                    .into_iter()
+                    .map(|name| SectionRow::Metric(group.clone(), name.clone())),
+            );
+        }
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prom::test_data::{memory_store, scrape_all};
+
+    #[test]
+    fn test_group_values() {
+        let text = "# TYPE queries_total counter
+queries_total{subsystem=\"db\"} 10
+queries_total{subsystem=\"cache\"} 3
+# TYPE requests_total counter
+requests_total{subsystem=\"http\"} 7
+requests_total{subsystem=\"(none)\"} 1
+# TYPE up gauge
+up 1
+";
+        let mut history = memory_store();
+        for metric in scrape_all(text, 1) {
+            history.insert_scrape(metric);
+        }
+        let metrics = history.get_metrics_headers();
+        assert_eq!(
+            group_values(history.as_ref(), &metrics, "subsystem"),
+            vec![
+                Some("(none)".to_string()),
+                Some("cache".to_string()),
+                Some("db".to_string()),
+                Some("http".to_string()),
+                None
+            ]
+        );
+        let group = |value: &str| Some(value.to_string());
+        let queries = history.get_metric("queries_total").unwrap();
+        assert!(in_group(queries, "subsystem", &group("db")));
+        assert!(!in_group(queries, "subsystem", &group("http")));
+        let up = history.get_metric("up").unwrap();
+        assert!(in_group(up, "subsystem", &None));
+        // a label value reading like the group without the label is another group
+        assert!(!in_group(up, "subsystem", &group(NO_GROUP)));
+        assert_eq!(group_name(&None), NO_GROUP);
+    }
+
+    #[test]
+    fn test_section_rows() {
+        let text = "# TYPE up gauge
+up{instance=\"node1\"} 1
+up{instance=\"node2\"} 1
+# TYPE queue_size gauge
+queue_size{instance=\"node2\"} 3
+# TYPE build_info gauge
+build_info 1
+";
+        let mut history = memory_store();
+        for metric in scrape_all(text, 1) {
+            history.insert_scrape(metric);
+        }
+        let metrics = vec![
+            "up".to_string(),
+            "queue_size".to_string(),
+            "build_info".to_string(),
+        ];
+        let node = |name: &str| Some(name.to_string());
+        let metric =
+            |group: &Group, name: &str| SectionRow::Metric(group.clone(), name.to_string());
+        let targets = vec![node("node1"), node("node2"), node("node3")];
+        let rows = section_rows(
+            history.as_ref(),
+            &metrics,
+            TARGET_LABEL,
+            targets.clone(),
+            &HashSet::new(),
+        );
+        assert_eq!(
+            rows,
+            vec![
+                SectionRow::Heading(node("node1"), 1),
+                metric(&node("node1"), "up"),
+                SectionRow::Heading(node("node2"), 2),
+                metric(&node("node2"), "up"),
+                metric(&node("node2"), "queue_size"),
+                // a target without series yet still gets a section
+                SectionRow::Heading(node("node3"), 0),
+                SectionRow::Heading(None, 1),
+                metric(&None, "build_info"),
+            ]
+        );
+
+        let collapsed = HashSet::from([node("node2")]);
+        let rows = section_rows(
+            history.as_ref(),
+            &metrics,
+            TARGET_LABEL,
+            targets,
+            &collapsed,
+        );
+        assert_eq!(rows[2], SectionRow::Heading(node("node2"), 2));
+        assert_eq!(rows[3], SectionRow::Heading(node("node3"), 0));
+    }
+}
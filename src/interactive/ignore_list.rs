@@ -0,0 +1,111 @@
+//! Metrics never listed, kept across sessions and endpoints in
+//! `~/.config/promviz/ignored.toml`
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::config::config_dir;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IgnoreList {
+    /// Regexes matching the whole metric name, e.g. `go_gc_.*`, so a plain
+    /// name ignores just that metric
+    patterns: Vec<String>,
+    #[serde(skip)]
+    regexes: Vec<Regex>,
+}
+
+impl IgnoreList {
+    /// Location of the ignore list, `None` without a config directory
+    pub fn default_path() -> Option<PathBuf> {
+        Some(config_dir()?.join("ignored.toml"))
+    }
+
+    /// Ignore list saved at `path`, empty if nothing was saved yet
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("failed to read ignore list {}", path.display()))
+            }
+        };
+        let saved: Self = toml::from_str(&content)
+            .with_context(|| format!("invalid ignore list {}", path.display()))?;
+        let mut ignore_list = Self::default();
+        for pattern in saved.patterns {
+            ignore_list
+                .add(&pattern)
+                .with_context(|| format!("invalid ignore list {}", path.display()))?;
+        }
+        Ok(ignore_list)
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create {}", dir.display()))?;
+        }
+        std::fs::write(path, toml::to_string(self)?)
+            .with_context(|| format!("failed to write ignore list {}", path.display()))
+    }
+
+    /// Ignore the metrics matching `pattern`, unless it is in the list already
+    pub fn add(&mut self, pattern: &str) -> anyhow::Result<()> {
+        if self.patterns.iter().any(|p| p == pattern) {
+            return Ok(());
+        }
+        let regex = Regex::new(&format!("^(?:{})$", pattern))
+            .with_context(|| format!("invalid pattern '{}'", pattern))?;
+        self.patterns.push(pattern.to_string());
+        self.regexes.push(regex);
+        Ok(())
+    }
+
+    /// Stop ignoring `pattern`, returning whether it was in the list
+    pub fn remove(&mut self, pattern: &str) -> bool {
+        let Some(index) = self.patterns.iter().position(|p| p == pattern) else {
+            return false;
+        };
+        self.patterns.remove(index);
+        self.regexes.remove(index);
+        true
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        self.regexes.iter().any(|regex| regex.is_match(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ignore_list() {
+        let mut ignore_list = IgnoreList::default();
+        ignore_list.add("go_gc_.*").unwrap();
+        ignore_list
+            .add(&regex::escape("process_cpu_seconds_total"))
+            .unwrap();
+        assert!(ignore_list.add("(").is_err());
+        assert!(ignore_list.matches("go_gc_duration_seconds"));
+        assert!(ignore_list.matches("process_cpu_seconds_total"));
+        assert!(!ignore_list.matches("process_cpu_seconds_total_2"));
+        assert!(!ignore_list.matches("my_go_gc_runs"));
+
+        let path =
+            std::env::temp_dir().join(format!("promviz-ignored-{}.toml", std::process::id()));
+        ignore_list.save(&path).unwrap();
+        let mut loaded = IgnoreList::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(loaded.matches("go_gc_duration_seconds"));
+        assert!(loaded.remove("go_gc_.*"));
+        assert!(!loaded.remove("go_gc_.*"));
+        assert!(!loaded.matches("go_gc_duration_seconds"));
+    }
+}
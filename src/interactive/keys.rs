@@ -0,0 +1,301 @@
+use serde::Deserialize;
+
+/// Keys bound to the single character actions
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct KeyBindings {
+    pub quit: char,
+    /// Pressed twice to jump to the first item
+    pub goto: char,
+    pub last: char,
+    pub toggle_rate: char,
+    pub mark: char,
+    pub hide: char,
+    pub unhide_all: char,
+    pub pin: char,
+    pub export: char,
+    pub export_series: char,
+    pub export_json: char,
+    pub top_movers: char,
+    pub payload: char,
+    pub dashboard: char,
+    pub targets: char,
+    pub aggregate: char,
+    pub time_window: char,
+    pub increase_window: char,
+    pub expression: char,
+    pub error_ratio: char,
+    pub search: char,
+    pub label_filter: char,
+    pub pause: char,
+    pub unit: char,
+    pub threshold: char,
+    pub increase_interval: char,
+    pub decrease_interval: char,
+    pub help: char,
+    pub bar_orientation: char,
+    pub bar_labels: char,
+    pub wider_bars: char,
+    pub narrower_bars: char,
+    pub scroll_bars_left: char,
+    pub scroll_bars_right: char,
+    pub compare: char,
+    pub summary_view: char,
+    pub compare_mode: char,
+    pub sort: char,
+    pub favorite: char,
+    pub favorites_tab: char,
+    pub ignore: char,
+    pub metadata: char,
+    pub components: char,
+    pub copy_name: char,
+    pub copy_series: char,
+    pub copy_value: char,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            quit: 'q',
+            goto: 'g',
+            last: 'G',
+            toggle_rate: 'r',
+            mark: ' ',
+            hide: 'x',
+            unhide_all: 'X',
+            pin: 'P',
+            export: 'E',
+            export_series: 'e',
+            export_json: 'J',
+            top_movers: 't',
+            payload: 'B',
+            dashboard: 'b',
+            targets: 'H',
+            aggregate: 'a',
+            time_window: 'w',
+            increase_window: 'W',
+            expression: ':',
+            error_ratio: 'R',
+            search: '/',
+            label_filter: 'f',
+            pause: 'p',
+            unit: 'u',
+            threshold: 'A',
+            increase_interval: '+',
+            decrease_interval: '-',
+            help: '?',
+            bar_orientation: 'o',
+            bar_labels: '%',
+            wider_bars: '>',
+            narrower_bars: '<',
+            scroll_bars_left: '[',
+            scroll_bars_right: ']',
+            compare: 'c',
+            summary_view: 'v',
+            compare_mode: 'D',
+            sort: 's',
+            favorite: 'F',
+            favorites_tab: 'T',
+            ignore: 'I',
+            metadata: 'd',
+            components: 'i',
+            copy_name: 'y',
+            copy_series: 'Y',
+            copy_value: 'C',
+        }
+    }
+}
+
+/// Part of the UI whose most relevant keys are shown in the footer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyContext {
+    /// A prompt, e.g. the search, is open
+    Prompt,
+    MetricList,
+    LabelsList,
+    /// The top movers, payload sizes, targets or the expression graph
+    Screen,
+}
+
+impl KeyBindings {
+    /// Key names and descriptions of all bindings, for the help overlay
+    pub fn help_entries(&self) -> Vec<(String, &'static str)> {
+        let fixed = [
+            ("Up/Down", "move through the focused list"),
+            ("PgUp/PgDn", "move a page through the focused list"),
+            ("Home/End", "jump to the first or last item"),
+            ("Tab/Left/Right", "switch between metrics and labels"),
+            ("Esc", "reset the zoom, close the expression graph"),
+            ("click", "select a metric or series"),
+            ("mouse wheel", "scroll the lists and the history table"),
+            ("mouse drag", "zoom into the selected time range"),
+            ("1-9", "switch to the tab of an endpoint"),
+        ];
+        let bound = [
+            (self.help, "show or close this help"),
+            (self.quit, "quit"),
+            (self.last, "jump to the last item"),
+            (self.toggle_rate, "switch between raw values and rate"),
+            (self.time_window, "cycle the time window of the graph"),
+            (
+                self.increase_window,
+                "cycle the window of the counter increase",
+            ),
+            (
+                self.aggregate,
+                "plot the sum, then the mean and range of all series",
+            ),
+            (self.mark, "mark metric, or overlay series on the graph"),
+            (self.hide, "hide the marked metrics"),
+            (self.unhide_all, "show all hidden metrics"),
+            (self.ignore, "hide the marked metrics in every session"),
+            (self.pin, "pin the marked metrics to the top"),
+            (
+                self.favorite,
+                "add or remove the marked metrics as favorites",
+            ),
+            (self.favorites_tab, "list only the favorites"),
+            (self.metadata, "show the metadata of the metric"),
+            (
+                self.components,
+                "show the raw series of histograms and summaries",
+            ),
+            (self.search, "search metrics"),
+            (self.label_filter, "filter the labels pane"),
+            (self.expression, "evaluate an expression"),
+            (self.error_ratio, "graph the error ratio of a counter"),
+            (self.unit, "set the unit of the metric"),
+            (self.threshold, "set the alert threshold of the metric"),
+            (self.bar_orientation, "cycle the bar orientation"),
+            (self.bar_labels, "show bucket counts or percentages"),
+            (self.wider_bars, "widen the histogram bars"),
+            (self.narrower_bars, "narrow the histogram bars"),
+            (self.scroll_bars_left, "scroll the histogram bars left"),
+            (self.scroll_bars_right, "scroll the histogram bars right"),
+            (self.compare, "compare with the selected series"),
+            (
+                self.compare_mode,
+                "cycle the comparison: graphs, ratio, difference",
+            ),
+            (
+                self.summary_view,
+                "show the latest quantiles or their history",
+            ),
+            (self.sort, "sort metrics by name, value or rate of change"),
+            (self.top_movers, "show the top movers"),
+            (self.payload, "show the payload bytes per metric"),
+            (self.dashboard, "show the dashboard of the config file"),
+            (self.targets, "show the health of the targets"),
+            (self.pause, "pause or resume scraping"),
+            (self.increase_interval, "scrape less often"),
+            (self.decrease_interval, "scrape more often"),
+            (self.copy_name, "copy the metric name"),
+            (self.copy_series, "copy the series with its labels"),
+            (self.copy_value, "copy the latest value of the series"),
+            (self.export_series, "export the series as CSV"),
+            (self.export, "export the marked metrics"),
+            (self.export_json, "export the history as JSON"),
+        ];
+        fixed
+            .into_iter()
+            .map(|(name, description)| (name.to_string(), description))
+            .chain(std::iter::once((
+                format!("{0}{0}", key_name(self.goto)),
+                "jump to the first item",
+            )))
+            .chain(
+                bound
+                    .into_iter()
+                    .map(|(c, description)| (key_name(c), description)),
+            )
+            .collect()
+    }
+
+    /// Short key names and descriptions of the keys most relevant in
+    /// `context`, for the footer
+    pub fn footer_entries(&self, context: KeyContext) -> Vec<(String, &'static str)> {
+        let bound = match context {
+            KeyContext::Prompt => {
+                return vec![
+                    ("Enter".to_string(), "apply"),
+                    ("Esc".to_string(), "cancel"),
+                ]
+            }
+            KeyContext::MetricList => vec![
+                (self.search, "search"),
+                (self.mark, "mark"),
+                (self.hide, "hide"),
+                (self.pin, "pin"),
+                (self.sort, "sort"),
+                (self.toggle_rate, "rate"),
+                (self.time_window, "window"),
+                (self.expression, "expr"),
+            ],
+            KeyContext::LabelsList => vec![
+                (self.label_filter, "filter"),
+                (self.mark, "overlay"),
+                (self.aggregate, "sum"),
+                (self.compare, "compare"),
+                (self.compare_mode, "ratio"),
+                (self.toggle_rate, "rate"),
+                (self.time_window, "window"),
+                (self.export_series, "csv"),
+            ],
+            KeyContext::Screen => vec![],
+        };
+        let moves = match context {
+            KeyContext::Screen => ("Esc", "back"),
+            _ => ("Tab", "switch pane"),
+        };
+        std::iter::once((moves.0.to_string(), moves.1))
+            .chain(
+                bound
+                    .into_iter()
+                    .map(|(c, description)| (key_name(c), description)),
+            )
+            .chain([(key_name(self.help), "help"), (key_name(self.quit), "quit")])
+            .collect()
+    }
+}
+
+fn key_name(c: char) -> String {
+    match c {
+        ' ' => "space".to_string(),
+        c => c.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_help_entries_follow_bindings() {
+        let keys = KeyBindings {
+            quit: 'Q',
+            ..Default::default()
+        };
+        let entries = keys.help_entries();
+        assert!(entries.contains(&("Q".to_string(), "quit")));
+        assert!(entries.contains(&("gg".to_string(), "jump to the first item")));
+        assert!(entries.contains(&(
+            "space".to_string(),
+            "mark metric, or overlay series on the graph"
+        )));
+    }
+
+    #[test]
+    fn test_footer_entries_follow_context() {
+        let keys = KeyBindings::default();
+        let names = |context| -> Vec<String> {
+            keys.footer_entries(context)
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect()
+        };
+        assert_eq!(names(KeyContext::Prompt), vec!["Enter", "Esc"]);
+        assert_eq!(names(KeyContext::Screen), vec!["Esc", "?", "q"]);
+        assert!(names(KeyContext::MetricList).contains(&"/".to_string()));
+        assert!(names(KeyContext::LabelsList).contains(&"c".to_string()));
+    }
+}
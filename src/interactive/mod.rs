@@ -1,10 +1,12 @@
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event as CEvent, KeyCode},
+    event::{self, DisableMouseCapture, EnableMouseCapture, Event as CEvent},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
+use std::collections::HashMap;
 use std::error::Error;
 use tokio::{
+    net::TcpListener,
     sync::{broadcast, mpsc},
     task,
 };
@@ -16,19 +18,156 @@ use std::{
 
 use ratatui::{backend::CrosstermBackend, Terminal};
 
-use crate::{interactive::app::App, prom::MetricScraper};
+use crate::{
+    interactive::{
+        action::Action,
+        app::{App, Screen},
+        autosave::{Autosave, CRASHED_SESSION},
+        control::ControlRequest,
+        notifications::Notifier,
+    },
+    prom::MetricScraper,
+};
+mod action;
 mod app;
+mod autosave;
+mod clipboard;
+mod control;
+mod dashboard;
+mod favorites;
+mod grouping;
+mod ignore_list;
+mod keys;
+mod notifications;
+mod profile;
+mod session;
+mod summary;
 mod ui;
-pub use ui::format_value;
+pub use dashboard::Dashboard;
+pub use keys::KeyBindings;
+pub use profile::ProfileName;
+pub use session::Session;
+pub use ui::{
+    set_display_zone, set_lang, set_theme, set_time_format, DisplayZone, Lang, Theme, TimeFormat,
+};
+
+/// Longest time between two redraws in low-power mode, e.g. to refresh relative times
+const LOW_POWER_REFRESH: Duration = Duration::from_secs(10);
 
-enum Event<I> {
+enum Event<I, M> {
     Input(I),
+    Mouse(M),
     Tick,
+    Control(ControlRequest),
 }
 
-pub async fn show(endpoint: String, scrape_interval: u64) -> Result<(), Box<dyn Error>> {
-    let metric_scraper = MetricScraper::new(endpoint.clone(), scrape_interval);
+impl<I, M> From<ControlRequest> for Event<I, M> {
+    fn from(request: ControlRequest) -> Self {
+        Event::Control(request)
+    }
+}
+
+/// Settings of the interactive session
+pub struct ShowOptions {
+    /// Source shown in the header
+    pub endpoint: String,
+    /// Seconds between two scrapes
+    pub scrape_interval: u64,
+    pub keys: KeyBindings,
+    /// Unit per metric name
+    pub units: HashMap<String, String>,
+    /// Alert threshold per metric name
+    pub thresholds: HashMap<String, f64>,
+    /// Print the session summary on exit
+    pub print_summary: bool,
+    /// Redraw only on input or new data
+    pub low_power: bool,
+    pub show_footer: bool,
+    /// Restored at the start
+    pub session: Option<Session>,
+    pub autosave_interval: Option<Duration>,
+    /// Send desktop notifications
+    pub notify: bool,
+    pub dashboard: Dashboard,
+    /// Start on the dashboard screen
+    pub show_dashboard: bool,
+    /// Listener of the control API
+    pub control_listener: Option<TcpListener>,
+    pub profile: Option<ProfileName>,
+}
+
+/// Show the metrics of `metric_scraper`, and the ones of `tabs` in tabs of
+/// their own, until the user quits
+pub async fn show(
+    options: ShowOptions,
+    metric_scraper: MetricScraper,
+    tabs: Vec<(String, MetricScraper)>,
+) -> Result<(), Box<dyn Error>> {
+    let ShowOptions {
+        endpoint,
+        scrape_interval,
+        keys,
+        units,
+        thresholds,
+        print_summary,
+        low_power,
+        show_footer,
+        session,
+        autosave_interval,
+        notify,
+        dashboard,
+        show_dashboard,
+        control_listener,
+        profile,
+    } = options;
+    let started = Instant::now();
+    let (tab_endpoints, tab_scrapers): (Vec<String>, Vec<MetricScraper>) = tabs.into_iter().unzip();
     let mut app = App::new(&endpoint, scrape_interval, metric_scraper);
+    for (endpoint, metric_scraper) in tab_endpoints.iter().zip(tab_scrapers) {
+        app.add_tab(endpoint, metric_scraper);
+    }
+    app.graph_view.units = units;
+    app.graph_view.thresholds = thresholds;
+    app.keys = keys;
+    app.show_footer = show_footer;
+    app.dashboard = dashboard;
+    if show_dashboard {
+        app.screen = Screen::Dashboard;
+    }
+    if let Some(path) = favorites::Favorites::default_path() {
+        app.load_favorites(path);
+    }
+    if let Some(path) = ignore_list::IgnoreList::default_path() {
+        app.load_ignore_list(path);
+    }
+    if let Some(session) = session {
+        app.restore_session(session);
+    }
+    match profile {
+        Some(ProfileName::Auto) => app.detect_profile = true,
+        Some(name) => {
+            if let Some(profile) = name.profile() {
+                app.apply_profile(profile);
+            }
+        }
+        None => {}
+    }
+    let mut autosave = match autosave_interval.map(Autosave::start) {
+        Some(Ok((autosave, crashed))) => {
+            if crashed {
+                app.status_msg = Some(format!(
+                    "The last run ended unexpectedly, :load-session {} recovers its state",
+                    CRASHED_SESSION
+                ));
+            }
+            Some(autosave)
+        }
+        Some(Err(err)) => {
+            log::warn!("Autosave disabled: {:#}", err);
+            None
+        }
+        None => None,
+    };
     log::info!("app: {app:#?}");
     // setup terminal
     enable_raw_mode()?;
@@ -41,10 +180,17 @@ pub async fn show(endpoint: String, scrape_interval: u64) -> Result<(), Box<dyn
 
     // Set up an input loop using TUI and Crossterm
     let mut last_tick = Instant::now();
-    let tick_rate = Duration::from_millis(250);
+    let tick_rate = if low_power {
+        Duration::from_secs(1)
+    } else {
+        Duration::from_millis(250)
+    };
     let (notify_shutdown, _) = broadcast::channel(1);
     let mut notify_shutdown_rx1 = notify_shutdown.subscribe();
     let (tx, mut rx) = mpsc::channel(1);
+    if let Some(listener) = control_listener {
+        task::spawn(control::serve(listener, tx.clone()));
+    }
     log::info!("Spawning input loop...");
     task::spawn(async move {
         loop {
@@ -53,8 +199,13 @@ pub async fn show(endpoint: String, scrape_interval: u64) -> Result<(), Box<dyn
                 .unwrap_or_else(|| Duration::from_secs(0));
 
             if crossterm::event::poll(timeout).expect("that poll works") {
-                if let CEvent::Key(key) = event::read().expect("that can read events") {
-                    if let Err(e) = tx.send(Event::Input(key)).await {
+                let event = match event::read().expect("that can read events") {
+                    CEvent::Key(key) => Some(Event::Input(key)),
+                    CEvent::Mouse(mouse) => Some(Event::Mouse(mouse)),
+                    _ => None,
+                };
+                if let Some(event) = event {
+                    if let Err(e) = tx.send(event).await {
                         log::error!("Error sending event: {}", e);
                     }
                 }
@@ -80,27 +231,89 @@ pub async fn show(endpoint: String, scrape_interval: u64) -> Result<(), Box<dyn
 
     //render loop, which calls terminal.draw() on every iteration.
     log::info!("Starting render loop...");
-    loop {
-        terminal.draw(|f| ui::draw(f, &mut app).expect("failed to draw ui"))?;
-
-        match rx.recv().await {
-            Some(Event::Input(event)) => match event.code {
-                KeyCode::Char('q') => {
-                    log::info!("Shuting down...");
-                    if let Err(e) = notify_shutdown.send(()) {
-                        log::error!("Error sending shutdown signal: {}", e);
-                    }
-                    break;
+    let mut dirty = true;
+    // time and scrape status updates of the last redraw
+    let mut drawn: Option<(Instant, u64)> = None;
+    // breaches are kept for the session summary even without notifications
+    let mut notifier = Notifier::default();
+    let mut notified_updates = 0;
+    'render: loop {
+        // scrapes at a fast interval are stored once per frame
+        app.metric_scraper.deliver_batch();
+        let updates = app.metric_scraper.get_status_read_guard()?.updates();
+        if notified_updates != updates {
+            notified_updates = updates;
+            let error_msg = {
+                let status = app.metric_scraper.get_status_read_guard()?;
+                // only once the retries are exhausted
+                status.error_msg.clone().filter(|_| status.retry.is_none())
+            };
+            let history = app.metric_scraper.get_history_lock()?;
+            let messages = notifier.check(
+                history.as_ref(),
+                &app.graph_view.thresholds,
+                error_msg.as_deref(),
+            );
+            if notify {
+                for message in messages {
+                    notifications::send(&message);
                 }
-                KeyCode::Down => app.on_down()?,
-                KeyCode::Up => app.on_up()?,
-                KeyCode::Tab | KeyCode::BackTab | KeyCode::Right | KeyCode::Left => app.on_tab()?,
-                _ => {} //app.dispatch_input(event.code),
-            },
-            Some(Event::Tick) => {}
-            None => {}
+            }
+        }
+        app.on_detect_profile()?;
+        let stale = drawn.is_none_or(|(at, drawn_updates)| {
+            drawn_updates != updates || at.elapsed() >= LOW_POWER_REFRESH
+        });
+        if !low_power || dirty || stale {
+            terminal.draw(|f| ui::draw(f, &mut app).expect("failed to draw ui"))?;
+            drawn = Some((Instant::now(), updates));
+            dirty = false;
+        }
+
+        // handle all queued events before drawing again
+        let mut event = rx.recv().await;
+        loop {
+            let action = match event {
+                // any key closes the help
+                Some(Event::Input(_)) if app.show_help => Some(Action::ToggleHelp),
+                Some(Event::Input(event)) if app.prompt.is_some() => {
+                    Some(Action::PromptInput(event.code))
+                }
+                Some(Event::Input(event)) => app.keys.action(event.code),
+                Some(Event::Mouse(event)) => action::mouse_action(&event),
+                Some(Event::Control(request)) => {
+                    let reply = control::handle(&mut app, request.command);
+                    // the client may have given up waiting
+                    let _ = request.reply.send(reply);
+                    dirty = true;
+                    None
+                }
+                Some(Event::Tick) => None,
+                None => None,
+            };
+            if let Some(action) = action {
+                app.dispatch(action)?;
+                dirty = true;
+            }
+            if app.should_quit {
+                log::info!("Shuting down...");
+                if let Err(e) = notify_shutdown.send(()) {
+                    log::error!("Error sending shutdown signal: {}", e);
+                }
+                break 'render;
+            }
+            match rx.try_recv() {
+                Ok(next) => event = Some(next),
+                Err(_) => break,
+            }
+        }
+        if let Some(autosave) = &mut autosave {
+            autosave.tick(|| app.session());
         }
     }
+    if let Some(autosave) = autosave {
+        autosave.finish(app.session());
+    }
 
     // restore terminal
     disable_raw_mode()?;
@@ -111,5 +324,11 @@ pub async fn show(endpoint: String, scrape_interval: u64) -> Result<(), Box<dyn
     )?;
     terminal.show_cursor()?;
 
+    if print_summary {
+        print!(
+            "{}",
+            summary::session_summary(&app, started.elapsed(), &notifier.breaches)?
+        );
+    }
     Ok(())
 }
@@ -0,0 +1,166 @@
+//! Desktop notifications when series go above their alert threshold or the
+//! scrape keeps failing, to be noticed while the terminal is in the
+//! background. They are sent with `notify-send`, or `osascript` on macOS.
+use std::collections::{BTreeSet, HashMap};
+use std::process::Command;
+
+use chrono::{DateTime, Local};
+
+use crate::interactive::{app::series_name, ui::alerting_labels};
+use crate::prom::HistoryStore;
+
+/// More series going above their threshold at once are notified together
+const MAX_SERIES_NOTIFIED: usize = 3;
+
+/// A series going above its alert threshold
+#[derive(Clone, Debug, PartialEq)]
+pub struct Breach {
+    pub series: String,
+    pub threshold: f64,
+    pub at: DateTime<Local>,
+}
+
+#[derive(Debug, Default)]
+pub struct Notifier {
+    /// Series above their threshold at the last check
+    above: BTreeSet<String>,
+    failing: bool,
+    /// Every breach of the session, oldest first, for the session summary
+    pub breaches: Vec<Breach>,
+}
+
+impl Notifier {
+    /// Messages for the series that went above their threshold, and for the
+    /// scrape when it started failing, since the last check. `error_msg` is
+    /// the error once the retries are exhausted.
+    pub fn check(
+        &mut self,
+        history: &dyn HistoryStore,
+        thresholds: &HashMap<String, f64>,
+        error_msg: Option<&str>,
+    ) -> Vec<String> {
+        let mut messages = Vec::new();
+        if let Some(error_msg) = error_msg.filter(|_| !self.failing) {
+            messages.push(format!("Scrape failing: {}", error_msg));
+        }
+        self.failing = error_msg.is_some();
+
+        let mut above = BTreeSet::new();
+        let mut breaches = Vec::new();
+        let now = Local::now();
+        for (name, threshold) in thresholds {
+            let Some(metric) = history.get_metric(name) else {
+                continue;
+            };
+            for labels in alerting_labels(metric, *threshold) {
+                let series = series_name(name, &labels);
+                if !self.above.contains(&series) {
+                    breaches.push(format!("{} is above {}", series, threshold));
+                    self.breaches.push(Breach {
+                        series: series.clone(),
+                        threshold: *threshold,
+                        at: now,
+                    });
+                }
+                above.insert(series);
+            }
+        }
+        self.above = above;
+        breaches.sort();
+        if breaches.len() > MAX_SERIES_NOTIFIED {
+            messages.push(format!(
+                "{} series went above their threshold",
+                breaches.len()
+            ));
+        } else {
+            messages.extend(breaches);
+        }
+        messages
+    }
+}
+
+/// Show `message` as a desktop notification, without waiting for it
+pub fn send(message: &str) {
+    let mut command = notify_command(message);
+    std::thread::spawn(move || match command.status() {
+        Ok(status) if status.success() => {}
+        Ok(status) => log::error!("Notification command failed with {}", status),
+        Err(err) => log::error!("Failed to run the notification command: {}", err),
+    });
+}
+
+#[cfg(target_os = "macos")]
+fn notify_command(message: &str) -> Command {
+    let mut command = Command::new("osascript");
+    command.arg("-e").arg(format!(
+        "display notification {:?} with title \"promviz\"",
+        message
+    ));
+    command
+}
+
+#[cfg(not(target_os = "macos"))]
+fn notify_command(message: &str) -> Command {
+    let mut command = Command::new("notify-send");
+    command.arg("promviz").arg(message);
+    command
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prom::test_data::{gauge, memory_store};
+
+    use super::*;
+
+    #[test]
+    fn test_notifications_on_transitions() {
+        let mut history = memory_store();
+        let scrape = |values: [f64; 2], timestamp| {
+            gauge(
+                "queue_size",
+                &[("queue=\"a\"", values[0]), ("queue=\"b\"", values[1])],
+                timestamp,
+            )
+        };
+        let thresholds = HashMap::from([("queue_size".to_string(), 100.0)]);
+        let mut notifier = Notifier::default();
+
+        history.insert_scrape(scrape([120.0, 80.0], 1));
+        assert_eq!(
+            notifier.check(history.as_ref(), &thresholds, None),
+            vec!["queue_size{queue=\"a\"} is above 100"]
+        );
+        // still above, and the failing scrape is notified once
+        assert_eq!(
+            notifier.check(history.as_ref(), &thresholds, Some("timeout")),
+            vec!["Scrape failing: timeout"]
+        );
+        assert!(notifier
+            .check(history.as_ref(), &thresholds, Some("timeout"))
+            .is_empty());
+
+        history.insert_scrape(scrape([90.0, 110.0], 2));
+        assert_eq!(
+            notifier.check(history.as_ref(), &thresholds, None),
+            vec!["queue_size{queue=\"b\"} is above 100"]
+        );
+        history.insert_scrape(scrape([120.0, 110.0], 3));
+        assert_eq!(
+            notifier.check(history.as_ref(), &thresholds, None),
+            vec!["queue_size{queue=\"a\"} is above 100"]
+        );
+        let breached: Vec<&str> = notifier
+            .breaches
+            .iter()
+            .map(|breach| breach.series.as_str())
+            .collect();
+        assert_eq!(
+            breached,
+            vec![
+                "queue_size{queue=\"a\"}",
+                "queue_size{queue=\"b\"}",
+                "queue_size{queue=\"a\"}"
+            ]
+        );
+    }
+}
@@ -0,0 +1,199 @@
+//! Built-in settings for the metrics of well-known exporters: hidden noise,
+//! units, pinned metrics and dashboard panels
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+
+use super::dashboard::{ChartType, Panel};
+
+/// Profile selected with --profile
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ProfileName {
+    /// Detected from the metric names of the first scrape
+    Auto,
+    Node,
+    Cadvisor,
+    Jvm,
+    Go,
+}
+
+#[derive(Debug)]
+pub struct Profile {
+    pub name: &'static str,
+    /// Prefix of the metric names only this exporter exposes
+    detect: &'static str,
+    /// Prefixes of the metric names hidden from the metric list
+    pub hidden: &'static [&'static str],
+    /// Unit per metric name, for metrics without a unit in their name
+    pub units: &'static [(&'static str, &'static str)],
+    pub pinned: &'static [&'static str],
+    /// Metric, chart and whether to graph the rate of the dashboard panels
+    panels: &'static [(&'static str, ChartType, bool)],
+}
+
+/// In detection order, the node_exporter exposes the Go runtime metrics as well
+const PROFILES: [Profile; 4] = [
+    Profile {
+        name: "node",
+        detect: "node_",
+        hidden: &["go_", "process_", "promhttp_", "node_scrape_collector_"],
+        units: &[
+            ("node_load1", "load"),
+            ("node_load5", "load"),
+            ("node_load15", "load"),
+            ("node_procs_running", "processes"),
+            ("node_filefd_allocated", "fds"),
+        ],
+        pinned: &[
+            "node_load1",
+            "node_cpu_seconds_total",
+            "node_memory_MemAvailable_bytes",
+            "node_filesystem_avail_bytes",
+        ],
+        panels: &[
+            ("node_load1", ChartType::Line, false),
+            ("node_cpu_seconds_total", ChartType::Line, true),
+            ("node_memory_MemAvailable_bytes", ChartType::Line, false),
+            ("node_filesystem_avail_bytes", ChartType::Bars, false),
+            ("node_network_receive_bytes_total", ChartType::Line, true),
+            ("node_network_transmit_bytes_total", ChartType::Line, true),
+        ],
+    },
+    Profile {
+        name: "cadvisor",
+        detect: "container_",
+        hidden: &[
+            "cadvisor_version_info",
+            "container_tasks_state",
+            "container_memory_failures_total",
+            "container_scrape_error",
+            "machine_",
+        ],
+        units: &[
+            ("container_processes", "processes"),
+            ("container_threads", "threads"),
+        ],
+        pinned: &[
+            "container_cpu_usage_seconds_total",
+            "container_memory_working_set_bytes",
+        ],
+        panels: &[
+            ("container_cpu_usage_seconds_total", ChartType::Line, true),
+            ("container_memory_working_set_bytes", ChartType::Line, false),
+            (
+                "container_network_receive_bytes_total",
+                ChartType::Line,
+                true,
+            ),
+            (
+                "container_network_transmit_bytes_total",
+                ChartType::Line,
+                true,
+            ),
+        ],
+    },
+    Profile {
+        name: "jvm",
+        detect: "jvm_",
+        hidden: &[
+            "jvm_buffer_pool_",
+            "jvm_info",
+            "jvm_memory_pool_allocated_bytes",
+        ],
+        units: &[
+            ("jvm_threads_current", "threads"),
+            ("jvm_threads_daemon", "threads"),
+            ("jvm_classes_loaded", "classes"),
+        ],
+        pinned: &[
+            "jvm_memory_bytes_used",
+            "jvm_threads_current",
+            "jvm_gc_collection_seconds",
+        ],
+        panels: &[
+            ("jvm_memory_bytes_used", ChartType::Line, false),
+            ("jvm_gc_collection_seconds_sum", ChartType::Line, true),
+            ("jvm_threads_current", ChartType::Value, false),
+            ("process_cpu_seconds_total", ChartType::Line, true),
+        ],
+    },
+    Profile {
+        name: "go",
+        detect: "go_goroutines",
+        hidden: &[
+            "go_memstats_buck_hash_sys_bytes",
+            "go_memstats_gc_sys_bytes",
+            "go_memstats_mcache_",
+            "go_memstats_mspan_",
+            "go_memstats_other_sys_bytes",
+            "go_memstats_lookups_total",
+        ],
+        units: &[("go_goroutines", "goroutines"), ("go_threads", "threads")],
+        pinned: &[
+            "go_goroutines",
+            "go_memstats_heap_alloc_bytes",
+            "go_gc_duration_seconds",
+        ],
+        panels: &[
+            ("go_goroutines", ChartType::Line, false),
+            ("go_memstats_heap_alloc_bytes", ChartType::Line, false),
+            ("go_gc_duration_seconds_count", ChartType::Line, true),
+            ("process_resident_memory_bytes", ChartType::Line, false),
+        ],
+    },
+];
+
+impl ProfileName {
+    /// The named profile, `None` for `Auto`
+    pub fn profile(self) -> Option<&'static Profile> {
+        let name = match self {
+            ProfileName::Auto => return None,
+            ProfileName::Node => "node",
+            ProfileName::Cadvisor => "cadvisor",
+            ProfileName::Jvm => "jvm",
+            ProfileName::Go => "go",
+        };
+        PROFILES.iter().find(|profile| profile.name == name)
+    }
+}
+
+impl Profile {
+    /// The profile of the exporter exposing the metrics `names`, if known
+    pub fn detect<'n>(names: impl IntoIterator<Item = &'n str>) -> Option<&'static Profile> {
+        let names: Vec<&str> = names.into_iter().collect();
+        PROFILES
+            .iter()
+            .find(|profile| names.iter().any(|name| name.starts_with(profile.detect)))
+    }
+
+    pub fn panels(&self) -> Vec<Panel> {
+        self.panels
+            .iter()
+            .map(|(metric, chart, rate)| Panel {
+                title: None,
+                metric: metric.to_string(),
+                labels: BTreeMap::new(),
+                chart: *chart,
+                rate: *rate,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        let node = Profile::detect(["go_goroutines", "node_load1", "process_open_fds"]);
+        assert_eq!(node.map(|profile| profile.name), Some("node"));
+        assert!(node.unwrap().hidden.contains(&"go_"));
+        let go = Profile::detect(["go_goroutines", "http_requests_total"]);
+        assert_eq!(go.map(|profile| profile.name), Some("go"));
+        assert!(Profile::detect(["http_requests_total"]).is_none());
+        assert!(ProfileName::Auto.profile().is_none());
+        assert_eq!(ProfileName::Cadvisor.profile().unwrap().panels().len(), 4);
+    }
+}
@@ -0,0 +1,135 @@
+//! Named sessions, saving the target and the UI state under
+//! `~/.config/promviz/sessions/<name>.toml`
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use clap::{parser::ValueSource, ArgMatches};
+use serde::{Deserialize, Serialize};
+
+use crate::cli::Cli;
+use crate::config::config_dir;
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    /// Scraped endpoint, used unless another one is given
+    pub endpoint: Option<String>,
+    pub scrape_interval: Option<u64>,
+    pub pinned: BTreeSet<String>,
+    pub hidden: BTreeSet<String>,
+    pub search: String,
+    pub label_filter: String,
+    pub expression: Option<String>,
+    /// Unit per metric name, on top of the units of the config file
+    pub units: BTreeMap<String, String>,
+    /// Alert threshold per metric name, on top of the ones of the config file
+    pub thresholds: BTreeMap<String, f64>,
+    /// Metrics shown as rate
+    pub rate: BTreeSet<String>,
+    pub aggregate: bool,
+    /// The aggregate is the mean and range instead of the sum
+    pub spread: bool,
+    pub selected_metric: Option<String>,
+}
+
+impl Session {
+    pub fn load(name: &str) -> anyhow::Result<Self> {
+        let path = session_path(name)?;
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("failed to read session {}", path.display()))?;
+        toml::from_str(&content).with_context(|| format!("invalid session {}", path.display()))
+    }
+
+    /// Write the session, returning the path of the file
+    pub fn save(&self, name: &str) -> anyhow::Result<PathBuf> {
+        let path = session_path(name)?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create {}", dir.display()))?;
+        }
+        write_atomically(&path, &toml::to_string(self)?)
+            .with_context(|| format!("failed to write session {}", path.display()))?;
+        Ok(path)
+    }
+
+    /// Use the target of the session for the settings not given as flag or
+    /// env variable. The scrape interval is taken out, so it doesn't override
+    /// a given one when the session is restored.
+    pub fn merge_into(&mut self, cli: &mut Cli, matches: &ArgMatches) {
+        let unset = |id: &str| {
+            !matches!(
+                matches.value_source(id),
+                Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+            )
+        };
+        let scrape_interval = self.scrape_interval.take();
+        if unset("endpoint") {
+            if let Some(endpoint) = self.endpoint.clone() {
+                cli.endpoint = endpoint;
+            }
+        }
+        if unset("scrape_interval") {
+            if let Some(Ok(scrape_interval)) = scrape_interval.map(u16::try_from) {
+                cli.scrape_interval = scrape_interval;
+            }
+        }
+    }
+}
+
+/// Write `contents` to a temporary file next to `path`, sync it to disk and
+/// rename it over `path`, so a crash never leaves `path` half written
+pub fn write_atomically(path: &Path, contents: &str) -> anyhow::Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    let mut file = std::fs::File::create(&tmp_path)
+        .with_context(|| format!("failed to create {}", tmp_path.display()))?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()?;
+    std::fs::rename(&tmp_path, path)
+        .with_context(|| format!("failed to rename {}", tmp_path.display()))?;
+    Ok(())
+}
+
+/// Sessions are plain names, so they can't point outside the sessions directory
+pub fn session_path(name: &str) -> anyhow::Result<PathBuf> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        anyhow::bail!(
+            "invalid session name '{}', use letters, digits, - and _",
+            name
+        );
+    }
+    let dir = config_dir().context("no config directory, set HOME or XDG_CONFIG_HOME")?;
+    Ok(dir.join("sessions").join(format!("{}.toml", name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_round_trip() {
+        let session = Session {
+            endpoint: Some("http://api:9090/metrics".to_string()),
+            pinned: BTreeSet::from(["requests_total".to_string()]),
+            units: BTreeMap::from([("queue_size".to_string(), "jobs".to_string())]),
+            aggregate: true,
+            spread: true,
+            ..Default::default()
+        };
+        let text = toml::to_string(&session).unwrap();
+        assert_eq!(toml::from_str::<Session>(&text).unwrap(), session);
+        assert_eq!(toml::from_str::<Session>("").unwrap(), Session::default());
+    }
+
+    #[test]
+    fn test_session_names() {
+        assert!(session_path("api-prod_2").is_ok() || config_dir().is_none());
+        assert!(session_path("../config").is_err());
+        assert!(session_path("").is_err());
+    }
+}
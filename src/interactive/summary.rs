@@ -0,0 +1,143 @@
+use std::error::Error;
+use std::fmt::Write;
+use std::time::Duration;
+
+use crate::interactive::{app::App, notifications::Breach, ui::format_time};
+use crate::prom::{HistoryStore, Sample};
+
+/// Number of series listed as most changed
+const TOP_CHANGED: usize = 5;
+
+/// A gauge or counter series and its first and last value of the session
+struct Change {
+    series: String,
+    first: f64,
+    last: f64,
+}
+
+/// The `n` series with the largest absolute change between their first and last sample
+fn top_changed(history: &dyn HistoryStore, n: usize) -> Vec<Change> {
+    let value = |sample: &Sample| match sample {
+        Sample::GaugeSample(s) | Sample::CounterSample(s) => Some(s.value),
+        _ => None,
+    };
+    let mut changes = vec![];
+    for name in history.get_metrics_headers() {
+        let Some(metric) = history.get_metric(&name) else {
+            continue;
+        };
+        for (labels, time_series) in &metric.time_series {
+            let first = time_series.samples.front().and_then(value);
+            let last = time_series.samples.back().and_then(value);
+            if let (Some(first), Some(last)) = (first, last) {
+                if first != last {
+                    changes.push(Change {
+                        series: format!("{}{{{}}}", name, labels),
+                        first,
+                        last,
+                    });
+                }
+            }
+        }
+    }
+    changes.sort_by(|a, b| {
+        (b.last - b.first)
+            .abs()
+            .total_cmp(&(a.last - a.first).abs())
+    });
+    changes.truncate(n);
+    changes
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!("{}h{:02}m{:02}s", secs / 3600, secs % 3600 / 60, secs % 60)
+}
+
+/// Summary of the session, printed on exit so the terminal scrollback documents it
+pub fn session_summary(
+    app: &App,
+    duration: Duration,
+    breaches: &[Breach],
+) -> Result<String, Box<dyn Error>> {
+    let status = app.metric_scraper.get_status_read_guard()?;
+    let mut text = String::new();
+    writeln!(text, "promviz session summary")?;
+    writeln!(text, "  Duration: {}", format_duration(duration))?;
+    writeln!(text, "  Source:   {}", app.endpoint)?;
+    writeln!(
+        text,
+        "  Scrapes:  {} successful, {} failed",
+        status.scrape_count, status.failure_count
+    )?;
+    if let Some(error_msg) = &status.error_msg {
+        writeln!(text, "  Last error: {}", error_msg)?;
+    }
+
+    if !breaches.is_empty() {
+        writeln!(text, "  Alerts fired:")?;
+        for breach in breaches {
+            writeln!(
+                text,
+                "    {} {} went above {}",
+                format_time(breach.at),
+                breach.series,
+                breach.threshold
+            )?;
+        }
+    }
+
+    let changes = top_changed(app.metric_scraper.get_history_lock()?.as_ref(), TOP_CHANGED);
+    if !changes.is_empty() {
+        writeln!(text, "  Most changed series:")?;
+        for change in changes {
+            writeln!(
+                text,
+                "    {}: {} -> {} ({:+})",
+                change.series,
+                change.first,
+                change.last,
+                change.last - change.first
+            )?;
+        }
+    }
+    if !app.exported_files.is_empty() {
+        writeln!(text, "  Exported files:")?;
+        for file in &app.exported_files {
+            writeln!(text, "    {}", file)?;
+        }
+    }
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prom::test_data::{gauge, memory_store};
+
+    use super::*;
+
+    #[test]
+    fn test_top_changed() {
+        let mut history = memory_store();
+        for (timestamp, values) in [(10, [1.0, 5.0, 7.0]), (20, [4.0, -5.0, 7.0])] {
+            let series = [
+                ("room=\"a\"", values[0]),
+                ("room=\"b\"", values[1]),
+                ("room=\"c\"", values[2]),
+            ];
+            history.insert_scrape(gauge("temperature", &series, timestamp));
+        }
+        let changes = top_changed(history.as_ref(), 5);
+        let series: Vec<&str> = changes.iter().map(|c| c.series.as_str()).collect();
+        assert_eq!(
+            series,
+            vec!["temperature{room=\"b\"}", "temperature{room=\"a\"}"]
+        );
+        assert_eq!(top_changed(history.as_ref(), 1).len(), 1);
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_secs(3723)), "1h02m03s");
+    }
+}
@@ -0,0 +1,50 @@
+use crate::prom::{Metric, Trend};
+
+/// Whether any series of the metric changed in the latest scrape
+pub fn has_activity(metric: &Metric) -> bool {
+    metric.trend != Trend::Unchanged
+}
+
+/// Badge shown next to the metric name for the direction its series moved in
+/// the latest scrape
+pub fn badge(trend: Trend) -> char {
+    match trend {
+        Trend::Unchanged => '•',
+        Trend::Increased => '▲',
+        Trend::Decreased => '▼',
+        Trend::Mixed => '↕',
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prom::test_data::gauge;
+    use crate::prom::Retention;
+
+    use super::*;
+
+    #[test]
+    fn test_activity_and_badge() {
+        let scrape = |values: [f64; 2], timestamp| {
+            gauge(
+                "queue_size",
+                &[("queue=\"a\"", values[0]), ("queue=\"b\"", values[1])],
+                timestamp,
+            )
+        };
+        let mut metric = scrape([1.0, 1.0], 10).into_metric();
+        assert!(!has_activity(&metric));
+        let mut update = |values, timestamp| {
+            metric.update_time_series(
+                scrape(values, timestamp).value_per_labels,
+                &Retention::default(),
+            );
+            (has_activity(&metric), badge(metric.trend))
+        };
+        assert_eq!(update([2.0, 1.0], 20), (true, '▲'));
+        assert_eq!(update([2.0, f64::NAN], 30), (true, '↕'));
+        assert_eq!(update([2.0, f64::NAN], 40), (false, '•'));
+        assert_eq!(update([1.0, f64::NAN], 50), (true, '▼'));
+        assert_eq!(update([2.0, 0.0], 60), (true, '↕'));
+    }
+}
@@ -0,0 +1,58 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::prom::{HistoryStore, Metric, Sample};
+
+/// Series of a gauge or counter whose latest value is above `threshold`
+pub fn alerting_labels(metric: &Metric, threshold: f64) -> HashSet<String> {
+    metric
+        .time_series
+        .iter()
+        .filter(|(_, time_series)| {
+            matches!(
+                time_series.samples.back(),
+                Some(Sample::GaugeSample(sample) | Sample::CounterSample(sample))
+                    if sample.value > threshold
+            )
+        })
+        .map(|(labels, _)| labels.clone())
+        .collect()
+}
+
+/// Number of series above the threshold of their metric
+pub fn alert_count(history: &dyn HistoryStore, thresholds: &HashMap<String, f64>) -> usize {
+    thresholds
+        .iter()
+        .filter_map(|(name, threshold)| {
+            Some(alerting_labels(history.get_metric(name)?, *threshold).len())
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prom::test_data::{gauge, memory_store};
+
+    use super::*;
+
+    #[test]
+    fn test_alerts() {
+        let mut history = memory_store();
+        history.insert_scrape(gauge(
+            "queue_size",
+            &[("queue=\"a\"", 120.0), ("queue=\"b\"", 80.0)],
+            1,
+        ));
+        let metric = history.get_metric("queue_size").unwrap();
+        assert_eq!(
+            alerting_labels(metric, 100.0),
+            HashSet::from(["queue=\"a\"".to_string()])
+        );
+        assert_eq!(alerting_labels(metric, 120.0), HashSet::new());
+
+        let thresholds = HashMap::from([
+            ("queue_size".to_string(), 50.0),
+            ("missing".to_string(), 0.0),
+        ]);
+        assert_eq!(alert_count(history.as_ref(), &thresholds), 2);
+    }
+}
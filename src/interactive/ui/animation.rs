@@ -0,0 +1,96 @@
+//! Attention effects computed from the elapsed time instead of counted
+//! frames, so they run at the same speed whatever the redraw rate, and don't
+//! rely on the terminal supporting blinking text.
+use std::time::{Duration, Instant};
+
+use ratatui::style::{Modifier, Style};
+
+use super::theme;
+
+/// Length of one on/off cycle of pulsing text
+const PULSE_PERIOD: Duration = Duration::from_millis(1000);
+/// How long a new scrape error is flashed before it starts pulsing
+const FLASH_DURATION: Duration = Duration::from_secs(2);
+
+#[derive(Debug)]
+pub struct Animation {
+    started: Instant,
+    /// When the current scrape error appeared
+    error_since: Option<Instant>,
+}
+
+impl Default for Animation {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            error_since: None,
+        }
+    }
+}
+
+impl Animation {
+    /// Whether pulsing text is in its highlighted half at `now`. Always on if
+    /// animations are disabled.
+    pub fn pulse(&self, now: Instant) -> bool {
+        if !theme().animate {
+            return true;
+        }
+        let elapsed = now.saturating_duration_since(self.started).as_millis();
+        elapsed % PULSE_PERIOD.as_millis() < PULSE_PERIOD.as_millis() / 2
+    }
+
+    /// Note whether there is a scrape error at `now`, to flash it when it's new
+    pub fn track_error(&mut self, has_error: bool, now: Instant) {
+        self.error_since = match self.error_since {
+            Some(since) if has_error => Some(since),
+            None if has_error => Some(now),
+            _ => None,
+        };
+    }
+
+    /// Style of the scrape error: flashing while it's new, then pulsing
+    pub fn error_style(&self, now: Instant) -> Style {
+        let style = Style::default().fg(theme().error);
+        let is_new = self
+            .error_since
+            .is_some_and(|since| now.saturating_duration_since(since) < FLASH_DURATION);
+        match (theme().animate, is_new, self.pulse(now)) {
+            (false, _, _) => style.add_modifier(Modifier::BOLD),
+            (true, true, true) => style.add_modifier(Modifier::BOLD | Modifier::REVERSED),
+            (true, _, true) => style.add_modifier(Modifier::BOLD),
+            (true, _, false) => style,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pulse_follows_the_clock() {
+        let animation = Animation::default();
+        let at = |millis| animation.started + Duration::from_millis(millis);
+        assert!(animation.pulse(at(0)));
+        assert!(animation.pulse(at(499)));
+        assert!(!animation.pulse(at(500)));
+        assert!(animation.pulse(at(2_100)));
+    }
+
+    #[test]
+    fn test_new_errors_flash() {
+        let mut animation = Animation::default();
+        let started = animation.started;
+        let at = |millis| started + Duration::from_millis(millis);
+        let flashing = Style::default()
+            .fg(theme().error)
+            .add_modifier(Modifier::BOLD | Modifier::REVERSED);
+        animation.track_error(true, at(1_000));
+        animation.track_error(true, at(2_000));
+        assert_eq!(animation.error_style(at(2_000)), flashing);
+        assert_ne!(animation.error_style(at(3_000)), flashing);
+        animation.track_error(false, at(4_000));
+        animation.track_error(true, at(5_000));
+        assert_eq!(animation.error_style(at(5_000)), flashing);
+    }
+}
@@ -0,0 +1,93 @@
+use std::collections::{HashSet, VecDeque};
+
+use crate::prom::{Metric, MetricType, Sample, TimeSeries};
+
+/// Number of samples before the latest one used for the rolling mean and deviation
+const WINDOW: usize = 30;
+/// Samples needed before a series can be considered anomalous
+const MIN_SAMPLES: usize = 5;
+/// Deviation from the rolling mean, in standard deviations, that counts as an anomaly
+const SIGMAS: f64 = 3.0;
+
+/// Number of standard deviations the latest gauge value is away from the
+/// mean of the samples before it
+pub fn z_score(samples: &VecDeque<Sample>) -> Option<f64> {
+    let values: Vec<f64> = samples
+        .iter()
+        .rev()
+        .take(WINDOW + 1)
+        .filter_map(|sample| match sample {
+            Sample::GaugeSample(s) => Some(s.value),
+            _ => None,
+        })
+        .collect();
+    let (latest, previous) = values.split_first()?;
+    if previous.len() < MIN_SAMPLES {
+        return None;
+    }
+    let n = previous.len() as f64;
+    let mean = previous.iter().sum::<f64>() / n;
+    let stddev = (previous.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n).sqrt();
+    let deviation = (latest - mean).abs();
+    if stddev == 0.0 {
+        // a flat series is anomalous as soon as it moves at all
+        return Some(if deviation == 0.0 { 0.0 } else { f64::INFINITY });
+    }
+    Some(deviation / stddev)
+}
+
+fn is_anomalous(time_series: &TimeSeries) -> bool {
+    z_score(&time_series.samples).is_some_and(|z| z > SIGMAS)
+}
+
+/// Labels of the gauge series whose latest value is an anomaly
+pub fn anomalous_labels(metric: &Metric) -> HashSet<String> {
+    if !matches!(metric.details.metric_type, MetricType::Gauge) {
+        return HashSet::new();
+    }
+    metric
+        .time_series
+        .iter()
+        .filter(|(_, time_series)| is_anomalous(time_series))
+        .map(|(labels, _)| labels.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prom::SingleValueSample;
+
+    use super::*;
+
+    fn gauges(values: &[f64]) -> VecDeque<Sample> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(timestamp, &value)| {
+                Sample::GaugeSample(SingleValueSample {
+                    timestamp: timestamp as u64,
+                    value,
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_z_score() {
+        assert_eq!(z_score(&gauges(&[1.0, 2.0, 3.0])), None);
+        let alternating = [1.0, 3.0, 1.0, 3.0, 1.0, 3.0];
+        assert_eq!(
+            z_score(&gauges(&[&alternating[..], &[4.0]].concat())),
+            Some(2.0)
+        );
+        assert_eq!(
+            z_score(&gauges(&[&alternating[..], &[8.0]].concat())),
+            Some(6.0)
+        );
+        assert_eq!(z_score(&gauges(&[5.0; 6])), Some(0.0));
+        assert_eq!(
+            z_score(&gauges(&[5.0, 5.0, 5.0, 5.0, 5.0, 6.0])),
+            Some(f64::INFINITY)
+        );
+    }
+}
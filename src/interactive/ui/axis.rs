@@ -0,0 +1,100 @@
+/// Bounds of a value axis snapped to 1, 2 or 5 times a power of ten, with
+/// ticks at every `step` between them
+#[derive(Debug, PartialEq)]
+pub struct NiceScale {
+    pub min: f64,
+    pub max: f64,
+    pub step: f64,
+}
+
+impl NiceScale {
+    /// Scale covering `min..=max` with at most about `max_ticks` ticks. A
+    /// flat series gets one unit of headroom on both sides.
+    pub fn new(min: f64, max: f64, max_ticks: usize) -> NiceScale {
+        let (min, max) = if min < max {
+            (min, max)
+        } else {
+            (min - 1.0, max + 1.0)
+        };
+        let intervals = (max_ticks.max(2) - 1) as f64;
+        let mut step = nice_step((max - min) / intervals);
+        // Snapping the bounds outwards can add an interval
+        while (max / step).ceil() - (min / step).floor() > intervals {
+            step = nice_step(step * 1.5);
+        }
+        NiceScale {
+            // Adding 0.0 turns -0.0 into 0.0
+            min: (min / step).floor() * step + 0.0,
+            max: (max / step).ceil() * step + 0.0,
+            step,
+        }
+    }
+
+    pub fn bounds(&self) -> [f64; 2] {
+        [self.min, self.max]
+    }
+
+    /// Values of the ticks from `min` to `max`, evenly spaced as ratatui
+    /// places the axis labels
+    pub fn ticks(&self) -> Vec<f64> {
+        let count = ((self.max - self.min) / self.step).round() as usize;
+        (0..=count)
+            .map(|i| {
+                let tick = self.min + i as f64 * self.step;
+                // Strip the float noise of the multiplication, e.g. 0.30000000000000004
+                let scale = 10f64.powi((-self.step.log10().floor()).max(0.0) as i32);
+                (tick * scale).round() / scale + 0.0
+            })
+            .collect()
+    }
+}
+
+/// Number of y-axis ticks that fit a chart of `height` rows, leaving a blank
+/// row between the labels
+pub fn max_ticks(height: u16) -> usize {
+    (height as usize / 2).clamp(2, 6)
+}
+
+/// Smallest of 1, 2 or 5 times a power of ten that is at least `value`
+fn nice_step(value: f64) -> f64 {
+    let power = 10f64.powf(value.log10().floor());
+    let nice = match value / power {
+        f if f <= 1.0 => 1.0,
+        f if f <= 2.0 => 2.0,
+        f if f <= 5.0 => 5.0,
+        _ => 10.0,
+    };
+    nice * power
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nice_scale() {
+        let scale = NiceScale::new(3.2, 37.258, 5);
+        assert_eq!(scale.bounds(), [0.0, 40.0]);
+        assert_eq!(scale.ticks(), vec![0.0, 10.0, 20.0, 30.0, 40.0]);
+
+        let scale = NiceScale::new(0.12, 0.31, 4);
+        assert_eq!(scale.step, 0.1);
+        assert_eq!(scale.ticks(), vec![0.1, 0.2, 0.3, 0.4]);
+
+        let scale = NiceScale::new(0.12, 0.31, 6);
+        assert_eq!(scale.ticks(), vec![0.1, 0.15, 0.2, 0.25, 0.3, 0.35]);
+
+        let scale = NiceScale::new(-3.0, 1200.0, 3);
+        assert_eq!(scale.ticks(), vec![-2000.0, 0.0, 2000.0]);
+
+        let scale = NiceScale::new(7.0, 7.0, 5);
+        assert_eq!(scale.ticks(), vec![6.0, 6.5, 7.0, 7.5, 8.0]);
+    }
+
+    #[test]
+    fn test_max_ticks() {
+        assert_eq!(max_ticks(3), 2);
+        assert_eq!(max_ticks(9), 4);
+        assert_eq!(max_ticks(40), 6);
+    }
+}
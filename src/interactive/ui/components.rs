@@ -0,0 +1,171 @@
+//! The raw series a histogram or summary is exposed as, its `_bucket` series
+//! or quantiles, `_sum` and `_count`, to check them one by one instead of the
+//! synthesized view
+use std::collections::{HashMap, VecDeque};
+
+use crate::prom::{Metric, MetricDetails, MetricType, Sample, SingleValueSample, TimeSeries};
+
+/// A series per component of every series of a histogram or summary, keyed
+/// by its exposed name, e.g. `latency_seconds_bucket{method="GET",le="0.5"}`.
+/// `None` for other metric types.
+pub fn components(metric: &Metric) -> Option<Metric> {
+    if !matches!(
+        metric.details.metric_type,
+        MetricType::Histogram | MetricType::Summary
+    ) {
+        return None;
+    }
+    let name = &metric.details.name;
+    let mut time_series: HashMap<String, TimeSeries> = HashMap::new();
+    for (labels, series) in &metric.time_series {
+        // series without labels carry a placeholder label key and map
+        let (labels, labels_map) = if labels.contains('=') {
+            (Some(labels.as_str()), series.labels.clone())
+        } else {
+            (None, HashMap::new())
+        };
+        for sample in &series.samples {
+            for (suffix, label, sample) in component_samples(sample) {
+                let mut key_labels: Vec<String> = labels.map(String::from).into_iter().collect();
+                let mut component_labels = labels_map.clone();
+                if let Some((label, value)) = label {
+                    key_labels.push(format!("{}=\"{}\"", label, value));
+                    component_labels.insert(label.to_string(), value);
+                }
+                let key = if key_labels.is_empty() {
+                    format!("{}{}", name, suffix)
+                } else {
+                    format!("{}{}{{{}}}", name, suffix, key_labels.join(","))
+                };
+                time_series
+                    .entry(key)
+                    .or_insert_with(|| TimeSeries {
+                        labels: component_labels,
+                        samples: VecDeque::new(),
+                        missed_scrapes: series.missed_scrapes,
+                    })
+                    .samples
+                    .push_back(sample);
+            }
+        }
+    }
+    Some(Metric {
+        details: MetricDetails {
+            metric_type: MetricType::Counter,
+            ..metric.details.clone()
+        },
+        time_series,
+        trend: metric.trend,
+    })
+}
+
+/// Whether a series of [`components`] holds a number of observations, its
+/// `_bucket` and `_count` series, rather than values in the unit of the metric
+pub fn is_count(name: &str, series: &str) -> bool {
+    ["_bucket", "_count"].iter().any(|suffix| {
+        series
+            .strip_prefix(name)
+            .is_some_and(|rest| rest.starts_with(suffix))
+    })
+}
+
+/// Name suffix, extra label and value of a series a sample is exposed as
+type Component = (&'static str, Option<(&'static str, String)>, Sample);
+
+fn component_samples(sample: &Sample) -> Vec<Component> {
+    let counter = |timestamp, value| Sample::CounterSample(SingleValueSample { timestamp, value });
+    match sample {
+        Sample::HistogramSample(sample) => {
+            let mut samples: Vec<_> = sample
+                .bucket_values
+                .iter()
+                .map(|bucket| {
+                    (
+                        "_bucket",
+                        Some(("le", bucket.name.clone())),
+                        counter(sample.timestamp, bucket.value as f64),
+                    )
+                })
+                .collect();
+            samples.push(("_sum", None, counter(sample.timestamp, sample.sum)));
+            samples.push((
+                "_count",
+                None,
+                counter(sample.timestamp, sample.count as f64),
+            ));
+            samples
+        }
+        Sample::SummarySample(sample) => {
+            let timestamp = sample.time.timestamp() as u64;
+            let mut samples: Vec<_> = sample
+                .quantiles
+                .iter()
+                .map(|quantile| {
+                    (
+                        "",
+                        Some(("quantile", quantile.name.clone())),
+                        Sample::GaugeSample(SingleValueSample {
+                            timestamp,
+                            value: quantile.value,
+                        }),
+                    )
+                })
+                .collect();
+            samples.push(("_sum", None, counter(timestamp, sample.sum)));
+            samples.push(("_count", None, counter(timestamp, sample.count as f64)));
+            samples
+        }
+        Sample::GaugeSample(_) | Sample::CounterSample(_) => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prom::test_data::{memory_store, scrape};
+
+    use super::*;
+
+    #[test]
+    fn test_histogram_components() {
+        let text = "# TYPE latency_seconds histogram
+latency_seconds_bucket{method=\"GET\",le=\"0.5\"} 3
+latency_seconds_bucket{method=\"GET\",le=\"+Inf\"} 4
+latency_seconds_sum{method=\"GET\"} 1.5
+latency_seconds_count{method=\"GET\"} 4
+";
+        let mut history = memory_store();
+        for timestamp in [1, 2] {
+            history.insert_scrape(scrape(text, timestamp));
+        }
+        let metric = history.get_metric("latency_seconds").unwrap();
+        let raw = components(metric).unwrap();
+        assert_eq!(
+            raw.get_labels(),
+            vec![
+                "latency_seconds_bucket{method=\"GET\",le=\"+Inf\"}",
+                "latency_seconds_bucket{method=\"GET\",le=\"0.5\"}",
+                "latency_seconds_count{method=\"GET\"}",
+                "latency_seconds_sum{method=\"GET\"}",
+            ]
+        );
+        let bucket = &raw.time_series["latency_seconds_bucket{method=\"GET\",le=\"0.5\"}"];
+        assert_eq!(bucket.samples.len(), 2);
+        assert_eq!(bucket.labels["le"], "0.5");
+        assert!(matches!(
+            bucket.samples.back(),
+            Some(Sample::CounterSample(sample)) if sample.value == 3.0
+        ));
+        assert!(components(&raw).is_none());
+    }
+
+    #[test]
+    fn test_is_count() {
+        assert!(is_count(
+            "latency_seconds",
+            "latency_seconds_bucket{le=\"0.5\"}"
+        ));
+        assert!(is_count("latency_seconds", "latency_seconds_count"));
+        assert!(!is_count("latency_seconds", "latency_seconds_sum"));
+        assert!(!is_count("latency_seconds", "{method=\"GET\"}"));
+    }
+}
@@ -0,0 +1,76 @@
+use std::collections::VecDeque;
+
+use crate::prom::Sample;
+
+/// Whether a histogram or summary sample carries distribution data worth
+/// drawing, i.e. quantiles or buckets besides `+Inf`
+pub fn has_distribution(sample: &Sample) -> bool {
+    match sample {
+        Sample::HistogramSample(histogram) => histogram.bucket_values.len() >= 2,
+        Sample::SummarySample(summary) => !summary.quantiles.is_empty(),
+        _ => true,
+    }
+}
+
+/// Time, per-second rate of the count and average observed value, i.e.
+/// `rate(x_sum) / rate(x_count)`, between consecutive histogram or summary
+/// samples. Intervals with a counter reset are skipped, and intervals without
+/// new observations have no average.
+pub fn count_rate_and_average(samples: &VecDeque<Sample>) -> Vec<(f64, f64, Option<f64>)> {
+    let totals: Vec<(i64, f64, u64)> = samples
+        .iter()
+        .filter_map(|sample| match sample {
+            Sample::HistogramSample(histogram) => {
+                Some((histogram.timestamp as i64, histogram.sum, histogram.count))
+            }
+            Sample::SummarySample(summary) => {
+                Some((summary.time.timestamp(), summary.sum, summary.count))
+            }
+            _ => None,
+        })
+        .collect();
+    totals
+        .windows(2)
+        .filter_map(|pair| {
+            let ((prev_time, prev_sum, prev_count), (time, sum, count)) = (pair[0], pair[1]);
+            if time <= prev_time || count < prev_count {
+                return None;
+            }
+            let observations = count - prev_count;
+            let rate = observations as f64 / (time - prev_time) as f64;
+            let average = (observations > 0).then(|| (sum - prev_sum) / observations as f64);
+            Some((time as f64, rate, average))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prom::HistogramValueSample;
+
+    fn histogram(timestamp: u64, sum: f64, count: u64) -> Sample {
+        Sample::HistogramSample(HistogramValueSample {
+            timestamp,
+            bucket_values: vec![],
+            sum,
+            count,
+        })
+    }
+
+    #[test]
+    fn test_count_rate_and_average() {
+        let samples = VecDeque::from([
+            histogram(100, 1.0, 10),
+            histogram(110, 4.0, 30),
+            histogram(120, 4.0, 30),
+            // restart
+            histogram(130, 0.5, 2),
+        ]);
+        assert!(!has_distribution(&samples[0]));
+        assert_eq!(
+            count_rate_and_average(&samples),
+            vec![(110.0, 2.0, Some(0.15)), (120.0, 0.0, None)]
+        );
+    }
+}
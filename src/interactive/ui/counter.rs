@@ -0,0 +1,62 @@
+use std::collections::VecDeque;
+
+use crate::prom::Sample;
+
+/// Increase of a counter over the samples since `since`, treating any
+/// decrease as a counter reset, and the seconds covered by those samples
+pub fn counter_increase(samples: &VecDeque<Sample>, since: u64) -> Option<(f64, u64)> {
+    let points: Vec<(u64, f64)> = samples
+        .iter()
+        .filter_map(|sample| match sample {
+            Sample::CounterSample(s) if s.timestamp >= since => Some((s.timestamp, s.value)),
+            _ => None,
+        })
+        .collect();
+    let (first, last) = (points.first()?, points.last()?);
+    if last.0 <= first.0 {
+        return None;
+    }
+    let increase: f64 = points
+        .windows(2)
+        .map(|pair| {
+            let (previous, current) = (pair[0].1, pair[1].1);
+            if current >= previous {
+                current - previous
+            } else {
+                current
+            }
+        })
+        .sum();
+    Some((increase, last.0 - first.0))
+}
+
+/// Per-second increase of a counter over the samples since `since`
+pub fn counter_rate(samples: &VecDeque<Sample>, since: u64) -> Option<f64> {
+    counter_increase(samples, since).map(|(increase, span)| increase / span as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prom::SingleValueSample;
+
+    use super::*;
+
+    fn counter(timestamp: u64, value: f64) -> Sample {
+        Sample::CounterSample(SingleValueSample { timestamp, value })
+    }
+
+    #[test]
+    fn test_counter_rate() {
+        let samples = VecDeque::from([counter(0, 10.0), counter(10, 30.0), counter(20, 50.0)]);
+        assert_eq!(counter_rate(&samples, 0), Some(2.0));
+        assert_eq!(counter_rate(&samples, 10), Some(2.0));
+        assert_eq!(counter_rate(&samples, 20), None);
+    }
+
+    #[test]
+    fn test_counter_increase_with_reset() {
+        let samples = VecDeque::from([counter(0, 100.0), counter(10, 120.0), counter(20, 5.0)]);
+        assert_eq!(counter_increase(&samples, 0), Some((25.0, 20)));
+        assert_eq!(counter_rate(&samples, 0), Some(25.0 / 20.0));
+    }
+}
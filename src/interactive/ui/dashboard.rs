@@ -0,0 +1,290 @@
+use std::borrow::Cow;
+
+use ratatui::{
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    symbols,
+    text::{Line, Span},
+    widgets::{
+        Axis, Bar, BarChart, BarGroup, Block, Borders, Chart, Dataset, GraphType, Paragraph,
+    },
+    Frame,
+};
+
+use crate::interactive::app::{GraphView, ValueMode};
+use crate::interactive::dashboard::{ChartType, Dashboard, Panel};
+use crate::prom::{HistoryStore, Metric, TimeSeries};
+
+use super::{
+    axis::{max_ticks, NiceScale},
+    components::{components, is_count},
+    format_value,
+    graph_data::{series_points, GraphData},
+    i18n::tr,
+    theme,
+    time_format::format_timestamp,
+    units::with_unit,
+};
+
+/// Bars are scaled to this value, as bar charts only take integers
+const BAR_SCALE: f64 = 1000.0;
+
+/// Draw the panels of `dashboard` in a grid filling `area`
+pub fn draw(
+    f: &mut Frame,
+    area: Rect,
+    dashboard: &Dashboard,
+    history: &dyn HistoryStore,
+    graph_view: &GraphView,
+) {
+    if dashboard.panels.is_empty() {
+        let paragraph =
+            Paragraph::new("No panels, add [[dashboard.panels]] with a metric to the config file")
+                .block(Block::default().title("Dashboard").borders(Borders::ALL));
+        f.render_widget(paragraph, area);
+        return;
+    }
+    let columns = dashboard.columns.max(1) as usize;
+    let rows = dashboard.panels.len().div_ceil(columns);
+    let row_areas = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(vec![Constraint::Ratio(1, rows as u32); rows])
+        .split(area);
+    for (panels, row_area) in dashboard.panels.chunks(columns).zip(row_areas.iter()) {
+        let panel_areas = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints(vec![Constraint::Ratio(1, columns as u32); columns])
+            .split(*row_area);
+        for (panel, panel_area) in panels.iter().zip(panel_areas.iter()) {
+            draw_panel(f, *panel_area, panel, history, graph_view);
+        }
+    }
+}
+
+/// The metric of a panel, the raw series of a histogram or summary when
+/// named like `latency_seconds_count`
+fn panel_metric<'a>(name: &str, history: &'a dyn HistoryStore) -> Option<Cow<'a, Metric>> {
+    if let Some(metric) = history.get_metric(name) {
+        return Some(components(metric).map_or(Cow::Borrowed(metric), Cow::Owned));
+    }
+    let base = ["_bucket", "_count", "_sum"]
+        .iter()
+        .find_map(|suffix| name.strip_suffix(suffix))?;
+    let mut metric = components(history.get_metric(base)?)?;
+    metric.time_series.retain(|key, _| {
+        key.strip_prefix(name)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('{'))
+    });
+    Some(Cow::Owned(metric))
+}
+
+fn draw_panel(
+    f: &mut Frame,
+    area: Rect,
+    panel: &Panel,
+    history: &dyn HistoryStore,
+    graph_view: &GraphView,
+) {
+    let mode = if panel.rate {
+        ValueMode::Rate
+    } else {
+        ValueMode::Raw
+    };
+    let mut title = panel.title();
+    if panel.rate {
+        title.push_str(" (rate)");
+    }
+    let block = Block::default().title(title).borders(Borders::ALL);
+    let Some(metric) = panel_metric(&panel.metric, history) else {
+        let paragraph = Paragraph::new(format!("No metric {}", panel.metric)).block(block);
+        f.render_widget(paragraph, area);
+        return;
+    };
+    let mut series: Vec<(&String, &TimeSeries)> = metric
+        .time_series
+        .iter()
+        .filter(|(_, series)| panel.matches(series))
+        .collect();
+    series.sort_by_key(|(key, _)| *key);
+    // the points of every series, in the panel's mode
+    let points: Vec<(&str, Vec<(f64, f64)>)> = series
+        .iter()
+        .map(|(key, series)| (key.as_str(), series_points(&series.samples, mode)))
+        .filter(|(_, points)| !points.is_empty())
+        .collect();
+    if points.is_empty() {
+        let paragraph = Paragraph::new(tr("No matching series yet")).block(block);
+        f.render_widget(paragraph, area);
+        return;
+    }
+    let unit = graph_view.unit(&metric.details, mode).filter(|_| {
+        !series
+            .iter()
+            .any(|(key, _)| is_count(&metric.details.name, key))
+    });
+    match panel.chart {
+        ChartType::Line => draw_line(f, area, block, &points, unit.as_deref(), graph_view),
+        ChartType::Bars => draw_bars(f, area, block, panel, &series, &points, unit.as_deref()),
+        ChartType::Value => draw_value(f, area, block, &points, unit.as_deref()),
+    }
+}
+
+fn draw_line(
+    f: &mut Frame,
+    area: Rect,
+    block: Block,
+    points: &[(&str, Vec<(f64, f64)>)],
+    unit: Option<&str>,
+    graph_view: &GraphView,
+) {
+    let graphs: Vec<(&str, GraphData)> = points
+        .iter()
+        .filter_map(|(key, points)| {
+            let data = GraphData::from_points(points.clone(), None, graph_view.time_window())?;
+            Some((*key, data))
+        })
+        .collect();
+    if graphs.is_empty() {
+        let paragraph = Paragraph::new(tr("Not enough data yet")).block(block);
+        f.render_widget(paragraph, area);
+        return;
+    }
+    let bounds = GraphData::bounds(graphs.iter().map(|(_, data)| data));
+    let scale = NiceScale::new(
+        bounds.y_min,
+        bounds.y_max,
+        max_ticks(area.height.saturating_sub(4)),
+    );
+    let datasets = graphs
+        .iter()
+        .map(|(key, data)| {
+            Dataset::default()
+                .name(key.to_string())
+                .marker(symbols::Marker::Braille)
+                .style(Style::default().fg(theme().series_color(key)))
+                .graph_type(GraphType::Line)
+                .data(&data.data)
+        })
+        .collect();
+    let chart = Chart::new(datasets)
+        .block(block)
+        .x_axis(
+            Axis::default()
+                .labels(vec![
+                    Span::raw(format_timestamp(bounds.x_min as u64)),
+                    Span::raw(format_timestamp(bounds.x_max as u64)),
+                ])
+                .bounds([bounds.x_min, bounds.x_max]),
+        )
+        .y_axis(
+            Axis::default()
+                .labels(
+                    scale
+                        .ticks()
+                        .into_iter()
+                        .map(|tick| Span::raw(with_unit(tick, unit, format_value)))
+                        .collect(),
+                )
+                .bounds(scale.bounds()),
+        );
+    f.render_widget(chart, area);
+}
+
+fn draw_bars(
+    f: &mut Frame,
+    area: Rect,
+    block: Block,
+    panel: &Panel,
+    series: &[(&String, &TimeSeries)],
+    points: &[(&str, Vec<(f64, f64)>)],
+    unit: Option<&str>,
+) {
+    let latest: Vec<f64> = points
+        .iter()
+        .map(|(_, points)| points.last().map_or(0.0, |(_, value)| *value))
+        .collect();
+    let max = latest.iter().copied().fold(0.0, f64::max);
+    let bars: Vec<Bar> = points
+        .iter()
+        .zip(&latest)
+        .map(|((key, _), value)| {
+            let scaled = if max > 0.0 {
+                (value.max(0.0) / max * BAR_SCALE).round() as u64
+            } else {
+                0
+            };
+            Bar::default()
+                .value(scaled)
+                .text_value(with_unit(*value, unit, format_value))
+                .label(bar_label(panel, key, series).into())
+        })
+        .collect();
+    let inner_width = area.width.saturating_sub(2);
+    let bar_width = (inner_width / (bars.len() as u16).max(1))
+        .saturating_sub(1)
+        .clamp(1, 12);
+    let chart = BarChart::default()
+        .block(block)
+        .data(BarGroup::default().bars(&bars))
+        .bar_width(bar_width)
+        .bar_style(Style::default().fg(theme().graph))
+        .value_style(Style::default().fg(Color::Black).bg(theme().graph));
+    f.render_widget(chart, area);
+}
+
+/// The label values of a series left out of the panel's selector, e.g. "GET"
+/// for `{method="GET",code="200"}` in a panel selecting code 200
+fn bar_label(panel: &Panel, key: &str, series: &[(&String, &TimeSeries)]) -> String {
+    let Some((_, series)) = series.iter().find(|(series_key, _)| *series_key == key) else {
+        return key.to_string();
+    };
+    let mut labels: Vec<(&String, &String)> = series
+        .labels
+        .iter()
+        .filter(|(label, _)| !panel.labels.contains_key(*label))
+        .collect();
+    labels.sort();
+    match labels.is_empty() {
+        true => key.to_string(),
+        false => labels
+            .into_iter()
+            .map(|(_, value)| value.as_str())
+            .collect::<Vec<_>>()
+            .join(","),
+    }
+}
+
+fn draw_value(
+    f: &mut Frame,
+    area: Rect,
+    block: Block,
+    points: &[(&str, Vec<(f64, f64)>)],
+    unit: Option<&str>,
+) {
+    let total: f64 = points
+        .iter()
+        .filter_map(|(_, points)| points.last())
+        .map(|(_, value)| value)
+        .sum();
+    let mut lines = vec![Line::from(Span::styled(
+        with_unit(total, unit, format_value),
+        Style::default()
+            .fg(theme().graph)
+            .add_modifier(Modifier::BOLD),
+    ))];
+    if points.len() > 1 {
+        lines.push(Line::from(Span::styled(
+            format!("sum of {} series", points.len()),
+            Style::default().fg(Color::Gray),
+        )));
+    }
+    // vertically centered within the borders
+    let padding = area.height.saturating_sub(2 + lines.len() as u16) / 2;
+    let lines: Vec<Line> = std::iter::repeat_n(Line::from(""), padding as usize)
+        .chain(lines)
+        .collect();
+    let paragraph = Paragraph::new(lines)
+        .block(block)
+        .alignment(Alignment::Center);
+    f.render_widget(paragraph, area);
+}
@@ -0,0 +1,99 @@
+use ratatui::{
+    layout::Rect,
+    style::Style,
+    symbols,
+    text::Span,
+    widgets::{Axis, Block, Borders, Chart, Dataset, GraphType, Paragraph, Wrap},
+    Frame,
+};
+
+use crate::prom::{
+    expr::{self, Expr},
+    HistoryStore,
+};
+
+use super::{
+    axis::{max_ticks, NiceScale},
+    format_value,
+    i18n::tr,
+    theme,
+    time_format::format_timestamp,
+};
+
+/// Draw the series computed by the expression `text`
+pub fn draw(f: &mut Frame, area: Rect, text: &str, expr: &Expr, history: &dyn HistoryStore) {
+    let title = format!("{} (: to edit, Esc to close)", text);
+    let block = Block::default().title(title).borders(Borders::ALL);
+    let series = match expr::evaluate(expr, history) {
+        Ok(series) => series,
+        Err(err) => {
+            let paragraph = Paragraph::new(Span::styled(
+                err.to_string(),
+                Style::default().fg(theme().error),
+            ))
+            .block(block)
+            .wrap(Wrap { trim: true });
+            f.render_widget(paragraph, area);
+            return;
+        }
+    };
+
+    let data: Vec<Vec<(f64, f64)>> = series
+        .iter()
+        .map(|series| {
+            series
+                .points
+                .iter()
+                .map(|(timestamp, value)| (*timestamp as f64, *value))
+                .collect()
+        })
+        .collect();
+    let points = data.iter().flatten();
+    let x_min = points.clone().map(|(t, _)| *t).fold(f64::MAX, f64::min);
+    let x_max = points.clone().map(|(t, _)| *t).fold(f64::MIN, f64::max);
+    let y_min = points.clone().map(|(_, v)| *v).fold(f64::MAX, f64::min);
+    let y_max = points.map(|(_, v)| *v).fold(f64::MIN, f64::max);
+    if x_min >= x_max {
+        let paragraph = Paragraph::new(tr("Not enough data yet")).block(block);
+        f.render_widget(paragraph, area);
+        return;
+    }
+    let scale = NiceScale::new(y_min, y_max, max_ticks(area.height.saturating_sub(4)));
+
+    let datasets = series
+        .iter()
+        .zip(&data)
+        .map(|(series, data)| {
+            let name = series.to_string();
+            let color = theme().series_color(&name);
+            Dataset::default()
+                .name(name)
+                .marker(symbols::Marker::Braille)
+                .style(Style::default().fg(color))
+                .graph_type(GraphType::Line)
+                .data(data)
+        })
+        .collect();
+    let chart = Chart::new(datasets)
+        .block(block)
+        .x_axis(
+            Axis::default()
+                .labels(vec![
+                    Span::raw(format_timestamp(x_min as u64)),
+                    Span::raw(format_timestamp(x_max as u64)),
+                ])
+                .bounds([x_min, x_max]),
+        )
+        .y_axis(
+            Axis::default()
+                .labels(
+                    scale
+                        .ticks()
+                        .into_iter()
+                        .map(|tick| Span::raw(format_value(tick)))
+                        .collect(),
+                )
+                .bounds(scale.bounds()),
+        );
+    f.render_widget(chart, area);
+}
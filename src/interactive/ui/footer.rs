@@ -0,0 +1,43 @@
+use ratatui::{
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::Paragraph,
+    Frame,
+};
+
+use crate::interactive::app::{App, ElementInFocus, Screen};
+use crate::interactive::keys::KeyContext;
+
+use super::{i18n::tr, theme};
+
+/// Single line with the most relevant keys of the focused pane, like htop
+pub fn draw(f: &mut Frame, area: Rect, app: &App) {
+    let context = match (&app.prompt, &app.screen, &app.expression, &app.focus) {
+        (Some(_), _, _, _) => KeyContext::Prompt,
+        (None, Screen::TopMovers | Screen::Payload | Screen::Dashboard | Screen::Targets, _, _)
+        | (None, Screen::Expression, Some(_), _) => KeyContext::Screen,
+        (None, _, _, ElementInFocus::LabelsView) => KeyContext::LabelsList,
+        (None, _, _, ElementInFocus::MetricHeaders) => KeyContext::MetricList,
+    };
+    let spans: Vec<Span> = app
+        .keys
+        .footer_entries(context)
+        .into_iter()
+        .flat_map(|(key, description)| {
+            [
+                Span::styled(
+                    key,
+                    Style::default()
+                        .fg(theme().focus)
+                        .add_modifier(Modifier::BOLD),
+                ),
+                Span::styled(
+                    format!(" {}  ", tr(description)),
+                    Style::default().fg(Color::Gray),
+                ),
+            ]
+        })
+        .collect();
+    f.render_widget(Paragraph::new(Line::from(spans)), area);
+}
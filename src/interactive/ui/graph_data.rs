@@ -1,7 +1,18 @@
+use std::collections::{BTreeMap, HashSet, VecDeque};
+
 use chrono::{DateTime, Local, TimeZone};
 
+use crate::interactive::app::{CompareMode, ValueMode};
 use crate::prom::{Metric, Sample};
 
+/// Mean of several series with their range, see `GraphData::spread`
+pub struct Spread {
+    /// The mean, with the value axis covering the band
+    pub mean: GraphData,
+    /// Timestamp, minimum and maximum of every point of the mean
+    pub band: Vec<(f64, f64, f64)>,
+}
+
 pub struct GraphData {
     pub data: Vec<(f64, f64)>,
     pub first_time: DateTime<Local>,
@@ -13,26 +24,115 @@ pub struct GraphData {
 }
 
 impl GraphData {
-    pub fn parse(metric: &Metric, selected_label: &str) -> Option<Self> {
-        let samples = &metric
+    /// Collect the graph points of the selected series, optionally limited to
+    /// the time window `window` (unix seconds) and to the last `span` seconds.
+    pub fn parse(
+        metric: &Metric,
+        selected_label: &str,
+        window: Option<(f64, f64)>,
+        span: Option<u64>,
+        mode: ValueMode,
+    ) -> Option<Self> {
+        let samples = &metric.time_series.get(selected_label)?.samples;
+        Self::from_points(series_points(samples, mode), window, span)
+    }
+
+    /// Collect the graph points of the sum of all series of `metric`, except
+    /// the `excluded` ones. In rate mode the rates of the series are summed.
+    pub fn aggregate(
+        metric: &Metric,
+        excluded: &HashSet<String>,
+        window: Option<(f64, f64)>,
+        span: Option<u64>,
+        mode: ValueMode,
+    ) -> Option<Self> {
+        let mut sums: BTreeMap<u64, f64> = BTreeMap::new();
+        metric
             .time_series
-            .get(selected_label)
-            .expect("values for selected label")
-            .samples;
-        let data: Vec<(f64, f64)> = samples
             .iter()
-            .map(|entry| {
-                let (timestamp, value) = match entry {
-                    Sample::GaugeSample(single_value) => {
-                        (single_value.timestamp, single_value.value)
-                    }
-                    Sample::CounterSample(single_value) => {
-                        (single_value.timestamp, single_value.value)
-                    }
-                    _ => unimplemented!(),
-                };
-                (timestamp as f64, value)
+            .filter(|(labels, _)| !excluded.contains(*labels))
+            .flat_map(|(_, time_series)| series_points(&time_series.samples, mode))
+            .for_each(|(timestamp, value)| *sums.entry(timestamp as u64).or_default() += value);
+        let data = sums
+            .into_iter()
+            .map(|(timestamp, value)| (timestamp as f64, value))
+            .collect();
+        Self::from_points(data, window, span)
+    }
+
+    /// Collect the graph points of the mean of all series of `metric`, except
+    /// the `excluded` ones, and the band between their minimum and maximum
+    pub fn spread(
+        metric: &Metric,
+        excluded: &HashSet<String>,
+        window: Option<(f64, f64)>,
+        span: Option<u64>,
+        mode: ValueMode,
+    ) -> Option<Spread> {
+        // minimum, maximum, sum and number of the values at every timestamp
+        let mut points: BTreeMap<u64, (f64, f64, f64, usize)> = BTreeMap::new();
+        metric
+            .time_series
+            .iter()
+            .filter(|(labels, _)| !excluded.contains(*labels))
+            .flat_map(|(_, time_series)| series_points(&time_series.samples, mode))
+            .for_each(|(timestamp, value)| {
+                let point = points.entry(timestamp as u64).or_insert((
+                    f64::INFINITY,
+                    f64::NEG_INFINITY,
+                    0.0,
+                    0,
+                ));
+                *point = (
+                    point.0.min(value),
+                    point.1.max(value),
+                    point.2 + value,
+                    point.3 + 1,
+                );
+            });
+        let means = points
+            .iter()
+            .map(|(timestamp, (_, _, sum, count))| (*timestamp as f64, sum / *count as f64))
+            .collect();
+        let mean = Self::from_points(means, window, span)?;
+        let band: Vec<(f64, f64, f64)> = points
+            .into_iter()
+            .map(|(timestamp, (min, max, _, _))| (timestamp as f64, min, max))
+            .filter(|(timestamp, _, _)| *timestamp >= mean.x_min && *timestamp <= mean.x_max)
+            .collect();
+        let y_min = band
+            .iter()
+            .map(|(_, min, _)| *min)
+            .fold(mean.y_min, f64::min);
+        let y_max = band
+            .iter()
+            .map(|(_, _, max)| *max)
+            .fold(mean.y_max, f64::max);
+        Some(Spread {
+            mean: Self {
+                y_min,
+                y_max,
+                ..mean
+            },
+            band,
+        })
+    }
+
+    /// Graph the points within `window`, and within `span` seconds before the
+    /// newest point, so recent activity isn't squashed by a long history
+    pub fn from_points(
+        data: Vec<(f64, f64)>,
+        window: Option<(f64, f64)>,
+        span: Option<u64>,
+    ) -> Option<Self> {
+        let newest = data.last().map_or(0.0, |(timestamp, _)| *timestamp);
+        let data: Vec<(f64, f64)> = data
+            .into_iter()
+            .filter(|(timestamp, _)| match window {
+                Some((from, to)) => *timestamp >= from && *timestamp <= to,
+                None => true,
             })
+            .filter(|(timestamp, _)| span.is_none_or(|span| *timestamp >= newest - span as f64))
             .collect();
         if data.len() < 2 {
             return None;
@@ -63,4 +163,316 @@ impl GraphData {
             y_min,
         })
     }
+
+    /// Axes covering all of the non-empty `series`, without their points
+    pub fn bounds<'a>(series: impl IntoIterator<Item = &'a GraphData>) -> Self {
+        let mut series = series.into_iter();
+        let first = series.next().expect("at least one series");
+        let bounds = Self {
+            data: vec![],
+            first_time: first.first_time,
+            last_time: first.last_time,
+            x_max: first.x_max,
+            x_min: first.x_min,
+            y_max: first.y_max,
+            y_min: first.y_min,
+        };
+        series.fold(bounds, |bounds, data| Self {
+            data: vec![],
+            first_time: bounds.first_time.min(data.first_time),
+            last_time: bounds.last_time.max(data.last_time),
+            x_max: bounds.x_max.max(data.x_max),
+            x_min: bounds.x_min.min(data.x_min),
+            y_max: bounds.y_max.max(data.y_max),
+            y_min: bounds.y_min.min(data.y_min),
+        })
+    }
+
+    /// Extend the time axis to cover the one of `other` as well
+    pub fn with_time_axis_of(self, other: &GraphData) -> Self {
+        Self {
+            first_time: self.first_time.min(other.first_time),
+            last_time: self.last_time.max(other.last_time),
+            x_max: self.x_max.max(other.x_max),
+            x_min: self.x_min.min(other.x_min),
+            ..self
+        }
+    }
+}
+
+/// Point-wise ratio or difference of two series, at the timestamps present in
+/// both. Points dividing by zero are left out.
+pub fn combine_points(
+    points: &[(f64, f64)],
+    other: &[(f64, f64)],
+    mode: CompareMode,
+) -> Vec<(f64, f64)> {
+    let other: BTreeMap<u64, f64> = other
+        .iter()
+        .map(|(timestamp, value)| (*timestamp as u64, *value))
+        .collect();
+    points
+        .iter()
+        .filter_map(|(timestamp, value)| {
+            let other = other.get(&(*timestamp as u64))?;
+            let combined = match mode {
+                CompareMode::Ratio if *other == 0.0 => return None,
+                CompareMode::Ratio => value / other,
+                CompareMode::Difference => value - other,
+                CompareMode::Graphs => *value,
+            };
+            Some((*timestamp, combined))
+        })
+        .collect()
+}
+
+/// Points filling `band` between its minimum and maximum, one per dot of a
+/// `columns` by `rows` grid over the `x` and `y` bounds, so it's drawn as a
+/// shaded area. The band is interpolated between its timestamps.
+pub fn fill_band(
+    band: &[(f64, f64, f64)],
+    x: [f64; 2],
+    y: [f64; 2],
+    columns: u16,
+    rows: u16,
+) -> Vec<(f64, f64)> {
+    let step = |bounds: [f64; 2], steps: u16, index: u16| {
+        bounds[0] + (bounds[1] - bounds[0]) * f64::from(index) / f64::from(steps.max(2) - 1)
+    };
+    let mut points = vec![];
+    for column in 0..columns {
+        let x = step(x, columns, column);
+        let Some((lower, upper)) = band.windows(2).find_map(|pair| {
+            let ((from, from_min, from_max), (to, to_min, to_max)) = (pair[0], pair[1]);
+            if x < from || x > to {
+                return None;
+            }
+            let share = if to > from {
+                (x - from) / (to - from)
+            } else {
+                0.0
+            };
+            Some((
+                from_min + (to_min - from_min) * share,
+                from_max + (to_max - from_max) * share,
+            ))
+        }) else {
+            continue;
+        };
+        points.extend(
+            (0..rows)
+                .map(|row| step(y, rows, row))
+                .filter(|y| *y >= lower && *y <= upper)
+                .map(|y| (x, y)),
+        );
+    }
+    points
+}
+
+/// The (timestamp, value) points of a gauge or counter series
+pub fn series_points(samples: &VecDeque<Sample>, mode: ValueMode) -> Vec<(f64, f64)> {
+    let is_counter = matches!(samples.front(), Some(Sample::CounterSample(_)));
+    let data: Vec<(f64, f64)> = samples
+        .iter()
+        .map(|entry| {
+            let (timestamp, value) = match entry {
+                Sample::GaugeSample(single_value) => (single_value.timestamp, single_value.value),
+                Sample::CounterSample(single_value) => (single_value.timestamp, single_value.value),
+                _ => unimplemented!(),
+            };
+            (timestamp as f64, value)
+        })
+        .collect();
+    match mode {
+        ValueMode::Raw => data,
+        ValueMode::Rate => rate(&data, is_counter),
+    }
+}
+
+/// One graph per quantile of the summary `samples`, in the order of the
+/// latest sample. Quantiles without observations (NaN) are left out.
+pub fn quantile_series(
+    samples: &VecDeque<Sample>,
+    window: Option<(f64, f64)>,
+    span: Option<u64>,
+) -> Vec<(String, GraphData)> {
+    let mut points: BTreeMap<&str, Vec<(f64, f64)>> = BTreeMap::new();
+    for sample in samples {
+        if let Sample::SummarySample(sample) = sample {
+            for quantile in &sample.quantiles {
+                if quantile.value.is_finite() {
+                    points
+                        .entry(&quantile.name)
+                        .or_default()
+                        .push((sample.time.timestamp() as f64, quantile.value));
+                }
+            }
+        }
+    }
+    let names: Vec<&str> = match samples.back() {
+        Some(Sample::SummarySample(sample)) => sample
+            .quantiles
+            .iter()
+            .map(|quantile| quantile.name.as_str())
+            .collect(),
+        _ => vec![],
+    };
+    names
+        .into_iter()
+        .filter_map(|name| {
+            let data = GraphData::from_points(points.remove(name)?, window, span)?;
+            Some((name.to_string(), data))
+        })
+        .collect()
+}
+
+/// Per-second rate of change between consecutive points. For counters a
+/// decrease is a counter reset, so the new value is the increase since the reset.
+fn rate(data: &[(f64, f64)], is_counter: bool) -> Vec<(f64, f64)> {
+    data.windows(2)
+        .filter(|pair| pair[1].0 > pair[0].0)
+        .map(|pair| {
+            let (t0, v0) = pair[0];
+            let (t1, v1) = pair[1];
+            let increase = if is_counter && v1 < v0 { v1 } else { v1 - v0 };
+            (t1, increase / (t1 - t0))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prom::test_data::{gauge, scrape};
+    use crate::prom::Retention;
+
+    use super::*;
+
+    /// Three queue sizes scraped at 10 and 20 seconds
+    fn queue_sizes(first: [f64; 3], second: [f64; 3]) -> Metric {
+        let scrape = |values: [f64; 3], timestamp| {
+            let series = [
+                ("queue=\"a\"", values[0]),
+                ("queue=\"b\"", values[1]),
+                ("queue=\"c\"", values[2]),
+            ];
+            gauge("queue_size", &series, timestamp)
+        };
+        let mut metric = scrape(first, 10).into_metric();
+        metric.update_time_series(scrape(second, 20).value_per_labels, &Retention::default());
+        metric
+    }
+
+    #[test]
+    fn test_combine_points() {
+        let errors = [(10.0, 1.0), (20.0, 2.0), (30.0, 3.0)];
+        let requests = [(20.0, 8.0), (30.0, 0.0), (40.0, 10.0)];
+        assert_eq!(
+            combine_points(&errors, &requests, CompareMode::Ratio),
+            vec![(20.0, 0.25)]
+        );
+        assert_eq!(
+            combine_points(&errors, &requests, CompareMode::Difference),
+            vec![(20.0, -6.0), (30.0, 3.0)]
+        );
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let metric = queue_sizes([1.0, 2.0, 3.0], [2.0, 4.0, 6.0]);
+
+        let all =
+            GraphData::aggregate(&metric, &HashSet::new(), None, None, ValueMode::Raw).unwrap();
+        assert_eq!(all.data, vec![(10.0, 6.0), (20.0, 12.0)]);
+
+        let excluded = HashSet::from(["queue=\"c\"".to_string()]);
+        let some = GraphData::aggregate(&metric, &excluded, None, None, ValueMode::Rate);
+        assert!(some.is_none(), "a single rate point is not a graph");
+        let some = GraphData::aggregate(&metric, &excluded, None, None, ValueMode::Raw).unwrap();
+        assert_eq!(some.data, vec![(10.0, 3.0), (20.0, 6.0)]);
+    }
+
+    #[test]
+    fn test_spread() {
+        let metric = queue_sizes([1.0, 2.0, 6.0], [2.0, 4.0, 9.0]);
+
+        let spread =
+            GraphData::spread(&metric, &HashSet::new(), None, None, ValueMode::Raw).unwrap();
+        assert_eq!(spread.mean.data, vec![(10.0, 3.0), (20.0, 5.0)]);
+        assert_eq!(spread.band, vec![(10.0, 1.0, 6.0), (20.0, 2.0, 9.0)]);
+        assert_eq!((spread.mean.y_min, spread.mean.y_max), (1.0, 9.0));
+
+        let points = fill_band(&spread.band, [10.0, 20.0], [0.0, 10.0], 3, 11);
+        let column = |x: f64| -> Vec<f64> {
+            points
+                .iter()
+                .filter(|(point_x, _)| *point_x == x)
+                .map(|(_, y)| *y)
+                .collect()
+        };
+        assert_eq!(column(10.0), vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        // halfway between the timestamps, from 1.5 to 7.5
+        assert_eq!(column(15.0), vec![2.0, 3.0, 4.0, 5.0, 6.0, 7.0]);
+        assert_eq!(column(20.0).len(), 8);
+    }
+
+    #[test]
+    fn test_from_points_within_span() {
+        let data = vec![(0.0, 1.0), (100.0, 2.0), (250.0, 3.0), (300.0, 4.0)];
+        let graph = GraphData::from_points(data.clone(), None, Some(60)).unwrap();
+        let other = GraphData::from_points(vec![(250.0, -1.0), (400.0, 2.0)], None, None).unwrap();
+        let bounds = GraphData::bounds([&graph, &other]);
+        assert_eq!(
+            (bounds.x_min, bounds.x_max, bounds.y_min, bounds.y_max),
+            (graph.x_min, 400.0, -1.0, graph.y_max.max(2.0))
+        );
+        assert_eq!(graph.data, vec![(250.0, 3.0), (300.0, 4.0)]);
+        assert!(GraphData::from_points(data.clone(), None, Some(10)).is_none());
+        let graph = GraphData::from_points(data, Some((0.0, 250.0)), Some(200)).unwrap();
+        assert_eq!(graph.data, vec![(100.0, 2.0), (250.0, 3.0)]);
+    }
+
+    #[test]
+    fn test_quantile_series() {
+        let latency = |median: &str, p99: &str, timestamp| {
+            let text = format!(
+                "# TYPE latency_seconds summary\n\
+                 latency_seconds{{quantile=\"0.5\"}} {}\n\
+                 latency_seconds{{quantile=\"0.99\"}} {}\n\
+                 latency_seconds_sum 0\n\
+                 latency_seconds_count 0\n",
+                median, p99
+            );
+            scrape(&text, timestamp)
+        };
+        let mut metric = latency("NaN", "NaN", 10).into_metric();
+        metric.update_time_series(
+            latency("1", "4", 20).value_per_labels,
+            &Retention::default(),
+        );
+        metric.update_time_series(
+            latency("2", "8", 30).value_per_labels,
+            &Retention::default(),
+        );
+        let samples = &mut metric.time_series.values_mut().next().unwrap().samples;
+        let series = quantile_series(samples, None, None);
+        let names: Vec<&str> = series.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["0.5", "0.99"]);
+        assert_eq!(series[1].1.data, vec![(20.0, 4.0), (30.0, 8.0)]);
+        samples.truncate(2);
+        assert!(quantile_series(samples, None, None).is_empty());
+    }
+
+    #[test]
+    fn test_rate() {
+        let data = vec![(10.0, 100.0), (20.0, 150.0), (20.0, 150.0), (30.0, 150.0)];
+        assert_eq!(rate(&data, true), vec![(20.0, 5.0), (30.0, 0.0)]);
+        assert!(rate(&data[..1], true).is_empty());
+    }
+
+    #[test]
+    fn test_rate_with_counter_reset() {
+        let data = vec![(10.0, 100.0), (20.0, 150.0), (30.0, 20.0)];
+        assert_eq!(rate(&data, true), vec![(20.0, 5.0), (30.0, 2.0)]);
+        assert_eq!(rate(&data, false), vec![(20.0, 5.0), (30.0, -13.0)]);
+    }
 }
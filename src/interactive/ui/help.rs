@@ -0,0 +1,43 @@
+use ratatui::{
+    layout::{Constraint, Rect},
+    style::{Modifier, Style},
+    widgets::{Block, Borders, Clear, Row, Table},
+    Frame,
+};
+
+use crate::interactive::KeyBindings;
+
+use super::{i18n::tr, theme};
+
+/// Widest description plus key column, including borders
+const WIDTH: u16 = 64;
+const KEY_WIDTH: u16 = 16;
+
+/// Modal overlay listing all keybindings, centered in `area`
+pub fn draw(f: &mut Frame, area: Rect, keys: &KeyBindings) {
+    let entries = keys.help_entries();
+    let width = WIDTH.min(area.width);
+    let height = (entries.len() as u16 + 3).min(area.height);
+    let popup = Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    );
+    let rows = entries
+        .into_iter()
+        .map(|(key, description)| Row::new(vec![key, tr(description).to_string()]));
+    let table = Table::new(rows, [Constraint::Length(KEY_WIDTH), Constraint::Fill(1)])
+        .header(
+            Row::new(vec![tr("Key"), tr("Action")])
+                .style(Style::default().add_modifier(Modifier::BOLD)),
+        )
+        .block(
+            Block::default()
+                .title(tr("Keybindings (any key to close)"))
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(theme().focus)),
+        );
+    f.render_widget(Clear, popup);
+    f.render_widget(table, popup);
+}
@@ -1,9 +1,11 @@
 use chrono::{DateTime, Local, TimeZone};
 
+use crate::interactive::app::BarLabels;
 use crate::prom::{Metric, Sample};
 
 pub struct BucketData {
     bucket: String,
+    label: String,
     value: u64,
     percentage: f64,
     inc_per_bucket: u64,
@@ -18,8 +20,10 @@ impl BucketData {
         inc_per_bucket: u64,
         inc_per_bucket_percentage: f64,
     ) -> Self {
+        let label = format_bucket_label(&bucket);
         Self {
             bucket,
+            label,
             value,
             percentage,
             inc_per_bucket,
@@ -31,6 +35,11 @@ impl BucketData {
         &self.bucket
     }
 
+    /// Short form of the bucket boundary, suitable for bar labels
+    pub fn get_label(&self) -> &String {
+        &self.label
+    }
+
     pub fn get_value(&self) -> u64 {
         self.value
     }
@@ -46,6 +55,14 @@ impl BucketData {
     pub fn get_inc_per_bucket_percentage(&self) -> f64 {
         self.inc_per_bucket_percentage
     }
+
+    /// Text drawn on the bar of this bucket
+    pub fn bar_label(&self, labels: BarLabels) -> String {
+        match labels {
+            BarLabels::Count => self.inc_per_bucket.to_string(),
+            BarLabels::Percent => format!("{:.1}%", self.inc_per_bucket_percentage),
+        }
+    }
 }
 
 pub struct HistogramData {
@@ -57,12 +74,7 @@ pub struct HistogramData {
 
 impl HistogramData {
     pub fn parse(metric: &Metric, selected_label: &str) -> Option<Self> {
-        let last_sample = &metric
-            .time_series
-            .get(selected_label)
-            .expect("values for selected label")
-            .samples
-            .last();
+        let last_sample = &metric.time_series.get(selected_label)?.samples.back();
 
         let mut data = vec![];
         let mut timestamp = 0;
@@ -77,11 +89,14 @@ impl HistogramData {
                 let inc_per_bucket = if index == 0 {
                     bucket.value
                 } else {
-                    bucket.value - histogram.bucket_values[index - 1].value
+                    bucket
+                        .value
+                        .saturating_sub(histogram.bucket_values[index - 1].value)
                 };
-                let percentage = (bucket.value as f64 / histogram.count as f64) * 100.0;
-                let inc_per_bucket_percentage =
-                    (inc_per_bucket as f64) / (histogram.count as f64) * 100.0;
+                // avoid NaN percentages before the first observation
+                let count = histogram.count.max(1) as f64;
+                let percentage = (bucket.value as f64 / count) * 100.0;
+                let inc_per_bucket_percentage = (inc_per_bucket as f64) / count * 100.0;
                 data.push(BucketData::new(
                     bucket.name.clone(),
                     bucket.value,
@@ -109,3 +124,70 @@ impl HistogramData {
         })
     }
 }
+
+const SI_PREFIXES: [(f64, &str); 8] = [
+    (1e12, "T"),
+    (1e9, "G"),
+    (1e6, "M"),
+    (1e3, "k"),
+    (1.0, ""),
+    (1e-3, "m"),
+    (1e-6, "µ"),
+    (1e-9, "n"),
+];
+
+/// Format a bucket boundary (`le` value) with an SI prefix and at most three
+/// significant digits, e.g. `0.00048828125` becomes `488µ`.
+pub fn format_bucket_label(bucket: &str) -> String {
+    let Ok(value) = bucket.parse::<f64>() else {
+        return bucket.to_string();
+    };
+    if value == 0.0 || !value.is_finite() {
+        return bucket.to_string();
+    }
+    let (scale, prefix) = SI_PREFIXES
+        .iter()
+        .find(|(scale, _)| value.abs() >= *scale)
+        .unwrap_or(&SI_PREFIXES[SI_PREFIXES.len() - 1]);
+    let scaled = value / scale;
+    let decimals = if scaled.abs() >= 100.0 {
+        0
+    } else if scaled.abs() >= 10.0 {
+        1
+    } else {
+        2
+    };
+    let formatted = format!("{:.1$}", scaled, decimals);
+    let trimmed = if formatted.contains('.') {
+        formatted.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        &formatted
+    };
+    format!("{}{}", trimmed, prefix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_bucket_label() {
+        assert_eq!(format_bucket_label("0.00048828125"), "488µ");
+        assert_eq!(format_bucket_label("0.005"), "5m");
+        assert_eq!(format_bucket_label("0.025"), "25m");
+        assert_eq!(format_bucket_label("0.25"), "250m");
+        assert_eq!(format_bucket_label("2.5"), "2.5");
+        assert_eq!(format_bucket_label("10"), "10");
+        assert_eq!(format_bucket_label("1500"), "1.5k");
+        assert_eq!(format_bucket_label("1048576"), "1.05M");
+        assert_eq!(format_bucket_label("0"), "0");
+        assert_eq!(format_bucket_label("+Inf"), "+Inf");
+    }
+
+    #[test]
+    fn test_bar_label() {
+        let bucket = BucketData::new("0.5".to_string(), 40, 80.0, 15, 30.0);
+        assert_eq!(bucket.bar_label(BarLabels::Count), "15");
+        assert_eq!(bucket.bar_label(BarLabels::Percent), "30.0%");
+    }
+}
@@ -1,145 +1,775 @@
+use std::collections::VecDeque;
+
 use log::error;
 use ratatui::{
-    layout::{Constraint, Layout, Rect},
+    layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     symbols,
     text::Span,
-    widgets::{Axis, BarChart, Block, Borders, Chart, Dataset, GraphType, Row, Table, TableState},
+    widgets::{
+        Axis, Bar, BarChart, BarGroup, Block, Borders, Cell, Chart, Dataset, GraphType, Paragraph,
+        Row, Table, TableState,
+    },
     Frame,
 };
 
+use crate::interactive::app::{
+    series_name, BarOrientation, CompareMode, GraphView, ValueMode, INCREASE_WINDOWS,
+};
 use crate::prom::{Metric, MetricType, Sample, SummaryValueSample};
-use chrono::prelude::*;
 
-use super::{format_value, graph_data::GraphData, histogram_data::HistogramData};
+use super::{
+    axis::{max_ticks, NiceScale},
+    components::is_count,
+    count_data::{count_rate_and_average, has_distribution},
+    counter::counter_increase,
+    draw_no_data, format_value,
+    graph_data::{combine_points, fill_band, quantile_series, series_points, GraphData},
+    histogram_data::HistogramData,
+    i18n::tr,
+    theme,
+    time_format::{format_time, format_timestamp, time_width},
+    units::{humanize, with_unit},
+};
+
+/// Narrowest bar for which the histogram is drawn as a bar chart
+const MIN_BAR_WIDTH: u16 = 3;
+
+/// Columns between the bars of the histogram
+const BAR_GAP: u16 = 1;
+
+/// Width of the pane of the counter increases next to the graph
+const INCREASE_WIDTH: u16 = 20;
 
+#[allow(clippy::too_many_arguments)]
 pub fn draw(
     f: &mut Frame,
     chunk_right: Rect,
     chunk_left: Rect,
     metric: &Metric,
     selected_label: &str,
+    visible_labels: &[String],
+    compared: Option<&Metric>,
+    graph_view: &mut GraphView,
 ) {
+    let name = &metric.details.name;
+    let Some(series) = metric.time_series.get(selected_label) else {
+        draw_no_data(f, chunk_right, tr("Graph"));
+        draw_no_data(f, chunk_left, tr("History"));
+        return;
+    };
+    let samples = &series.samples;
     match metric.details.metric_type {
-        MetricType::Histogram => {
-            if let Some(histogram_data) = HistogramData::parse(metric, selected_label) {
-                draw_histogram_table(f, chunk_left, &histogram_data);
-                draw_histogram(f, chunk_right, &histogram_data);
-            }
+        // only _sum and _count, e.g. summaries without quantiles
+        MetricType::Histogram | MetricType::Summary
+            if samples
+                .back()
+                .is_some_and(|sample| !has_distribution(sample)) =>
+        {
+            let unit = graph_view.unit(&metric.details, ValueMode::Raw);
+            draw_count_only(
+                f,
+                chunk_right,
+                chunk_left,
+                samples,
+                graph_view,
+                unit.as_deref(),
+            );
         }
+        MetricType::Histogram => match HistogramData::parse(metric, selected_label) {
+            Some(histogram_data) if !histogram_data.data.is_empty() => {
+                let unit = graph_view.unit(&metric.details, ValueMode::Raw);
+                draw_histogram_table(f, chunk_left, &histogram_data, unit.as_deref());
+                draw_histogram(f, chunk_right, &histogram_data, graph_view);
+            }
+            _ => {
+                draw_no_data(f, chunk_right, tr("Histogram"));
+                draw_no_data(f, chunk_left, tr("Histogram Details"));
+            }
+        },
         MetricType::Summary => {
-            if let Some(Sample::SummarySample(summary_sample)) = &metric
-                .time_series
-                .get(selected_label)
-                .expect("values for selected label")
-                .samples
-                .last()
-            {
-                draw_summary_table(f, chunk_left, summary_sample);
-                draw_summary(f, chunk_right, summary_sample);
+            if let Some(Sample::SummarySample(summary_sample)) = samples.back() {
+                let unit = graph_view.unit(&metric.details, ValueMode::Raw);
+                draw_summary_table(f, chunk_left, summary_sample, unit.as_deref());
+                if graph_view.quantiles_over_time {
+                    let title = with_window("Quantiles".to_string(), graph_view);
+                    let series =
+                        quantile_series(samples, graph_view.zoom, graph_view.time_window());
+                    if series.is_empty() {
+                        graph_view.plot_area = None;
+                        draw_empty_graph(f, chunk_right, title);
+                    } else {
+                        draw_graph(
+                            f,
+                            chunk_right,
+                            &series,
+                            graph_view,
+                            title,
+                            unit.as_deref(),
+                            None,
+                            None,
+                            None,
+                        );
+                    }
+                } else {
+                    draw_summary(f, chunk_right, summary_sample, graph_view.bar_orientation);
+                }
+            } else {
+                draw_no_data(f, chunk_right, tr("Summary"));
+                draw_no_data(f, chunk_left, tr("Summary Details"));
             }
         }
+        _ if samples.is_empty() => {
+            draw_no_data(f, chunk_right, tr("Graph"));
+            draw_no_data(f, chunk_left, tr("History"));
+        }
         _ => {
-            if let Some(graph_data) = GraphData::parse(metric, selected_label) {
-                draw_graph(f, chunk_right, &graph_data);
+            let mode = graph_view.value_mode(name);
+            let unit = series_unit(metric, selected_label, graph_view, mode);
+            // thresholds apply to the values of the series, not their rate or sum
+            let threshold = graph_view
+                .thresholds
+                .get(name)
+                .copied()
+                .filter(|_| mode == ValueMode::Raw && !graph_view.aggregate);
+            let mut title = graph_title(graph_view, mode);
+            let plotted = plotted_series(metric, selected_label, visible_labels, graph_view);
+            // the range of the aggregated series, around their mean
+            let mut band = None;
+            // the increase of a single counter series, shown next to the graph
+            let mut increases = None;
+            let graph_data = if graph_view.aggregate {
+                // series hidden by the labels filter are left out as well
+                let mut excluded = graph_view.excluded_series(&metric.details.name);
+                excluded.extend(
+                    metric
+                        .time_series
+                        .keys()
+                        .filter(|labels| !visible_labels.contains(labels))
+                        .cloned(),
+                );
+                let included = metric
+                    .time_series
+                    .keys()
+                    .filter(|labels| !excluded.contains(*labels))
+                    .count();
+                if graph_view.spread {
+                    title = format!(
+                        "{} - mean and range of {}/{} series",
+                        title,
+                        included,
+                        metric.time_series.len()
+                    );
+                    GraphData::spread(
+                        metric,
+                        &excluded,
+                        graph_view.zoom,
+                        graph_view.time_window(),
+                        mode,
+                    )
+                    .map(|spread| {
+                        band = Some(spread.band);
+                        vec![(String::new(), spread.mean)]
+                    })
+                } else {
+                    title = format!(
+                        "{} - sum of {}/{} series",
+                        title,
+                        included,
+                        metric.time_series.len()
+                    );
+                    GraphData::aggregate(
+                        metric,
+                        &excluded,
+                        graph_view.zoom,
+                        graph_view.time_window(),
+                        mode,
+                    )
+                    .map(|data| vec![(String::new(), data)])
+                }
+            } else if plotted.len() > 1 {
+                title = format!("{} - {} series", title, plotted.len());
+                let series: Vec<(String, GraphData)> = plotted
+                    .into_iter()
+                    .filter_map(|labels| {
+                        let data = GraphData::parse(
+                            metric,
+                            &labels,
+                            graph_view.zoom,
+                            graph_view.time_window(),
+                            mode,
+                        )?;
+                        Some((labels, data))
+                    })
+                    .collect();
+                (!series.is_empty()).then_some(series)
             } else {
-                draw_empty_graph(f, chunk_right);
+                increases = counter_increases(metric, selected_label, graph_view);
+                GraphData::parse(
+                    metric,
+                    selected_label,
+                    graph_view.zoom,
+                    graph_view.time_window(),
+                    mode,
+                )
+                .map(|data| vec![(String::new(), data)])
+            };
+            let main_bounds = graph_data
+                .as_ref()
+                .map(|series| GraphData::bounds(series.iter().map(|(_, data)| data)));
+            let chunk_right = match increases {
+                Some(increases) => {
+                    let chunks = Layout::default()
+                        .direction(Direction::Horizontal)
+                        .constraints([Constraint::Min(0), Constraint::Length(INCREASE_WIDTH)])
+                        .split(chunk_right);
+                    draw_increases(f, chunks[1], &increases, graph_view.increase_window());
+                    chunks[0]
+                }
+                None => chunk_right,
+            };
+            let compared = compared
+                .and_then(|compared| compared_graph(metric, selected_label, compared, graph_view));
+            let (chunk_right, compared_data) = match compared {
+                Some((compared_data, compared_title, compared_unit)) => {
+                    let chunks = Layout::default()
+                        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+                        .split(chunk_right);
+                    title = format!("{} - {}", series_name(name, selected_label), title);
+                    // drawn first, so the plot area used for mouse zooming is the main graph
+                    let compared_data = compared_data.map(|data| vec![(String::new(), data)]);
+                    match &compared_data {
+                        Some(compared_data) => draw_graph(
+                            f,
+                            chunks[1],
+                            compared_data,
+                            graph_view,
+                            compared_title,
+                            compared_unit.as_deref(),
+                            main_bounds.as_ref(),
+                            None,
+                            None,
+                        ),
+                        None => draw_empty_graph(f, chunks[1], compared_title),
+                    }
+                    (chunks[0], compared_data)
+                }
+                None => (chunk_right, None),
+            };
+            if let Some(graph_data) = graph_data {
+                draw_graph(
+                    f,
+                    chunk_right,
+                    &graph_data,
+                    graph_view,
+                    title,
+                    unit.as_deref(),
+                    compared_data.as_ref().map(|series| &series[0].1),
+                    threshold,
+                    band.as_deref(),
+                );
+            } else {
+                graph_view.plot_area = None;
+                draw_empty_graph(f, chunk_right, title);
             }
-            draw_table(f, chunk_left, metric, selected_label);
+            graph_view.table_area = Some(chunk_left);
+            draw_table(
+                f,
+                chunk_left,
+                metric,
+                selected_label,
+                mode,
+                unit.as_deref(),
+                &mut graph_view.history_scroll,
+            );
         }
     }
 }
 
 #[allow(clippy::cast_precision_loss)]
-fn draw_table(f: &mut Frame, area: Rect, metric: &Metric, selected_label: &str) {
-    let samples = &metric
-        .time_series
-        .get(selected_label)
-        .expect("values for selected label")
-        .samples;
-    let title = format!("History ({})", samples.len());
-
-    let rows = samples.iter().map(|entry| {
-        let (timestamp, value) = match entry {
-            Sample::GaugeSample(single_value) => (single_value.timestamp, single_value.value),
-            Sample::CounterSample(single_value) => (single_value.timestamp, single_value.value),
-            _ => {
-                error!("History table is not implemented for this kind of sample.");
-                unimplemented!();
-            }
-        };
-        let time = Local
-            .timestamp_opt(timestamp as i64, 0)
-            .unwrap()
-            .to_rfc2822();
-        Row::new(vec![time, format_value(value)])
+fn draw_table(
+    f: &mut Frame,
+    area: Rect,
+    metric: &Metric,
+    selected_label: &str,
+    mode: ValueMode,
+    unit: Option<&str>,
+    scroll: &mut usize,
+) {
+    let Some(series) = metric.time_series.get(selected_label) else {
+        return;
+    };
+    let samples = &series.samples;
+    if !samples
+        .iter()
+        .all(|s| matches!(s, Sample::GaugeSample(_) | Sample::CounterSample(_)))
+    {
+        error!("History table is not implemented for this kind of sample.");
+        unimplemented!();
+    }
+    let points = series_points(samples, mode);
+    let title = format!("{} ({})", tr("History"), points.len());
+    let value_header = match mode {
+        ValueMode::Raw => tr("Value"),
+        ValueMode::Rate => tr("Rate/s"),
+    };
+
+    let rows = points.iter().map(|(timestamp, value)| {
+        Row::new(vec![
+            format_timestamp(*timestamp as u64),
+            with_unit(*value, unit, format_value),
+        ])
     });
 
     let t = Table::new(
         rows,
         &[
-            Constraint::Length(50),
-            Constraint::Length(15),
+            Constraint::Length(time_width() + 1),
+            Constraint::Length(20),
             Constraint::Percentage(100),
         ],
     )
     .block(Block::default().borders(Borders::ALL).title(title))
-    .header(Row::new(vec!["Time", "Value"]).style(Style::default().add_modifier(Modifier::BOLD)))
+    .header(
+        Row::new(vec![tr("Time"), value_header])
+            .style(Style::default().add_modifier(Modifier::BOLD)),
+    )
     .highlight_style(Style::default().add_modifier(Modifier::BOLD));
 
     let mut state = TableState::default();
-    state.select(Some(samples.len() - 1));
+    if let Some(newest) = points.len().checked_sub(1) {
+        *scroll = (*scroll).min(newest);
+        state.select(Some(newest - *scroll));
+    }
 
     f.render_stateful_widget(t, area, &mut state);
 }
 
-fn draw_graph(f: &mut Frame, area: Rect, points: &GraphData) {
-    let datasets = vec![Dataset::default()
-        .marker(symbols::Marker::Braille)
-        .style(Style::default().fg(Color::LightGreen))
-        .graph_type(GraphType::Line)
-        .data(&points.data)];
+/// The series plotted on the graph of a gauge or counter, see
+/// `GraphView::plotted_series`. Only the selected one for other metric types.
+pub fn plotted_series(
+    metric: &Metric,
+    selected_label: &str,
+    visible_labels: &[String],
+    graph_view: &GraphView,
+) -> Vec<String> {
+    match metric.details.metric_type {
+        MetricType::Histogram | MetricType::Summary => vec![selected_label.to_string()],
+        _ => graph_view.plotted_series(&metric.details.name, selected_label, visible_labels),
+    }
+}
+
+/// Graph data, title and unit of the series compared with the selected one,
+/// if it's a gauge or counter that still exists. Depending on the compare
+/// mode it's the compared series itself, or its ratio or difference with the
+/// selected series.
+fn compared_graph(
+    metric: &Metric,
+    selected_label: &str,
+    compared: &Metric,
+    graph_view: &GraphView,
+) -> Option<(Option<GraphData>, String, Option<String>)> {
+    let (_, labels) = graph_view.compare.as_ref()?;
+    if matches!(
+        compared.details.metric_type,
+        MetricType::Histogram | MetricType::Summary
+    ) || !compared.time_series.contains_key(labels)
+    {
+        return None;
+    }
+    let name = &compared.details.name;
+    let mode = graph_view.value_mode(name);
+    let operator = match graph_view.compare_mode {
+        CompareMode::Graphs => {
+            let data = GraphData::parse(
+                compared,
+                labels,
+                graph_view.zoom,
+                graph_view.time_window(),
+                mode,
+            );
+            let title = format!(
+                "{} - {}",
+                series_name(name, labels),
+                graph_title(graph_view, mode)
+            );
+            return Some((data, title, graph_view.unit(&compared.details, mode)));
+        }
+        CompareMode::Ratio => "/",
+        CompareMode::Difference => "-",
+    };
+    let selected_mode = graph_view.value_mode(&metric.details.name);
+    let points = combine_points(
+        &series_points(&metric.time_series[selected_label].samples, selected_mode),
+        &series_points(&compared.time_series[labels].samples, mode),
+        graph_view.compare_mode,
+    );
+    let data = GraphData::from_points(points, graph_view.zoom, graph_view.time_window());
+    let title = with_window(
+        format!(
+            "{} {} {}",
+            series_name(&metric.details.name, selected_label),
+            operator,
+            series_name(name, labels)
+        ),
+        graph_view,
+    );
+    let unit = match graph_view.compare_mode {
+        CompareMode::Difference => graph_view.unit(&compared.details, mode),
+        _ => None,
+    };
+    Some((data, title, unit))
+}
 
-    let mut five_percent_span = (points.y_max - points.y_min) * 0.05;
-    if five_percent_span == 0.0 {
-        five_percent_span = 1.0;
+/// Draw the series on one chart, with a legend of their labels if there are
+/// several of them. The time axis covers `synced` as well, to line it up with
+/// the graph it is compared with. With a `threshold`, it's drawn as a line and
+/// series ending above it are drawn in the error color.
+#[allow(clippy::too_many_arguments)]
+fn draw_graph(
+    f: &mut Frame,
+    area: Rect,
+    series: &[(String, GraphData)],
+    graph_view: &mut GraphView,
+    title: String,
+    unit: Option<&str>,
+    synced: Option<&GraphData>,
+    threshold: Option<f64>,
+    band: Option<&[(f64, f64, f64)]>,
+) {
+    let mut points = GraphData::bounds(series.iter().map(|(_, data)| data));
+    if let Some(synced) = synced {
+        points = points.with_time_axis_of(synced);
     }
-    let y_min_axis = points.y_min - five_percent_span;
-    let y_max_axis = points.y_max + five_percent_span;
+    // The borders and the time labels take four rows
+    let scale = NiceScale::new(
+        points.y_min,
+        points.y_max,
+        max_ticks(area.height.saturating_sub(4)),
+    );
+
+    let x_labels = vec![
+        Span::raw(format_time(points.first_time)),
+        Span::raw(format_time(points.last_time)),
+    ];
+    let y_labels: Vec<Span> = scale
+        .ticks()
+        .into_iter()
+        .map(|tick| Span::raw(tick_label(tick, unit)))
+        .collect();
 
+    let block = Block::default().title(title).borders(Borders::ALL);
+    graph_view.plot_area = Some(plot_area(block.inner(area), &x_labels, &y_labels));
+    graph_view.x_bounds = [points.x_min, points.x_max];
+
+    // drawn first, under the lines
+    let band_points = band
+        .map(|band| {
+            let plot = graph_view.plot_area.unwrap_or_default();
+            fill_band(
+                band,
+                graph_view.x_bounds,
+                scale.bounds(),
+                // twice the braille resolution, so no dot column is skipped
+                plot.width * 4,
+                plot.height * 8,
+            )
+        })
+        .unwrap_or_default();
+    let threshold_line: Vec<(f64, f64)> = threshold
+        .map(|threshold| vec![(points.x_min, threshold), (points.x_max, threshold)])
+        .unwrap_or_default();
+    let mut datasets: Vec<Dataset> = vec![];
+    if !band_points.is_empty() {
+        datasets.push(
+            Dataset::default()
+                .marker(symbols::Marker::Braille)
+                .style(Style::default().fg(Color::DarkGray))
+                .graph_type(GraphType::Scatter)
+                .data(&band_points),
+        );
+    }
+    datasets.extend(series.iter().map(|(labels, data)| {
+        let above_threshold = threshold
+            .zip(data.data.last())
+            .is_some_and(|(threshold, (_, value))| *value > threshold);
+        let color = if above_threshold {
+            theme().error
+        } else if series.len() > 1 {
+            theme().series_color(labels)
+        } else {
+            theme().graph
+        };
+        let dataset = Dataset::default()
+            .marker(symbols::Marker::Braille)
+            .style(Style::default().fg(color))
+            .graph_type(GraphType::Line)
+            .data(&data.data);
+        if series.len() > 1 {
+            dataset.name(labels.clone())
+        } else {
+            dataset
+        }
+    }));
+    if !threshold_line.is_empty() {
+        datasets.push(
+            Dataset::default()
+                .marker(symbols::Marker::Dot)
+                .style(Style::default().fg(theme().error))
+                .graph_type(GraphType::Line)
+                .data(&threshold_line),
+        );
+    }
+
+    let mut y_axis = Axis::default().labels(y_labels).bounds(scale.bounds());
+    if let Some(unit) = unit {
+        y_axis = y_axis.title(unit.to_string());
+    }
     let chart = Chart::new(datasets)
-        .block(Block::default().title("Graph").borders(Borders::ALL))
+        .block(block)
         .x_axis(
             Axis::default()
-                .labels(vec![
-                    Span::raw(points.first_time.format("%H:%M:%S").to_string()),
-                    Span::raw(points.last_time.format("%H:%M:%S").to_string()),
-                ])
+                .labels(x_labels)
                 .bounds([points.x_min, points.x_max]),
         )
-        .y_axis(
-            Axis::default()
-                .labels(vec![
-                    Span::raw(format_value(y_min_axis)),
-                    Span::raw(format_value(y_max_axis)),
-                ])
-                .bounds([
-                    points.y_min - five_percent_span,
-                    points.y_max + five_percent_span,
-                ]),
-        );
+        .y_axis(y_axis)
+        .hidden_legend_constraints((Constraint::Ratio(1, 2), Constraint::Ratio(1, 2)));
     f.render_widget(chart, area);
+
+    // Highlight the columns of an ongoing mouse selection
+    if let (Some((start, current)), Some(plot)) = (graph_view.drag, graph_view.plot_area) {
+        let from = start.min(current).max(plot.left());
+        let to = start.max(current).min(plot.right().saturating_sub(1));
+        let buffer = f.buffer_mut();
+        for x in from..=to {
+            for y in plot.top()..plot.bottom() {
+                buffer.get_mut(x, y).set_bg(Color::DarkGray);
+            }
+        }
+    }
+}
+
+/// The area the chart plots its data in, i.e. `inner` minus the axes and
+/// their labels. Mirrors the layout done by ratatui's `Chart`.
+fn plot_area(inner: Rect, x_labels: &[Span], y_labels: &[Span]) -> Rect {
+    let y_labels_width = y_labels.iter().map(Span::width).max().unwrap_or_default() as u16;
+    let first_x_label_width = x_labels.first().map(Span::width).unwrap_or_default() as u16;
+    let left = inner.left() + y_labels_width.max(first_x_label_width.saturating_sub(1)) + 1;
+    // x labels and x axis take the two bottom rows
+    let height = inner.height.saturating_sub(2);
+    Rect::new(
+        left.min(inner.right()),
+        inner.top(),
+        inner.right().saturating_sub(left),
+        height,
+    )
+}
+
+fn graph_title(graph_view: &GraphView, mode: ValueMode) -> String {
+    let title = match mode {
+        ValueMode::Raw => tr("Graph").to_string(),
+        ValueMode::Rate => tr("Graph (rate/s)").to_string(),
+    };
+    with_window(title, graph_view)
 }
 
-fn draw_empty_graph(f: &mut Frame, area: Rect) {
+/// Append the time window and zoom state to a graph title
+fn with_window(mut title: String, graph_view: &GraphView) -> String {
+    if let Some(window) = graph_view.time_window() {
+        title.push_str(&format!(" last {}", window_label(window)));
+    }
+    if graph_view.zoom.is_some() {
+        title.push_str(" [zoomed, Esc to reset]");
+    }
+    title
+}
+
+/// "30s", "5m" or "1h"
+fn window_label(seconds: u64) -> String {
+    match seconds {
+        s if s % 3600 == 0 => format!("{}h", s / 3600),
+        s if s % 60 == 0 => format!("{}m", s / 60),
+        s => format!("{}s", s),
+    }
+}
+
+/// Unit of the values of a series, none for the observation counts of the raw
+/// series of a histogram
+fn series_unit(
+    metric: &Metric,
+    selected_label: &str,
+    graph_view: &GraphView,
+    mode: ValueMode,
+) -> Option<String> {
+    graph_view
+        .unit(&metric.details, mode)
+        .filter(|_| !is_count(&metric.details.name, selected_label))
+}
+
+/// Increase of a counter series over every window of `INCREASE_WINDOWS`,
+/// like Prometheus' `increase()`
+fn counter_increases(
+    metric: &Metric,
+    selected_label: &str,
+    graph_view: &GraphView,
+) -> Option<Vec<(u64, String)>> {
+    if !matches!(metric.details.metric_type, MetricType::Counter) {
+        return None;
+    }
+    let samples = &metric.time_series.get(selected_label)?.samples;
+    let latest = match samples.back()? {
+        Sample::CounterSample(sample) => sample.timestamp,
+        _ => return None,
+    };
+    let unit = series_unit(metric, selected_label, graph_view, ValueMode::Raw);
+    let increases = INCREASE_WINDOWS
+        .iter()
+        .map(|window| {
+            let increase = counter_increase(samples, latest.saturating_sub(*window)).map_or_else(
+                || "-".to_string(),
+                |(increase, _)| with_unit(increase, unit.as_deref(), format_value),
+            );
+            (*window, increase)
+        })
+        .collect();
+    Some(increases)
+}
+
+/// The counter increases, with the one of the `selected` window highlighted
+fn draw_increases(f: &mut Frame, area: Rect, increases: &[(u64, String)], selected: u64) {
+    let rows = increases.iter().map(|(window, increase)| {
+        let row = Row::new(vec![window_label(*window), increase.clone()]);
+        if *window == selected {
+            row.style(
+                Style::default()
+                    .fg(theme().focus)
+                    .add_modifier(Modifier::BOLD),
+            )
+        } else {
+            row
+        }
+    });
+    let table = Table::new(rows, [Constraint::Length(4), Constraint::Min(0)])
+        .block(Block::default().title(tr("Increase")).borders(Borders::ALL));
+    f.render_widget(table, area);
+}
+
+/// Count rate and average value of histograms and summaries without buckets
+/// or quantiles
+fn draw_count_only(
+    f: &mut Frame,
+    chunk_right: Rect,
+    chunk_left: Rect,
+    samples: &VecDeque<Sample>,
+    graph_view: &mut GraphView,
+    unit: Option<&str>,
+) {
+    let points = count_rate_and_average(samples);
+    let chunks = Layout::default()
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+        .split(chunk_right);
+    let rates = points
+        .iter()
+        .map(|(time, rate, _)| (*time, *rate))
+        .collect();
+    let averages = points
+        .iter()
+        .filter_map(|(time, _, average)| Some((*time, (*average)?)))
+        .collect();
+    let graphs = [
+        (
+            chunks[0],
+            rates,
+            with_window("Count (rate/s)".to_string(), graph_view),
+            None,
+        ),
+        (
+            chunks[1],
+            averages,
+            with_window("Average".to_string(), graph_view),
+            unit,
+        ),
+    ];
+    for (area, data, title, unit) in graphs {
+        match GraphData::from_points(data, graph_view.zoom, graph_view.time_window()) {
+            Some(graph_data) => draw_graph(
+                f,
+                area,
+                &[(String::new(), graph_data)],
+                graph_view,
+                title,
+                unit,
+                None,
+                None,
+                None,
+            ),
+            None => draw_empty_graph(f, area, title),
+        }
+    }
+
+    let rows = points.iter().map(|(time, rate, average)| {
+        Row::new(vec![
+            format_timestamp(*time as u64),
+            format_value(*rate),
+            average.map_or_else(
+                || "-".to_string(),
+                |average| with_unit(average, unit, format_value),
+            ),
+        ])
+    });
+    let t = Table::new(
+        rows,
+        &[
+            Constraint::Length(time_width() + 1),
+            Constraint::Length(12),
+            Constraint::Percentage(100),
+        ],
+    )
+    .block(Block::default().borders(Borders::ALL).title(format!(
+        "{} ({})",
+        tr("History"),
+        points.len()
+    )))
+    .header(
+        Row::new(vec![tr("Time"), tr("Count/s"), tr("Average")])
+            .style(Style::default().add_modifier(Modifier::BOLD)),
+    );
+    let mut state = TableState::default();
+    state.select(points.len().checked_sub(1));
+    f.render_stateful_widget(t, chunk_left, &mut state);
+}
+
+/// Axis label of `tick`, scaled to a readable size for a known unit
+fn tick_label(tick: f64, unit: Option<&str>) -> String {
+    unit.and_then(|unit| humanize(tick, unit))
+        .unwrap_or_else(|| format_value(tick))
+}
+
+/// Upper bound of a bucket, scaled to a readable size for a known unit
+fn bucket_label(bucket: &str, unit: Option<&str>) -> String {
+    bucket
+        .parse::<f64>()
+        .ok()
+        .filter(|bound| bound.is_finite())
+        .zip(unit)
+        .and_then(|(bound, unit)| humanize(bound, unit))
+        .unwrap_or_else(|| bucket.to_string())
+}
+
+fn draw_empty_graph(f: &mut Frame, area: Rect, title: String) {
     let chart = Chart::new(vec![])
-        .block(Block::default().title("Graph").borders(Borders::ALL))
+        .block(Block::default().title(title).borders(Borders::ALL))
         .x_axis(Axis::default())
         .y_axis(Axis::default());
     f.render_widget(chart, area);
 }
 
-fn draw_histogram_table(f: &mut Frame, area: Rect, histogram_data: &HistogramData) {
+fn draw_histogram_table(
+    f: &mut Frame,
+    area: Rect,
+    histogram_data: &HistogramData,
+    unit: Option<&str>,
+) {
     let chunks = Layout::default()
         .constraints([Constraint::Percentage(25), Constraint::Min(8)].as_ref())
         .split(area);
@@ -148,15 +778,15 @@ fn draw_histogram_table(f: &mut Frame, area: Rect, histogram_data: &HistogramDat
     let title_details = "Histogram Details".to_string();
 
     let row_details = [Row::new(vec![
-        histogram_data.time.to_rfc2822(),
+        format_time(histogram_data.time),
         histogram_data.count.to_string(),
-        format!("{:.2}", histogram_data.sum),
+        with_unit(histogram_data.sum, unit, |sum| format!("{:.2}", sum)),
     ])];
 
     let t = Table::new(
         row_details,
         &[
-            Constraint::Length(40),
+            Constraint::Length(time_width() + 1),
             Constraint::Length(15),
             Constraint::Length(15),
             Constraint::Percentage(100),
@@ -164,7 +794,8 @@ fn draw_histogram_table(f: &mut Frame, area: Rect, histogram_data: &HistogramDat
     )
     .block(Block::default().borders(Borders::ALL).title(title_details))
     .header(
-        Row::new(vec!["Time", "Count", "Sum"]).style(Style::default().add_modifier(Modifier::BOLD)),
+        Row::new(vec![tr("Time"), tr("Count"), tr("Sum")])
+            .style(Style::default().add_modifier(Modifier::BOLD)),
     )
     .highlight_style(Style::default().add_modifier(Modifier::BOLD));
     f.render_widget(t, chunks[0]);
@@ -174,7 +805,7 @@ fn draw_histogram_table(f: &mut Frame, area: Rect, histogram_data: &HistogramDat
 
     let rows = histogram_data.data.iter().map(|entry| {
         Row::new(vec![
-            entry.get_bucket().clone(),
+            bucket_label(entry.get_bucket(), unit),
             entry.get_value().to_string(),
             format!("{:.2}", entry.get_percentage()),
             entry.get_inc_per_bucket().to_string(),
@@ -201,28 +832,170 @@ fn draw_histogram_table(f: &mut Frame, area: Rect, histogram_data: &HistogramDat
     f.render_widget(t, chunks[1]);
 }
 
-fn draw_histogram(f: &mut Frame, area: Rect, histogram_data: &HistogramData) {
-    let data: Vec<(&str, u64)> = histogram_data
+fn draw_histogram(
+    f: &mut Frame,
+    area: Rect,
+    histogram_data: &HistogramData,
+    graph_view: &mut GraphView,
+) {
+    let data: Vec<(&str, u64, String)> = histogram_data
         .data
         .iter()
         .map(|bucket_value| {
             (
-                bucket_value.get_bucket().as_str(),
+                bucket_value.get_label().as_str(),
                 bucket_value.get_inc_per_bucket(),
+                bucket_value.bar_label(graph_view.bar_labels),
             )
         })
         .collect();
-    let bar_width = area.width / (data.len() + 1) as u16;
+    let fitted_width = (area.width / (data.len() + 1) as u16).max(1);
+    graph_view.fitted_bar_width = fitted_width;
+    let bar_width = graph_view.bar_width.unwrap_or(fitted_width);
+    let label_width = data
+        .iter()
+        .map(|(label, _, _)| label.chars().count())
+        .max()
+        .unwrap_or_default() as u16;
+    // Labels wider than the fitted bars overlap each other, so switch to a
+    // table with one horizontal bar per row instead, unless the width was
+    // chosen with the keys
+    let horizontal = match graph_view.bar_orientation {
+        BarOrientation::Auto => {
+            graph_view.bar_width.is_none() && bar_width < label_width.max(MIN_BAR_WIDTH)
+        }
+        BarOrientation::Vertical => false,
+        BarOrientation::Horizontal => true,
+    };
+    if horizontal {
+        draw_histogram_rows(f, area, &data);
+        return;
+    }
+
+    // bars that don't fit are scrolled to, one bucket at a time
+    let visible = ((area.width.saturating_sub(2) + BAR_GAP) / (bar_width + BAR_GAP)).max(1);
+    let first = graph_view
+        .bar_scroll
+        .min(data.len().saturating_sub(visible as usize));
+    graph_view.bar_scroll = first;
+    let shown = &data[first..data.len().min(first + visible as usize)];
+    let title = if shown.len() < data.len() {
+        format!(
+            "Histogram: buckets {}-{} of {}",
+            first + 1,
+            first + shown.len(),
+            data.len()
+        )
+    } else {
+        "Histogram".to_string()
+    };
+    let block = Block::default().title(title).borders(Borders::ALL);
+
+    // labels wider than their bars are drawn on a line of their own, leaving
+    // out the ones overlapping the previous label
+    let thinned = label_width > bar_width;
+    let bars: Vec<Bar> = shown
+        .iter()
+        .map(|(label, value, text)| {
+            let bar = Bar::default().value(*value).text_value(text.clone());
+            if thinned {
+                bar
+            } else {
+                bar.label((*label).into())
+            }
+        })
+        .collect();
     let t = BarChart::default()
-        .block(Block::default().title("Histogram").borders(Borders::ALL))
-        .data(&data)
+        .data(BarGroup::default().bars(&bars))
         .bar_width(bar_width)
-        .bar_style(Style::default().fg(Color::LightGreen))
-        .value_style(Style::default().fg(Color::Black).bg(Color::LightGreen));
+        .bar_gap(BAR_GAP)
+        .bar_style(Style::default().fg(theme().graph))
+        .value_style(Style::default().fg(Color::Black).bg(theme().graph));
+    if !thinned {
+        f.render_widget(t.block(block), area);
+        return;
+    }
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+    let chunks = Layout::default()
+        .constraints([Constraint::Min(0), Constraint::Length(1)])
+        .split(inner);
+    f.render_widget(t, chunks[0]);
+    let labels: Vec<&str> = shown.iter().map(|(label, _, _)| *label).collect();
+    f.render_widget(
+        Paragraph::new(thinned_labels(&labels, bar_width)),
+        chunks[1],
+    );
+}
+
+/// Line with the labels of bars `bar_width` wide, each starting below its
+/// bar, leaving out the labels that would touch the previous one
+fn thinned_labels(labels: &[&str], bar_width: u16) -> String {
+    let mut line = String::new();
+    for (index, label) in labels.iter().enumerate() {
+        let start = index * (bar_width + BAR_GAP) as usize;
+        let end = line.chars().count();
+        if index == 0 || start > end {
+            line.push_str(&" ".repeat(start - end));
+            line.push_str(label);
+        }
+    }
+    line
+}
+
+fn draw_histogram_rows(f: &mut Frame, area: Rect, data: &[(&str, u64, String)]) {
+    let label_width = data
+        .iter()
+        .map(|(label, _, _)| label.chars().count())
+        .max()
+        .unwrap_or_default() as u16;
+    let value_width = data
+        .iter()
+        .map(|(_, _, text)| text.chars().count())
+        .max()
+        .unwrap_or_default() as u16;
+    // borders and column spacing
+    let bar_space = area.width.saturating_sub(label_width + value_width + 4) as f64;
+    let max = data
+        .iter()
+        .map(|(_, value, _)| *value)
+        .max()
+        .unwrap_or_default();
+
+    let rows = data.iter().map(|(label, value, text)| {
+        let len = if max == 0 {
+            0
+        } else {
+            (*value as f64 / max as f64 * bar_space).round() as usize
+        };
+        Row::new(vec![
+            Cell::from(label.to_string()),
+            Cell::from(Span::styled(
+                "█".repeat(len),
+                Style::default().fg(theme().graph),
+            )),
+            Cell::from(text.clone()),
+        ])
+    });
+
+    let t = Table::new(
+        rows,
+        &[
+            Constraint::Length(label_width),
+            Constraint::Fill(1),
+            Constraint::Length(value_width),
+        ],
+    )
+    .block(Block::default().title("Histogram").borders(Borders::ALL));
     f.render_widget(t, area);
 }
 
-fn draw_summary_table(f: &mut Frame, area: Rect, summary_data: &SummaryValueSample) {
+fn draw_summary_table(
+    f: &mut Frame,
+    area: Rect,
+    summary_data: &SummaryValueSample,
+    unit: Option<&str>,
+) {
     let chunks = Layout::default()
         .constraints([Constraint::Percentage(25), Constraint::Min(8)].as_ref())
         .split(area);
@@ -231,15 +1004,15 @@ fn draw_summary_table(f: &mut Frame, area: Rect, summary_data: &SummaryValueSamp
     let title_details = "Summary Details".to_string();
 
     let row_details = [Row::new(vec![
-        summary_data.time.to_rfc2822(),
+        format_time(summary_data.time),
         summary_data.count.to_string(),
-        format!("{:.2}", summary_data.sum),
+        with_unit(summary_data.sum, unit, |sum| format!("{:.2}", sum)),
     ])];
 
     let t = Table::new(
         row_details,
         &[
-            Constraint::Length(40),
+            Constraint::Length(time_width() + 1),
             Constraint::Length(15),
             Constraint::Length(15),
             Constraint::Percentage(100),
@@ -247,7 +1020,8 @@ fn draw_summary_table(f: &mut Frame, area: Rect, summary_data: &SummaryValueSamp
     )
     .block(Block::default().borders(Borders::ALL).title(title_details))
     .header(
-        Row::new(vec!["Time", "Count", "Sum"]).style(Style::default().add_modifier(Modifier::BOLD)),
+        Row::new(vec![tr("Time"), tr("Count"), tr("Sum")])
+            .style(Style::default().add_modifier(Modifier::BOLD)),
     )
     .highlight_style(Style::default().add_modifier(Modifier::BOLD));
     f.render_widget(t, chunks[0]);
@@ -255,27 +1029,82 @@ fn draw_summary_table(f: &mut Frame, area: Rect, summary_data: &SummaryValueSamp
     // Draw histogram buckets details
     let title = "Summary Data Details".to_string();
 
-    let rows = summary_data
-        .quantiles
-        .iter()
-        .map(|entry| Row::new(vec![entry.name.clone(), entry.value.to_string()]));
+    let rows = summary_data.quantiles.iter().map(|entry| {
+        Row::new(vec![
+            entry.name.clone(),
+            with_unit(entry.value, unit, |value| value.to_string()),
+        ])
+    });
 
     let t = Table::new(rows, &[Constraint::Length(15), Constraint::Percentage(100)])
         .block(Block::default().borders(Borders::ALL).title(title))
         .header(
-            Row::new(vec!["Quantil", "Value"]).style(Style::default().add_modifier(Modifier::BOLD)),
+            Row::new(vec![tr("Quantil"), tr("Value")])
+                .style(Style::default().add_modifier(Modifier::BOLD)),
         )
         .highlight_style(Style::default().add_modifier(Modifier::BOLD));
     f.render_widget(t, chunks[1]);
 }
 
-fn draw_summary(f: &mut Frame, area: Rect, summary_data: &SummaryValueSample) {
-    let bar_width = area.width / (summary_data.quantiles.len() + 1) as u16;
+fn draw_summary(
+    f: &mut Frame,
+    area: Rect,
+    summary_data: &SummaryValueSample,
+    orientation: BarOrientation,
+) {
     let t = BarChart::default()
         .block(Block::default().title("Summary").borders(Borders::ALL))
-        .data(summary_data)
-        .bar_width(bar_width)
-        .bar_style(Style::default().fg(Color::LightGreen))
-        .value_style(Style::default().fg(Color::Black).bg(Color::LightGreen));
+        .data(summary_data);
+    let t = if orientation == BarOrientation::Horizontal {
+        t.direction(Direction::Horizontal).bar_width(1)
+    } else {
+        let bar_width = area.width / (summary_data.quantiles.len() + 1) as u16;
+        t.bar_width(bar_width)
+    };
+    let t = t
+        .bar_style(Style::default().fg(theme().graph))
+        .value_style(Style::default().fg(Color::Black).bg(theme().graph));
     f.render_widget(t, area);
 }
+
+impl<'a> From<&SummaryValueSample> for BarGroup<'a> {
+    fn from(val: &SummaryValueSample) -> Self {
+        let mut min = f64::MAX;
+        let mut max = f64::MIN;
+        val.quantiles.iter().for_each(|data_point| {
+            if data_point.value > max {
+                max = data_point.value;
+            }
+            if data_point.value < min {
+                min = data_point.value;
+            }
+        });
+        //Low level is the bar height for the min
+        //value. For non-zero values we want a
+        //small bar to be displayed.
+        let low_level = if min != 0.0 { 5.0 } else { 0.0 };
+
+        //Scale so we have at least 100 steps
+        let scale_span = 100.0 - low_level;
+
+        //3.25, 2.25
+        let bars: Vec<Bar> = val
+            .quantiles
+            .iter()
+            .map(|m| {
+                // equal quantiles are drawn at full height
+                let percent = if max > min {
+                    (m.value - min) / (max - min)
+                } else {
+                    1.0
+                };
+                let new_val = (percent * scale_span) + low_level;
+                Bar::default()
+                    .value(new_val.round() as u64)
+                    .text_value(format_value(m.value))
+                    .label(m.name.clone().into())
+            })
+            .collect();
+        BarGroup::default().bars(&bars)
+    }
+}
@@ -0,0 +1,301 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use serde::Deserialize;
+
+/// Language of the UI strings
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Lang {
+    #[default]
+    En,
+    Sv,
+}
+
+/// Swedish translations, keyed by the English text used in the code
+const SV: &[(&str, &str)] = &[
+    // info header
+    ("Metrics endpoint", "Ändpunkt"),
+    ("History", "Historik"),
+    ("samples", "värden"),
+    ("Scraping interval", "Hämtningsintervall"),
+    ("PAUSED", "PAUSAD"),
+    ("ALERT", "LARM"),
+    ("series above threshold", "serier över tröskelvärdet"),
+    (
+        "Prom-tui scraper is failing with error",
+        "Hämtningen misslyckas med felet",
+    ),
+    ("retry", "nytt försök"),
+    ("next scrape in", "nästa hämtning om"),
+    ("in", "om"),
+    ("Selected metric", "Vald metrik"),
+    // panes and tables
+    ("Metrics", "Metriker"),
+    ("Favorites", "Favoriter"),
+    ("Labels", "Etiketter"),
+    ("Graph", "Graf"),
+    ("Graph (rate/s)", "Graf (takt/s)"),
+    ("Time", "Tid"),
+    ("Value", "Värde"),
+    ("Rate/s", "Takt/s"),
+    ("Count", "Antal"),
+    ("Count/s", "Antal/s"),
+    ("Sum", "Summa"),
+    ("Average", "Medel"),
+    ("Increase", "Ökning"),
+    ("Histogram", "Histogram"),
+    ("Histogram Details", "Histogramdetaljer"),
+    ("Summary", "Sammanfattning"),
+    ("Summary Details", "Sammanfattningsdetaljer"),
+    ("Quantil", "Kvantil"),
+    ("Not enough data yet", "Inte tillräckligt med data än"),
+    ("No data", "Ingen data"),
+    ("No matching series yet", "Inga matchande serier än"),
+    // help
+    (
+        "Keybindings (any key to close)",
+        "Tangenter (valfri tangent stänger)",
+    ),
+    ("Key", "Tangent"),
+    ("Action", "Åtgärd"),
+    (
+        "move through the focused list",
+        "flytta i den aktiva listan",
+    ),
+    (
+        "move a page through the focused list",
+        "flytta en sida i den aktiva listan",
+    ),
+    (
+        "jump to the first or last item",
+        "hoppa till första eller sista raden",
+    ),
+    (
+        "switch between metrics and labels",
+        "växla mellan metriker och etiketter",
+    ),
+    (
+        "reset the zoom, close the expression graph",
+        "återställ zoomen, stäng uttrycksgrafen",
+    ),
+    ("select a metric or series", "välj en metrik eller serie"),
+    (
+        "scroll the lists and the history table",
+        "rulla listorna och historiktabellen",
+    ),
+    (
+        "zoom into the selected time range",
+        "zooma in på det valda tidsintervallet",
+    ),
+    (
+        "switch to the tab of an endpoint",
+        "byt till en ändpunkts flik",
+    ),
+    ("jump to the first item", "hoppa till första raden"),
+    (
+        "show or close this help",
+        "visa eller stäng den här hjälpen",
+    ),
+    ("quit", "avsluta"),
+    ("jump to the last item", "hoppa till sista raden"),
+    (
+        "switch between raw values and rate",
+        "växla mellan råvärden och takt",
+    ),
+    (
+        "cycle the time window of the graph",
+        "växla grafens tidsfönster",
+    ),
+    (
+        "cycle the window of the counter increase",
+        "växla fönstret för räknarens ökning",
+    ),
+    (
+        "plot the sum, then the mean and range of all series",
+        "rita summan, sedan medel och spann av alla serier",
+    ),
+    (
+        "mark metric, or overlay series on the graph",
+        "markera metrik, eller lägg serien över grafen",
+    ),
+    ("hide the marked metrics", "dölj de markerade metrikerna"),
+    ("show all hidden metrics", "visa alla dolda metriker"),
+    (
+        "hide the marked metrics in every session",
+        "dölj de markerade metrikerna i alla sessioner",
+    ),
+    (
+        "pin the marked metrics to the top",
+        "fäst de markerade metrikerna överst",
+    ),
+    (
+        "add or remove the marked metrics as favorites",
+        "lägg till eller ta bort de markerade som favoriter",
+    ),
+    ("list only the favorites", "lista bara favoriterna"),
+    ("show the metadata of the metric", "visa metrikens metadata"),
+    (
+        "show the raw series of histograms and summaries",
+        "visa de råa serierna för histogram och sammanfattningar",
+    ),
+    ("search metrics", "sök metriker"),
+    ("filter the labels pane", "filtrera etikettpanelen"),
+    ("evaluate an expression", "beräkna ett uttryck"),
+    (
+        "graph the error ratio of a counter",
+        "rita felkvoten för en räknare",
+    ),
+    ("set the unit of the metric", "ange metrikens enhet"),
+    (
+        "set the alert threshold of the metric",
+        "ange metrikens larmgräns",
+    ),
+    ("cycle the bar orientation", "växla staplarnas riktning"),
+    (
+        "show bucket counts or percentages",
+        "visa antal eller procent per hink",
+    ),
+    (
+        "widen the histogram bars",
+        "gör histogrammets staplar bredare",
+    ),
+    (
+        "narrow the histogram bars",
+        "gör histogrammets staplar smalare",
+    ),
+    (
+        "scroll the histogram bars left",
+        "rulla histogrammets staplar åt vänster",
+    ),
+    (
+        "scroll the histogram bars right",
+        "rulla histogrammets staplar åt höger",
+    ),
+    (
+        "compare with the selected series",
+        "jämför med den valda serien",
+    ),
+    (
+        "cycle the comparison: graphs, ratio, difference",
+        "växla jämförelse: grafer, kvot, skillnad",
+    ),
+    (
+        "show the latest quantiles or their history",
+        "visa de senaste kvantilerna eller deras historik",
+    ),
+    (
+        "sort metrics by name, value or rate of change",
+        "sortera metriker på namn, värde eller förändringstakt",
+    ),
+    ("show the top movers", "visa de största förändringarna"),
+    (
+        "show the payload bytes per metric",
+        "visa antalet byte per metrik i svaret",
+    ),
+    (
+        "show the dashboard of the config file",
+        "visa konfigurationsfilens instrumentpanel",
+    ),
+    ("show the health of the targets", "visa målens hälsa"),
+    (
+        "pause or resume scraping",
+        "pausa eller återuppta hämtningen",
+    ),
+    ("scrape less often", "hämta mer sällan"),
+    ("scrape more often", "hämta oftare"),
+    ("copy the metric name", "kopiera metrikens namn"),
+    (
+        "copy the series with its labels",
+        "kopiera serien med dess etiketter",
+    ),
+    (
+        "copy the latest value of the series",
+        "kopiera seriens senaste värde",
+    ),
+    ("export the series as CSV", "exportera serien som CSV"),
+    (
+        "export the marked metrics",
+        "exportera de markerade metrikerna",
+    ),
+    (
+        "export the history as JSON",
+        "exportera historiken som JSON",
+    ),
+    // footer
+    ("apply", "verkställ"),
+    ("cancel", "avbryt"),
+    ("back", "tillbaka"),
+    ("switch pane", "byt panel"),
+    ("help", "hjälp"),
+    ("search", "sök"),
+    ("mark", "markera"),
+    ("hide", "dölj"),
+    ("pin", "fäst"),
+    ("sort", "sortera"),
+    ("rate", "takt"),
+    ("window", "fönster"),
+    ("expr", "uttryck"),
+    ("filter", "filtrera"),
+    ("overlay", "överlagra"),
+    ("sum", "summa"),
+    ("compare", "jämför"),
+    ("ratio", "kvot"),
+    ("csv", "csv"),
+];
+
+static STRINGS: OnceLock<HashMap<String, String>> = OnceLock::new();
+
+/// Set the language of the UI strings, with `overrides` of single strings
+/// keyed by their English text, e.g. from the config file. Only the first
+/// call has an effect.
+pub fn set_lang(lang: Lang, overrides: HashMap<String, String>) {
+    let table = match lang {
+        Lang::En => &[][..],
+        Lang::Sv => SV,
+    };
+    let mut strings: HashMap<String, String> = table
+        .iter()
+        .map(|(text, translation)| (text.to_string(), translation.to_string()))
+        .collect();
+    strings.extend(overrides);
+    if STRINGS.set(strings).is_err() {
+        log::warn!("language already initialized");
+    }
+}
+
+/// `text` in the selected language, as is if it has no translation
+pub fn tr(text: &'static str) -> &'static str {
+    STRINGS
+        .get()
+        .and_then(|strings| strings.get(text))
+        .map_or(text, String::as_str)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::interactive::keys::{KeyBindings, KeyContext};
+
+    use super::*;
+
+    #[test]
+    fn test_swedish_covers_help_and_footer() {
+        let translated: HashSet<&str> = SV.iter().map(|(text, _)| *text).collect();
+        assert_eq!(translated.len(), SV.len(), "duplicate translations");
+        let keys = KeyBindings::default();
+        let contexts = [
+            KeyContext::Prompt,
+            KeyContext::MetricList,
+            KeyContext::LabelsList,
+            KeyContext::Screen,
+        ];
+        let footer = contexts
+            .into_iter()
+            .flat_map(|context| keys.footer_entries(context));
+        for (_, description) in keys.help_entries().into_iter().chain(footer) {
+            assert!(translated.contains(description), "{}", description);
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use ratatui::layout::Rect;
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Paragraph, Wrap};
+use ratatui::Frame;
+
+use crate::interactive::ui::time_format::format_timestamp;
+use crate::prom::Metric;
+
+/// HELP, TYPE and UNIT, label keys, series count and the time covered by the samples
+/// of the metric
+pub fn lines(metric: &Metric) -> Vec<Line<'static>> {
+    let details = &metric.details;
+    let help = if details.docstring.is_empty() {
+        "-"
+    } else {
+        details.docstring.as_str()
+    };
+    let mut values_per_label: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+    for (key, time_series) in &metric.time_series {
+        // series without labels carry a placeholder label map
+        if !key.contains('=') {
+            continue;
+        }
+        for (label, value) in &time_series.labels {
+            values_per_label.entry(label).or_default().insert(value);
+        }
+    }
+    let labels = if values_per_label.is_empty() {
+        "-".to_string()
+    } else {
+        values_per_label
+            .iter()
+            .map(|(label, values)| format!("{} ({})", label, values.len()))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let stale = metric.stale_labels().len();
+    let mut series = format!("Series: {}", metric.time_series.len());
+    if stale > 0 {
+        series.push_str(&format!(" ({} stale)", stale));
+    }
+    let first = metric
+        .time_series
+        .values()
+        .filter_map(|time_series| time_series.samples.front())
+        .map(|sample| sample.timestamp())
+        .min();
+    let last = metric
+        .time_series
+        .values()
+        .filter_map(|time_series| time_series.samples.back())
+        .map(|sample| sample.timestamp())
+        .max();
+    let samples = match (first, last) {
+        (Some(first), Some(last)) => format!(
+            "Samples: {} - {}",
+            format_timestamp(first),
+            format_timestamp(last)
+        ),
+        _ => "Samples: -".to_string(),
+    };
+    let mut metric_type = format!(
+        "TYPE: {}",
+        format!("{:?}", details.metric_type).to_lowercase()
+    );
+    if let Some(unit) = &details.unit {
+        metric_type.push_str(&format!(", UNIT: {}", unit));
+    }
+    vec![
+        Line::from(format!("HELP: {}", help)),
+        Line::from(metric_type),
+        Line::from(format!("Labels: {}", labels)),
+        Line::from(series),
+        Line::from(samples),
+    ]
+}
+
+pub fn draw(f: &mut Frame, area: Rect, lines: Vec<Line>) {
+    let block = Block::default().borders(Borders::ALL).title("Metadata");
+    let paragraph = Paragraph::new(lines).block(block).wrap(Wrap { trim: true });
+    f.render_widget(paragraph, area);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prom::test_data::scrape;
+
+    #[test]
+    fn test_metadata_lines() {
+        let text = "# HELP http_requests_total Handled requests
+# TYPE http_requests_total counter
+http_requests_total{code=\"200\",method=\"GET\"} 10
+http_requests_total{code=\"500\",method=\"GET\"} 2
+";
+        let metric = scrape(text, 1_700_000_000).into_metric();
+        let text: Vec<String> = super::lines(&metric)
+            .iter()
+            .map(|line| line.to_string())
+            .collect();
+        assert_eq!(text[0], "HELP: Handled requests");
+        assert_eq!(text[1], "TYPE: counter");
+        assert_eq!(text[2], "Labels: code (2), method (1)");
+        assert_eq!(text[3], "Series: 2");
+        assert!(text[4].starts_with("Samples: "));
+    }
+}
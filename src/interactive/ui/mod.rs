@@ -1,58 +1,314 @@
 use ratatui::layout::{Constraint, Direction, Layout, Rect};
 use ratatui::style::{Color, Modifier, Style};
 use ratatui::text::{Line, Span};
-use ratatui::widgets::{Block, BorderType, Borders, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::widgets::{
+    Block, BorderType, Borders, List, ListItem, ListState, Paragraph, Tabs, Wrap,
+};
 use ratatui::Frame;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
+use std::time::{Duration, Instant};
 
-use crate::interactive::app::{App, ElementInFocus};
-use crate::prom::Metric;
+use crate::interactive::app::{App, ElementInFocus, GraphView, PromptKind, Screen};
+use crate::interactive::grouping::{self, Group, SectionRow};
+use crate::prom::{MetadataMismatch, Metric, MetricDetails, TargetStatus, Trend};
 
+mod activity;
+mod alerts;
+mod animation;
+mod anomaly;
+mod axis;
+mod components;
+mod count_data;
+mod counter;
+mod dashboard;
+mod expression;
+mod footer;
 mod graph_data;
+mod help;
 mod histogram_data;
 mod history;
+mod i18n;
+mod metadata;
+mod payload;
+mod preview;
 mod search;
 mod style;
+mod targets;
+mod theme;
+mod time_format;
+mod top_movers;
+mod units;
+pub use alerts::alerting_labels;
+pub use animation::Animation;
+pub use components::components;
+use i18n::tr;
+pub use i18n::{set_lang, Lang};
+pub use search::SearchQuery;
+pub use theme::{set_theme, theme, Theme};
+pub use time_format::{format_time, set_display_zone, set_time_format, DisplayZone, TimeFormat};
+pub use units::detect_unit;
 
-const fn focus_color(has_focus: bool) -> Color {
+fn focus_color(has_focus: bool) -> Color {
     if has_focus {
-        Color::LightGreen
+        theme().focus
     } else {
         Color::Gray
     }
 }
 
 pub fn draw(f: &mut Frame, app: &mut App) -> Result<(), Box<dyn Error>> {
+    let now = Instant::now();
+    let has_error = app
+        .metric_scraper
+        .get_status_read_guard()?
+        .error_msg
+        .is_some();
+    app.animation.track_error(has_error, now);
+    let header = info_header_text(app, now);
+    // room for at least 3 lines, growing while the status lines pile up
+    let header_height = header.len().max(3) as u16 + 2;
+    let footer_height = u16::from(app.show_footer);
+    let tabs_height = u16::from(app.tabs.len() > 1);
     let chunks = Layout::default()
-        .constraints([Constraint::Length(2 + 3), Constraint::Min(8)].as_ref())
+        .constraints(
+            [
+                Constraint::Length(tabs_height),
+                Constraint::Length(header_height),
+                Constraint::Min(8),
+                Constraint::Length(footer_height),
+            ]
+            .as_ref(),
+        )
         .split(f.size());
-    draw_info_header(f, chunks[0], app);
-    draw_main(f, chunks[1], app)?;
+    if app.tabs.len() > 1 {
+        draw_tabs(f, chunks[0], app);
+    }
+    draw_info_header(f, chunks[1], header);
+    if app.show_footer {
+        footer::draw(f, chunks[3], app);
+    }
+    let chunks = &chunks[1..];
+    app.metric_list_area = None;
+    app.labels_list_area = None;
+    app.graph_view.table_area = None;
+    match app.screen {
+        Screen::Browse => draw_main(f, chunks[1], app)?,
+        Screen::Dashboard => dashboard::draw(
+            f,
+            chunks[1],
+            &app.dashboard,
+            app.metric_scraper.get_history_lock()?.as_ref(),
+            &app.graph_view,
+        ),
+        Screen::TopMovers => top_movers::draw(
+            f,
+            chunks[1],
+            app.metric_scraper.get_history_lock()?.as_ref(),
+        ),
+        Screen::Payload => payload::draw(
+            f,
+            chunks[1],
+            app.metric_scraper.get_history_lock()?.as_ref(),
+            app.keys.payload,
+        ),
+        Screen::Targets => targets::draw(
+            f,
+            chunks[1],
+            &app.metric_scraper.get_status_read_guard()?.targets,
+            app.keys.targets,
+        ),
+        Screen::Expression => match &app.expression {
+            Some((text, expr)) => expression::draw(
+                f,
+                chunks[1],
+                text,
+                expr,
+                app.metric_scraper.get_history_lock()?.as_ref(),
+            ),
+            None => draw_main(f, chunks[1], app)?,
+        },
+    }
+    if app.show_help {
+        help::draw(f, f.size(), &app.keys);
+    }
     Ok(())
 }
 
-fn draw_info_header(f: &mut Frame, area: Rect, app: &App) {
-    let endpoint = format!("Metrics endpoint: {}", app.endpoint);
-    let scrape_interval = format!("Scraping interval: {}s", app.scrape_interval);
-    let mut text = vec![Line::from(endpoint), Line::from(scrape_interval)];
+/// Tabs of the scraped endpoints, numbered by their keys
+fn draw_tabs(f: &mut Frame, area: Rect, app: &App) {
+    let titles: Vec<String> = app
+        .tabs
+        .iter()
+        .enumerate()
+        .map(|(index, tab)| format!("{} {}", index + 1, tab.title))
+        .collect();
+    let tabs = Tabs::new(titles).select(app.active_tab).highlight_style(
+        Style::default()
+            .fg(theme().focus)
+            .add_modifier(Modifier::BOLD),
+    );
+    f.render_widget(tabs, area);
+}
 
-    let error_msg_guard = app
+fn info_header_text(app: &App, now: Instant) -> Vec<Line<'static>> {
+    let (usage, alert_count) = {
+        let history = app
+            .metric_scraper
+            .get_history_lock()
+            .expect("to get history lock");
+        let alert_count = alerts::alert_count(history.as_ref(), &app.graph_view.thresholds);
+        (history.memory_usage(), alert_count)
+    };
+    let status_guard = app
         .metric_scraper
-        .get_error_msg_read_guard()
-        .expect("to get error msg guard");
-    if let Some(error_msg) = &*error_msg_guard {
-        text.push(Line::from(Span::styled(
-            format!("Prom-tui scraper is failing with error: {}", error_msg),
+        .get_status_read_guard()
+        .expect("to get scrape status guard");
+    let source = match &status_guard.grpc_health {
+        Some(grpc_health) => format!("{} ({})", app.endpoint, grpc_health),
+        None => app.endpoint.to_string(),
+    };
+    let endpoint = format!(
+        "{}: {}  {}: {} {}, ~{}",
+        tr("Metrics endpoint"),
+        source,
+        tr("History"),
+        usage.samples,
+        tr("samples"),
+        units::format_bytes(usage.bytes as f64)
+    );
+    let interval = match app.metric_scraper.scrape_interval() {
+        Some(interval) if interval < Duration::from_secs(1) => {
+            format!("{}ms, batched", interval.as_millis())
+        }
+        _ => format!("{}s", app.scrape_interval),
+    };
+    let mut scrape_interval = vec![Span::raw(format!(
+        "{}: {}",
+        tr("Scraping interval"),
+        interval
+    ))];
+    if status_guard.paused {
+        scrape_interval.push(Span::styled(
+            format!(" {}", tr("PAUSED")),
             Style::default()
-                .fg(Color::Red)
-                .add_modifier(Modifier::BOLD | Modifier::SLOW_BLINK),
+                .fg(Color::Black)
+                .bg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        ));
+    }
+    if alert_count > 0 {
+        scrape_interval.push(Span::styled(
+            format!(
+                " {} {} {}",
+                tr("ALERT"),
+                alert_count,
+                tr("series above threshold")
+            ),
+            Style::default()
+                .fg(Color::Black)
+                .bg(theme().error)
+                .add_modifier(Modifier::BOLD),
+        ));
+    }
+    if status_guard.skipped_count > 0 {
+        let last_duration = status_guard.last_duration.unwrap_or_default();
+        scrape_interval.push(Span::styled(
+            format!(
+                " (scrapes too slow, last took {:.1}s, {} skipped)",
+                last_duration.as_secs_f64(),
+                status_guard.skipped_count
+            ),
+            Style::default().fg(Color::Yellow),
+        ));
+    }
+    if let Some(spill_path) = &status_guard.spill_path {
+        scrape_interval.push(Span::styled(
+            format!(
+                " (memory limit reached, {} old samples moved to {}, :restore-history loads them)",
+                status_guard.spilled_samples,
+                spill_path.display()
+            ),
+            Style::default().fg(Color::Yellow),
+        ));
+    }
+    let mut text = vec![Line::from(endpoint), Line::from(scrape_interval)];
+
+    if let Some(error_msg) = &status_guard.error_msg {
+        let retry = match (&status_guard.retry, status_guard.rate_limited_until) {
+            // waiting for the delay the endpoint asked for
+            (_, Some(until)) => format!(
+                " ({} {}s…)",
+                tr("next scrape in"),
+                until
+                    .saturating_duration_since(Instant::now())
+                    .as_secs_f64()
+                    .ceil()
+            ),
+            (Some(retry), None) => format!(
+                " ({} {}/{} {} {}s…)",
+                tr("retry"),
+                retry.attempt,
+                retry.max_attempts,
+                tr("in"),
+                retry
+                    .next_at
+                    .saturating_duration_since(Instant::now())
+                    .as_secs_f64()
+                    .ceil()
+            ),
+            (None, None) => String::new(),
+        };
+        text.push(Line::from(Span::styled(
+            format!(
+                "{}: {}{}",
+                tr("Prom-tui scraper is failing with error"),
+                error_msg,
+                retry
+            ),
+            // Only draw attention once the retries are exhausted
+            if status_guard.retry.is_some() || status_guard.rate_limited_until.is_some() {
+                Style::default().fg(Color::Yellow)
+            } else {
+                app.animation.error_style(now)
+            },
         )));
     }
 
     if let Some(selected_metric) = &app.selected_metric {
-        text.push(Line::from(format!("Selected metric: {}", selected_metric)));
+        text.push(Line::from(format!(
+            "{}: {}",
+            tr("Selected metric"),
+            selected_metric
+        )));
+    }
+
+    if let Some(prompt) = &app.prompt {
+        let prefix = match prompt.kind {
+            PromptKind::Expression => ":",
+            PromptKind::Search => "/",
+            PromptKind::LabelFilter => "labels: ",
+            PromptKind::Unit => "unit: ",
+            PromptKind::Threshold => "threshold: ",
+        };
+        text.push(Line::from(vec![
+            Span::styled(prefix, Style::default().fg(theme().focus)),
+            Span::raw(prompt.input.clone()),
+            Span::raw(if app.animation.pulse(now) { "_" } else { " " }),
+        ]));
+    }
+
+    if let Some(status_msg) = &app.status_msg {
+        text.push(Line::from(Span::styled(
+            status_msg.clone(),
+            Style::default().fg(Color::Yellow),
+        )));
     }
 
+    text
+}
+
+fn draw_info_header(f: &mut Frame, area: Rect, text: Vec<Line>) {
     let title = format!("PROMVIZ {}", env!("CARGO_PKG_VERSION"));
     let block = Block::default().borders(Borders::ALL).title(title);
     let paragraph = Paragraph::new(text).block(block).wrap(Wrap { trim: true });
@@ -60,23 +316,93 @@ fn draw_info_header(f: &mut Frame, area: Rect, app: &App) {
 }
 
 fn draw_main(f: &mut Frame, area: Rect, app: &mut App) -> Result<(), Box<dyn Error>> {
-    let metric_headers = app.metric_scraper.get_history_lock()?.get_metrics_headers();
+    let metric_headers = app.get_metrics_headers()?;
+    let (metric_highlights, metric_trends) = {
+        let history = app.metric_scraper.get_history_lock()?;
+        let trends: HashMap<String, Trend> = metric_headers
+            .iter()
+            .filter_map(|header| Some((header.clone(), history.get_metric(header)?.trend)))
+            .collect();
+        let matching = |matches: fn(&Metric) -> bool| {
+            metric_headers
+                .iter()
+                .filter(|header| history.get_metric(header).is_some_and(matches))
+                .cloned()
+                .collect()
+        };
+        let thresholds = &app.graph_view.thresholds;
+        let highlights = Highlights {
+            alerts: metric_headers
+                .iter()
+                .filter(|header| {
+                    let metric = history.get_metric(header);
+                    let threshold = thresholds.get(*header);
+                    metric.zip(threshold).is_some_and(|(metric, threshold)| {
+                        !alerts::alerting_labels(metric, *threshold).is_empty()
+                    })
+                })
+                .cloned()
+                .collect(),
+            anomalies: matching(|metric| !anomaly::anomalous_labels(metric).is_empty()),
+            changed: matching(activity::has_activity),
+            ..Highlights::default()
+        };
+        (highlights, trends)
+    };
+    let sections = if app.shows_sections() {
+        Some(app.sync_section_rows()?)
+    } else {
+        // e.g. once a single target is left
+        app.section = None;
+        None
+    };
     //Select first entry in list, if none is selected
-    if app.metric_list_state.selected().is_none() && !metric_headers.is_empty() {
+    if sections.is_none()
+        && app.metric_list_state.selected().is_none()
+        && !metric_headers.is_empty()
+    {
         app.metric_list_state.select(Some(0));
         app.selected_metric = metric_headers.first().cloned();
     }
+    let groups = app.get_groups()?;
+    if app.shows_groups() {
+        // groups may appear or expire, the selected one stays selected
+        match groups
+            .iter()
+            .position(|group| Some(group) == app.selected_group.as_ref())
+        {
+            Some(index) => app.group_list_state.select(Some(index)),
+            None => {
+                app.group_list_state
+                    .select((!groups.is_empty()).then_some(0));
+                app.selected_group = groups.first().cloned();
+            }
+        }
+    }
 
     #[allow(clippy::option_if_let_else)]
+    let history = app.metric_scraper.get_history_lock()?;
     let metric_headers_area = if let Some(selected_metric) = &app.selected_metric {
-        if let Some(metric) = app
-            .metric_scraper
-            .get_history_lock()?
-            .get_metric(selected_metric)
-        {
-            if app.labels_list_state.selected().is_none() && !metric.time_series.is_empty() {
-                let labels: Vec<&String> = metric.get_labels();
-                app.selected_label = labels.first().map(|&s| s.clone());
+        if let Some(metric) = history.get_metric(selected_metric) {
+            let components = app.components(metric);
+            let metric = components.as_ref().unwrap_or(metric);
+            let labels = app.visible_labels(metric);
+            let compared = app
+                .graph_view
+                .compare
+                .as_ref()
+                .and_then(|(name, _)| history.get_metric(name));
+            // the selected series may have expired
+            if app
+                .selected_label
+                .as_ref()
+                .is_some_and(|selected| !labels.contains(selected))
+            {
+                app.selected_label = None;
+                app.labels_list_state.select(None);
+            }
+            if app.labels_list_state.selected().is_none() && !labels.is_empty() {
+                app.selected_label = labels.first().cloned();
                 app.labels_list_state.select(Some(0));
             }
             let chunks = Layout::default()
@@ -89,15 +415,20 @@ fn draw_main(f: &mut Frame, area: Rect, app: &mut App) -> Result<(), Box<dyn Err
                 .direction(Direction::Vertical)
                 .split(chunks[0]);
 
-            draw_details(
+            app.labels_list_area = Some(draw_details(
                 f,
                 chunks[1],
                 chunks_left[1],
                 metric,
+                &labels,
+                &app.label_filter,
                 matches!(app.focus, ElementInFocus::LabelsView),
                 &mut app.labels_list_state,
                 &app.selected_label,
-            );
+                compared,
+                app.show_metadata,
+                &mut app.graph_view,
+            ));
             chunks_left[0]
         } else {
             area
@@ -106,6 +437,66 @@ fn draw_main(f: &mut Frame, area: Rect, app: &mut App) -> Result<(), Box<dyn Err
         area
     };
 
+    let list_name = if app.show_favorites {
+        tr("Favorites")
+    } else {
+        tr("Metrics")
+    };
+    // while grouping, the values of the label are listed first
+    let list_name = match (&app.group_by, &app.group) {
+        (Some(label), None) => label.clone(),
+        (Some(label), Some(group)) => {
+            format!("{} {}={}", list_name, label, grouping::group_name(group))
+        }
+        (None, _) => list_name.to_string(),
+    };
+    let metrics_title = if app.search.is_empty() {
+        list_name
+    } else {
+        format!("{} /{}", list_name, app.search.text)
+    };
+    app.metric_list_area = Some(metric_headers_area);
+    if app.shows_groups() {
+        let names: Vec<String> = groups
+            .iter()
+            .map(|group| grouping::group_name(group).to_string())
+            .collect();
+        // selected by index, a value may read like the group without the label
+        draw_list(
+            f,
+            metric_headers_area,
+            &names,
+            matches!(app.focus, ElementInFocus::MetricHeaders),
+            &None,
+            &mut app.group_list_state,
+            &metrics_title,
+            None,
+            &Highlights::default(),
+        );
+        return Ok(());
+    }
+    let markers = ListMarkers::Metrics {
+        marked: &app.marked_metrics,
+        pinned: &app.pinned_metrics,
+        favorites: &app.favorite_metrics,
+        trends: &metric_trends,
+    };
+    if let Some(rows) = &sections {
+        let statuses = app.metric_scraper.get_status_read_guard()?.targets.clone();
+        draw_sections(
+            f,
+            metric_headers_area,
+            rows,
+            &statuses,
+            &app.collapsed_sections,
+            matches!(app.focus, ElementInFocus::MetricHeaders),
+            &mut app.metric_list_state,
+            &format!("{} ({})", metrics_title, metric_headers.len()),
+            &markers,
+            &metric_highlights,
+        );
+        return Ok(());
+    }
     draw_list(
         f,
         metric_headers_area,
@@ -113,12 +504,176 @@ fn draw_main(f: &mut Frame, area: Rect, app: &mut App) -> Result<(), Box<dyn Err
         matches!(app.focus, ElementInFocus::MetricHeaders),
         &app.selected_metric,
         &mut app.metric_list_state,
-        "Metrics",
+        &metrics_title,
+        Some(markers),
+        &metric_highlights,
     );
 
     Ok(())
 }
 
+/// Draw the metric list split into a section per target, every heading with
+/// the health of its target
+#[allow(clippy::too_many_arguments)]
+fn draw_sections(
+    f: &mut Frame,
+    area: Rect,
+    rows: &[SectionRow],
+    statuses: &[TargetStatus],
+    collapsed: &HashSet<Group>,
+    has_focus: bool,
+    state: &mut ListState,
+    title: &str,
+    markers: &ListMarkers,
+    highlights: &Highlights,
+) {
+    let list_block = Block::default()
+        .borders(Borders::ALL)
+        .style(Style::default().fg(Color::White))
+        .title(title.to_string())
+        .border_type(BorderType::Plain);
+    let now = Instant::now();
+    let list_item: Vec<ListItem> = rows
+        .iter()
+        .map(|row| match row {
+            SectionRow::Heading(group, count) => {
+                let fold = if collapsed.contains(group) {
+                    "▸"
+                } else {
+                    "▾"
+                };
+                let mut spans = vec![Span::styled(
+                    format!("{} {} ({})", fold, grouping::group_name(group), count),
+                    Style::default().add_modifier(Modifier::BOLD),
+                )];
+                if let Some(status) = statuses
+                    .iter()
+                    .find(|status| status.target.instance == *group)
+                {
+                    let (health, color, _) = targets::health(status, now);
+                    spans.push(Span::styled(
+                        format!(" ● {}", health),
+                        Style::default().fg(color),
+                    ));
+                }
+                ListItem::new(Line::from(spans))
+            }
+            SectionRow::Metric(_, name) => ListItem::new(Line::from(vec![
+                Span::styled(
+                    format!("  {}", markers.prefix(name)),
+                    Style::default().fg(markers.color(name)),
+                ),
+                Span::styled(name.clone(), highlights.style(name)),
+            ])),
+        })
+        .collect();
+    let focus_color = focus_color(has_focus);
+    let list = List::new(list_item).block(list_block).highlight_style(
+        Style::default()
+            .bg(focus_color)
+            .fg(Color::Black)
+            .add_modifier(Modifier::BOLD),
+    );
+    f.render_stateful_widget(list, area, state);
+}
+
+/// Item sets decorated with a marker in a list
+enum ListMarkers<'a> {
+    Metrics {
+        marked: &'a HashSet<String>,
+        pinned: &'a HashSet<String>,
+        favorites: &'a HashSet<String>,
+        /// Direction the metrics moved in the latest scrape
+        trends: &'a HashMap<String, Trend>,
+    },
+    /// Checkboxes, checked unless the item is excluded
+    Checkboxes { excluded: &'a HashSet<String> },
+    /// Bullets in the colors of the series plotted on the graph
+    Series { plotted: &'a [String] },
+}
+
+impl ListMarkers<'_> {
+    fn prefix(&self, item: &String) -> String {
+        match self {
+            ListMarkers::Metrics {
+                marked,
+                pinned,
+                favorites,
+                trends,
+            } => format!(
+                "{}{}{} ",
+                if pinned.contains(item) {
+                    "^"
+                } else if favorites.contains(item) {
+                    "★"
+                } else {
+                    " "
+                },
+                if marked.contains(item) { "*" } else { " " },
+                trends
+                    .get(item)
+                    .map_or(' ', |trend| activity::badge(*trend))
+            ),
+            ListMarkers::Checkboxes { excluded } => {
+                if excluded.contains(item) {
+                    "[ ] ".to_string()
+                } else {
+                    "[x] ".to_string()
+                }
+            }
+            ListMarkers::Series { plotted } => {
+                if plotted.contains(item) {
+                    "● ".to_string()
+                } else {
+                    "  ".to_string()
+                }
+            }
+        }
+    }
+
+    fn color(&self, item: &String) -> Color {
+        match self {
+            ListMarkers::Series { plotted } if plotted.contains(item) => theme().series_color(item),
+            _ => Color::Yellow,
+        }
+    }
+}
+
+/// Items drawn in another style in a list
+#[derive(Default)]
+struct Highlights {
+    /// Drawn in bold in the error color, above their alert threshold
+    alerts: HashSet<String>,
+    /// Drawn in the error color
+    anomalies: HashSet<String>,
+    /// Greyed out
+    stale: HashSet<String>,
+    /// Drawn in bold, e.g. metrics with values that changed in the latest scrape
+    changed: HashSet<String>,
+}
+
+impl Highlights {
+    fn style(&self, item: &String) -> Style {
+        let style = if self.alerts.contains(item) {
+            Style::default()
+                .fg(theme().error)
+                .add_modifier(Modifier::BOLD)
+        } else if self.anomalies.contains(item) {
+            Style::default().fg(theme().error)
+        } else if self.stale.contains(item) {
+            Style::default().fg(Color::DarkGray)
+        } else {
+            Style::default()
+        };
+        if self.changed.contains(item) {
+            style.add_modifier(Modifier::BOLD)
+        } else {
+            style
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn draw_list(
     f: &mut Frame,
     area: Rect,
@@ -127,16 +682,14 @@ fn draw_list(
     selected_label_option: &Option<String>,
     state: &mut ListState,
     title_prefix: &str,
+    markers: Option<ListMarkers>,
+    highlights: &Highlights,
 ) {
     if let Some(selected_label) = selected_label_option {
         // if the list is updated we need to be sure that the state index is still point to the correct item
-        let current_index = items
-            .iter()
-            .position(|a| a == selected_label)
-            .expect("index to be found");
-        let state_index = state.selected().expect("state index to be present");
-        if state_index != current_index {
-            state.select(Some(current_index))
+        let current_index = items.iter().position(|a| a == selected_label);
+        if current_index.is_some() && state.selected() != current_index {
+            state.select(current_index)
         }
     }
 
@@ -146,13 +699,24 @@ fn draw_list(
         .style(Style::default().fg(Color::White))
         .title(title)
         .border_type(BorderType::Plain);
+    // e.g. when the search or the labels filter matches nothing
+    if items.is_empty() {
+        f.render_widget(Paragraph::new(tr("No data")).block(list_block), area);
+        return;
+    }
     let list_item: Vec<ListItem> = items
         .iter()
         .map(|header| {
-            ListItem::new(Line::from(vec![Span::styled(
-                header.clone(),
-                Style::default(),
-            )]))
+            let mut spans = vec![];
+            if let Some(markers) = &markers {
+                spans.push(Span::styled(
+                    markers.prefix(header),
+                    Style::default().fg(markers.color(header)),
+                ));
+            }
+            let style = highlights.style(header);
+            spans.push(Span::styled(header.clone(), style));
+            ListItem::new(Line::from(spans))
         })
         .collect();
     let focus_color = focus_color(has_focus);
@@ -165,39 +729,477 @@ fn draw_list(
     f.render_stateful_widget(list, area, state);
 }
 
+/// Draw the labels list and the history of the selected series, returning
+/// the area of the labels list
+#[allow(clippy::too_many_arguments)]
 fn draw_details(
     f: &mut Frame,
     chunk_right: Rect,
     chunk_left: Rect,
     metric: &Metric,
+    labels: &[String],
+    label_filter: &str,
     is_in_focus: bool,
     labels_state: &mut ListState,
     selected_label_option: &Option<String>,
-) {
-    let labels: Vec<String> = metric.get_labels().iter().map(|&s| s.clone()).collect();
+    compared: Option<&Metric>,
+    show_metadata: bool,
+    graph_view: &mut GraphView,
+) -> Rect {
+    let labels_title = if label_filter.is_empty() {
+        tr("Labels").to_string()
+    } else {
+        format!("{} f:{}", tr("Labels"), label_filter)
+    };
+    let excluded = graph_view.excluded_series(&metric.details.name);
+    let plotted = match selected_label_option {
+        Some(selected_label) => history::plotted_series(metric, selected_label, labels, graph_view),
+        None => vec![],
+    };
+    let markers = if graph_view.aggregate {
+        Some(ListMarkers::Checkboxes {
+            excluded: &excluded,
+        })
+    } else if plotted.len() > 1 {
+        Some(ListMarkers::Series { plotted: &plotted })
+    } else {
+        None
+    };
+    let chunk_right = match &metric.details.mismatch {
+        Some(mismatch) => {
+            let lines = mismatch_lines(&metric.details, mismatch);
+            let chunks = Layout::default()
+                .constraints([
+                    Constraint::Length(lines.len() as u16 + 2),
+                    Constraint::Min(0),
+                ])
+                .split(chunk_right);
+            draw_mismatch(f, chunks[0], lines);
+            chunks[1]
+        }
+        None => chunk_right,
+    };
+    let chunk_right = if show_metadata {
+        let lines = metadata::lines(metric);
+        let chunks = Layout::default()
+            .constraints([
+                Constraint::Length(lines.len() as u16 + 2),
+                Constraint::Min(0),
+            ])
+            .split(chunk_right);
+        metadata::draw(f, chunks[0], lines);
+        chunks[1]
+    } else {
+        chunk_right
+    };
     let chunks = Layout::default()
         .constraints([Constraint::Percentage(25), Constraint::Min(16)].as_ref())
         .split(chunk_right);
     draw_list(
         f,
         chunks[0],
-        &labels,
+        labels,
         is_in_focus,
         selected_label_option,
         labels_state,
-        "Labels",
+        &labels_title,
+        markers,
+        &Highlights {
+            alerts: graph_view
+                .thresholds
+                .get(&metric.details.name)
+                .map(|threshold| alerts::alerting_labels(metric, *threshold))
+                .unwrap_or_default(),
+            anomalies: anomaly::anomalous_labels(metric),
+            stale: metric.stale_labels(),
+            ..Highlights::default()
+        },
     );
     if let Some(selected_label) = selected_label_option {
-        history::draw(f, chunks[1], chunk_left, metric, selected_label);
+        if is_in_focus {
+            preview::draw(f, chunks[0], metric, selected_label);
+        }
+        history::draw(
+            f,
+            chunks[1],
+            chunk_left,
+            metric,
+            selected_label,
+            labels,
+            compared,
+            graph_view,
+        );
+    } else {
+        draw_no_data(f, chunks[1], tr("Graph"));
+        draw_no_data(f, chunk_left, tr("History"));
+    }
+    chunks[0]
+}
+
+/// Placeholder of a pane without anything to show
+fn draw_no_data(f: &mut Frame, area: Rect, title: &str) {
+    let block = Block::default().title(title).borders(Borders::ALL);
+    f.render_widget(Paragraph::new(tr("No data")).block(block), area);
+}
+
+/// Both versions of the metadata that changed between scrapes
+fn mismatch_lines(details: &MetricDetails, mismatch: &MetadataMismatch) -> Vec<Line<'static>> {
+    let mut lines = vec![];
+    if details.metric_type != mismatch.metric_type {
+        lines.push(Line::from(format!(
+            "TYPE {:?} first, {:?} in {} scrape(s)",
+            details.metric_type, mismatch.metric_type, mismatch.count
+        )));
+    }
+    if details.docstring != mismatch.docstring {
+        lines.push(Line::from(format!("HELP first: {}", details.docstring)));
+        lines.push(Line::from(format!(
+            "HELP in {} scrape(s): {}",
+            mismatch.count, mismatch.docstring
+        )));
     }
+    lines
+}
+
+fn draw_mismatch(f: &mut Frame, area: Rect, lines: Vec<Line>) {
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme().error))
+        .title(Span::styled(
+            " ! Metadata changed between scrapes ",
+            Style::default()
+                .fg(Color::Black)
+                .bg(theme().error)
+                .add_modifier(Modifier::BOLD),
+        ));
+    let paragraph = Paragraph::new(lines).block(block);
+    f.render_widget(paragraph, area);
 }
 
 pub fn format_value(value: f64) -> String {
     // Use e notation for really small values
-    if value != 0.0 && value < 0.001 {
+    if value != 0.0 && value.abs() < 0.001 {
         format!("{0:.1$e}", value, 3)
     } else {
         let prec = if (value - value.floor()) == 0.0 { 0 } else { 3 };
         format!("{:.1$}", value, prec)
     }
 }
+
+/// Snapshots of whole screens rendered from fixture scrapes. Run the tests
+/// with `UPDATE_SNAPSHOTS=1` to write the snapshots after a deliberate change
+/// of the UI, and review the diff of `src/interactive/ui/snapshots`.
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+
+    use super::*;
+    use crate::interactive::action::Action;
+    use crate::prom::test_data::target_statuses;
+
+    const START: u64 = 1_700_000_000;
+
+    fn scrape(requests: u32, queue: &str, buckets: [u32; 3], duration_count: u32) -> String {
+        format!(
+            "# HELP http_requests_total Handled requests
+# TYPE http_requests_total counter
+http_requests_total{{code=\"200\"}} {requests}
+http_requests_total{{code=\"500\"}} 3
+# HELP queue_size Queued jobs
+# TYPE queue_size gauge
+queue_size{{queue=\"mail\"}} {queue}
+queue_size{{queue=\"push\"}} 2
+# HELP request_seconds Request latency
+# TYPE request_seconds histogram
+request_seconds_bucket{{le=\"0.1\"}} {}
+request_seconds_bucket{{le=\"0.5\"}} {}
+request_seconds_bucket{{le=\"+Inf\"}} {}
+request_seconds_sum 42.5
+request_seconds_count {}
+# HELP rpc_seconds RPC latency
+# TYPE rpc_seconds summary
+rpc_seconds{{quantile=\"0.5\"}} 0.2
+rpc_seconds{{quantile=\"0.9\"}} 0.7
+rpc_seconds{{quantile=\"0.99\"}} 1.3
+rpc_seconds_sum 30
+rpc_seconds_count {duration_count}
+",
+            buckets[0], buckets[1], buckets[2], buckets[2]
+        )
+    }
+
+    fn fixture_app(error_msg: Option<&str>) -> App<'static> {
+        set_display_zone(DisplayZone::Utc);
+        let scrapes = [
+            scrape(100, "4", [10, 40, 50], 20),
+            scrape(130, "9", [15, 60, 80], 26),
+            scrape(190, "6", [30, 90, 110], 40),
+            scrape(200, "NaN", [32, 95, 120], 41),
+        ];
+        let scrapes: Vec<(u64, &str)> = scrapes
+            .iter()
+            .enumerate()
+            .map(|(i, text)| (START + 15 * i as u64, text.as_str()))
+            .collect();
+        App::with_fixture(&scrapes, error_msg)
+    }
+
+    fn select_metric(app: &mut App, metric: &str) {
+        let headers = app.get_metrics_headers().unwrap();
+        let index = headers.iter().position(|header| header == metric);
+        app.metric_list_state.select(index);
+        app.selected_metric = Some(metric.to_string());
+    }
+
+    /// Text of the screen drawn for `app`, one line per row
+    fn render(app: &mut App) -> String {
+        let mut terminal = Terminal::new(TestBackend::new(100, 32)).unwrap();
+        terminal.draw(|f| draw(f, app).unwrap()).unwrap();
+        let buffer = terminal.backend().buffer();
+        (0..buffer.area.height)
+            .map(|y| {
+                let line: String = (0..buffer.area.width)
+                    .map(|x| buffer.get(x, y).symbol())
+                    .collect();
+                line.trim_end().to_string() + "\n"
+            })
+            .collect::<String>()
+            // the version in the title changes with every release
+            .replace(env!("CARGO_PKG_VERSION"), "x.y.z")
+    }
+
+    fn assert_snapshot(name: &str, screen: &str) {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("src/interactive/ui/snapshots")
+            .join(format!("{}.txt", name));
+        if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, screen).unwrap();
+            return;
+        }
+        let expected = std::fs::read_to_string(&path).unwrap_or_else(|_| {
+            panic!(
+                "no snapshot {}, run with UPDATE_SNAPSHOTS=1",
+                path.display()
+            )
+        });
+        assert!(
+            expected == screen,
+            "screen differs from {}, run with UPDATE_SNAPSHOTS=1 to update it\n{}",
+            path.display(),
+            screen
+        );
+    }
+
+    #[test]
+    fn test_snapshot_metric_list() {
+        let mut app = fixture_app(None);
+        assert_snapshot("metric_list", &render(&mut app));
+    }
+
+    #[test]
+    fn test_snapshot_gauge_graph() {
+        let mut app = fixture_app(None);
+        select_metric(&mut app, "queue_size");
+        app.selected_label = Some("queue=\"mail\"".to_string());
+        app.labels_list_state.select(Some(0));
+        assert_snapshot("gauge_graph", &render(&mut app));
+    }
+
+    #[test]
+    fn test_snapshot_aggregate_spread() {
+        let mut app = fixture_app(None);
+        select_metric(&mut app, "queue_size");
+        app.selected_label = Some("queue=\"mail\"".to_string());
+        app.labels_list_state.select(Some(0));
+        app.on_toggle_aggregate();
+        app.on_toggle_aggregate();
+        assert_snapshot("aggregate_spread", &render(&mut app));
+    }
+
+    #[test]
+    fn test_snapshot_histogram() {
+        let mut app = fixture_app(None);
+        select_metric(&mut app, "request_seconds");
+        assert_snapshot("histogram", &render(&mut app));
+    }
+
+    #[test]
+    fn test_snapshot_histogram_narrow_bars() {
+        let mut app = fixture_app(None);
+        select_metric(&mut app, "request_seconds");
+        app.graph_view.bar_width = Some(2);
+        assert_snapshot("histogram_narrow_bars", &render(&mut app));
+    }
+
+    #[test]
+    fn test_snapshot_histogram_scrolled() {
+        let mut app = fixture_app(None);
+        select_metric(&mut app, "request_seconds");
+        app.graph_view.bar_width = Some(25);
+        app.dispatch(Action::ScrollBarsRight).unwrap();
+        app.dispatch(Action::ScrollBarsRight).unwrap();
+        let screen = render(&mut app);
+        // clamped to the last bucket that fills the pane
+        assert_eq!(app.graph_view.bar_scroll, 1);
+        assert_snapshot("histogram_scrolled", &screen);
+    }
+
+    #[test]
+    fn test_snapshot_histogram_components() {
+        let mut app = fixture_app(None);
+        select_metric(&mut app, "request_seconds");
+        app.on_toggle_components();
+        assert_snapshot("histogram_components", &render(&mut app));
+    }
+
+    #[test]
+    fn test_snapshot_summary() {
+        let mut app = fixture_app(None);
+        select_metric(&mut app, "rpc_seconds");
+        assert_snapshot("summary", &render(&mut app));
+    }
+
+    #[test]
+    fn test_snapshot_grouped() {
+        let mut app = fixture_app(None);
+        app.group_by = Some("queue".to_string());
+        assert_snapshot("grouped", &render(&mut app));
+        app.group = Some(Some("mail".to_string()));
+        app.selected_metric = None;
+        app.metric_list_state.select(None);
+        assert_snapshot("grouped_metrics", &render(&mut app));
+    }
+
+    #[test]
+    fn test_snapshot_no_metrics() {
+        let mut app = App::with_fixture(&[], None);
+        assert_snapshot("no_metrics", &render(&mut app));
+    }
+
+    #[test]
+    fn test_snapshot_no_labels() {
+        let mut app = fixture_app(None);
+        select_metric(&mut app, "queue_size");
+        app.label_filter = "nomatch".to_string();
+        assert_snapshot("no_labels", &render(&mut app));
+    }
+
+    #[test]
+    fn test_no_samples() {
+        let app = fixture_app(None);
+        let history = app.metric_scraper.get_history_lock().unwrap();
+        for name in ["http_requests_total", "request_seconds", "rpc_seconds"] {
+            let mut metric = history.get_metric(name).unwrap().clone();
+            metric
+                .time_series
+                .values_mut()
+                .for_each(|series| series.samples.clear());
+            let label = metric.time_series.keys().next().unwrap().clone();
+            let mut graph_view = GraphView::default();
+            let mut terminal = Terminal::new(TestBackend::new(100, 32)).unwrap();
+            terminal
+                .draw(|f| {
+                    let (left, right) = (Rect::new(0, 0, 30, 32), Rect::new(30, 0, 70, 32));
+                    history::draw(
+                        f,
+                        right,
+                        left,
+                        &metric,
+                        &label,
+                        std::slice::from_ref(&label),
+                        None,
+                        &mut graph_view,
+                    );
+                })
+                .unwrap();
+            let buffer = terminal.backend().buffer();
+            let screen: String = buffer.content().iter().map(|cell| cell.symbol()).collect();
+            assert!(screen.contains("No data"), "{}: {}", name, screen);
+        }
+    }
+
+    #[test]
+    fn test_snapshot_dashboard() {
+        let mut app = fixture_app(None);
+        app.dashboard = toml::from_str(
+            r#"
+            [[panels]]
+            metric = "http_requests_total"
+            rate = true
+
+            [[panels]]
+            metric = "queue_size"
+            chart = "bars"
+
+            [[panels]]
+            metric = "request_seconds_count"
+            chart = "value"
+
+            [[panels]]
+            title = "Median RPC latency"
+            metric = "rpc_seconds"
+            labels = { quantile = "0.5" }
+            "#,
+        )
+        .unwrap();
+        app.screen = Screen::Dashboard;
+        assert_snapshot("dashboard", &render(&mut app));
+    }
+
+    #[test]
+    fn test_snapshot_sections() {
+        set_display_zone(DisplayZone::Utc);
+        let scrapes = [START, START + 15].map(|timestamp| {
+            (
+                timestamp,
+                "# HELP up Whether the target is up
+# TYPE up gauge
+up{instance=\"node1\"} 1
+up{instance=\"node2\"} 1
+# HELP queue_size Queued jobs
+# TYPE queue_size gauge
+queue_size{instance=\"node2\",queue=\"mail\"} 3
+",
+            )
+        });
+        let mut app = App::with_fixture(&scrapes, None);
+        let (_, status) = app.metric_scraper.shared();
+        status.write().unwrap().targets = target_statuses(&[
+            ("node1", None),
+            ("node2", None),
+            ("node3", Some("connection refused")),
+        ]);
+        assert_snapshot("sections", &render(&mut app));
+        app.collapsed_sections.insert(Some("node1".to_string()));
+        app.dispatch(Action::Down).unwrap();
+        app.dispatch(Action::Down).unwrap();
+        assert_snapshot("sections_collapsed", &render(&mut app));
+    }
+
+    #[test]
+    fn test_snapshot_payload() {
+        let mut app = fixture_app(None);
+        app.screen = Screen::Payload;
+        assert_snapshot("payload", &render(&mut app));
+    }
+
+    #[test]
+    fn test_snapshot_tabs() {
+        let mut app = fixture_app(None);
+        let other = fixture_app(None);
+        app.add_tab("http://node2:9100/metrics", other.metric_scraper);
+        select_metric(&mut app, "queue_size");
+        assert_snapshot("tabs", &render(&mut app));
+    }
+
+    #[test]
+    fn test_snapshot_scrape_error() {
+        let mut app = fixture_app(Some("connection refused"));
+        app.show_footer = true;
+        assert_snapshot("scrape_error", &render(&mut app));
+    }
+}
@@ -0,0 +1,108 @@
+use ratatui::{
+    layout::{Constraint, Rect},
+    style::{Modifier, Style},
+    widgets::{Block, Borders, Row, Table},
+    Frame,
+};
+
+use crate::prom::HistoryStore;
+
+use super::units::format_bytes;
+
+/// A metric family and its share of the latest scrape
+pub struct FamilyCost {
+    pub metric: String,
+    pub series: usize,
+    pub bytes: usize,
+}
+
+/// Families of the text exposition by their bytes in the latest scrape,
+/// largest first, and the bytes of all of them
+pub fn family_costs(history: &dyn HistoryStore) -> (Vec<FamilyCost>, usize) {
+    let mut costs: Vec<FamilyCost> = history
+        .get_metrics_headers()
+        .into_iter()
+        .filter_map(|name| {
+            let metric = history.get_metric(&name)?;
+            (metric.details.payload_bytes > 0).then_some(FamilyCost {
+                series: metric.time_series.len(),
+                bytes: metric.details.payload_bytes,
+                metric: name,
+            })
+        })
+        .collect();
+    costs.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.metric.cmp(&b.metric)));
+    let total = costs.iter().map(|cost| cost.bytes).sum();
+    (costs, total)
+}
+
+pub fn draw(f: &mut Frame, area: Rect, history: &dyn HistoryStore, key: char) {
+    let (mut costs, total) = family_costs(history);
+    // one row per line, minus borders and header
+    costs.truncate(area.height.saturating_sub(3) as usize);
+    let title = format!(
+        "Payload bytes per metric family, {} in total ({} to return)",
+        format_bytes(total as f64),
+        key
+    );
+    let rows = costs.iter().enumerate().map(|(index, cost)| {
+        Row::new(vec![
+            (index + 1).to_string(),
+            cost.metric.clone(),
+            cost.series.to_string(),
+            format_bytes(cost.bytes as f64),
+            format!("{:.1}%", cost.bytes as f64 * 100.0 / total.max(1) as f64),
+            format_bytes((cost.bytes / cost.series.max(1)) as f64),
+        ])
+    });
+    let t = Table::new(
+        rows,
+        &[
+            Constraint::Length(4),
+            Constraint::Percentage(46),
+            Constraint::Percentage(10),
+            Constraint::Percentage(14),
+            Constraint::Percentage(10),
+            Constraint::Percentage(14),
+        ],
+    )
+    .block(Block::default().borders(Borders::ALL).title(title))
+    .header(
+        Row::new(vec![
+            "#",
+            "Metric",
+            "Series",
+            "Bytes",
+            "Share",
+            "Per series",
+        ])
+        .style(Style::default().add_modifier(Modifier::BOLD)),
+    );
+    f.render_widget(t, area);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prom::test_data::{memory_store, scrape_all};
+
+    #[test]
+    fn test_family_costs() {
+        let text = "# TYPE up gauge
+up 1
+# HELP requests_total Handled requests
+# TYPE requests_total counter
+requests_total{code=\"200\"} 10
+requests_total{code=\"500\"} 2
+";
+        let mut history = memory_store();
+        for metric in scrape_all(text, 1) {
+            history.insert_scrape(metric);
+        }
+        let (costs, total) = family_costs(history.as_ref());
+        assert_eq!(total, text.len());
+        assert_eq!(costs[0].metric, "requests_total");
+        assert_eq!(costs[0].series, 2);
+        assert_eq!(costs[1].bytes, "# TYPE up gauge\nup 1\n".len());
+    }
+}
@@ -0,0 +1,95 @@
+use std::collections::VecDeque;
+
+use ratatui::{
+    layout::Rect,
+    style::Style,
+    widgets::{Block, Borders, Clear, Sparkline},
+    Frame,
+};
+
+use crate::prom::{Metric, Sample};
+
+use super::{format_value, theme};
+
+/// Size of the preview, including its border
+const WIDTH: u16 = 32;
+const HEIGHT: u16 = 4;
+
+/// Latest values of a gauge or counter series scaled to 0..=100, oldest first
+fn sparkline_data(samples: &VecDeque<Sample>, len: usize) -> Option<(Vec<u64>, f64)> {
+    let values: Vec<f64> = samples
+        .iter()
+        .rev()
+        .take(len)
+        .filter_map(|sample| match sample {
+            Sample::GaugeSample(s) | Sample::CounterSample(s) => Some(s.value),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .into_iter()
+        .rev()
+        .collect();
+    let latest = *values.last()?;
+    let min = values.iter().copied().fold(f64::MAX, f64::min);
+    let max = values.iter().copied().fold(f64::MIN, f64::max);
+    let range = if max > min { max - min } else { 1.0 };
+    let data = values
+        .iter()
+        .map(|value| ((value - min) / range * 100.0).round() as u64)
+        .collect();
+    Some((data, latest))
+}
+
+/// Draw a sparkline of the highlighted series in the bottom right corner of `area`
+pub fn draw(f: &mut Frame, area: Rect, metric: &Metric, label: &str) {
+    if area.width < WIDTH + 2 || area.height < HEIGHT + 2 {
+        return;
+    }
+    let Some(time_series) = metric.time_series.get(label) else {
+        return;
+    };
+    let corner = Rect::new(
+        area.right() - WIDTH - 1,
+        area.bottom() - HEIGHT - 1,
+        WIDTH,
+        HEIGHT,
+    );
+    let Some((data, latest)) = sparkline_data(&time_series.samples, (WIDTH - 2) as usize) else {
+        return;
+    };
+    let sparkline = Sparkline::default()
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!("Preview {}", format_value(latest))),
+        )
+        .style(Style::default().fg(theme().graph))
+        .data(&data);
+    f.render_widget(Clear, corner);
+    f.render_widget(sparkline, corner);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prom::SingleValueSample;
+
+    use super::*;
+
+    #[test]
+    fn test_sparkline_data() {
+        let mut samples: VecDeque<Sample> = [5.0, 10.0, 20.0, 15.0]
+            .iter()
+            .enumerate()
+            .map(|(timestamp, &value)| {
+                Sample::GaugeSample(SingleValueSample {
+                    timestamp: timestamp as u64,
+                    value,
+                })
+            })
+            .collect();
+        assert_eq!(sparkline_data(&samples, 3), Some((vec![0, 100, 50], 15.0)));
+        samples.truncate(1);
+        assert_eq!(sparkline_data(&samples, 3), Some((vec![0], 5.0)));
+        assert_eq!(sparkline_data(&VecDeque::new(), 3), None);
+    }
+}
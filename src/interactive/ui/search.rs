@@ -1,52 +1,111 @@
-// use crossterm::event::KeyCode;
-// use tui::{
-//     backend::Backend,
-//     layout::Rect,
-//     text::Text,
-//     widgets::{Block, Borders, Paragraph},
-//     Frame,
-// };
-
-// pub struct SearchWidget {
-//     active: bool,
-//     pub input: Vec<char>,
-// }
-
-// impl SearchWidget {
-//     pub fn new(active: bool, input: Vec<char>) -> Self {
-//         SearchWidget {
-//             active: active,
-//             input: input,
-//         }
-//     }
-// }
-
-// impl SearchWidget {
-//     fn render<B: Backend>(&self, f: &mut Frame<B>, area: &Rect) {
-//         let component = Paragraph::new(Text::from(self.input.iter().collect::<String>())).block(
-//             Block::default()
-//                 .borders(Borders::ALL)
-//                 .title(super::style::create_styled_title("Search", self.active)),
-//         );
-//         f.render_widget(component, *area);
-//     }
-
-//     fn set_active(&mut self, active: bool) {
-//         self.active = active;
-//     }
-
-//     fn handle_input(&mut self, key_code: crossterm::event::KeyCode) {
-//         match key_code {
-//             KeyCode::Char(c) => {
-//                 self.input.push(c);
-//             }
-//             KeyCode::Esc => {
-//                 self.input.clear();
-//             }
-//             KeyCode::Backspace => {
-//                 self.input.pop();
-//             }
-//             _ => {}
-//         }
-//     }
-// }
+use std::collections::HashMap;
+
+use crate::prom::Metric;
+
+/// A search in the metric list. All space separated terms must match:
+/// - `text` a part of the metric name
+/// - `label:key` or `label:key=text` a label of a series, with a value containing text
+/// - `help:text` a part of the metric's HELP docstring
+///
+/// Matching ignores case.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SearchQuery {
+    pub text: String,
+    names: Vec<String>,
+    labels: Vec<(String, Option<String>)>,
+    help: Vec<String>,
+}
+
+impl SearchQuery {
+    pub fn parse(text: &str) -> Self {
+        let mut query = SearchQuery {
+            text: text.to_string(),
+            ..Default::default()
+        };
+        for term in text.to_lowercase().split_whitespace() {
+            if let Some(label) = term.strip_prefix("label:") {
+                query.labels.push(match label.split_once('=') {
+                    Some((key, value)) => {
+                        (key.to_string(), Some(value.trim_matches('"').to_string()))
+                    }
+                    None => (label.to_string(), None),
+                });
+            } else if let Some(help) = term.strip_prefix("help:") {
+                query.help.push(help.to_string());
+            } else {
+                query.names.push(term.to_string());
+            }
+        }
+        query
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty() && self.labels.is_empty() && self.help.is_empty()
+    }
+
+    /// Whether the metric matches, i.e. its name and docstring match and at
+    /// least one of its series matches the label terms
+    pub fn matches_metric(&self, metric: &Metric) -> bool {
+        let name = metric.details.name.to_lowercase();
+        let docstring = metric.details.docstring.to_lowercase();
+        self.names.iter().all(|term| name.contains(term))
+            && self.help.iter().all(|term| docstring.contains(term))
+            && (self.labels.is_empty()
+                || metric
+                    .time_series
+                    .values()
+                    .any(|time_series| self.matches_series(&time_series.labels)))
+    }
+
+    /// Whether a series with these labels matches the label terms
+    pub fn matches_series(&self, labels: &HashMap<String, String>) -> bool {
+        self.labels.iter().all(|(key, value)| {
+            labels.iter().any(|(label_key, label_value)| {
+                label_key.eq_ignore_ascii_case(key)
+                    && value
+                        .as_ref()
+                        .is_none_or(|value| label_value.to_lowercase().contains(value))
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prom::test_data::scrape;
+
+    use super::*;
+
+    fn metric() -> Metric {
+        let text = "# HELP http_request_duration_seconds Request latency
+# TYPE http_request_duration_seconds gauge
+http_request_duration_seconds{pod=\"api-1\"} 1
+http_request_duration_seconds{pod=\"web-1\"} 1
+";
+        scrape(text, 0).into_metric()
+    }
+
+    #[test]
+    fn test_matches_metric() {
+        let metric = metric();
+        let matches = |text| SearchQuery::parse(text).matches_metric(&metric);
+        assert!(matches(""));
+        assert!(matches("REQUEST duration"));
+        assert!(!matches("request bytes"));
+        assert!(matches("help:latency"));
+        assert!(!matches("help:size"));
+        assert!(matches("label:pod=api"));
+        assert!(matches("label:pod=\"web\" http"));
+        assert!(!matches("label:pod=db"));
+        assert!(!matches("label:namespace"));
+    }
+
+    #[test]
+    fn test_matches_series() {
+        let query = SearchQuery::parse("label:pod=api");
+        let series = |pod: &str| HashMap::from([("pod".to_string(), pod.to_string())]);
+        assert!(query.matches_series(&series("api-1")));
+        assert!(!query.matches_series(&series("web-1")));
+        assert!(SearchQuery::parse("http").matches_series(&series("web-1")));
+    }
+}
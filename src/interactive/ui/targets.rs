@@ -0,0 +1,102 @@
+use std::time::Instant;
+
+use ratatui::{
+    layout::{Constraint, Rect},
+    style::{Color, Modifier, Style},
+    text::Span,
+    widgets::{Block, Borders, Cell, Row, Table},
+    Frame,
+};
+
+use crate::prom::TargetStatus;
+
+/// Health of a target and what's known about it: the error of its latest
+/// scrape, and the delay it imposed while it's rate limiting
+pub fn health(status: &TargetStatus, now: Instant) -> (&'static str, Color, String) {
+    match (&status.error, status.rate_limited_until) {
+        (_, Some(until)) if until > now => {
+            let delay = until.saturating_duration_since(now).as_secs_f64().ceil();
+            let details = match &status.error {
+                Some(error) => format!("next scrape in {}s, {}", delay, error),
+                None => format!("next scrape in {}s", delay),
+            };
+            ("rate limited", Color::Yellow, details)
+        }
+        (Some(error), _) => ("down", Color::Red, error.clone()),
+        (None, _) => ("up", Color::Green, String::new()),
+    }
+}
+
+pub fn draw(f: &mut Frame, area: Rect, targets: &[TargetStatus], key: char) {
+    let now = Instant::now();
+    let down = targets
+        .iter()
+        .filter(|status| status.error.is_some())
+        .count();
+    let title = format!(
+        "Targets, {} of {} up ({} to return)",
+        targets.len() - down,
+        targets.len(),
+        key
+    );
+    let rows = targets.iter().map(|status| {
+        let (health, color, details) = health(status, now);
+        Row::new(vec![
+            Cell::from(status.name().to_string()),
+            Cell::from(Span::styled(health, Style::default().fg(color))),
+            Cell::from(details),
+        ])
+    });
+    let t = Table::new(
+        rows,
+        &[
+            Constraint::Percentage(30),
+            Constraint::Length(12),
+            Constraint::Percentage(60),
+        ],
+    )
+    .block(Block::default().borders(Borders::ALL).title(title))
+    .header(
+        Row::new(vec!["Target", "Health", "Details"])
+            .style(Style::default().add_modifier(Modifier::BOLD)),
+    );
+    f.render_widget(t, area);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::prom::Target;
+
+    #[test]
+    fn test_health() {
+        let now = Instant::now();
+        let mut status = TargetStatus {
+            target: Target {
+                url: "http://node1:9100/metrics".to_string(),
+                instance: Some("node1".to_string()),
+            },
+            error: None,
+            rate_limited_until: None,
+        };
+        assert_eq!(health(&status, now), ("up", Color::Green, String::new()));
+        status.error = Some("connection refused".to_string());
+        assert_eq!(
+            health(&status, now),
+            ("down", Color::Red, "connection refused".to_string())
+        );
+        status.rate_limited_until = Some(now + Duration::from_millis(6500));
+        assert_eq!(
+            health(&status, now),
+            (
+                "rate limited",
+                Color::Yellow,
+                "next scrape in 7s, connection refused".to_string()
+            )
+        );
+        // the delay passed, the target is scraped again with the next scrape
+        assert_eq!(health(&status, now + Duration::from_secs(7)).0, "down");
+    }
+}
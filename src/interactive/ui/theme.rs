@@ -0,0 +1,93 @@
+use std::sync::OnceLock;
+
+use ratatui::style::Color;
+
+/// Colors and text effects used by the UI
+#[derive(Clone, Debug)]
+pub struct Theme {
+    /// Highlight of the selected item in the focused list
+    pub focus: Color,
+    /// Graph lines and bars
+    pub graph: Color,
+    /// Scrape error messages
+    pub error: Color,
+    /// Pulse and flash important text, off in low-power and reduced-motion mode
+    pub animate: bool,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            focus: Color::LightGreen,
+            graph: Color::LightGreen,
+            error: Color::Red,
+            animate: true,
+        }
+    }
+}
+
+/// Colors of the series of graphs with several lines, picked by their labels
+const SERIES_COLORS: [Color; 10] = [
+    Color::Yellow,
+    Color::LightCyan,
+    Color::LightMagenta,
+    Color::LightRed,
+    Color::LightBlue,
+    Color::White,
+    Color::Cyan,
+    Color::Magenta,
+    Color::LightYellow,
+    Color::Blue,
+];
+
+impl Theme {
+    /// Color of the series with `labels`, e.g. `code="500"`, on a graph with
+    /// several series. Picked by the hash of the labels, so a series keeps its
+    /// color in every view and session.
+    pub fn series_color(&self, labels: &str) -> Color {
+        let labels = labels.trim_start_matches('{').trim_end_matches('}');
+        SERIES_COLORS[(fnv1a(labels) % SERIES_COLORS.len() as u64) as usize]
+    }
+}
+
+/// 64 bit FNV-1a hash, unlike `DefaultHasher` the same in every build
+fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+static THEME: OnceLock<Theme> = OnceLock::new();
+
+/// Set the theme used for drawing. Only the first call has an effect.
+pub fn set_theme(theme: Theme) {
+    if THEME.set(theme).is_err() {
+        log::warn!("theme already initialized");
+    }
+}
+
+pub fn theme() -> &'static Theme {
+    THEME.get_or_init(Theme::default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_series_color() {
+        let theme = Theme::default();
+        assert_eq!(fnv1a(""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a("a"), 0xaf63_dc4c_8601_ec8c);
+        // the same series in the labels of a metric and of an expression result
+        assert_eq!(
+            theme.series_color("code=\"500\""),
+            theme.series_color("{code=\"500\"}")
+        );
+        let colors: std::collections::HashSet<Color> = ["200", "404", "500", "503"]
+            .iter()
+            .map(|code| theme.series_color(&format!("code=\"{}\"", code)))
+            .collect();
+        assert!(colors.len() > 1);
+    }
+}
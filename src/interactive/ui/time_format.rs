@@ -0,0 +1,221 @@
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, FixedOffset, Local, TimeZone, Utc};
+use serde::Deserialize;
+
+/// How timestamps are shown in tables and graph axes
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum TimeFormat {
+    Rfc2822,
+    Rfc3339,
+    /// Age of the timestamp, e.g. "42s ago"
+    Relative,
+    /// A strftime pattern like "%H:%M:%S"
+    Pattern(String),
+}
+
+impl Default for TimeFormat {
+    fn default() -> Self {
+        Self::Pattern("%H:%M:%S".to_string())
+    }
+}
+
+impl FromStr for TimeFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "rfc2822" => Ok(Self::Rfc2822),
+            "rfc3339" => Ok(Self::Rfc3339),
+            "relative" => Ok(Self::Relative),
+            _ if s.contains('%') && !StrftimeItems::new(s).any(|item| item == Item::Error) => {
+                Ok(Self::Pattern(s.to_string()))
+            }
+            _ => Err(format!(
+                "expected rfc2822, rfc3339, relative or a strftime pattern, got \"{}\"",
+                s
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for TimeFormat {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// Time zone timestamps are shown in
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum DisplayZone {
+    #[default]
+    Local,
+    Utc,
+    /// A fixed offset like "+05:30"
+    Offset(FixedOffset),
+    /// An IANA time zone like "Europe/Berlin", see `set_display_zone`
+    Named(String),
+}
+
+impl FromStr for DisplayZone {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "local" => Ok(Self::Local),
+            "utc" | "z" => Ok(Self::Utc),
+            _ if s.starts_with(['+', '-']) => s
+                .parse()
+                .map(Self::Offset)
+                .map_err(|_| format!("expected an offset like +05:30, got \"{}\"", s)),
+            _ if zoneinfo_dir().join(s).is_file() => Ok(Self::Named(s.to_string())),
+            _ => Err(format!(
+                "expected UTC, Local, an offset like +05:30 or an IANA time zone like Europe/Berlin, got \"{}\"",
+                s
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for DisplayZone {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl DisplayZone {
+    fn convert(&self, time: DateTime<Local>) -> DateTime<FixedOffset> {
+        match self {
+            // named zones are applied to Local through TZ
+            Self::Local | Self::Named(_) => time.fixed_offset(),
+            Self::Utc => time.with_timezone(&Utc).fixed_offset(),
+            Self::Offset(offset) => time.with_timezone(offset),
+        }
+    }
+}
+
+/// Directory of the IANA time zone database
+fn zoneinfo_dir() -> &'static Path {
+    Path::new("/usr/share/zoneinfo")
+}
+
+impl TimeFormat {
+    fn format(&self, time: DateTime<FixedOffset>, now: DateTime<FixedOffset>) -> String {
+        match self {
+            Self::Rfc2822 => time.to_rfc2822(),
+            Self::Rfc3339 => time.to_rfc3339(),
+            Self::Relative => {
+                let secs = (now - time).num_seconds().max(0);
+                match secs {
+                    0..=59 => format!("{}s ago", secs),
+                    60..=3599 => format!("{}m{:02}s ago", secs / 60, secs % 60),
+                    _ => format!("{}h{:02}m ago", secs / 3600, secs % 3600 / 60),
+                }
+            }
+            Self::Pattern(pattern) => time.format(pattern).to_string(),
+        }
+    }
+
+    /// Width of the timestamps shown, the one of the longest relative age
+    /// rather than of the current time for `Relative`
+    fn width(&self, now: DateTime<FixedOffset>) -> usize {
+        match self {
+            Self::Relative => "99h59m ago".len(),
+            _ => self.format(now, now).chars().count(),
+        }
+    }
+}
+
+static TIME_FORMAT: OnceLock<TimeFormat> = OnceLock::new();
+static DISPLAY_ZONE: OnceLock<DisplayZone> = OnceLock::new();
+
+/// Set the format used for all timestamps. Only the first call has an effect.
+pub fn set_time_format(time_format: TimeFormat) {
+    if TIME_FORMAT.set(time_format).is_err() {
+        log::warn!("time format already initialized");
+    }
+}
+
+/// Set the time zone used for all timestamps. Only the first call has an
+/// effect. Named zones set TZ for the process, so call it before starting
+/// other threads.
+pub fn set_display_zone(zone: DisplayZone) {
+    if let DisplayZone::Named(name) = &zone {
+        std::env::set_var("TZ", name);
+    }
+    if DISPLAY_ZONE.set(zone).is_err() {
+        log::warn!("display time zone already initialized");
+    }
+}
+
+pub fn format_time(time: DateTime<Local>) -> String {
+    let zone = DISPLAY_ZONE.get_or_init(DisplayZone::default);
+    TIME_FORMAT
+        .get_or_init(TimeFormat::default)
+        .format(zone.convert(time), zone.convert(Local::now()))
+}
+
+/// Width of a formatted timestamp, for sizing table columns
+pub fn time_width() -> u16 {
+    let now = DISPLAY_ZONE
+        .get_or_init(DisplayZone::default)
+        .convert(Local::now());
+    TIME_FORMAT.get_or_init(TimeFormat::default).width(now) as u16
+}
+
+/// Format a unix timestamp in seconds
+pub fn format_timestamp(timestamp: u64) -> String {
+    format_time(Local.timestamp_opt(timestamp as i64, 0).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_time_format() {
+        assert_eq!("RFC3339".parse(), Ok(TimeFormat::Rfc3339));
+        assert_eq!("relative".parse(), Ok(TimeFormat::Relative));
+        assert_eq!(
+            "%d %H:%M".parse(),
+            Ok(TimeFormat::Pattern("%d %H:%M".to_string()))
+        );
+        assert!("iso".parse::<TimeFormat>().is_err());
+        assert!("%Q".parse::<TimeFormat>().is_err());
+    }
+
+    #[test]
+    fn test_display_zone() {
+        assert_eq!("UTC".parse(), Ok(DisplayZone::Utc));
+        assert_eq!("local".parse(), Ok(DisplayZone::Local));
+        let offset = FixedOffset::east_opt(5 * 3600 + 1800).unwrap();
+        assert_eq!("+05:30".parse(), Ok(DisplayZone::Offset(offset)));
+        assert!("Mars/Olympus_Mons".parse::<DisplayZone>().is_err());
+
+        let time = Local.timestamp_opt(0, 0).unwrap();
+        let format = TimeFormat::Pattern("%H:%M %z".to_string());
+        let formatted = |zone: DisplayZone| format.format(zone.convert(time), zone.convert(time));
+        assert_eq!(formatted(DisplayZone::Utc), "00:00 +0000");
+        assert_eq!(formatted(DisplayZone::Offset(offset)), "05:30 +0530");
+    }
+
+    #[test]
+    fn test_format_relative() {
+        let now = Local.timestamp_opt(10_000, 0).unwrap().fixed_offset();
+        let format = |secs| TimeFormat::Relative.format(now - chrono::Duration::seconds(secs), now);
+        assert_eq!(format(5), "5s ago");
+        assert_eq!(format(125), "2m05s ago");
+        assert_eq!(format(7260), "2h01m ago");
+        assert_eq!(TimeFormat::Relative.width(now), format(359_999).len());
+        assert_eq!(TimeFormat::default().width(now), "00:00:00".len());
+    }
+}
@@ -0,0 +1,81 @@
+use ratatui::{
+    layout::{Constraint, Rect},
+    style::{Modifier, Style},
+    widgets::{Block, Borders, Row, Table},
+    Frame,
+};
+
+use crate::prom::{HistoryStore, MetricType, Sample};
+
+use super::{counter::counter_rate, format_value};
+
+/// Time span the rates are computed over, in seconds
+const WINDOW: u64 = 5 * 60;
+
+/// A counter series and its rate over the last window
+pub struct Mover {
+    pub metric: String,
+    pub labels: String,
+    pub rate: f64,
+}
+
+/// The `n` counter series with the highest rate over the last window
+pub fn top_movers(history: &dyn HistoryStore, n: usize) -> Vec<Mover> {
+    let mut movers = vec![];
+    for name in history.get_metrics_headers() {
+        let Some(metric) = history.get_metric(&name) else {
+            continue;
+        };
+        if !matches!(metric.details.metric_type, MetricType::Counter) {
+            continue;
+        }
+        for (labels, time_series) in &metric.time_series {
+            let latest = match time_series.samples.back() {
+                Some(Sample::CounterSample(sample)) => sample.timestamp,
+                _ => continue,
+            };
+            if let Some(rate) = counter_rate(&time_series.samples, latest.saturating_sub(WINDOW)) {
+                movers.push(Mover {
+                    metric: name.clone(),
+                    labels: labels.clone(),
+                    rate,
+                });
+            }
+        }
+    }
+    movers.sort_by(|a, b| b.rate.total_cmp(&a.rate));
+    movers.truncate(n);
+    movers
+}
+
+pub fn draw(f: &mut Frame, area: Rect, history: &dyn HistoryStore) {
+    // one row per line, minus borders and header
+    let movers = top_movers(history, area.height.saturating_sub(3) as usize);
+    let title = format!(
+        "Top counters by rate over the last {}m (t to return)",
+        WINDOW / 60
+    );
+    let rows = movers.iter().enumerate().map(|(index, mover)| {
+        Row::new(vec![
+            (index + 1).to_string(),
+            mover.metric.clone(),
+            mover.labels.clone(),
+            format_value(mover.rate),
+        ])
+    });
+    let t = Table::new(
+        rows,
+        &[
+            Constraint::Length(4),
+            Constraint::Percentage(40),
+            Constraint::Percentage(45),
+            Constraint::Percentage(15),
+        ],
+    )
+    .block(Block::default().borders(Borders::ALL).title(title))
+    .header(
+        Row::new(vec!["#", "Metric", "Labels", "Rate/s"])
+            .style(Style::default().add_modifier(Modifier::BOLD)),
+    );
+    f.render_widget(t, area);
+}
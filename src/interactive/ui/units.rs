@@ -0,0 +1,128 @@
+//! Human-friendly values for the units promviz knows, bytes and seconds,
+//! optionally per second as in the rate view
+
+use crate::prom::MetricDetails;
+
+use super::format_value;
+
+const KNOWN_UNITS: [&str; 2] = ["bytes", "seconds"];
+
+/// Unit of the `# UNIT` line of a metric, otherwise a known unit at the end
+/// of its name, e.g. `seconds` for `http_request_duration_seconds_total`
+pub fn detect_unit(details: &MetricDetails) -> Option<String> {
+    if details.unit.is_some() {
+        return details.unit.clone();
+    }
+    let name = details.name.strip_suffix("_total").unwrap_or(&details.name);
+    KNOWN_UNITS
+        .into_iter()
+        .find(|unit| {
+            name.strip_suffix(unit)
+                .is_some_and(|base| base.ends_with('_'))
+        })
+        .map(String::from)
+}
+
+/// `value` scaled to a readable size if `unit` is a known unit, e.g.
+/// `1.2 GiB` for bytes or `350 ms/s` for seconds per second
+pub fn humanize(value: f64, unit: &str) -> Option<String> {
+    let (base, per_second) = match unit.strip_suffix("/s") {
+        Some(base) => (base, "/s"),
+        None => (unit, ""),
+    };
+    let text = match base {
+        "bytes" => format_bytes(value),
+        "seconds" => format_seconds(value),
+        _ => return None,
+    };
+    Some(format!("{}{}", text, per_second))
+}
+
+/// `value` scaled to a readable size for a known unit, otherwise formatted by
+/// `format` and followed by the unit, if any
+pub fn with_unit(value: f64, unit: Option<&str>, format: fn(f64) -> String) -> String {
+    match unit {
+        Some(unit) => {
+            humanize(value, unit).unwrap_or_else(|| format!("{} {}", format(value), unit))
+        }
+        None => format(value),
+    }
+}
+
+/// Bytes in the largest binary unit keeping the number at least 1, e.g. "12.3 MiB"
+pub fn format_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 6] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value.abs() >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", format_value(value))
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// Seconds in the largest unit from nanoseconds to days keeping the number
+/// at least 1, e.g. "350 ms"
+fn format_seconds(seconds: f64) -> String {
+    let (value, unit) = match seconds.abs() {
+        abs if abs == 0.0 || !abs.is_finite() => (seconds, "s"),
+        abs if abs < 1e-6 => (seconds * 1e9, "ns"),
+        abs if abs < 1e-3 => (seconds * 1e6, "µs"),
+        abs if abs < 1.0 => (seconds * 1e3, "ms"),
+        abs if abs < 60.0 => (seconds, "s"),
+        abs if abs < 3600.0 => (seconds / 60.0, "min"),
+        abs if abs < 86400.0 => (seconds / 3600.0, "h"),
+        _ => (seconds / 86400.0, "d"),
+    };
+    let value = format!("{:.1}", value);
+    format!("{} {}", value.strip_suffix(".0").unwrap_or(&value), unit)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prom::MetricType;
+
+    use super::*;
+
+    #[test]
+    fn test_detect_unit() {
+        let details = |name: &str, unit: Option<&str>| MetricDetails {
+            name: name.to_string(),
+            docstring: String::new(),
+            metric_type: MetricType::Counter,
+            unit: unit.map(String::from),
+            mismatch: None,
+            payload_bytes: 0,
+        };
+        assert_eq!(
+            detect_unit(&details("sent_bytes_total", None)).as_deref(),
+            Some("bytes")
+        );
+        assert_eq!(
+            detect_unit(&details("gc_duration_seconds", None)).as_deref(),
+            Some("seconds")
+        );
+        assert_eq!(
+            detect_unit(&details("uptime", Some("seconds"))).as_deref(),
+            Some("seconds")
+        );
+        assert_eq!(detect_unit(&details("requests_total", None)), None);
+        assert_eq!(detect_unit(&details("totalbytes", None)), None);
+    }
+
+    #[test]
+    fn test_humanize() {
+        assert_eq!(humanize(1288490188.8, "bytes").as_deref(), Some("1.2 GiB"));
+        assert_eq!(humanize(512.0, "bytes").as_deref(), Some("512 B"));
+        assert_eq!(humanize(-2048.0, "bytes/s").as_deref(), Some("-2.0 KiB/s"));
+        assert_eq!(humanize(0.35, "seconds").as_deref(), Some("350 ms"));
+        assert_eq!(humanize(0.0000125, "seconds").as_deref(), Some("12.5 µs"));
+        assert_eq!(humanize(5400.0, "seconds").as_deref(), Some("1.5 h"));
+        assert_eq!(humanize(0.0, "seconds").as_deref(), Some("0 s"));
+        assert_eq!(humanize(3.0, "jobs"), None);
+    }
+}
@@ -1,12 +1,31 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local};
 use log::LevelFilter;
-use log4rs::append::file::FileAppender;
+use log4rs::append::rolling_file::policy::compound::roll::fixed_window::FixedWindowRoller;
+use log4rs::append::rolling_file::policy::compound::trigger::size::SizeTrigger;
+use log4rs::append::rolling_file::policy::compound::CompoundPolicy;
+use log4rs::append::rolling_file::RollingFileAppender;
 use log4rs::config::{Appender, Config, Root};
 use log4rs::encode::pattern::PatternEncoder;
 
-pub fn app_config(file_name: &str, level: LevelFilter) -> Config {
-    let log_file = FileAppender::builder()
+/// Size at which the log file is rotated
+const MAX_LOG_SIZE: u64 = 10 * 1024 * 1024;
+/// Rotated files kept per session, as `<log file>.1` (newest) and up
+const ROTATED_LOGS: u32 = 3;
+/// Sessions whose logs are kept in the log directory
+const KEPT_SESSION_LOGS: usize = 10;
+
+pub fn app_config(file_name: &Path, level: LevelFilter) -> Config {
+    let roll_pattern = format!("{}.{{}}", file_name.display());
+    let roller = FixedWindowRoller::builder()
+        .build(&roll_pattern, ROTATED_LOGS)
+        .unwrap();
+    let policy = CompoundPolicy::new(Box::new(SizeTrigger::new(MAX_LOG_SIZE)), Box::new(roller));
+    let log_file = RollingFileAppender::builder()
         .encoder(Box::new(PatternEncoder::new("{d} - {l} - {m}{n}")))
-        .build(file_name)
+        .build(file_name, Box::new(policy))
         .unwrap();
 
     Config::builder()
@@ -15,24 +34,86 @@ pub fn app_config(file_name: &str, level: LevelFilter) -> Config {
         .unwrap()
 }
 
+/// Platform log directory: `~/Library/Logs/promviz` on macOS,
+/// `%LOCALAPPDATA%\promviz\logs` on Windows and `$XDG_STATE_HOME/promviz`,
+/// falling back to `~/.local/state/promviz`, elsewhere
+pub fn log_dir() -> Option<PathBuf> {
+    let env_dir = |name| {
+        std::env::var_os(name)
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+    };
+    if cfg!(target_os = "macos") {
+        Some(env_dir("HOME")?.join("Library/Logs/promviz"))
+    } else if cfg!(windows) {
+        Some(env_dir("LOCALAPPDATA")?.join("promviz").join("logs"))
+    } else {
+        let state_dir =
+            env_dir("XDG_STATE_HOME").or_else(|| Some(env_dir("HOME")?.join(".local/state")))?;
+        Some(state_dir.join("promviz"))
+    }
+}
+
+/// Log file of a session started at `started`, e.g.
+/// `promviz-20240612-153000-4242.log`, unique per process
+pub fn session_log_file(dir: &Path, started: DateTime<Local>) -> PathBuf {
+    dir.join(format!(
+        "promviz-{}-{}.log",
+        started.format("%Y%m%d-%H%M%S"),
+        std::process::id()
+    ))
+}
+
+/// Remove the logs, including the rotated ones, of all but the latest `keep`
+/// sessions in `dir`
+pub fn prune_session_logs(dir: &Path, keep: usize) -> std::io::Result<()> {
+    let mut sessions: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        if let Some((session, _)) = name
+            .strip_prefix("promviz-")
+            .and_then(|name| name.split_once(".log"))
+        {
+            sessions.entry(session.to_string()).or_default().push(path);
+        }
+    }
+    // the timestamps in the names sort the sessions oldest first
+    let outdated = sessions.len().saturating_sub(keep);
+    for path in sessions.into_values().take(outdated).flatten() {
+        std::fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+/// Log file of this session in the platform log directory, or the temporary
+/// directory if there is none, removing the logs of old sessions
+pub fn default_log_file() -> PathBuf {
+    let dir = log_dir().unwrap_or_else(std::env::temp_dir);
+    // the directory doesn't exist before the first session
+    let _ = prune_session_logs(&dir, KEPT_SESSION_LOGS - 1);
+    session_log_file(&dir, Local::now())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use chrono::TimeZone;
     use regex::Regex;
     use std::fs::{remove_file, File};
     use std::io::Read;
-    use std::path::Path;
 
     #[test]
     fn test_logger_config_creation() {
-        let file_name = "test.file";
-        let _handle = log4rs::init_config(app_config(file_name, LevelFilter::Info)).unwrap();
+        let path = std::env::temp_dir().join(format!("promviz-test-{}.file", std::process::id()));
+        let _handle = log4rs::init_config(app_config(&path, LevelFilter::Info)).unwrap();
         log::info!("test logging");
 
-        let path = Path::new(file_name);
         let display = path.display();
         assert!(path.exists());
-        let mut file = match File::open(path) {
+        let mut file = match File::open(&path) {
             Err(why) => panic!("couldn't open {}: {}", display, why),
             Ok(file) => file,
         };
@@ -47,18 +128,45 @@ mod tests {
                 assert!(reg_match.is_match(&s))
             }
         }
-        remove_file(path).unwrap_or_else(|_| panic!("unable to remove file: {}", file_name));
+        remove_file(&path).unwrap_or_else(|_| panic!("unable to remove file: {}", display));
+    }
+
+    #[test]
+    fn test_prune_session_logs() {
+        let dir = std::env::temp_dir().join(format!("promviz-logs-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let started = |hour| Local.with_ymd_and_hms(2024, 6, 12, hour, 0, 0).unwrap();
+        let logs: Vec<PathBuf> = (10..13)
+            .map(|hour| session_log_file(&dir, started(hour)))
+            .collect();
+        let rotated = PathBuf::from(format!("{}.1", logs[0].display()));
+        let other = dir.join("notes.txt");
+        for path in logs.iter().chain([&rotated, &other]) {
+            File::create(path).unwrap();
+        }
+        assert!(logs[0].ends_with(format!(
+            "promviz-20240612-100000-{}.log",
+            std::process::id()
+        )));
+
+        prune_session_logs(&dir, 2).unwrap();
+        let exists: Vec<bool> = logs
+            .iter()
+            .chain([&rotated, &other])
+            .map(|path| path.exists())
+            .collect();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(exists, vec![false, true, true, false, true]);
     }
 
     // #[test]
+    #[allow(dead_code)]
     //TODO: fix this test so wrong config gets verified
     fn test_use_wrong_level() {
-        let file_name = "test.file";
-        log4rs::init_config(app_config(file_name, LevelFilter::Error)).unwrap();
+        let path = Path::new("test.file");
+        log4rs::init_config(app_config(path, LevelFilter::Error)).unwrap();
         log::info!("test logging");
 
-        let path = Path::new(file_name);
-
         let mut file = match File::open(path) {
             Err(why) => panic!("couldn't open file: {}", why),
             Ok(file) => file,
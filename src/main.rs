@@ -1,33 +1,423 @@
-use crate::logging::app_config;
-use clap::Parser;
-use cli::Cli;
+use crate::logging::{app_config, default_log_file};
+use clap::{CommandFactory, FromArgMatches};
+use cli::{Cli, Command};
+use config::Config;
+use interactive::ShowOptions;
+use prom::{
+    AuthCommand, DnsSd, HttpClientConfig, MetricFilter, MetricScraper, Pipeline, Push,
+    QueryOptions, RemoteWrite, Retention, SampleSink, ScrapeOptions, SelfMetrics, SinkFormat,
+    StorageCapacity, StorageKind, Target,
+};
 use regex::Regex;
+use std::time::Duration;
 
 mod cli;
+mod config;
 mod interactive;
 mod logging;
 mod prom;
+mod web;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let cli = Cli::parse();
+    let matches = Cli::command().get_matches();
+    let mut cli = Cli::from_arg_matches(&matches)?;
 
     // initialize the logger
-    log4rs::init_config(app_config("log.out", cli.loglevel)).unwrap();
+    let log_file = cli.log_file.clone().unwrap_or_else(default_log_file);
+    log4rs::init_config(app_config(&log_file, cli.loglevel)).unwrap();
     log::info!("Starting the application!");
 
+    let mut config = Config::load(cli.config.as_deref())?;
+    config.merge_into(&mut cli, &matches);
+    let session = match &cli.session {
+        Some(name) => {
+            log::info!("Restoring session {}", name);
+            let mut session = interactive::Session::load(name)?;
+            session.merge_into(&mut cli, &matches);
+            Some(session)
+        }
+        None => None,
+    };
+    let mut theme = config.theme()?;
+    theme.animate = !cli.low_power && !cli.reduced_motion;
+    interactive::set_theme(theme);
+    interactive::set_time_format(cli.time_format.clone());
+    interactive::set_display_zone(cli.timezone.clone());
+    interactive::set_lang(cli.lang, std::mem::take(&mut config.strings));
+
     let regex = Regex::new(":(\\d{2,5})/").unwrap();
-    let endpoint = match cli.port {
-        Some(port) => regex
+    // a gRPC service is scraped on its mapped metrics port, checking its health on every scrape
+    let grpc_health = match &cli.grpc {
+        Some(grpc) => {
+            cli.endpoint = prom::grpc_health::metrics_endpoint(grpc, &cli.grpc_metrics_port)?;
+            cli.port = None;
+            Some(prom::grpc_health::HealthCheck {
+                url: grpc.clone(),
+                timeout: Duration::from_secs(cli.scrape_timeout.into()),
+            })
+        }
+        None => None,
+    };
+
+    let endpoint = match (cli.file, cli.port) {
+        (Some(file), _) => format!("file://{}", file.display()),
+        (None, Some(port)) => regex
             .replace(&cli.endpoint, format!(":{port}/", port = port))
             .to_string(),
-        None => cli.endpoint,
+        (None, None) => cli.endpoint,
     };
     log::info!("Reading metrics from endpoint: {}", endpoint);
     log::info!("Scraping interval is: {}s", cli.scrape_interval);
 
+    // custom stages implementing prom::pipeline::Stage can be added here
+    let mut pipeline = config.pipeline(MetricFilter::new(cli.filter, cli.exclude), cli.selector)?;
+    if let Some(format) = cli.headless {
+        if format == SinkFormat::RemoteWrite && cli.remote_write.is_none() {
+            return Err("--headless remote-write needs a --remote-write URL".into());
+        }
+        let writer: Box<dyn std::io::Write + Send> = match &cli.output {
+            Some(path) => Box::new(std::fs::File::create(path)?),
+            None => Box::new(std::io::stdout()),
+        };
+        pipeline = pipeline.with_stage(SampleSink::new(format, writer)?);
+    }
+    if let Some(url) = cli.remote_write.take() {
+        log::info!("Forwarding scrapes to remote write URL: {}", url);
+        // a client of its own, the scrape client carries the credentials of the endpoint
+        let client = HttpClientConfig {
+            timeout: Some(Duration::from_secs(cli.scrape_timeout.into())),
+            ..Default::default()
+        }
+        .build()?;
+        pipeline = pipeline.with_stage(RemoteWrite::start(url, client));
+    }
+    if let Some(gateway) = cli.push.take() {
+        let instance =
+            cli.push_instance
+                .take()
+                .unwrap_or_else(|| match reqwest::Url::parse(&endpoint) {
+                    Ok(url) if url.host_str().is_some() => format!(
+                        "{}:{}",
+                        url.host_str().unwrap_or_default(),
+                        url.port_or_known_default().unwrap_or_default()
+                    ),
+                    _ => endpoint.clone(),
+                });
+        let url = prom::grouping_url(&gateway, &cli.push_job, &instance);
+        log::info!("Pushing the latest values to: {}", url);
+        // like the remote write, without the credentials of the endpoint
+        let client = HttpClientConfig {
+            timeout: Some(Duration::from_secs(cli.scrape_timeout.into())),
+            ..Default::default()
+        }
+        .build()?;
+        pipeline = pipeline.with_stage(Push::start(url, client));
+    }
+
+    // credentials of a helper command replace the ones of the config file
+    let auth_command = cli.auth_command.map(|command| {
+        let username = config.auth.username.take();
+        config.auth.bearer_token = None;
+        AuthCommand::new(command, username, Duration::from_secs(cli.auth_refresh))
+    });
+
+    let client = HttpClientConfig {
+        tls_ca: cli.tls_ca,
+        insecure_skip_verify: cli.insecure_skip_verify,
+        headers: cli.headers,
+        proxy: cli.proxy,
+        proxy_auth: cli.proxy_auth,
+        timeout: Some(Duration::from_secs(cli.scrape_timeout.into())),
+        basic_auth: config
+            .auth
+            .username
+            .map(|username| (username, config.auth.password)),
+        bearer_token: config.auth.bearer_token,
+    }
+    .build()?;
+
+    if let Some(Command::Lint { source }) = cli.command {
+        let source = source.unwrap_or(endpoint);
+        let errors = lint(&client, &source, auth_command.as_ref()).await?;
+        std::process::exit(if errors > 0 { 1 } else { 0 });
+    }
+
+    // --max-samples is a shorthand for a bounded storage of that many samples
+    let (storage, storage_capacity) = match cli.max_samples {
+        Some(max_samples) => (StorageKind::Bounded, StorageCapacity::Samples(max_samples)),
+        None => (cli.storage, cli.storage_capacity),
+    };
+    log::info!("Storing history in {:?} storage", storage);
+    let retention = Retention {
+        max_age: cli.retention,
+        expire_after: cli.expire_after,
+    };
+    let store = if cli.headless.is_some() {
+        // nothing shows the history, only the latest samples are kept
+        StorageKind::Bounded.create(StorageCapacity::Samples(2), retention)
+    } else {
+        storage.create(storage_capacity, retention)
+    };
+    // stdin and imports are processed at once, the other sources keep on scraping
+    let snapshot = cli.stdin || endpoint == "-" || cli.import.is_some();
+    let sinks = pipeline.clone();
+
+    // the scrapers of the other hosts of --tabs, each shown in its own tab
+    let mut tabs = vec![];
+    let (source, metric_scraper) = match (cli.import, cli.otlp_listen) {
+        _ if cli.stdin || endpoint == "-" => {
+            log::info!("Reading metrics from stdin");
+            let text = std::io::read_to_string(std::io::stdin())?;
+            (
+                "stdin".to_string(),
+                MetricScraper::with_metrics_text(&text, pipeline, store),
+            )
+        }
+        (Some(path), _) => {
+            log::info!("Importing query results from {}", path.display());
+            let json = std::fs::read(&path)?;
+            (
+                format!("import {}", path.display()),
+                MetricScraper::with_imported_json(&json, pipeline, store)?,
+            )
+        }
+        (None, Some(addr)) => {
+            let listener = tokio::net::TcpListener::bind(&addr).await?;
+            log::info!("Receiving OTLP metrics on: {}", listener.local_addr()?);
+            let source = format!("OTLP receiver http://{}/v1/metrics", listener.local_addr()?);
+            (
+                source,
+                MetricScraper::with_otlp_receiver(listener, pipeline, store),
+            )
+        }
+        (None, None) if !cli.query.is_empty() => {
+            log::info!("Querying {} for {:?}", endpoint, cli.query);
+            let source = format!("{} ({} queries)", endpoint, cli.query.len());
+            let options = QueryOptions {
+                server: endpoint.clone(),
+                queries: cli.query,
+                range: cli.query_range,
+                auth_command,
+            };
+            (
+                source,
+                MetricScraper::with_query(
+                    options,
+                    cli.scrape_interval as u64,
+                    pipeline,
+                    client,
+                    store,
+                ),
+            )
+        }
+        (None, None) => {
+            let mut source = endpoint.clone();
+            let mut dns_sd = None;
+            let mut k8s = None;
+            let targets = if let Some(name) = cli.dns_sd {
+                source = format!("DNS SD {} ({})", name, endpoint);
+                dns_sd = Some(DnsSd {
+                    name,
+                    record_type: cli.dns_sd_type,
+                    url: endpoint.clone(),
+                    refresh: Duration::from_secs(cli.dns_sd_refresh.max(1)),
+                });
+                // found by the first scrape
+                vec![]
+            } else if cli.k8s {
+                let discovery = prom::k8s::Discovery {
+                    api: cli.k8s_api,
+                    namespace: cli.k8s_namespace,
+                    selector: cli.k8s_selector,
+                    refresh: Duration::from_secs(cli.k8s_refresh.max(1)),
+                };
+                source = match &discovery.namespace {
+                    Some(namespace) => format!("Kubernetes namespace {}", namespace),
+                    None => "Kubernetes".to_string(),
+                };
+                if cli.tabs {
+                    // the tabs are fixed, one per target found at the start
+                    let targets = discovery.discover().await?;
+                    log::info!("Discovered {} Kubernetes targets", targets.len());
+                    targets
+                } else {
+                    k8s = Some(discovery);
+                    // found by the first scrape
+                    vec![]
+                }
+            } else {
+                let hosts = match &cli.hosts {
+                    Some(path) => prom::read_hosts(path)?,
+                    None => vec![],
+                };
+                Target::expand(&endpoint, &hosts)?
+            };
+            let options = ScrapeOptions {
+                targets,
+                url: endpoint.clone(),
+                scrape_interval: cli.scrape_interval as u64,
+                fast_interval: cli.fast_interval.map(Duration::from_millis),
+                retries: cli.scrape_retries,
+                overlap_policy: cli.overlap_policy,
+                pipeline,
+                auth_command,
+                dns_sd,
+                k8s,
+                conflict_policy: cli.target_conflicts,
+                grpc_health,
+            };
+            if cli.tabs {
+                let mut scrapers: Vec<(String, MetricScraper)> = options
+                    .targets
+                    .iter()
+                    .map(|target| {
+                        let options = ScrapeOptions {
+                            targets: vec![Target {
+                                url: target.url.clone(),
+                                instance: None,
+                            }],
+                            url: target.url.clone(),
+                            ..options.clone()
+                        };
+                        let store = storage.create(storage_capacity, retention);
+                        let scraper = MetricScraper::new(options, client.clone(), store);
+                        (target.url.clone(), scraper)
+                    })
+                    .collect();
+                // the first host is shown initially
+                tabs = scrapers.split_off(1);
+                scrapers.remove(0)
+            } else {
+                (source, MetricScraper::new(options, client, store))
+            }
+        }
+    };
+    if let Some(memory_limit) = cli.memory_limit {
+        metric_scraper.watch_memory(memory_limit * 1024 * 1024, 0);
+        for (index, (_, scraper)) in tabs.iter().enumerate() {
+            scraper.watch_memory(memory_limit * 1024 * 1024, index + 1);
+        }
+    }
+
+    if let Some(addr) = &cli.web {
+        let listener = tokio::net::TcpListener::bind(listen_addr(addr)).await?;
+        log::info!("Serving the web dashboard on: {}", listener.local_addr()?);
+        let dashboard = web::Dashboard::new(source.clone(), &metric_scraper);
+        tokio::spawn(web::serve(listener, dashboard));
+    }
+    if let Some(addr) = &cli.self_metrics {
+        let listener = tokio::net::TcpListener::bind(listen_addr(addr)).await?;
+        log::info!(
+            "Serving the metrics of promviz on: {}",
+            listener.local_addr()?
+        );
+        tokio::spawn(SelfMetrics::new(&metric_scraper).serve(listener));
+    }
+
+    if cli.headless.is_some() {
+        log::info!("Running headless");
+        let timeout = Duration::from_secs(cli.scrape_timeout.into());
+        headless(&metric_scraper, &sinks, cli.scrapes, snapshot, timeout).await?;
+        return Ok(());
+    }
+
+    let control_listener = match &cli.control_listen {
+        Some(addr) => {
+            let listener = tokio::net::TcpListener::bind(listen_addr(addr)).await?;
+            log::info!("Serving the control API on: {}", listener.local_addr()?);
+            Some(listener)
+        }
+        None => None,
+    };
+
     // start dashboard
     log::info!("Showing the dashboard");
-    interactive::show(endpoint.clone(), cli.scrape_interval as u64).await?;
+    let options = ShowOptions {
+        endpoint: source,
+        scrape_interval: cli.scrape_interval as u64,
+        keys: config.keys,
+        units: config.units,
+        thresholds: config.thresholds,
+        print_summary: cli.summary,
+        low_power: cli.low_power,
+        show_footer: !cli.hide_footer,
+        session,
+        autosave_interval: (cli.autosave > 0).then(|| Duration::from_secs(cli.autosave)),
+        notify: cli.notify,
+        dashboard: config.dashboard,
+        show_dashboard: cli.dashboard,
+        control_listener,
+        profile: cli.profile,
+    };
+    interactive::show(options, metric_scraper, tabs).await?;
     Ok(())
 }
+
+/// `addr` to bind, where ":8099" listens on all interfaces
+fn listen_addr(addr: &str) -> String {
+    match addr.starts_with(':') {
+        true => format!("0.0.0.0{}", addr),
+        false => addr.to_string(),
+    }
+}
+
+/// Keep scraping until `scrapes` scrapes are done, or until interrupted, then
+/// wait up to `timeout` for the background requests of the `sinks`
+async fn headless(
+    scraper: &MetricScraper,
+    sinks: &Pipeline,
+    scrapes: Option<u64>,
+    snapshot: bool,
+    timeout: Duration,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut interrupted = std::pin::pin!(tokio::signal::ctrl_c());
+    loop {
+        // for the web dashboard and the metrics of promviz
+        scraper.deliver_batch();
+        let updates = scraper.get_status_read_guard()?.updates();
+        if snapshot || scrapes.is_some_and(|scrapes| updates >= scrapes) {
+            break;
+        }
+        tokio::select! {
+            _ = &mut interrupted => break,
+            _ = tokio::time::sleep(Duration::from_millis(100)) => {}
+        }
+    }
+    let deadline = tokio::time::Instant::now() + timeout;
+    while !sinks.flushed() && tokio::time::Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    Ok(())
+}
+
+/// Print the lint report of the metrics at `source`, returning the number of errors
+async fn lint(
+    client: &reqwest::Client,
+    source: &str,
+    auth_command: Option<&AuthCommand>,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let text = if source == "-" {
+        std::io::read_to_string(std::io::stdin())?
+    } else if source.contains("://") {
+        prom::fetch_metrics_text(client, source, auth_command).await?
+    } else {
+        std::fs::read_to_string(source)
+            .map_err(|err| format!("failed to read {}: {}", source, err))?
+    };
+    let findings = prom::lint::lint(&text);
+    for finding in &findings {
+        println!("{}", finding);
+    }
+    let errors = findings
+        .iter()
+        .filter(|finding| finding.severity == prom::lint::Severity::Error)
+        .count();
+    println!(
+        "{}: {} errors, {} warnings",
+        source,
+        errors,
+        findings.len() - errors
+    );
+    Ok(errors)
+}
@@ -0,0 +1,126 @@
+//! Credentials obtained from an external helper, e.g. `pass show prom/api`,
+//! so secrets don't end up in arguments, env variables or the config file.
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use tokio::process::Command;
+
+use super::http_client::basic_authorization;
+
+/// Longest time the helper may take, e.g. while waiting for a gpg-agent
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Runs a shell command printing a token, or the password of `username`, and
+/// caches the resulting `Authorization` header value for `refresh`.
+#[derive(Clone)]
+pub struct AuthCommand {
+    command: String,
+    username: Option<String>,
+    refresh: Duration,
+    cached: Arc<Mutex<Option<(String, Instant)>>>,
+}
+
+impl AuthCommand {
+    pub fn new(command: String, username: Option<String>, refresh: Duration) -> Self {
+        Self {
+            command,
+            username,
+            refresh,
+            cached: Arc::default(),
+        }
+    }
+
+    /// Value of the `Authorization` header, running the helper if the cached
+    /// credentials are missing or older than the refresh interval
+    pub async fn authorization(&self) -> anyhow::Result<String> {
+        if let Some((value, fetched)) = self.cached.lock().expect("auth cache lock").as_ref() {
+            if fetched.elapsed() < self.refresh {
+                return Ok(value.clone());
+            }
+        }
+        let secret = self.run().await?;
+        let value = match &self.username {
+            Some(username) => basic_authorization(username, &secret),
+            None => format!("Bearer {}", secret),
+        };
+        *self.cached.lock().expect("auth cache lock") = Some((value.clone(), Instant::now()));
+        Ok(value)
+    }
+
+    /// Forget the cached credentials, e.g. after they were rejected
+    pub fn invalidate(&self) {
+        *self.cached.lock().expect("auth cache lock") = None;
+    }
+
+    /// First line of the helper's output
+    async fn run(&self) -> anyhow::Result<String> {
+        log::info!("Running auth command: {}", self.command);
+        let output = Command::new("sh")
+            .arg("-c")
+            .arg(&self.command)
+            .kill_on_drop(true)
+            .output();
+        let output = tokio::time::timeout(COMMAND_TIMEOUT, output)
+            .await
+            .with_context(|| format!("auth command timed out after {:?}", COMMAND_TIMEOUT))?
+            .context("failed to run auth command")?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "auth command failed ({}): {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        let stdout =
+            String::from_utf8(output.stdout).context("auth command output is not UTF-8")?;
+        match stdout.lines().next().map(str::trim) {
+            Some(secret) if !secret.is_empty() => Ok(secret.to_string()),
+            _ => anyhow::bail!("auth command printed no credentials"),
+        }
+    }
+}
+
+/// Leaves out the cached credentials
+impl fmt::Debug for AuthCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuthCommand")
+            .field("command", &self.command)
+            .field("username", &self.username)
+            .field("refresh", &self.refresh)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_authorization_is_cached() {
+        let path = std::env::temp_dir().join(format!("promviz-auth-{}", std::process::id()));
+        // prints the number of runs so far
+        let command = format!("echo x >> {0}; wc -l < {0}", path.display());
+        let auth = AuthCommand::new(command, None, Duration::from_secs(3600));
+        assert_eq!(auth.authorization().await.unwrap(), "Bearer 1");
+        assert_eq!(auth.authorization().await.unwrap(), "Bearer 1");
+        auth.invalidate();
+        assert_eq!(auth.authorization().await.unwrap(), "Bearer 2");
+        std::fs::remove_file(path).unwrap();
+
+        let auth = AuthCommand::new(
+            "printf 'open sesame\\nignored'".to_string(),
+            Some("Aladdin".to_string()),
+            Duration::ZERO,
+        );
+        assert_eq!(
+            auth.authorization().await.unwrap(),
+            "Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ=="
+        );
+        assert!(!format!("{:?}", auth).contains("QWxh"));
+
+        let auth = AuthCommand::new("exit 3".to_string(), None, Duration::ZERO);
+        assert!(auth.authorization().await.is_err());
+    }
+}
@@ -0,0 +1,343 @@
+//! Scrape targets resolved from DNS records and refreshed periodically, like
+//! the `dns_sd_configs` of Prometheus. SRV records are looked up with a
+//! minimal DNS client querying the first name server of `/etc/resolv.conf`,
+//! A and AAAA records with the resolver of the system.
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, bail, Context};
+use reqwest::Url;
+use tokio::net::{lookup_host, UdpSocket};
+use tokio::time::timeout;
+
+use super::targets::Target;
+
+const RESOLV_CONF: &str = "/etc/resolv.conf";
+
+/// Longest wait for the answer of the name server
+const DNS_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Largest response accepted, advertised with EDNS to avoid truncation
+const MAX_RESPONSE_SIZE: u16 = 4096;
+
+const TYPE_SRV: u16 = 33;
+const TYPE_OPT: u16 = 41;
+const CLASS_IN: u16 = 1;
+
+/// Record type looked up for the targets
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum RecordType {
+    /// Host and port of every record
+    #[default]
+    Srv,
+    /// Every IPv4 address, on the port of the endpoint
+    A,
+    /// Every IPv6 address, on the port of the endpoint
+    Aaaa,
+}
+
+#[derive(Clone, Debug)]
+pub struct DnsSd {
+    /// The name looked up, e.g. `_metrics._tcp.example.com`
+    pub name: String,
+    pub record_type: RecordType,
+    /// Endpoint whose host and port are replaced by those of every record,
+    /// giving the scheme and path of the targets
+    pub url: String,
+    /// Time between two lookups
+    pub refresh: Duration,
+}
+
+impl DnsSd {
+    /// The current targets, labeled with their address as instance
+    pub async fn resolve(&self) -> anyhow::Result<Vec<Target>> {
+        let url =
+            Url::parse(&self.url).with_context(|| format!("invalid endpoint {}", self.url))?;
+        let mut addresses = match self.record_type {
+            RecordType::Srv => lookup_srv(&self.name).await?,
+            RecordType::A | RecordType::Aaaa => {
+                let port = url
+                    .port_or_known_default()
+                    .ok_or_else(|| anyhow!("no port in the endpoint {}", self.url))?;
+                lookup_host((self.name.as_str(), port))
+                    .await
+                    .with_context(|| format!("failed to resolve {}", self.name))?
+                    .filter(|address| address.is_ipv4() == (self.record_type == RecordType::A))
+                    .map(|address| (address.ip().to_string(), port))
+                    .collect()
+            }
+        };
+        addresses.sort();
+        addresses.dedup();
+        if addresses.is_empty() {
+            bail!("no {:?} records found for {}", self.record_type, self.name);
+        }
+        addresses
+            .into_iter()
+            .map(|(host, port)| {
+                let host = match host.contains(':') {
+                    true => format!("[{}]", host),
+                    false => host,
+                };
+                let mut target = url.clone();
+                target
+                    .set_host(Some(&host))
+                    .with_context(|| format!("the endpoint {} has no host", self.url))?;
+                target
+                    .set_port(Some(port))
+                    .map_err(|_| anyhow!("the endpoint {} has no port", self.url))?;
+                Ok(Target {
+                    url: target.to_string(),
+                    instance: Some(format!("{}:{}", host, port)),
+                })
+            })
+            .collect()
+    }
+}
+
+/// The first name server of the system, or a local one
+fn nameserver() -> SocketAddr {
+    let configured = std::fs::read_to_string(RESOLV_CONF).ok().and_then(|text| {
+        text.lines().find_map(|line| {
+            let address = line.trim().strip_prefix("nameserver")?;
+            address.trim().parse::<IpAddr>().ok()
+        })
+    });
+    SocketAddr::new(configured.unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST)), 53)
+}
+
+/// Host and port of every SRV record of `name`
+async fn lookup_srv(name: &str) -> anyhow::Result<Vec<(String, u16)>> {
+    let server = nameserver();
+    let local: SocketAddr = match server {
+        SocketAddr::V4(_) => "0.0.0.0:0".parse()?,
+        SocketAddr::V6(_) => "[::]:0".parse()?,
+    };
+    let socket = UdpSocket::bind(local).await?;
+    socket.connect(server).await?;
+    let id = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .subsec_nanos() as u16;
+    socket.send(&encode_query(id, name, TYPE_SRV)?).await?;
+    let mut response = vec![0; MAX_RESPONSE_SIZE as usize];
+    let len = timeout(DNS_TIMEOUT, socket.recv(&mut response))
+        .await
+        .map_err(|_| anyhow!("no answer from the name server {} for {}", server, name))??;
+    decode_srv_response(id, &response[..len]).with_context(|| format!("failed to look up {}", name))
+}
+
+/// A recursive query of one record type, advertising `MAX_RESPONSE_SIZE`
+fn encode_query(id: u16, name: &str, record_type: u16) -> anyhow::Result<Vec<u8>> {
+    let mut query = Vec::with_capacity(512);
+    query.extend_from_slice(&id.to_be_bytes());
+    // recursion desired
+    query.extend_from_slice(&0x0100u16.to_be_bytes());
+    // one question and the OPT record
+    for count in [1u16, 0, 0, 1] {
+        query.extend_from_slice(&count.to_be_bytes());
+    }
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            bail!("invalid DNS name {}", name);
+        }
+        query.push(label.len() as u8);
+        query.extend_from_slice(label.as_bytes());
+    }
+    query.push(0);
+    query.extend_from_slice(&record_type.to_be_bytes());
+    query.extend_from_slice(&CLASS_IN.to_be_bytes());
+    // OPT: root name, type, payload size as class, no extended flags or options
+    query.push(0);
+    query.extend_from_slice(&TYPE_OPT.to_be_bytes());
+    query.extend_from_slice(&MAX_RESPONSE_SIZE.to_be_bytes());
+    query.extend_from_slice(&[0; 6]);
+    Ok(query)
+}
+
+/// Reads a response, following the compression pointers of its names
+struct Reader<'a> {
+    message: &'a [u8],
+    position: usize,
+}
+
+impl Reader<'_> {
+    fn u8(&mut self) -> anyhow::Result<u8> {
+        let byte = *self
+            .message
+            .get(self.position)
+            .ok_or_else(|| anyhow!("truncated DNS response"))?;
+        self.position += 1;
+        Ok(byte)
+    }
+
+    fn u16(&mut self) -> anyhow::Result<u16> {
+        Ok(u16::from_be_bytes([self.u8()?, self.u8()?]))
+    }
+
+    fn skip(&mut self, len: usize) -> anyhow::Result<()> {
+        if self.position + len > self.message.len() {
+            bail!("truncated DNS response");
+        }
+        self.position += len;
+        Ok(())
+    }
+
+    fn name(&mut self) -> anyhow::Result<String> {
+        let mut labels = vec![];
+        // position after the name, once the first pointer is followed
+        let mut end = None;
+        let mut position = self.position;
+        // every pointer must go back, so a name can't loop
+        let mut limit = position;
+        loop {
+            let len = *self
+                .message
+                .get(position)
+                .ok_or_else(|| anyhow!("truncated DNS response"))?;
+            match len {
+                0 => {
+                    position += 1;
+                    break;
+                }
+                len if len & 0xc0 == 0xc0 => {
+                    let low = *self
+                        .message
+                        .get(position + 1)
+                        .ok_or_else(|| anyhow!("truncated DNS response"))?;
+                    let target = (usize::from(len & 0x3f) << 8) | usize::from(low);
+                    if target >= limit {
+                        bail!("invalid name compression in DNS response");
+                    }
+                    end.get_or_insert(position + 2);
+                    position = target;
+                    limit = target;
+                }
+                len => {
+                    let label = self
+                        .message
+                        .get(position + 1..position + 1 + usize::from(len))
+                        .ok_or_else(|| anyhow!("truncated DNS response"))?;
+                    labels.push(String::from_utf8_lossy(label).into_owned());
+                    position += 1 + usize::from(len);
+                }
+            }
+        }
+        self.position = end.unwrap_or(position);
+        Ok(labels.join("."))
+    }
+}
+
+/// Host and port of the SRV records in the answer to the query `id`
+fn decode_srv_response(id: u16, message: &[u8]) -> anyhow::Result<Vec<(String, u16)>> {
+    let mut reader = Reader {
+        message,
+        position: 0,
+    };
+    if reader.u16()? != id {
+        bail!("DNS response to another query");
+    }
+    let flags = reader.u16()?;
+    if flags & 0x0200 != 0 {
+        bail!("truncated DNS response");
+    }
+    match flags & 0x000f {
+        0 => {}
+        3 => bail!("no such name"),
+        code => bail!("name server failed with response code {}", code),
+    }
+    let questions = reader.u16()?;
+    let answers = reader.u16()?;
+    reader.skip(4)?;
+    for _ in 0..questions {
+        reader.name()?;
+        reader.skip(4)?;
+    }
+    let mut records = vec![];
+    for _ in 0..answers {
+        reader.name()?;
+        let record_type = reader.u16()?;
+        reader.skip(6)?;
+        let len = usize::from(reader.u16()?);
+        let end = reader.position + len;
+        // CNAMEs and other records in the answer are skipped
+        if record_type == TYPE_SRV {
+            // priority and weight
+            reader.skip(4)?;
+            let port = reader.u16()?;
+            records.push((reader.name()?, port));
+        }
+        reader.position = end;
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_query() {
+        let query = encode_query(0x1234, "_metrics._tcp.example.com.", TYPE_SRV).unwrap();
+        assert_eq!(&query[..4], &[0x12, 0x34, 0x01, 0x00]);
+        assert_eq!(&query[12..21], b"\x08_metrics");
+        // name, type and class, then the 11 bytes of the OPT record
+        assert_eq!(query.len(), 12 + 27 + 4 + 11);
+        assert!(encode_query(1, "a..b", TYPE_SRV).is_err());
+    }
+
+    #[test]
+    fn test_decode_srv_response() {
+        let mut response = vec![0x12, 0x34, 0x81, 0x80, 0, 1, 0, 2, 0, 0, 0, 0];
+        let question = encode_query(0x1234, "_metrics._tcp.example.com", TYPE_SRV).unwrap();
+        // the question, without the OPT record
+        response.extend_from_slice(&question[12..question.len() - 11]);
+        for (port, host) in [(9100u16, &b"\x05node1"[..]), (9200, &b"\x05node2"[..])] {
+            // pointer to the question name, SRV, IN, TTL
+            response.extend_from_slice(&[0xc0, 12, 0, 33, 0, 1, 0, 0, 0, 60]);
+            let mut data = vec![0, 10, 0, 5];
+            data.extend_from_slice(&port.to_be_bytes());
+            data.extend_from_slice(host);
+            // pointer to "example.com" in the question
+            data.extend_from_slice(&[0xc0, 12 + 9 + 5]);
+            response.extend_from_slice(&(data.len() as u16).to_be_bytes());
+            response.extend_from_slice(&data);
+        }
+        assert_eq!(
+            decode_srv_response(0x1234, &response).unwrap(),
+            vec![
+                ("node1.example.com".to_string(), 9100),
+                ("node2.example.com".to_string(), 9200)
+            ]
+        );
+        assert!(decode_srv_response(0x4321, &response).is_err());
+        assert!(decode_srv_response(0x1234, &response[..response.len() - 3]).is_err());
+
+        // NXDOMAIN
+        response[3] = 0x83;
+        let err = decode_srv_response(0x1234, &response).unwrap_err();
+        assert_eq!(err.to_string(), "no such name");
+
+        // a pointer to itself
+        let looping = [0x12, 0x34, 0x81, 0x80, 0, 1, 0, 0, 0, 0, 0, 0, 0xc0, 12];
+        assert!(decode_srv_response(0x1234, &looping).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_a_records() {
+        let dns_sd = DnsSd {
+            name: "localhost".to_string(),
+            record_type: RecordType::A,
+            url: "https://example.com:9100/stats".to_string(),
+            refresh: Duration::from_secs(30),
+        };
+        let targets = dns_sd.resolve().await.unwrap();
+        assert_eq!(
+            targets,
+            vec![Target {
+                url: "https://127.0.0.1:9100/stats".to_string(),
+                instance: Some("127.0.0.1:9100".to_string()),
+            }]
+        );
+    }
+}
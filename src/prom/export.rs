@@ -0,0 +1,286 @@
+use std::collections::VecDeque;
+use std::io::{self, Write};
+
+use super::model::{Metric, Sample, SingleScrapeMetric};
+
+/// Write the history of `metrics` as CSV with one row per sample.
+///
+/// Histograms and summaries are written as their `_sum` and `_count` series.
+pub fn write_csv<W: Write>(writer: &mut W, metrics: &[&Metric]) -> io::Result<()> {
+    writeln!(writer, "metric,labels,timestamp,value")?;
+    for metric in metrics {
+        let name = &metric.details.name;
+        for labels in metric.get_labels() {
+            let labels_field = csv_field(labels);
+            for sample in &metric.time_series[labels].samples {
+                match sample {
+                    Sample::GaugeSample(sample) | Sample::CounterSample(sample) => writeln!(
+                        writer,
+                        "{},{},{},{}",
+                        name, labels_field, sample.timestamp, sample.value
+                    )?,
+                    Sample::HistogramSample(sample) => {
+                        let timestamp = sample.timestamp;
+                        writeln!(
+                            writer,
+                            "{}_sum,{},{},{}",
+                            name, labels_field, timestamp, sample.sum
+                        )?;
+                        writeln!(
+                            writer,
+                            "{}_count,{},{},{}",
+                            name, labels_field, timestamp, sample.count
+                        )?;
+                    }
+                    Sample::SummarySample(sample) => {
+                        let timestamp = sample.time.timestamp();
+                        writeln!(
+                            writer,
+                            "{}_sum,{},{},{}",
+                            name, labels_field, timestamp, sample.sum
+                        )?;
+                        writeln!(
+                            writer,
+                            "{}_count,{},{},{}",
+                            name, labels_field, timestamp, sample.count
+                        )?;
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Write the samples of a single time series as CSV with one row per sample.
+///
+/// Histograms get a column per bucket and summaries a column per quantile,
+/// followed by the sum and count.
+pub fn write_series_csv<W: Write>(writer: &mut W, samples: &VecDeque<Sample>) -> io::Result<()> {
+    match samples.front() {
+        None | Some(Sample::GaugeSample(_) | Sample::CounterSample(_)) => {
+            writeln!(writer, "timestamp,value")?
+        }
+        Some(Sample::HistogramSample(sample)) => {
+            let buckets = sample.bucket_values.iter().map(|b| b.name.as_str());
+            write_header(writer, buckets)?
+        }
+        Some(Sample::SummarySample(sample)) => {
+            let quantiles = sample.quantiles.iter().map(|q| q.name.as_str());
+            write_header(writer, quantiles)?
+        }
+    }
+    for sample in samples {
+        match sample {
+            Sample::GaugeSample(sample) | Sample::CounterSample(sample) => {
+                writeln!(writer, "{},{}", sample.timestamp, sample.value)?
+            }
+            Sample::HistogramSample(sample) => {
+                write!(writer, "{}", sample.timestamp)?;
+                for bucket in &sample.bucket_values {
+                    write!(writer, ",{}", bucket.value)?;
+                }
+                writeln!(writer, ",{},{}", sample.sum, sample.count)?;
+            }
+            Sample::SummarySample(sample) => {
+                write!(writer, "{}", sample.time.timestamp())?;
+                for quantile in &sample.quantiles {
+                    write!(writer, ",{}", quantile.value)?;
+                }
+                writeln!(writer, ",{},{}", sample.sum, sample.count)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_header<'a, W: Write>(
+    writer: &mut W,
+    columns: impl Iterator<Item = &'a str>,
+) -> io::Result<()> {
+    write!(writer, "timestamp")?;
+    for column in columns {
+        write!(writer, ",{}", csv_field(column))?;
+    }
+    writeln!(writer, ",sum,count")
+}
+
+/// Write the complete history of `metrics` as a JSON document of the form
+/// `{"metrics": [...]}`, with the details, label maps and samples of each metric.
+pub fn write_json<W: Write>(writer: &mut W, metrics: &[&Metric]) -> io::Result<()> {
+    serde_json::to_writer_pretty(&mut *writer, &serde_json::json!({ "metrics": metrics }))?;
+    writeln!(writer)
+}
+
+/// Write the samples of a single scrape as CSV rows like the ones of
+/// `write_csv`, without the header.
+///
+/// Histograms and summaries are written as their `_bucket` series or
+/// quantiles, `_sum` and `_count`.
+pub fn write_scrape_csv<W: Write>(
+    writer: &mut W,
+    metrics: &[SingleScrapeMetric],
+) -> io::Result<()> {
+    for (mut labels, value, timestamp) in metrics.iter().flat_map(SingleScrapeMetric::points) {
+        let name = labels.remove("__name__").unwrap_or_default();
+        let labels: Vec<String> = labels
+            .iter()
+            .map(|(label, value)| format!("{}=\"{}\"", label, value))
+            .collect();
+        writeln!(
+            writer,
+            "{},{},{},{}",
+            name,
+            csv_field(&labels.join(",")),
+            timestamp / 1000,
+            value
+        )?;
+    }
+    Ok(())
+}
+
+/// Write the samples of a single scrape as JSON lines of the form
+/// `{"metric": ..., "labels": {...}, "timestamp": ..., "value": ...}`, split
+/// like the rows of `write_scrape_csv`
+pub fn write_scrape_json<W: Write>(
+    writer: &mut W,
+    metrics: &[SingleScrapeMetric],
+) -> io::Result<()> {
+    for (mut labels, value, timestamp) in metrics.iter().flat_map(SingleScrapeMetric::points) {
+        let name = labels.remove("__name__").unwrap_or_default();
+        let line = serde_json::json!({
+            "metric": name,
+            "labels": labels,
+            "timestamp": timestamp / 1000,
+            "value": value,
+        });
+        serde_json::to_writer(&mut *writer, &line)?;
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+/// Quote a CSV field if needed
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prom::test_data::{generate_metric_lines, scrape_all};
+
+    use super::*;
+
+    #[test]
+    fn test_write_csv() {
+        let metrics: Vec<Metric> = scrape_all(&generate_metric_lines().join("\n"), 1700000000)
+            .into_iter()
+            .map(SingleScrapeMetric::into_metric)
+            .collect();
+        let selected: Vec<&Metric> = metrics
+            .iter()
+            .filter(|m| m.details.name == "metric_2" || m.details.name == "response_time")
+            .collect();
+
+        let mut out = Vec::new();
+        write_csv(&mut out, &selected).unwrap();
+        let csv = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "metric,labels,timestamp,value");
+        assert_eq!(
+            lines[1],
+            "metric_2,\"shard=\"\"0\"\",label1=\"\"test1\"\"\",1700000000,5"
+        );
+        assert!(lines.contains(&"response_time_count,\"env=\"\"testing\"\"\",1700000000,6451"));
+        assert_eq!(lines.len(), 6);
+    }
+
+    #[test]
+    fn test_write_json() {
+        let metrics: Vec<Metric> = scrape_all(&generate_metric_lines().join("\n"), 1700000000)
+            .into_iter()
+            .map(SingleScrapeMetric::into_metric)
+            .collect();
+        let selected: Vec<&Metric> = metrics
+            .iter()
+            .filter(|m| m.details.name == "metric_2")
+            .collect();
+
+        let mut out = Vec::new();
+        write_json(&mut out, &selected).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(
+            json["metrics"][0]["details"],
+            serde_json::json!({
+                "name": "metric_2",
+                "docstring": "Description",
+                "metric_type": "counter",
+            })
+        );
+        let time_series = &json["metrics"][0]["time_series"]["shard=\"0\",label1=\"test1\""];
+        assert_eq!(time_series["labels"]["shard"], "0");
+        assert_eq!(
+            time_series["samples"],
+            serde_json::json!([{"timestamp": 1700000000, "value": 5.0}])
+        );
+    }
+
+    #[test]
+    fn test_write_series_csv() {
+        let metric = scrape_all(&generate_metric_lines().join("\n"), 1700000000)
+            .into_iter()
+            .map(SingleScrapeMetric::into_metric)
+            .find(|m| m.details.name == "response_time_no_labels")
+            .expect("histogram in test data");
+        let samples = &metric.time_series.values().next().unwrap().samples;
+
+        let mut out = Vec::new();
+        write_series_csv(&mut out, samples).unwrap();
+        let csv = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                "timestamp,0.005,0.01,0.025,0.05,0.1,0.25,0.5,+Inf,sum,count",
+                "1700000000,3,4,13,25,57,148,319,6563,32899.06535799631,6451",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_write_scrape() {
+        let metrics: Vec<SingleScrapeMetric> =
+            scrape_all(&generate_metric_lines().join("\n"), 1700000000)
+                .into_iter()
+                .filter(|m| m.name == "metric_2" || m.name == "response_time_no_labels")
+                .collect();
+
+        let mut out = Vec::new();
+        write_scrape_csv(&mut out, &metrics).unwrap();
+        let csv = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert!(lines.contains(&"metric_2,\"label1=\"\"test1\"\",shard=\"\"0\"\"\",1700000000,5"));
+        assert!(
+            lines.contains(&"response_time_no_labels_bucket,\"le=\"\"+Inf\"\"\",1700000000,6563")
+        );
+        assert!(lines.contains(&"response_time_no_labels_count,,1700000000,6451"));
+
+        let mut out = Vec::new();
+        write_scrape_json(&mut out, &metrics).unwrap();
+        let json: serde_json::Value =
+            serde_json::from_slice(out.split(|b| *b == b'\n').next().unwrap()).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "metric": "metric_2",
+                "labels": {"label1": "test1", "shard": "0"},
+                "timestamp": 1700000000,
+                "value": 5.0,
+            })
+        );
+    }
+}
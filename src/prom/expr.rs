@@ -0,0 +1,580 @@
+//! A small subset of PromQL, evaluated against the local history.
+//!
+//! Supported are selectors with label matchers (`name{label="value"}`,
+//! `!=`), `rate()`, `sum()` with an optional `by (label, ...)` clause,
+//! number literals and `+ - * /` between series and numbers.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use anyhow::{anyhow, bail};
+
+use super::model::Sample;
+use super::store::HistoryStore;
+
+/// Labels of a series, sorted by name
+pub type Labels = BTreeMap<String, String>;
+
+/// A series computed by an expression
+#[derive(Clone, Debug, PartialEq)]
+pub struct Series {
+    pub labels: Labels,
+    /// (unix timestamp, value) points, ordered by time
+    pub points: Vec<(u64, f64)>,
+}
+
+impl fmt::Display for Series {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let labels: Vec<String> = self
+            .labels
+            .iter()
+            .map(|(name, value)| format!("{}=\"{}\"", name, value))
+            .collect();
+        write!(f, "{{{}}}", labels.join(","))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl BinaryOp {
+    fn apply(self, lhs: f64, rhs: f64) -> f64 {
+        match self {
+            BinaryOp::Add => lhs + rhs,
+            BinaryOp::Sub => lhs - rhs,
+            BinaryOp::Mul => lhs * rhs,
+            BinaryOp::Div => lhs / rhs,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Matcher {
+    pub label: String,
+    pub value: String,
+    pub negated: bool,
+}
+
+/// A parsed expression
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Number(f64),
+    Selector {
+        metric: String,
+        matchers: Vec<Matcher>,
+    },
+    Rate(Box<Expr>),
+    Sum {
+        by: Vec<String>,
+        expr: Box<Expr>,
+    },
+    Binary {
+        op: BinaryOp,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+    },
+}
+
+/// Result of evaluating an expression
+enum Value {
+    Scalar(f64),
+    Vector(Vec<Series>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(f64),
+    Str(String),
+    Punct(&'static str),
+}
+
+fn tokenize(input: &str) -> anyhow::Result<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut chars = input.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_alphabetic() || c == '_' || c == ':' {
+            let mut ident = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_ascii_alphanumeric() || c == '_' || c == ':' {
+                    ident.push(c);
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            tokens.push(Token::Ident(ident));
+        } else if c.is_ascii_digit() || c == '.' {
+            let mut number = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_ascii_digit() || c == '.' || c == 'e' {
+                    number.push(c);
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            let value = number
+                .parse()
+                .map_err(|_| anyhow!("invalid number '{}'", number))?;
+            tokens.push(Token::Number(value));
+        } else if c == '"' {
+            chars.next();
+            let mut value = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => value.extend(chars.next()),
+                    Some(c) => value.push(c),
+                    None => bail!("unterminated string"),
+                }
+            }
+            tokens.push(Token::Str(value));
+        } else {
+            chars.next();
+            let punct = match c {
+                '!' if chars.peek() == Some(&'=') => {
+                    chars.next();
+                    "!="
+                }
+                '(' => "(",
+                ')' => ")",
+                '{' => "{",
+                '}' => "}",
+                ',' => ",",
+                '=' => "=",
+                '+' => "+",
+                '-' => "-",
+                '*' => "*",
+                '/' => "/",
+                _ => bail!("unexpected character '{}'", c),
+            };
+            tokens.push(Token::Punct(punct));
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn is_punct(&self, punct: &str) -> bool {
+        matches!(self.peek(), Some(Token::Punct(p)) if *p == punct)
+    }
+
+    fn expect(&mut self, punct: &str) -> anyhow::Result<()> {
+        match self.next() {
+            Some(Token::Punct(p)) if p == punct => Ok(()),
+            Some(token) => bail!("expected '{}', got {:?}", punct, token),
+            None => bail!("expected '{}', got end of expression", punct),
+        }
+    }
+
+    fn ident(&mut self) -> anyhow::Result<String> {
+        match self.next() {
+            Some(Token::Ident(ident)) => Ok(ident),
+            Some(token) => bail!("expected a name, got {:?}", token),
+            None => bail!("expected a name, got end of expression"),
+        }
+    }
+
+    fn expr(&mut self) -> anyhow::Result<Expr> {
+        let mut lhs = self.term()?;
+        while self.is_punct("+") || self.is_punct("-") {
+            let op = if self.is_punct("+") {
+                BinaryOp::Add
+            } else {
+                BinaryOp::Sub
+            };
+            self.next();
+            let rhs = self.term()?;
+            lhs = Expr::Binary {
+                op,
+                lhs: Box::new(lhs),
+                rhs: Box::new(rhs),
+            };
+        }
+        Ok(lhs)
+    }
+
+    fn term(&mut self) -> anyhow::Result<Expr> {
+        let mut lhs = self.factor()?;
+        while self.is_punct("*") || self.is_punct("/") {
+            let op = if self.is_punct("*") {
+                BinaryOp::Mul
+            } else {
+                BinaryOp::Div
+            };
+            self.next();
+            let rhs = self.factor()?;
+            lhs = Expr::Binary {
+                op,
+                lhs: Box::new(lhs),
+                rhs: Box::new(rhs),
+            };
+        }
+        Ok(lhs)
+    }
+
+    fn factor(&mut self) -> anyhow::Result<Expr> {
+        match self.next() {
+            Some(Token::Number(value)) => Ok(Expr::Number(value)),
+            Some(Token::Punct("-")) => Ok(Expr::Binary {
+                op: BinaryOp::Mul,
+                lhs: Box::new(Expr::Number(-1.0)),
+                rhs: Box::new(self.factor()?),
+            }),
+            Some(Token::Punct("(")) => {
+                let expr = self.expr()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Some(Token::Ident(name)) if name == "rate" && self.is_punct("(") => {
+                self.expect("(")?;
+                let expr = self.expr()?;
+                self.expect(")")?;
+                Ok(Expr::Rate(Box::new(expr)))
+            }
+            Some(Token::Ident(name)) if name == "sum" => {
+                // both `sum by (a) (expr)` and `sum (expr) by (a)` are valid
+                let mut by = self.by_clause()?;
+                self.expect("(")?;
+                let expr = self.expr()?;
+                self.expect(")")?;
+                if by.is_empty() {
+                    by = self.by_clause()?;
+                }
+                Ok(Expr::Sum {
+                    by,
+                    expr: Box::new(expr),
+                })
+            }
+            Some(Token::Ident(metric)) => {
+                let mut matchers = vec![];
+                if self.is_punct("{") {
+                    self.next();
+                    while !self.is_punct("}") {
+                        let label = self.ident()?;
+                        let negated = match self.next() {
+                            Some(Token::Punct("=")) => false,
+                            Some(Token::Punct("!=")) => true,
+                            _ => bail!("expected '=' or '!=' after label '{}'", label),
+                        };
+                        let Some(Token::Str(value)) = self.next() else {
+                            bail!("expected a quoted value for label '{}'", label);
+                        };
+                        matchers.push(Matcher {
+                            label,
+                            value,
+                            negated,
+                        });
+                        if !self.is_punct("}") {
+                            self.expect(",")?;
+                        }
+                    }
+                    self.next();
+                }
+                Ok(Expr::Selector { metric, matchers })
+            }
+            Some(token) => bail!("unexpected {:?}", token),
+            None => bail!("unexpected end of expression"),
+        }
+    }
+
+    fn by_clause(&mut self) -> anyhow::Result<Vec<String>> {
+        if !matches!(self.peek(), Some(Token::Ident(ident)) if ident == "by") {
+            return Ok(vec![]);
+        }
+        self.next();
+        self.expect("(")?;
+        let mut labels = vec![];
+        while !self.is_punct(")") {
+            labels.push(self.ident()?);
+            if !self.is_punct(")") {
+                self.expect(",")?;
+            }
+        }
+        self.next();
+        Ok(labels)
+    }
+}
+
+/// Parse an expression like `sum by (job) (rate(http_requests_total))`
+pub fn parse(input: &str) -> anyhow::Result<Expr> {
+    let mut parser = Parser {
+        tokens: tokenize(input)?,
+        pos: 0,
+    };
+    let expr = parser.expr()?;
+    if let Some(token) = parser.peek() {
+        bail!("unexpected {:?} after expression", token);
+    }
+    Ok(expr)
+}
+
+/// Evaluate `expr` against the history, giving one or more series
+pub fn evaluate(expr: &Expr, history: &dyn HistoryStore) -> anyhow::Result<Vec<Series>> {
+    match eval(expr, history)? {
+        Value::Scalar(_) => bail!("expression has no series, only a number"),
+        Value::Vector(series) => Ok(series),
+    }
+}
+
+fn eval(expr: &Expr, history: &dyn HistoryStore) -> anyhow::Result<Value> {
+    Ok(match expr {
+        Expr::Number(value) => Value::Scalar(*value),
+        Expr::Selector { metric, matchers } => Value::Vector(select(history, metric, matchers)?),
+        Expr::Rate(expr) => Value::Vector(
+            vector(eval(expr, history)?, "rate()")?
+                .into_iter()
+                .map(|series| Series {
+                    points: rate(&series.points),
+                    labels: series.labels,
+                })
+                .collect(),
+        ),
+        Expr::Sum { by, expr } => Value::Vector(sum(vector(eval(expr, history)?, "sum()")?, by)),
+        Expr::Binary { op, lhs, rhs } => binary(*op, eval(lhs, history)?, eval(rhs, history)?),
+    })
+}
+
+fn vector(value: Value, context: &str) -> anyhow::Result<Vec<Series>> {
+    match value {
+        Value::Vector(series) => Ok(series),
+        Value::Scalar(_) => bail!("{} expects series, not a number", context),
+    }
+}
+
+fn select(
+    history: &dyn HistoryStore,
+    metric_name: &str,
+    matchers: &[Matcher],
+) -> anyhow::Result<Vec<Series>> {
+    let metric = history
+        .get_metric(metric_name)
+        .ok_or_else(|| anyhow!("unknown metric '{}'", metric_name))?;
+    let mut selected = vec![];
+    for (key, time_series) in &metric.time_series {
+        // series without labels carry a placeholder label map
+        let labels: Labels = if key.contains('=') {
+            time_series.labels.clone().into_iter().collect()
+        } else {
+            Labels::new()
+        };
+        let matches = matchers.iter().all(|matcher| {
+            let value = labels.get(&matcher.label).map_or("", String::as_str);
+            (value == matcher.value) != matcher.negated
+        });
+        if !matches {
+            continue;
+        }
+        let points = time_series
+            .samples
+            .iter()
+            .map(|sample| match sample {
+                Sample::GaugeSample(s) | Sample::CounterSample(s) => Ok((s.timestamp, s.value)),
+                _ => Err(anyhow!(
+                    "'{}' is not a gauge or counter, which is all that is supported",
+                    metric_name
+                )),
+            })
+            .collect::<anyhow::Result<_>>()?;
+        selected.push(Series { labels, points });
+    }
+    selected.sort_by(|a, b| a.labels.cmp(&b.labels));
+    Ok(selected)
+}
+
+/// Per-second rate between consecutive points, treating decreases as counter resets
+fn rate(points: &[(u64, f64)]) -> Vec<(u64, f64)> {
+    points
+        .windows(2)
+        .filter(|pair| pair[1].0 > pair[0].0)
+        .map(|pair| {
+            let ((t0, v0), (t1, v1)) = (pair[0], pair[1]);
+            let increase = if v1 < v0 { v1 } else { v1 - v0 };
+            (t1, increase / (t1 - t0) as f64)
+        })
+        .collect()
+}
+
+fn sum(series: Vec<Series>, by: &[String]) -> Vec<Series> {
+    let mut groups: BTreeMap<Labels, BTreeMap<u64, f64>> = BTreeMap::new();
+    for series in series {
+        let labels: Labels = series
+            .labels
+            .into_iter()
+            .filter(|(name, _)| by.contains(name))
+            .collect();
+        let group = groups.entry(labels).or_default();
+        for (timestamp, value) in series.points {
+            *group.entry(timestamp).or_default() += value;
+        }
+    }
+    groups
+        .into_iter()
+        .map(|(labels, points)| Series {
+            labels,
+            points: points.into_iter().collect(),
+        })
+        .collect()
+}
+
+/// Apply `op` to the points of `lhs` and `rhs` with the same timestamp
+fn combine(op: BinaryOp, lhs: &[(u64, f64)], rhs: &[(u64, f64)]) -> Vec<(u64, f64)> {
+    let rhs: HashMap<u64, f64> = rhs.iter().copied().collect();
+    lhs.iter()
+        .filter_map(|(timestamp, value)| {
+            rhs.get(timestamp)
+                .map(|other| (*timestamp, op.apply(*value, *other)))
+        })
+        .collect()
+}
+
+fn binary(op: BinaryOp, lhs: Value, rhs: Value) -> Value {
+    let map = |series: Vec<Series>, f: &dyn Fn(f64) -> f64| {
+        series
+            .into_iter()
+            .map(|series| Series {
+                points: series.points.into_iter().map(|(t, v)| (t, f(v))).collect(),
+                labels: series.labels,
+            })
+            .collect()
+    };
+    match (lhs, rhs) {
+        (Value::Scalar(lhs), Value::Scalar(rhs)) => Value::Scalar(op.apply(lhs, rhs)),
+        (Value::Vector(lhs), Value::Scalar(rhs)) => Value::Vector(map(lhs, &|v| op.apply(v, rhs))),
+        (Value::Scalar(lhs), Value::Vector(rhs)) => Value::Vector(map(rhs, &|v| op.apply(lhs, v))),
+        (Value::Vector(lhs), Value::Vector(rhs)) => {
+            // A single series on one side is combined with every series on
+            // the other, otherwise series with identical labels are matched
+            let result = if rhs.len() == 1 {
+                lhs.into_iter()
+                    .map(|series| Series {
+                        points: combine(op, &series.points, &rhs[0].points),
+                        labels: series.labels,
+                    })
+                    .collect()
+            } else if lhs.len() == 1 {
+                rhs.into_iter()
+                    .map(|series| Series {
+                        points: combine(op, &lhs[0].points, &series.points),
+                        labels: series.labels,
+                    })
+                    .collect()
+            } else {
+                lhs.into_iter()
+                    .filter_map(|series| {
+                        let other = rhs.iter().find(|other| other.labels == series.labels)?;
+                        Some(Series {
+                            points: combine(op, &series.points, &other.points),
+                            labels: series.labels,
+                        })
+                    })
+                    .collect()
+            };
+            Value::Vector(result)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prom::test_data::{gauge, memory_store, scrape};
+
+    use super::*;
+
+    fn history() -> Box<dyn HistoryStore> {
+        let mut history = memory_store();
+        for (timestamp, values) in [(10, [10.0, 20.0, 1.0]), (20, [30.0, 60.0, 2.0])] {
+            let requests = format!(
+                "# TYPE requests_total counter\n\
+                 requests_total{{job=\"api\",code=\"200\"}} {}\n\
+                 requests_total{{job=\"api\",code=\"500\"}} {}\n",
+                values[0], values[1]
+            );
+            history.insert_scrape(scrape(&requests, timestamp));
+            history.insert_scrape(gauge("up", &[("", values[2])], timestamp));
+        }
+        history
+    }
+
+    fn points(input: &str) -> Vec<Vec<(u64, f64)>> {
+        let history = history();
+        evaluate(&parse(input).unwrap(), history.as_ref())
+            .unwrap()
+            .into_iter()
+            .map(|series| series.points)
+            .collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse("sum by (job) (rate(requests_total{code!=\"200\"}))").unwrap(),
+            Expr::Sum {
+                by: vec!["job".to_string()],
+                expr: Box::new(Expr::Rate(Box::new(Expr::Selector {
+                    metric: "requests_total".to_string(),
+                    matchers: vec![Matcher {
+                        label: "code".to_string(),
+                        value: "200".to_string(),
+                        negated: true,
+                    }],
+                }))),
+            }
+        );
+        assert_eq!(
+            parse("sum(up) by (job)").unwrap(),
+            parse("sum by (job) (up)").unwrap()
+        );
+        assert!(parse("rate(up").is_err());
+        assert!(parse("up{job=api}").is_err());
+        assert!(parse("up up").is_err());
+    }
+
+    #[test]
+    fn test_evaluate() {
+        assert_eq!(
+            points("requests_total{code=\"200\"}"),
+            vec![vec![(10, 10.0), (20, 30.0)]]
+        );
+        assert_eq!(
+            points("rate(requests_total)"),
+            vec![vec![(20, 2.0)], vec![(20, 4.0)]]
+        );
+        assert_eq!(
+            points("sum(requests_total) * 2"),
+            vec![vec![(10, 60.0), (20, 180.0)]]
+        );
+        assert_eq!(
+            points("requests_total{code=\"500\"} / sum(requests_total)"),
+            vec![vec![(10, 20.0 / 30.0), (20, 60.0 / 90.0)]]
+        );
+        assert_eq!(points("up - 1"), vec![vec![(10, 0.0), (20, 1.0)]]);
+
+        let history = history();
+        assert!(evaluate(&parse("1 + 2").unwrap(), history.as_ref()).is_err());
+        assert!(evaluate(&parse("missing").unwrap(), history.as_ref()).is_err());
+    }
+}
@@ -0,0 +1,44 @@
+use regex::Regex;
+
+/// Selects the metric families to store by name
+#[derive(Clone, Debug, Default)]
+pub struct MetricFilter {
+    /// A name has to match one of these, unless empty
+    include: Vec<Regex>,
+    /// Names matching any of these are dropped
+    exclude: Vec<Regex>,
+}
+
+impl MetricFilter {
+    pub fn new(include: Vec<Regex>, exclude: Vec<Regex>) -> Self {
+        Self { include, exclude }
+    }
+
+    /// Whether the metric family `name` is kept. The patterns are not
+    /// anchored, use `^...$` to match the whole name.
+    pub fn matches(&self, name: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|rgx| rgx.is_match(name)))
+            && !self.exclude.iter().any(|rgx| rgx.is_match(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches() {
+        let rgx = |pattern| Regex::new(pattern).unwrap();
+        assert!(MetricFilter::default().matches("anything"));
+
+        let filter = MetricFilter::new(vec![rgx("^http_"), rgx("_seconds$")], vec![rgx("debug")]);
+        assert!(filter.matches("http_requests_total"));
+        assert!(filter.matches("gc_duration_seconds"));
+        assert!(!filter.matches("process_cpu_total"));
+        assert!(!filter.matches("http_debug_requests_total"));
+
+        let filter = MetricFilter::new(vec![], vec![rgx("^go_")]);
+        assert!(filter.matches("process_cpu_total"));
+        assert!(!filter.matches("go_goroutines"));
+    }
+}
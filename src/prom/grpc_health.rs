@@ -0,0 +1,229 @@
+//! Client for the standard gRPC health checking protocol (`grpc.health.v1.Health/Check`),
+//! used to inspect gRPC services that expose their metrics on a separate port.
+
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context};
+use reqwest::{Client, Url};
+
+/// Serving status reported by the health service
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ServingStatus {
+    Unknown,
+    Serving,
+    NotServing,
+    ServiceUnknown,
+}
+
+impl std::fmt::Display for ServingStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let status = match self {
+            ServingStatus::Unknown => "UNKNOWN",
+            ServingStatus::Serving => "SERVING",
+            ServingStatus::NotServing => "NOT_SERVING",
+            ServingStatus::ServiceUnknown => "SERVICE_UNKNOWN",
+        };
+        write!(f, "{}", status)
+    }
+}
+
+/// A gRPC service whose health is checked on every scrape
+#[derive(Clone, Debug)]
+pub struct HealthCheck {
+    /// Address of the service, e.g. `http://localhost:50051`
+    pub url: String,
+    pub timeout: Duration,
+}
+
+impl HealthCheck {
+    /// The status of the whole server as shown in the header, or why it
+    /// could not be checked
+    pub async fn status(&self) -> String {
+        match check(&self.url, "", self.timeout).await {
+            Ok(status) => format!("gRPC {}: {}", self.url, status),
+            Err(err) => {
+                log::error!("gRPC health check of {} failed: {:#}", self.url, err);
+                format!("gRPC {}: health check failed: {:#}", self.url, err)
+            }
+        }
+    }
+}
+
+/// Ask the health service at `url` (e.g. `http://localhost:50051`) for the
+/// status of `service`, where an empty service means the whole server
+pub async fn check(url: &str, service: &str, timeout: Duration) -> anyhow::Result<ServingStatus> {
+    let url = Url::parse(url).with_context(|| format!("invalid gRPC address {}", url))?;
+    let mut builder = Client::builder().timeout(timeout);
+    if url.scheme() == "http" {
+        // gRPC without TLS has no protocol negotiation, so HTTP/2 must be assumed
+        builder = builder.http2_prior_knowledge();
+    }
+    let response = builder
+        .build()?
+        .post(url.join("/grpc.health.v1.Health/Check")?)
+        .header("content-type", "application/grpc")
+        .header("te", "trailers")
+        .body(encode_request(service))
+        .send()
+        .await?;
+    // errors are sent as a trailers-only response, i.e. in the headers
+    if let Some(status) = response.headers().get("grpc-status") {
+        if status != "0" {
+            let message = response
+                .headers()
+                .get("grpc-message")
+                .and_then(|message| message.to_str().ok())
+                .unwrap_or_default()
+                .to_string();
+            bail!(
+                "health check failed with gRPC status {:?}: {}",
+                status,
+                message
+            );
+        }
+    }
+    decode_response(&response.bytes().await?)
+}
+
+/// The metrics endpoint of the gRPC service at `url`, using the metrics port
+/// mapped to its gRPC port in `ports`
+pub fn metrics_endpoint(url: &str, ports: &[(u16, u16)]) -> anyhow::Result<String> {
+    let url = Url::parse(url).with_context(|| format!("invalid gRPC address {}", url))?;
+    let grpc_port = url
+        .port_or_known_default()
+        .ok_or_else(|| anyhow!("no port in gRPC address {}", url))?;
+    let (_, metrics_port) = ports
+        .iter()
+        .find(|(port, _)| *port == grpc_port)
+        .ok_or_else(|| anyhow!("no metrics port mapped for gRPC port {}", grpc_port))?;
+    let host = url
+        .host_str()
+        .ok_or_else(|| anyhow!("no host in gRPC address {}", url))?;
+    Ok(format!("http://{}:{}/metrics", host, metrics_port))
+}
+
+pub(crate) fn encode_varint(mut value: u64, buf: &mut Vec<u8>) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn decode_varint(buf: &mut &[u8]) -> anyhow::Result<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = buf
+            .split_first()
+            .ok_or_else(|| anyhow!("truncated varint"))?;
+        *buf = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    bail!("varint too long")
+}
+
+/// A length-prefixed `HealthCheckRequest { string service = 1; }` message
+fn encode_request(service: &str) -> Vec<u8> {
+    let mut message = vec![];
+    if !service.is_empty() {
+        message.push(0x0a);
+        encode_varint(service.len() as u64, &mut message);
+        message.extend_from_slice(service.as_bytes());
+    }
+    let mut frame = vec![0];
+    frame.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    frame.extend(message);
+    frame
+}
+
+/// Decode a length-prefixed `HealthCheckResponse { ServingStatus status = 1; }` message
+fn decode_response(body: &[u8]) -> anyhow::Result<ServingStatus> {
+    if body.len() < 5 {
+        bail!("empty health check response");
+    }
+    if body[0] != 0 {
+        bail!("compressed health check responses are not supported");
+    }
+    let len = u32::from_be_bytes([body[1], body[2], body[3], body[4]]) as usize;
+    let mut message = body
+        .get(5..5 + len)
+        .ok_or_else(|| anyhow!("truncated health check response"))?;
+    // a default status is not sent at all
+    let mut status = 0;
+    while !message.is_empty() {
+        let key = decode_varint(&mut message)?;
+        match (key >> 3, key & 0x7) {
+            (1, 0) => status = decode_varint(&mut message)?,
+            (_, 0) => {
+                decode_varint(&mut message)?;
+            }
+            (_, 2) => {
+                let len = decode_varint(&mut message)? as usize;
+                message = message
+                    .get(len..)
+                    .ok_or_else(|| anyhow!("truncated health check response"))?;
+            }
+            (_, wire_type) => bail!("unexpected protobuf wire type {}", wire_type),
+        }
+    }
+    Ok(match status {
+        1 => ServingStatus::Serving,
+        2 => ServingStatus::NotServing,
+        3 => ServingStatus::ServiceUnknown,
+        _ => ServingStatus::Unknown,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_request() {
+        assert_eq!(encode_request(""), vec![0, 0, 0, 0, 0]);
+        assert_eq!(
+            encode_request("api"),
+            vec![0, 0, 0, 0, 5, 0x0a, 3, b'a', b'p', b'i']
+        );
+    }
+
+    #[test]
+    fn test_decode_response() {
+        assert_eq!(
+            decode_response(&[0, 0, 0, 0, 2, 0x08, 1]).unwrap(),
+            ServingStatus::Serving
+        );
+        assert_eq!(
+            decode_response(&[0, 0, 0, 0, 0]).unwrap(),
+            ServingStatus::Unknown
+        );
+        assert!(decode_response(&[0, 0, 0, 0, 2, 0x08]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_health_check_status() {
+        let health_check = HealthCheck {
+            url: "http://127.0.0.1:1".to_string(),
+            timeout: Duration::from_secs(1),
+        };
+        let status = health_check.status().await;
+        assert!(
+            status.starts_with("gRPC http://127.0.0.1:1: health check failed"),
+            "{}",
+            status
+        );
+    }
+
+    #[test]
+    fn test_metrics_endpoint() {
+        let ports = [(50051, 9090)];
+        assert_eq!(
+            metrics_endpoint("http://api:50051", &ports).unwrap(),
+            "http://api:9090/metrics"
+        );
+        assert!(metrics_endpoint("http://api:50052", &ports).is_err());
+    }
+}
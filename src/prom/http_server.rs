@@ -0,0 +1,200 @@
+//! Minimal HTTP/1.1 server side, enough for the OTLP receiver, the control
+//! API and the web dashboard: one request at a time per connection, bodies sized by
+//! Content-Length
+use std::io::Read;
+
+use anyhow::Context;
+use flate2::read::GzDecoder;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+/// Largest accepted request body
+const MAX_BODY_SIZE: usize = 64 * 1024 * 1024;
+
+/// Longest accepted request line or header line, including the line break
+const MAX_LINE_LEN: usize = 8 * 1024;
+
+/// Most header lines accepted in a request
+const MAX_HEADERS: usize = 100;
+
+/// An HTTP request read from a connection
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub content_type: String,
+    /// The Host and Origin headers, if sent
+    pub host: Option<String>,
+    pub origin: Option<String>,
+    pub body: Vec<u8>,
+}
+
+/// Read the next HTTP request of a connection. Returns `None` once the client
+/// closed the connection. The same `reader` is to be used for all requests of
+/// the connection, as it may have buffered the start of the next one.
+pub async fn read_request(reader: &mut BufReader<TcpStream>) -> anyhow::Result<Option<Request>> {
+    let Some(request_line) = read_line(reader).await? else {
+        return Ok(None);
+    };
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or_default().to_string();
+
+    let mut content_length = 0;
+    let mut content_type = String::new();
+    let mut host = None;
+    let mut origin = None;
+    let mut gzip = false;
+    for header in 0.. {
+        let Some(line) = read_line(reader).await? else {
+            return Ok(None);
+        };
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if header == MAX_HEADERS {
+            anyhow::bail!("request has more than {} headers", MAX_HEADERS);
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            let value = value.trim();
+            match name.trim().to_ascii_lowercase().as_str() {
+                "content-length" => content_length = value.parse().context("bad content-length")?,
+                "content-type" => content_type = value.to_ascii_lowercase(),
+                "content-encoding" => gzip = value.eq_ignore_ascii_case("gzip"),
+                "host" => host = Some(value.to_string()),
+                "origin" => origin = Some(value.to_string()),
+                _ => {}
+            }
+        }
+    }
+    if content_length > MAX_BODY_SIZE {
+        anyhow::bail!("request body of {} bytes is too large", content_length);
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).await?;
+    if gzip {
+        let mut decoded = Vec::new();
+        GzDecoder::new(&body[..])
+            .take(MAX_BODY_SIZE as u64)
+            .read_to_end(&mut decoded)
+            .context("invalid gzip body")?;
+        body = decoded;
+    }
+    Ok(Some(Request {
+        method,
+        path,
+        content_type,
+        host,
+        origin,
+        body,
+    }))
+}
+
+/// Read a line of at most `MAX_LINE_LEN` bytes. Returns `None` at the end of
+/// the connection.
+async fn read_line(reader: &mut BufReader<TcpStream>) -> anyhow::Result<Option<String>> {
+    let mut line = Vec::new();
+    let read = reader
+        .take(MAX_LINE_LEN as u64)
+        .read_until(b'\n', &mut line)
+        .await?;
+    if read == 0 {
+        return Ok(None);
+    }
+    if read == MAX_LINE_LEN && !line.ends_with(b"\n") {
+        anyhow::bail!("request line or header longer than {} bytes", MAX_LINE_LEN);
+    }
+    Ok(Some(
+        String::from_utf8(line).context("request line is not UTF-8")?,
+    ))
+}
+
+/// Write an HTTP response with a JSON body
+pub async fn write_response(
+    stream: &mut TcpStream,
+    status: &str,
+    body: &str,
+) -> std::io::Result<()> {
+    write_typed_response(stream, status, "application/json", body).await
+}
+
+/// Write an HTTP response with a body of `content_type`
+pub async fn write_typed_response(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &str,
+) -> std::io::Result<()> {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    /// Reader of the server side of a connection the client sent `data` on
+    async fn received(data: Vec<u8>) -> BufReader<TcpStream> {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+        tokio::spawn(async move {
+            client.write_all(&data).await.unwrap();
+            // keep the connection open until the server is done
+            let _ = client.read(&mut [0; 1]).await;
+        });
+        BufReader::new(server)
+    }
+
+    #[tokio::test]
+    async fn test_pipelined_requests() {
+        let data = "POST /v1/metrics HTTP/1.1\r\nContent-Length: 2\r\n\r\n{}\
+                    GET /v1/status HTTP/1.1\r\nHost: localhost\r\n\r\n";
+        let mut reader = received(data.into()).await;
+        let first = read_request(&mut reader).await.unwrap().unwrap();
+        assert_eq!(
+            (first.method.as_str(), first.path.as_str()),
+            ("POST", "/v1/metrics")
+        );
+        assert_eq!(first.body, b"{}");
+        // the second request is read from the buffer of the first
+        let second = read_request(&mut reader).await.unwrap().unwrap();
+        assert_eq!(
+            (second.method.as_str(), second.path.as_str()),
+            ("GET", "/v1/status")
+        );
+        assert_eq!(second.host.as_deref(), Some("localhost"));
+    }
+
+    #[tokio::test]
+    async fn test_line_limits() {
+        let path = "a".repeat(MAX_LINE_LEN);
+        let data = format!("GET /{} HTTP/1.1\r\n\r\n", path);
+        let mut reader = received(data.into()).await;
+        let err = read_request(&mut reader).await.err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "request line or header longer than 8192 bytes"
+        );
+
+        let headers = "X-A: 1\r\n".repeat(MAX_HEADERS);
+        let data = format!("GET / HTTP/1.1\r\n{}X-B: 2\r\n\r\n", headers);
+        let mut reader = received(data.into()).await;
+        let err = read_request(&mut reader).await.err().unwrap();
+        assert_eq!(err.to_string(), "request has more than 100 headers");
+
+        let data = format!("GET / HTTP/1.1\r\n{}\r\n", headers);
+        let mut reader = received(data.into()).await;
+        assert!(read_request(&mut reader).await.unwrap().is_some());
+    }
+}
@@ -0,0 +1,348 @@
+//! Import of exported query results for offline viewing: a Prometheus
+//! `query_range` (or instant query) API response, or a Grafana snapshot.
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::Context;
+use serde::Deserialize;
+
+use super::model::{MetricType, SingleScrapeMetric};
+use super::otlp::sanitize_name;
+use super::pipeline::labels_key;
+use super::{Sample, SingleValueSample};
+
+/// Name of series without a `__name__` label, e.g. the result of `rate(...)`
+const UNNAMED_SERIES: &str = "query_result";
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ImportFile {
+    Prometheus { data: QueryData },
+    Grafana { dashboard: Dashboard },
+}
+
+/// Response of the query API of a Prometheus server
+#[derive(Deserialize)]
+struct QueryResponse {
+    status: String,
+    data: Option<QueryData>,
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct QueryData {
+    result: Vec<QueryResult>,
+}
+
+#[derive(Deserialize)]
+struct QueryResult {
+    #[serde(default)]
+    metric: BTreeMap<String, String>,
+    /// Range vector samples as `[unix seconds, "value"]`
+    #[serde(default)]
+    values: Vec<(f64, String)>,
+    /// Instant vector sample
+    value: Option<(f64, String)>,
+}
+
+#[derive(Deserialize)]
+struct Dashboard {
+    #[serde(default)]
+    panels: Vec<Panel>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Panel {
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    snapshot_data: Vec<SnapshotData>,
+    /// Panels of a collapsed row
+    #[serde(default)]
+    panels: Vec<Panel>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SnapshotData {
+    /// Legacy time series with `[value, unix millis]` points
+    Series {
+        target: String,
+        datapoints: Vec<(Option<f64>, f64)>,
+    },
+    /// Data frame with a time field and one or more value fields
+    Frame { fields: Vec<Field> },
+}
+
+#[derive(Deserialize)]
+struct Field {
+    #[serde(default)]
+    name: String,
+    #[serde(rename = "type", default)]
+    field_type: String,
+    #[serde(default)]
+    labels: BTreeMap<String, String>,
+    #[serde(default)]
+    values: Vec<Option<f64>>,
+}
+
+/// A series of the imported file
+#[derive(Debug, PartialEq)]
+struct ImportedSeries {
+    name: String,
+    labels: BTreeMap<String, String>,
+    /// (unix seconds, value)
+    points: Vec<(u64, f64)>,
+}
+
+/// Decode a Prometheus query API response or a Grafana snapshot into one
+/// scrape per metric and timestamp, ordered by time.
+pub fn decode_import(json: &[u8]) -> anyhow::Result<Vec<SingleScrapeMetric>> {
+    let file: ImportFile = serde_json::from_slice(json)
+        .context("expected a Prometheus query API response or a Grafana snapshot")?;
+    let series = match file {
+        ImportFile::Prometheus { data } => prometheus_series(data),
+        ImportFile::Grafana { dashboard } => {
+            let mut series = vec![];
+            grafana_series(&dashboard.panels, &mut series);
+            series
+        }
+    };
+    if series.is_empty() {
+        anyhow::bail!("the file contains no series");
+    }
+    Ok(into_scrapes(series))
+}
+
+/// Decode the responses of the query API of a Prometheus server to each of
+/// the `(query, body)` into one scrape per metric and timestamp, like
+/// `decode_import`. A query without results is not an error.
+pub fn decode_query_responses(
+    responses: &[(String, Vec<u8>)],
+) -> anyhow::Result<Vec<SingleScrapeMetric>> {
+    let mut series = vec![];
+    for (query, body) in responses {
+        let response: QueryResponse = serde_json::from_slice(body)
+            .with_context(|| format!("query '{}' got no query API response", query))?;
+        match (response.status.as_str(), response.data) {
+            ("success", Some(data)) => series.append(&mut prometheus_series(data)),
+            _ => anyhow::bail!(
+                "query '{}' failed: {}",
+                query,
+                response.error.as_deref().unwrap_or("unknown error")
+            ),
+        }
+    }
+    Ok(into_scrapes(series))
+}
+
+fn prometheus_series(data: QueryData) -> Vec<ImportedSeries> {
+    data.result
+        .into_iter()
+        .map(|mut result| {
+            let name = result
+                .metric
+                .remove("__name__")
+                .unwrap_or_else(|| UNNAMED_SERIES.to_string());
+            let points = result
+                .values
+                .into_iter()
+                .chain(result.value)
+                .filter_map(|(time, value)| Some((time as u64, value.parse().ok()?)))
+                .collect();
+            ImportedSeries {
+                name,
+                labels: result.metric,
+                points,
+            }
+        })
+        .collect()
+}
+
+fn grafana_series(panels: &[Panel], series: &mut Vec<ImportedSeries>) {
+    for panel in panels {
+        for data in &panel.snapshot_data {
+            match data {
+                SnapshotData::Series { target, datapoints } => {
+                    let (name, labels) = split_target(target);
+                    let points = datapoints
+                        .iter()
+                        .filter_map(|(value, millis)| Some(((*millis / 1000.0) as u64, (*value)?)))
+                        .collect();
+                    series.push(ImportedSeries {
+                        name,
+                        labels,
+                        points,
+                    });
+                }
+                SnapshotData::Frame { fields } => {
+                    let Some(times) = fields.iter().find(|field| field.field_type == "time") else {
+                        continue;
+                    };
+                    for field in fields.iter().filter(|field| field.field_type == "number") {
+                        let mut labels = field.labels.clone();
+                        let name = labels.remove("__name__").unwrap_or_else(|| {
+                            match panel.title.as_str() {
+                                "" => field.name.clone(),
+                                title => title.to_string(),
+                            }
+                        });
+                        let points = times
+                            .values
+                            .iter()
+                            .zip(&field.values)
+                            .filter_map(|(millis, value)| {
+                                Some(((((*millis)?) / 1000.0) as u64, (*value)?))
+                            })
+                            .collect();
+                        series.push(ImportedSeries {
+                            name: sanitize_name(&name),
+                            labels,
+                            points,
+                        });
+                    }
+                }
+            }
+        }
+        grafana_series(&panel.panels, series);
+    }
+}
+
+/// Split a legacy Grafana target like `up{job="node"}` into name and labels
+fn split_target(target: &str) -> (String, BTreeMap<String, String>) {
+    let Some((name, labels)) = target.split_once('{') else {
+        return (sanitize_name(target), BTreeMap::new());
+    };
+    let labels = labels
+        .trim_end_matches('}')
+        .split(',')
+        .filter_map(|pair| {
+            let (key, value) = pair.split_once('=')?;
+            Some((
+                key.trim().to_string(),
+                value.trim().trim_matches('"').to_string(),
+            ))
+        })
+        .collect();
+    let name = match name.trim() {
+        "" => UNNAMED_SERIES.to_string(),
+        name => sanitize_name(name),
+    };
+    (name, labels)
+}
+
+/// Group the points by metric and timestamp, as the history is built up one
+/// scrape at a time
+fn into_scrapes(series: Vec<ImportedSeries>) -> Vec<SingleScrapeMetric> {
+    let mut scrapes: BTreeMap<(u64, String), HashMap<String, Sample>> = BTreeMap::new();
+    for series in series {
+        // the query results don't tell the type, go by the naming convention
+        let is_counter = series.name.ends_with("_total");
+        let key = labels_key(
+            series
+                .labels
+                .iter()
+                .map(|(key, value)| (key.as_str(), value.as_str())),
+        );
+        for (timestamp, value) in series.points {
+            let sample = SingleValueSample { timestamp, value };
+            let sample = if is_counter {
+                Sample::CounterSample(sample)
+            } else {
+                Sample::GaugeSample(sample)
+            };
+            scrapes
+                .entry((timestamp, series.name.clone()))
+                .or_default()
+                .insert(key.clone(), sample);
+        }
+    }
+    scrapes
+        .into_iter()
+        .map(|((_, name), value_per_labels)| SingleScrapeMetric {
+            metric_type: if name.ends_with("_total") {
+                MetricType::Counter
+            } else {
+                MetricType::Gauge
+            },
+            name,
+            docstring: String::from("Imported query result"),
+            unit: None,
+            value_per_labels,
+            payload_bytes: 0,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_prometheus_query_range() {
+        let json = r#"{"status": "success", "data": {"resultType": "matrix", "result": [
+            {"metric": {"__name__": "http_requests_total", "code": "200"},
+             "values": [[1700000000, "10"], [1700000015.5, "12"]]},
+            {"metric": {"code": "500"}, "values": [[1700000015, "NaN"]]}]}}"#;
+        let scrapes = decode_import(json.as_bytes()).unwrap();
+        assert_eq!(scrapes.len(), 3);
+        assert_eq!(scrapes[0].name, "http_requests_total");
+        assert!(matches!(scrapes[0].metric_type, MetricType::Counter));
+        match scrapes[0].value_per_labels.get("code=\"200\"") {
+            Some(Sample::CounterSample(sample)) => {
+                assert_eq!((sample.timestamp, sample.value), (1700000000, 10.0))
+            }
+            _ => panic!("expected a counter sample"),
+        }
+        assert_eq!(scrapes[2].name, "query_result");
+        assert!(matches!(scrapes[2].metric_type, MetricType::Gauge));
+    }
+
+    #[test]
+    fn test_decode_query_responses() {
+        let response = |json: &str| ("up".to_string(), json.as_bytes().to_vec());
+        let empty = r#"{"status": "success", "data": {"resultType": "matrix", "result": []}}"#;
+        assert!(decode_query_responses(&[response(empty)])
+            .unwrap()
+            .is_empty());
+        let up = r#"{"status": "success", "data": {"resultType": "matrix", "result": [
+            {"metric": {"__name__": "up"}, "values": [[20, "1"], [10, "0"]]}]}}"#;
+        let scrapes = decode_query_responses(&[response(up), response(empty)]).unwrap();
+        assert_eq!(scrapes.len(), 2);
+        assert_eq!(
+            scrapes[0]
+                .value_per_labels
+                .values()
+                .next()
+                .unwrap()
+                .timestamp(),
+            10
+        );
+        let error = r#"{"status": "error", "errorType": "bad_data", "error": "parse error"}"#;
+        let err = decode_query_responses(&[response(error)]).err().unwrap();
+        assert_eq!(err.to_string(), "query 'up' failed: parse error");
+    }
+
+    #[test]
+    fn test_decode_grafana_snapshot() {
+        let json = r#"{"dashboard": {"panels": [
+            {"title": "Up", "snapshotData": [
+                {"target": "up{job=\"node\", instance=\"a:9100\"}",
+                 "datapoints": [[1, 1700000000000], [null, 1700000015000]]}]},
+            {"type": "row", "panels": [{"title": "Queue size", "snapshotData": [
+                {"fields": [
+                    {"name": "Time", "type": "time", "values": [1700000000000, 1700000015000]},
+                    {"name": "Value", "type": "number", "labels": {"queue": "jobs"},
+                     "values": [3, 5]}]}]}]}]}}"#;
+        let scrapes = decode_import(json.as_bytes()).unwrap();
+        let names: Vec<&str> = scrapes.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["Queue_size", "up", "Queue_size"]);
+        assert!(scrapes[1]
+            .value_per_labels
+            .contains_key("instance=\"a:9100\",job=\"node\""));
+        assert!(scrapes[2].value_per_labels.contains_key("queue=\"jobs\""));
+
+        assert!(decode_import(br#"{"data": {"result": []}}"#).is_err());
+        assert!(decode_import(b"up 1").is_err());
+    }
+}
@@ -0,0 +1,395 @@
+//! Discovery of the scrape targets of a Kubernetes cluster: the pods and
+//! services annotated with `prometheus.io/scrape: "true"`, read from the
+//! API server with the conventional `prometheus.io/port`, `prometheus.io/path`
+//! and `prometheus.io/scheme` annotations.
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::Context;
+use reqwest::{Client, Url};
+use serde::{de::DeserializeOwned, Deserialize};
+
+use super::{targets::Target, HttpClientConfig};
+
+/// Credentials and namespace of the pod's service account
+const SERVICE_ACCOUNT_DIR: &str = "/var/run/secrets/kubernetes.io/serviceaccount";
+
+const SCRAPE_ANNOTATION: &str = "prometheus.io/scrape";
+const PORT_ANNOTATION: &str = "prometheus.io/port";
+const PATH_ANNOTATION: &str = "prometheus.io/path";
+const SCHEME_ANNOTATION: &str = "prometheus.io/scheme";
+
+/// Where and what to discover
+#[derive(Clone, Debug, Default)]
+pub struct Discovery {
+    /// The API server, e.g. `http://127.0.0.1:8001` of `kubectl proxy`. The
+    /// targets are then scraped through its proxy, as pod and service
+    /// addresses are only reachable within the cluster. Defaults to the API
+    /// server of the cluster promviz runs in.
+    pub api: Option<String>,
+    /// Namespace to look in, all of them if not given
+    pub namespace: Option<String>,
+    /// Label selector of the pods and services, e.g. `app=web,tier!=cache`
+    pub selector: Option<String>,
+    /// Time between two discoveries
+    pub refresh: Duration,
+}
+
+#[derive(Deserialize)]
+struct List<T> {
+    items: Vec<T>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Metadata {
+    name: String,
+    namespace: String,
+    annotations: HashMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Pod {
+    metadata: Metadata,
+    #[serde(default)]
+    spec: PodSpec,
+    #[serde(default)]
+    status: PodStatus,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct PodSpec {
+    containers: Vec<Container>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Container {
+    ports: Vec<ContainerPort>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ContainerPort {
+    container_port: u16,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct PodStatus {
+    phase: String,
+    #[serde(rename = "podIP")]
+    pod_ip: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Service {
+    metadata: Metadata,
+    #[serde(default)]
+    spec: ServiceSpec,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ServiceSpec {
+    ports: Vec<ServicePort>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ServicePort {
+    port: u16,
+}
+
+/// Scrape settings of the annotations of an object, if it is to be scraped
+struct Annotated<'a> {
+    port: Option<u16>,
+    path: &'a str,
+    scheme: &'a str,
+}
+
+impl<'a> Annotated<'a> {
+    fn new(metadata: &'a Metadata) -> Option<Self> {
+        let annotation = |name: &str| metadata.annotations.get(name).map(String::as_str);
+        if annotation(SCRAPE_ANNOTATION) != Some("true") {
+            return None;
+        }
+        Some(Annotated {
+            port: annotation(PORT_ANNOTATION).and_then(|port| port.parse().ok()),
+            path: annotation(PATH_ANNOTATION).unwrap_or("/metrics"),
+            scheme: annotation(SCHEME_ANNOTATION).unwrap_or("http"),
+        })
+    }
+}
+
+impl Discovery {
+    /// The annotated pods and services, each labeled with its namespace and
+    /// name as instance
+    pub async fn discover(&self) -> anyhow::Result<Vec<Target>> {
+        let (api, client) = match &self.api {
+            Some(api) => (api.clone(), HttpClientConfig::default().build()?),
+            None => in_cluster_client()?,
+        };
+        let api = Url::parse(&api).with_context(|| format!("invalid API server {}", api))?;
+        let namespace = match &self.namespace {
+            Some(namespace) => format!("namespaces/{}/", namespace),
+            None => String::new(),
+        };
+        let pods: List<Pod> = self
+            .list(&client, &api, &format!("{}pods", namespace))
+            .await?;
+        let services: List<Service> = self
+            .list(&client, &api, &format!("{}services", namespace))
+            .await?;
+        // scraped through the API server when not running in the cluster
+        let proxy = self.api.as_ref().map(|_| &api);
+        let mut targets: Vec<Target> = pods
+            .items
+            .iter()
+            .filter_map(|pod| pod_target(pod, proxy))
+            .chain(
+                services
+                    .items
+                    .iter()
+                    .filter_map(|service| service_target(service, proxy)),
+            )
+            .collect();
+        targets.sort_by(|a, b| a.instance.cmp(&b.instance));
+        if targets.is_empty() {
+            anyhow::bail!(
+                "no pods or services with the {}: \"true\" annotation found",
+                SCRAPE_ANNOTATION
+            );
+        }
+        Ok(targets)
+    }
+
+    async fn list<T: DeserializeOwned>(
+        &self,
+        client: &Client,
+        api: &Url,
+        resource: &str,
+    ) -> anyhow::Result<List<T>> {
+        let mut url = api.join(&format!("api/v1/{}", resource))?;
+        if let Some(selector) = &self.selector {
+            url.query_pairs_mut().append_pair("labelSelector", selector);
+        }
+        let response = client
+            .get(url.clone())
+            .send()
+            .await
+            .with_context(|| format!("failed to list {}", resource))?;
+        let status = response.status();
+        if !status.is_success() {
+            anyhow::bail!(
+                "listing {} failed with {}: {}",
+                resource,
+                status,
+                response.text().await.unwrap_or_default()
+            );
+        }
+        response
+            .json()
+            .await
+            .with_context(|| format!("invalid list of {}", resource))
+    }
+}
+
+/// The API server and credentials of the service account of the pod
+/// promviz runs in
+fn in_cluster_client() -> anyhow::Result<(String, Client)> {
+    let (Ok(host), Ok(port)) = (
+        std::env::var("KUBERNETES_SERVICE_HOST"),
+        std::env::var("KUBERNETES_SERVICE_PORT"),
+    ) else {
+        anyhow::bail!("not running in a Kubernetes cluster, give the API server with --k8s-api");
+    };
+    let dir = Path::new(SERVICE_ACCOUNT_DIR);
+    let token = std::fs::read_to_string(dir.join("token"))
+        .context("failed to read the service account token")?;
+    let client = HttpClientConfig {
+        tls_ca: Some(dir.join("ca.crt")),
+        bearer_token: Some(token.trim().to_string()),
+        ..HttpClientConfig::default()
+    }
+    .build()?;
+    // an IPv6 address needs brackets in the URL
+    let host = match host.contains(':') {
+        true => format!("[{}]", host),
+        false => host,
+    };
+    Ok((format!("https://{}:{}/", host, port), client))
+}
+
+fn instance(metadata: &Metadata) -> String {
+    format!("{}/{}", metadata.namespace, metadata.name)
+}
+
+/// The target of a running pod, on the annotated port or the first port of
+/// its containers
+fn pod_target(pod: &Pod, proxy: Option<&Url>) -> Option<Target> {
+    let annotated = Annotated::new(&pod.metadata)?;
+    if pod.status.phase != "Running" {
+        return None;
+    }
+    let port = annotated.port.or_else(|| {
+        pod.spec
+            .containers
+            .iter()
+            .flat_map(|container| &container.ports)
+            .map(|port| port.container_port)
+            .next()
+    })?;
+    let url = match proxy {
+        Some(api) => proxy_url(api, "pods", &pod.metadata, &annotated, port),
+        None => {
+            let ip = pod.status.pod_ip.as_deref()?;
+            let ip = match ip.contains(':') {
+                true => format!("[{}]", ip),
+                false => ip.to_string(),
+            };
+            format!("{}://{}:{}{}", annotated.scheme, ip, port, annotated.path)
+        }
+    };
+    Some(Target {
+        url,
+        instance: Some(instance(&pod.metadata)),
+    })
+}
+
+/// The target of a service, on the annotated port or its first port
+fn service_target(service: &Service, proxy: Option<&Url>) -> Option<Target> {
+    let annotated = Annotated::new(&service.metadata)?;
+    let port = annotated
+        .port
+        .or_else(|| service.spec.ports.first().map(|port| port.port))?;
+    let metadata = &service.metadata;
+    let url = match proxy {
+        Some(api) => proxy_url(api, "services", metadata, &annotated, port),
+        None => format!(
+            "{}://{}.{}.svc:{}{}",
+            annotated.scheme, metadata.name, metadata.namespace, port, annotated.path
+        ),
+    };
+    Some(Target {
+        url,
+        instance: Some(instance(metadata)),
+    })
+}
+
+/// The URL of the API server proxying to a pod or service, e.g.
+/// `/api/v1/namespaces/default/pods/https:web-0:8443/proxy/metrics`
+fn proxy_url(
+    api: &Url,
+    resource: &str,
+    metadata: &Metadata,
+    annotated: &Annotated,
+    port: u16,
+) -> String {
+    let scheme = match annotated.scheme {
+        "https" => "https:",
+        _ => "",
+    };
+    format!(
+        "{}/api/v1/namespaces/{}/{}/{}{}:{}/proxy{}",
+        api.as_str().trim_end_matches('/'),
+        metadata.namespace,
+        resource,
+        scheme,
+        metadata.name,
+        port,
+        annotated.path
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PODS: &str = r#"{"items": [
+        {
+            "metadata": {"name": "web-0", "namespace": "shop",
+                "annotations": {"prometheus.io/scrape": "true", "prometheus.io/port": "9102"}},
+            "spec": {"containers": [{"ports": [{"containerPort": 8080}]}]},
+            "status": {"phase": "Running", "podIP": "10.1.2.3"}
+        },
+        {
+            "metadata": {"name": "worker-0", "namespace": "shop",
+                "annotations": {"prometheus.io/scrape": "true", "prometheus.io/path": "/stats"}},
+            "spec": {"containers": [{}, {"ports": [{"containerPort": 9000}]}]},
+            "status": {"phase": "Running", "podIP": "fd00::7"}
+        },
+        {
+            "metadata": {"name": "web-1", "namespace": "shop",
+                "annotations": {"prometheus.io/scrape": "true"}},
+            "spec": {"containers": [{"ports": [{"containerPort": 8080}]}]},
+            "status": {"phase": "Pending"}
+        },
+        {
+            "metadata": {"name": "db-0", "namespace": "shop"},
+            "spec": {"containers": [{"ports": [{"containerPort": 5432}]}]},
+            "status": {"phase": "Running", "podIP": "10.1.2.4"}
+        }
+    ]}"#;
+
+    const SERVICES: &str = r#"{"items": [
+        {
+            "metadata": {"name": "api", "namespace": "shop",
+                "annotations": {"prometheus.io/scrape": "true", "prometheus.io/scheme": "https"}},
+            "spec": {"ports": [{"port": 443}]}
+        },
+        {
+            "metadata": {"name": "cache", "namespace": "shop",
+                "annotations": {"prometheus.io/scrape": "false"}},
+            "spec": {"ports": [{"port": 6379}]}
+        }
+    ]}"#;
+
+    fn targets(proxy: Option<&Url>) -> Vec<(String, String)> {
+        let pods: List<Pod> = serde_json::from_str(PODS).unwrap();
+        let services: List<Service> = serde_json::from_str(SERVICES).unwrap();
+        pods.items
+            .iter()
+            .filter_map(|pod| pod_target(pod, proxy))
+            .chain(
+                services
+                    .items
+                    .iter()
+                    .filter_map(|service| service_target(service, proxy)),
+            )
+            .map(|target| (target.instance.unwrap(), target.url))
+            .collect()
+    }
+
+    #[test]
+    fn test_annotated_targets() {
+        let target = |instance: &str, url: &str| (instance.to_string(), url.to_string());
+        assert_eq!(
+            targets(None),
+            vec![
+                target("shop/web-0", "http://10.1.2.3:9102/metrics"),
+                target("shop/worker-0", "http://[fd00::7]:9000/stats"),
+                target("shop/api", "https://api.shop.svc:443/metrics"),
+            ]
+        );
+        let api = Url::parse("http://127.0.0.1:8001").unwrap();
+        assert_eq!(
+            targets(Some(&api)),
+            vec![
+                target(
+                    "shop/web-0",
+                    "http://127.0.0.1:8001/api/v1/namespaces/shop/pods/web-0:9102/proxy/metrics"
+                ),
+                target(
+                    "shop/worker-0",
+                    "http://127.0.0.1:8001/api/v1/namespaces/shop/pods/worker-0:9000/proxy/stats"
+                ),
+                target(
+                    "shop/api",
+                    "http://127.0.0.1:8001/api/v1/namespaces/shop/services/https:api:443/proxy/metrics"
+                ),
+            ]
+        );
+    }
+}
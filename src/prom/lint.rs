@@ -0,0 +1,253 @@
+//! Conformance checks of the exposition format, similar to
+//! `promtool check metrics`, run by `promviz lint`.
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+
+use regex::Regex;
+
+use super::parser::{
+    decode_labels, extract_labels, extract_name_docstring, extract_type, split_metric_lines,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Finding {
+    pub severity: Severity,
+    /// Metric family the finding is about
+    pub metric: String,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, "{:<8}{}: {}", severity, self.metric, self.message)
+    }
+}
+
+const TYPES: [&str; 5] = ["counter", "gauge", "histogram", "summary", "untyped"];
+
+/// Check the metrics `text`, grouped into families like when scraping. The
+/// findings are sorted by metric, errors first.
+pub fn lint(text: &str) -> Vec<Finding> {
+    let metric_name = Regex::new("^[a-zA-Z_:][a-zA-Z0-9_:]*$").unwrap();
+    let label_name = Regex::new("^[a-zA-Z_][a-zA-Z0-9_]*$").unwrap();
+    let lines = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(String::from)
+        .collect();
+    let mut findings = vec![];
+    let mut add = |severity, metric: &str, message: String| {
+        findings.push(Finding {
+            severity,
+            metric: metric.to_string(),
+            message,
+        })
+    };
+    let mut families = HashSet::new();
+    let mut series = HashSet::new();
+
+    for family_lines in split_metric_lines(lines) {
+        let help = family_lines
+            .iter()
+            .find_map(|line| extract_name_docstring(line));
+        let metric_type = family_lines.iter().find_map(|line| extract_type(line));
+        let samples: Vec<&String> = family_lines
+            .iter()
+            .filter(|line| !line.starts_with('#'))
+            .collect();
+        let name = match (&metric_type, &help, samples.first()) {
+            (Some((name, _)), _, _) | (None, Some((name, _)), _) => name.clone(),
+            (None, None, Some(sample)) => sample_name(sample).to_string(),
+            (None, None, None) => continue,
+        };
+
+        if !families.insert(name.clone()) {
+            add(
+                Severity::Error,
+                &name,
+                "metadata given more than once".to_string(),
+            );
+        }
+        if !metric_name.is_match(&name) {
+            add(Severity::Error, &name, "invalid metric name".to_string());
+        }
+        if help.is_none() {
+            add(Severity::Warning, &name, "no HELP".to_string());
+        }
+        let metric_type = match metric_type {
+            Some((_, metric_type)) if !TYPES.contains(&metric_type.as_str()) => {
+                add(
+                    Severity::Error,
+                    &name,
+                    format!("unknown TYPE '{}'", metric_type),
+                );
+                "untyped".to_string()
+            }
+            Some((type_name, _)) if type_name != name => {
+                add(
+                    Severity::Error,
+                    &name,
+                    format!("HELP and TYPE of different metrics ({})", type_name),
+                );
+                "untyped".to_string()
+            }
+            Some((_, metric_type)) => metric_type,
+            None => {
+                add(Severity::Warning, &name, "no TYPE".to_string());
+                "untyped".to_string()
+            }
+        };
+        match metric_type.as_str() {
+            "counter" if !name.ends_with("_total") => add(
+                Severity::Warning,
+                &name,
+                "counter names should end with _total".to_string(),
+            ),
+            "gauge" | "histogram" | "summary" | "untyped" if name.ends_with("_total") => add(
+                Severity::Warning,
+                &name,
+                format!(
+                    "_total suffix is reserved for counters, not {}",
+                    metric_type
+                ),
+            ),
+            _ => {}
+        }
+
+        let suffixes: &[&str] = match metric_type.as_str() {
+            "histogram" => &["_bucket", "_sum", "_count"],
+            "summary" => &["", "_sum", "_count"],
+            _ => &[""],
+        };
+        for sample in samples {
+            let sample_name = sample_name(sample);
+            if !suffixes
+                .iter()
+                .any(|suffix| sample_name == format!("{}{}", name, suffix))
+            {
+                add(
+                    Severity::Error,
+                    &name,
+                    format!("sample {} without its own HELP and TYPE", sample_name),
+                );
+            }
+            let labels: BTreeMap<String, String> = extract_labels(sample)
+                .map(|labels| decode_labels(&labels).into_iter().collect())
+                .unwrap_or_default();
+            for label in labels.keys() {
+                if !label_name.is_match(label) {
+                    add(
+                        Severity::Error,
+                        &name,
+                        format!("invalid label name '{}'", label),
+                    );
+                } else if label.starts_with("__") {
+                    add(
+                        Severity::Error,
+                        &name,
+                        format!("label name '{}' is reserved", label),
+                    );
+                }
+            }
+            let value = match sample.rfind('}') {
+                Some(end) => &sample[end + 1..],
+                None => &sample[sample_name.len()..],
+            };
+            let value = value.split_whitespace().next().map(str::parse::<f64>);
+            if !matches!(value, Some(Ok(_))) {
+                add(Severity::Error, &name, format!("invalid value: {}", sample));
+            }
+            let key = format!("{}{:?}", sample_name, labels);
+            if !series.insert(key) {
+                add(
+                    Severity::Error,
+                    &name,
+                    format!("duplicate series {}", series_name(sample_name, &labels)),
+                );
+            }
+        }
+    }
+    findings.sort_by(|a, b| (&a.metric, a.severity).cmp(&(&b.metric, b.severity)));
+    findings
+}
+
+/// Name of a sample line, e.g. `http_requests_total` of `http_requests_total{code="200"} 3`
+fn sample_name(line: &str) -> &str {
+    line.split(['{', ' ', '\t']).next().unwrap_or_default()
+}
+
+fn series_name(name: &str, labels: &BTreeMap<String, String>) -> String {
+    if labels.is_empty() {
+        return name.to_string();
+    }
+    let labels: Vec<String> = labels
+        .iter()
+        .map(|(key, value)| format!("{}=\"{}\"", key, value))
+        .collect();
+    format!("{}{{{}}}", name, labels.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(text: &str) -> Vec<String> {
+        lint(text).iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn test_lint_clean_metrics() {
+        let text = "# HELP requests_total Handled requests
+# TYPE requests_total counter
+requests_total{code=\"200\"} 10
+requests_total{code=\"500\"} 2
+# HELP latency_seconds Request latency
+# TYPE latency_seconds histogram
+latency_seconds_bucket{le=\"0.1\"} 3
+latency_seconds_bucket{le=\"+Inf\"} 4
+latency_seconds_sum 0.5
+latency_seconds_count 4
+";
+        assert_eq!(messages(text), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_lint_findings() {
+        let text = "# HELP requests Handled requests
+# TYPE requests counter
+requests{code=\"200\"} 10
+requests{code=\"200\"} 11
+requests{__code=\"500\",2xx=\"no\"} 2
+# TYPE queue_total gauge
+queue_total NaN
+queue_size 3
+# HELP temperature Temperature
+# TYPE temperature gauge
+temperature{room=\"a\"} warm
+";
+        assert_eq!(
+            messages(text),
+            vec![
+                "error   queue_total: sample queue_size without its own HELP and TYPE",
+                "warning queue_total: no HELP",
+                "warning queue_total: _total suffix is reserved for counters, not gauge",
+                "error   requests: duplicate series requests{code=\"200\"}",
+                "error   requests: invalid label name '2xx'",
+                "error   requests: label name '__code' is reserved",
+                "warning requests: counter names should end with _total",
+                "error   temperature: invalid value: temperature{room=\"a\"} warm",
+            ]
+        );
+    }
+}
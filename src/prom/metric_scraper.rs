@@ -1,126 +1,1037 @@
 use super::{
-    model::MetricHistory,
-    parser::{decode_single_scrape_metric, split_metric_lines},
+    auth_command::AuthCommand,
+    dns_sd::DnsSd,
+    grpc_health::HealthCheck,
+    http_server, import,
+    k8s::Discovery,
+    model::SingleScrapeMetric,
+    otlp,
+    parser::{decode_scrape, split_metric_lines},
+    pipeline::Pipeline,
+    query::{self, QueryOptions},
+    spill::{self, SpillFile},
+    store::HistoryStore,
+    targets::{self, ConflictPolicy, Target},
 };
-use std::sync::{Arc, RwLock, RwLockReadGuard};
+use anyhow::Context;
+use reqwest::{
+    header::{HeaderValue, AUTHORIZATION, RETRY_AFTER},
+    Client, StatusCode,
+};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard};
 use std::time::{Duration, Instant};
 use std::time::{SystemTime, UNIX_EPOCH};
-use tokio::{task, time::sleep};
+use tokio::{
+    io::BufReader,
+    net::{TcpListener, TcpStream},
+    sync::{watch, Notify},
+    task::{self, JoinSet},
+    time::sleep,
+};
+
+pub type MetricHistoryArc = Arc<RwLock<Box<dyn HistoryStore>>>;
+pub type ScrapeStatusArc = Arc<RwLock<ScrapeStatus>>;
+/// Processed scrapes waiting to be stored, oldest first
+type BatchArc = Arc<Mutex<Vec<Vec<SingleScrapeMetric>>>>;
+
+/// Scrapes buffered at most before they are stored without waiting for the
+/// next frame
+const MAX_BATCH: usize = 100;
+
+/// Longest delay between two retries of a failed scrape
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// Longest delay asked for by a Retry-After header that is respected
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(3600);
+
+/// Time between two checks of the memory limit
+const MEMORY_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Length of the body excerpt shown when an endpoint returns something else than metrics
+const SNIPPET_LEN: usize = 80;
+
+/// What to do with scrape ticks that pass while a scrape is still running
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OverlapPolicy {
+    /// Drop the missed ticks and wait for the next one
+    #[default]
+    Skip,
+    /// Start the next scrape right away, then continue at the regular interval
+    Queue,
+}
+
+/// Settings of the scrape loop
+#[derive(Clone, Debug)]
+pub struct ScrapeOptions {
+    /// The endpoint, or its template for a host list
+    pub url: String,
+    /// Endpoints scraped at once, see `Target::expand`
+    pub targets: Vec<Target>,
+    /// Seconds between two scrapes
+    pub scrape_interval: u64,
+    /// Time between two scrapes instead of `scrape_interval`, when below a
+    /// second. The scrapes are then stored in batches, see
+    /// `MetricScraper::deliver_batch`.
+    pub fast_interval: Option<Duration>,
+    /// Retries of a failed scrape before waiting for the next interval
+    pub retries: u32,
+    pub overlap_policy: OverlapPolicy,
+    /// Processing of every scrape, e.g. the metric families to keep
+    pub pipeline: Pipeline,
+    /// Helper providing the credentials of every scrape
+    pub auth_command: Option<AuthCommand>,
+    /// Replaces the targets with the ones found in DNS, periodically
+    pub dns_sd: Option<DnsSd>,
+    /// Replaces the targets with the ones found in Kubernetes, periodically
+    pub k8s: Option<Discovery>,
+    /// How series of several targets with the same labels are told apart
+    pub conflict_policy: ConflictPolicy,
+    /// gRPC service whose health is checked before every scrape
+    pub grpc_health: Option<HealthCheck>,
+}
+
+/// A scheduled retry of a failed scrape
+#[derive(Clone, Debug)]
+pub struct RetryState {
+    /// Number of the upcoming retry, starting at 1
+    pub attempt: u32,
+    pub max_attempts: u32,
+    pub next_at: Instant,
+}
+
+/// Health of one target as of its latest scrape
+#[derive(Clone, Debug)]
+pub struct TargetStatus {
+    pub target: Target,
+    /// Error of the latest scrape of the target, if it failed
+    pub error: Option<String>,
+    /// The target is rate limiting, it isn't scraped until then
+    pub rate_limited_until: Option<Instant>,
+}
+
+impl TargetStatus {
+    fn new(target: Target) -> Self {
+        Self {
+            target,
+            error: None,
+            rate_limited_until: None,
+        }
+    }
+
+    /// The `instance` label of the target, or its url
+    pub fn name(&self) -> &str {
+        self.target.instance.as_deref().unwrap_or(&self.target.url)
+    }
+}
+
+/// Outcome of the latest scrape
+#[derive(Clone, Debug, Default)]
+pub struct ScrapeStatus {
+    pub error_msg: Option<String>,
+    pub retry: Option<RetryState>,
+    /// Successful scrapes since the start
+    pub scrape_count: u64,
+    /// Failed scrapes and retries since the start
+    pub failure_count: u64,
+    /// Scrape ticks dropped because the previous scrape was still running
+    pub skipped_count: u64,
+    /// Time taken by the latest scrape, including retries
+    pub last_duration: Option<Duration>,
+    /// Scraping is paused by the user, new samples are not stored
+    pub paused: bool,
+    /// Every target is rate limiting, the next scrape waits until then
+    pub rate_limited_until: Option<Instant>,
+    /// Health of every target, in the order of `ScrapeOptions::targets`
+    pub targets: Vec<TargetStatus>,
+    /// Old samples moved to the spill file because of the memory limit
+    pub spilled_samples: usize,
+    /// The spill file, while it holds samples
+    pub spill_path: Option<PathBuf>,
+    /// Health of the gRPC service, as of the latest scrape
+    pub grpc_health: Option<String>,
+}
+
+impl ScrapeStatus {
+    /// Number of scrapes, successful or not. Changes whenever the history or
+    /// the error changes.
+    pub fn updates(&self) -> u64 {
+        self.scrape_count + self.failure_count
+    }
+}
 
-type MetricHistoryArc = Arc<RwLock<MetricHistory>>;
 #[derive(Debug)]
 pub struct MetricScraper {
     metrics_history: MetricHistoryArc,
-    error_msg: Arc<RwLock<Option<String>>>,
+    status: ScrapeStatusArc,
+    /// Time between two scrapes, if scraping an endpoint
+    scrape_interval: Option<watch::Sender<Duration>>,
+    /// Scrapes waiting to be stored, with a fast interval
+    batch: Option<BatchArc>,
+    /// Wakes the scrape loop to scrape right away, if scraping an endpoint
+    scrape_now: Option<Arc<Notify>>,
+    /// The scraped endpoint, if any
+    url: Option<String>,
+    /// Options and client of the scrape loop, to scrape another endpoint
+    /// with, see `retarget`
+    restart: Option<(ScrapeOptions, Client)>,
+    /// The scrape loop, stopped when the scraper is dropped
+    scrape_loop: Option<task::JoinHandle<()>>,
 }
 
 impl MetricScraper {
-    pub fn new(url: String, scrape_interval: u64) -> Self {
-        let metrics_history = MetricHistoryArc::new(RwLock::new(MetricHistory::new()));
-        let error_msg = Arc::new(RwLock::new(None));
+    pub fn new(options: ScrapeOptions, client: Client, store: Box<dyn HistoryStore>) -> Self {
+        let metrics_history = MetricHistoryArc::new(RwLock::new(store));
+        let status = ScrapeStatusArc::default();
+        let interval = options
+            .fast_interval
+            .unwrap_or(Duration::from_secs(options.scrape_interval));
+        let (scrape_interval, interval_rx) = watch::channel(interval);
+        let scrape_now = Arc::new(Notify::new());
+        let url = Some(options.url.clone());
+        let batch = options.fast_interval.map(|_| BatchArc::default());
+        let restart = Some((options.clone(), client.clone()));
+
+        let scrape_loop = {
+            let history = Arc::clone(&metrics_history);
+            let status = Arc::clone(&status);
+            let scrape_now = Arc::clone(&scrape_now);
+            let batch = batch.clone();
+            let mut options = options;
+            task::spawn(async move {
+                scrape_metric_endpoint(
+                    &client,
+                    &mut options,
+                    interval_rx,
+                    &scrape_now,
+                    &history,
+                    batch.as_ref(),
+                    &status,
+                )
+                .await;
+            })
+        };
+        Self {
+            metrics_history,
+            status,
+            scrape_interval: Some(scrape_interval),
+            batch,
+            scrape_now: Some(scrape_now),
+            url,
+            restart,
+            scrape_loop: Some(scrape_loop),
+        }
+    }
+
+    /// Scrape the single endpoint `url` instead, with the other options and
+    /// the current interval, into an empty history of the same kind. Returns
+    /// false if the metrics are not scraped from an endpoint, e.g. when
+    /// pushed over OTLP.
+    pub fn retarget(&mut self, url: &str) -> anyhow::Result<bool> {
+        let Some((options, client)) = self.restart.clone() else {
+            return Ok(false);
+        };
+        let store = self.get_history_lock()?.new_empty();
+        // the interval may have been changed since the start
+        let interval = self
+            .scrape_interval()
+            .unwrap_or(Duration::from_secs(options.scrape_interval));
+        let options = ScrapeOptions {
+            url: url.to_string(),
+            targets: Target::expand(url, &[])?,
+            scrape_interval: interval.as_secs().max(1),
+            fast_interval: (interval < Duration::from_secs(1)).then_some(interval),
+            // the endpoint replaces the discovered targets
+            dns_sd: None,
+            k8s: None,
+            ..options
+        };
+        // the old scrape loop is stopped when the old scraper is dropped
+        *self = MetricScraper::new(options, client, store);
+        Ok(true)
+    }
+
+    /// Receive metrics pushed over OTLP/HTTP instead of scraping an endpoint
+    pub fn with_otlp_receiver(
+        listener: TcpListener,
+        pipeline: Pipeline,
+        store: Box<dyn HistoryStore>,
+    ) -> Self {
+        let metrics_history = MetricHistoryArc::new(RwLock::new(store));
+        let status = ScrapeStatusArc::default();
 
         {
             let history = Arc::clone(&metrics_history);
-            let error_msg = Arc::clone(&error_msg);
+            let status = Arc::clone(&status);
             task::spawn(async move {
-                scrape_metric_endpoint(&url, &history, &error_msg, scrape_interval).await;
+                receive_otlp_metrics(listener, pipeline, history, status).await;
             });
         }
         Self {
             metrics_history,
-            error_msg,
+            status,
+            scrape_interval: None,
+            batch: None,
+            scrape_now: None,
+            url: None,
+            restart: None,
+            scrape_loop: None,
         }
     }
 
-    pub fn get_history_lock(&self) -> anyhow::Result<RwLockReadGuard<MetricHistory>> {
+    /// Read the history of PromQL queries from a Prometheus server, and the
+    /// new points every scrape interval
+    pub fn with_query(
+        options: QueryOptions,
+        scrape_interval: u64,
+        pipeline: Pipeline,
+        client: Client,
+        store: Box<dyn HistoryStore>,
+    ) -> Self {
+        let metrics_history = MetricHistoryArc::new(RwLock::new(store));
+        let status = ScrapeStatusArc::default();
+        let (scrape_interval, interval_rx) = watch::channel(Duration::from_secs(scrape_interval));
+        let scrape_now = Arc::new(Notify::new());
+
+        {
+            let history = Arc::clone(&metrics_history);
+            let status = Arc::clone(&status);
+            let scrape_now = Arc::clone(&scrape_now);
+            task::spawn(async move {
+                query_prometheus(
+                    &client,
+                    &options,
+                    &pipeline,
+                    interval_rx,
+                    &scrape_now,
+                    &history,
+                    &status,
+                )
+                .await;
+            });
+        }
+        Self {
+            metrics_history,
+            status,
+            scrape_interval: Some(scrape_interval),
+            batch: None,
+            scrape_now: Some(scrape_now),
+            url: None,
+            restart: None,
+            scrape_loop: None,
+        }
+    }
+
+    /// Show a single scrape of already fetched metrics, e.g. read from stdin
+    pub fn with_metrics_text(text: &str, pipeline: Pipeline, store: Box<dyn HistoryStore>) -> Self {
+        let metrics_history = MetricHistoryArc::new(RwLock::new(store));
+        update_history_with_new_scrape(&metrics_history, &pipeline, split_text(text));
+        let status = ScrapeStatusArc::default();
+        update_status(&status, None, None);
+        Self {
+            metrics_history,
+            status,
+            scrape_interval: None,
+            batch: None,
+            scrape_now: None,
+            url: None,
+            restart: None,
+            scrape_loop: None,
+        }
+    }
+
+    /// Scrapes of fixture texts taken at fixed unix seconds, failing with
+    /// `error_msg` if given, so the UI renders the same on every run
+    #[cfg(test)]
+    pub fn with_fixture(
+        scrapes: &[(u64, &str)],
+        error_msg: Option<&str>,
+        store: Box<dyn HistoryStore>,
+    ) -> Self {
+        let metrics_history = MetricHistoryArc::new(RwLock::new(store));
+        for (timestamp, text) in scrapes {
+            let metrics = decode_scrape(split_text(text), *timestamp);
+            update_history_with_metrics(&metrics_history, &Pipeline::default(), metrics);
+        }
+        let status = ScrapeStatusArc::default();
+        update_status(&status, error_msg.map(String::from), None);
+        Self {
+            metrics_history,
+            status,
+            scrape_interval: None,
+            batch: None,
+            scrape_now: None,
+            url: None,
+            restart: None,
+            scrape_loop: None,
+        }
+    }
+
+    /// Show exported query results, see `import::decode_import` for the formats
+    pub fn with_imported_json(
+        json: &[u8],
+        pipeline: Pipeline,
+        store: Box<dyn HistoryStore>,
+    ) -> anyhow::Result<Self> {
+        let metrics_history = MetricHistoryArc::new(RwLock::new(store));
+        update_history_with_metrics(&metrics_history, &pipeline, import::decode_import(json)?);
+        let status = ScrapeStatusArc::default();
+        update_status(&status, None, None);
+        Ok(Self {
+            metrics_history,
+            status,
+            scrape_interval: None,
+            batch: None,
+            scrape_now: None,
+            url: None,
+            restart: None,
+            scrape_loop: None,
+        })
+    }
+
+    pub fn get_history_lock(&self) -> anyhow::Result<RwLockReadGuard<'_, Box<dyn HistoryStore>>> {
         self.metrics_history
             .read()
             .map_err(|err| anyhow::anyhow!("failed to aquire lock of metrics history: {}", err))
     }
 
-    pub fn get_error_msg_read_guard(&self) -> anyhow::Result<RwLockReadGuard<Option<String>>> {
-        self.error_msg
+    /// The history and the status, for readers besides the UI, e.g. the web
+    /// dashboard
+    pub fn shared(&self) -> (MetricHistoryArc, ScrapeStatusArc) {
+        (Arc::clone(&self.metrics_history), Arc::clone(&self.status))
+    }
+
+    /// The scraped endpoint, `None` for pushed, piped or imported metrics
+    pub fn url(&self) -> Option<&str> {
+        self.url.as_deref()
+    }
+
+    /// Time between two scrapes, `None` if the metrics are not scraped
+    pub fn scrape_interval(&self) -> Option<Duration> {
+        self.scrape_interval
+            .as_ref()
+            .map(|scrape_interval| *scrape_interval.borrow())
+    }
+
+    /// Change the seconds between two scrapes, rescheduling the next scrape.
+    /// Returns false if the metrics are not scraped, e.g. when pushed over OTLP.
+    pub fn set_scrape_interval(&self, seconds: u64) -> bool {
+        match &self.scrape_interval {
+            Some(scrape_interval) => {
+                scrape_interval.send_replace(Duration::from_secs(seconds.max(1)));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Store the scrapes buffered since the last call at once, returning how
+    /// many. Called once per frame, so scrapes at a fast interval take the
+    /// lock of the history once per frame instead of once per scrape.
+    pub fn deliver_batch(&self) -> usize {
+        match &self.batch {
+            Some(batch) => store_batch(&self.metrics_history, batch),
+            None => 0,
+        }
+    }
+
+    /// Scrape right away instead of waiting for the next scheduled scrape.
+    /// Returns false if the metrics are not scraped, e.g. when pushed over OTLP.
+    pub fn scrape_now(&self) -> bool {
+        match &self.scrape_now {
+            Some(scrape_now) => {
+                scrape_now.notify_one();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Pause or resume storing new samples, returning whether it is paused now
+    pub fn toggle_pause(&self) -> anyhow::Result<bool> {
+        let mut status_guard = self
+            .status
+            .write()
+            .map_err(|err| anyhow::anyhow!("failed to aquire lock: {}", err))?;
+        status_guard.paused = !status_guard.paused;
+        Ok(status_guard.paused)
+    }
+
+    /// Check the memory use every `MEMORY_CHECK_INTERVAL`, and spill the
+    /// oldest samples to the temporary file of tab `tab` while it is above
+    /// `limit` bytes
+    pub fn watch_memory(&self, limit: u64, tab: usize) {
+        let history = Arc::clone(&self.metrics_history);
+        let status = Arc::clone(&self.status);
+        task::spawn(async move {
+            let spill_file = SpillFile::new(tab);
+            loop {
+                sleep(MEMORY_CHECK_INTERVAL).await;
+                match spill::resident_memory() {
+                    Some(used) if used > limit => spill_oldest(&history, &status, &spill_file),
+                    Some(_) => {}
+                    None => {
+                        log::warn!("memory use is not available, not enforcing memory limit");
+                        return;
+                    }
+                }
+            }
+        });
+    }
+
+    /// Read back the samples spilled because of the memory limit, returning
+    /// how many were restored
+    pub fn restore_spilled(&self) -> anyhow::Result<usize> {
+        let Some(path) = self.get_status_read_guard()?.spill_path.clone() else {
+            return Ok(0);
+        };
+        let spill_file = SpillFile { path };
+        // hold the lock while reading so nothing is spilled in between
+        let mut history_guard = self
+            .metrics_history
+            .write()
+            .map_err(|err| anyhow::anyhow!("failed to aquire lock of metrics history: {}", err))?;
+        let count = history_guard.restore(spill_file.read()?);
+        spill_file.remove()?;
+        drop(history_guard);
+        let mut status_guard = self
+            .status
+            .write()
+            .map_err(|err| anyhow::anyhow!("failed to aquire lock: {}", err))?;
+        status_guard.spilled_samples = 0;
+        status_guard.spill_path = None;
+        Ok(count)
+    }
+
+    pub fn get_status_read_guard(&self) -> anyhow::Result<RwLockReadGuard<'_, ScrapeStatus>> {
+        self.status
             .read()
             .map_err(|err| anyhow::anyhow!("failed to aquire lock: {}", err))
     }
 }
 
+impl Drop for MetricScraper {
+    fn drop(&mut self) {
+        if let Some(scrape_loop) = &self.scrape_loop {
+            scrape_loop.abort();
+        }
+    }
+}
+
 async fn scrape_metric_endpoint(
-    url: &str,
+    client: &Client,
+    options: &mut ScrapeOptions,
+    mut scrape_interval: watch::Receiver<Duration>,
+    scrape_now: &Notify,
     history: &MetricHistoryArc,
-    error_msg: &Arc<RwLock<Option<String>>>,
-    scrape_interval: u64,
+    batch: Option<&BatchArc>,
+    status: &ScrapeStatusArc,
 ) {
-    let mut last_tick = Instant::now();
-    let tick_rate = Duration::from_millis(scrape_interval * 1000);
-    let mut must_scrape = true;
+    let mut tick_rate = *scrape_interval.borrow_and_update();
+    let mut deadline = Instant::now();
+    let mut next_discovery = Instant::now();
+    let mut targets = vec![];
 
     loop {
-        // scrape and update history
-        if must_scrape {
-            let splitted_metrics_result = get_splitted_metrics_from_endpoint(url).await;
-
-            match splitted_metrics_result {
-                Ok(splitted_metrics) => {
-                    update_history_with_new_scrape(history, splitted_metrics);
-                    update_error_status(error_msg, None);
+        let started = Instant::now();
+        deadline += tick_rate;
+        if started >= next_discovery {
+            if let Some(dns_sd) = &options.dns_sd {
+                let found = dns_sd.resolve().await;
+                discover_targets(&dns_sd.name, found, &mut options.targets);
+                next_discovery = started + dns_sd.refresh;
+            } else if let Some(k8s) = &options.k8s {
+                let found = k8s.discover().await;
+                discover_targets("Kubernetes", found, &mut options.targets);
+                next_discovery = started + k8s.refresh;
+            }
+        }
+        if let Some(health_check) = &options.grpc_health {
+            let health = health_check.status().await;
+            status
+                .write()
+                .expect("to acquire write lock of scrape status")
+                .grpc_health = Some(health);
+        }
+        let mut attempt = 0;
+        let mut rate_limited_until = None;
+        // scrape and update history, retrying failed scrapes with an increasing delay
+        while !is_paused(status) {
+            let result = scrape_targets(client, options, &mut targets).await;
+            update_targets(status, &targets);
+            match result {
+                Ok((metrics, failed_targets)) => {
+                    match batch {
+                        Some(batch) => buffer_scrape(history, batch, &options.pipeline, metrics),
+                        None => update_history_with_metrics(history, &options.pipeline, metrics),
+                    }
+                    update_status(status, failed_targets, None);
+                    break;
                 }
                 Err(err) => {
-                    update_error_status(error_msg, Some(err.to_string()));
                     log::error!("Not able to scrape the metrics endpoint: {}", err);
+                    if let Some(until) = rate_limited(&targets) {
+                        // wait as long as asked instead of retrying rate limited targets
+                        log::warn!(
+                            "Endpoint is rate limiting, next scrape in {:?}",
+                            until.saturating_duration_since(Instant::now())
+                        );
+                        rate_limited_until = Some(until);
+                        update_status(status, Some(err.to_string()), None);
+                        break;
+                    }
+                    if attempt >= options.retries {
+                        update_status(status, Some(err.to_string()), None);
+                        break;
+                    }
+                    let delay = retry_delay(attempt);
+                    attempt += 1;
+                    let retry = RetryState {
+                        attempt,
+                        max_attempts: options.retries,
+                        next_at: Instant::now() + delay,
+                    };
+                    update_status(status, Some(err.to_string()), Some(retry));
+                    sleep(delay).await;
                 }
             }
-            // set must_scrape to false to avoid scraping again until the next tick
-            must_scrape = false;
-            // after scraping, sleep for the remaining time of the tick
-            let sleep_time = tick_rate
-                .checked_sub(last_tick.elapsed())
-                .unwrap_or_default();
-            sleep(sleep_time).await;
         }
-
-        // if time has elapsed since last tick, allow to scrape the endpoint again and update history
-        if last_tick.elapsed() >= tick_rate {
-            must_scrape = true;
-            // reset last tick
-            last_tick = Instant::now();
+        update_rate_limit(status, rate_limited_until);
+        let now = Instant::now();
+        let skipped;
+        (deadline, skipped) = schedule_next(options.overlap_policy, deadline, now, tick_rate);
+        let not_before = rate_limited_until.unwrap_or(now);
+        deadline = deadline.max(not_before);
+        if skipped > 0 {
+            log::warn!(
+                "Scrape took {:?}, skipping {} scrape(s)",
+                now - started,
+                skipped
+            );
+        }
+        update_timing(status, now - started, skipped);
+        // after scraping, sleep until the next scheduled scrape, which moves
+        // when the interval is changed meanwhile
+        loop {
+            tokio::select! {
+                _ = sleep(deadline.saturating_duration_since(Instant::now())) => break,
+                Ok(()) = scrape_interval.changed() => {
+                    tick_rate = *scrape_interval.borrow_and_update();
+                    deadline = (started + tick_rate).max(not_before);
+                }
+                _ = scrape_now.notified() => {
+                    // the following scrapes are scheduled from this one
+                    deadline = Instant::now();
+                    break;
+                }
+            }
         }
         //TODO ad signal to stop the loop when the app quit.
     }
 }
 
-fn update_history_with_new_scrape(history: &MetricHistoryArc, splitted_metrics: Vec<Vec<String>>) {
-    let mut history_guard = history
-        .write()
-        .expect("to acquire write lock of metrics history");
+async fn query_prometheus(
+    client: &Client,
+    options: &QueryOptions,
+    pipeline: &Pipeline,
+    mut scrape_interval: watch::Receiver<Duration>,
+    scrape_now: &Notify,
+    history: &MetricHistoryArc,
+    status: &ScrapeStatusArc,
+) {
+    let mut previous_end = None;
+    loop {
+        let step = scrape_interval.borrow_and_update().as_secs().max(1);
+        let now = get_timestamp_unix_epoch();
+        let start = query::next_start(previous_end, now, options.range, step);
+        if start <= now {
+            let end = start + (now - start) / step * step;
+            if is_paused(status) {
+                // the points of the pause are skipped, not caught up on
+                previous_end = Some(end);
+            } else {
+                match options.query_range(client, start, end, step).await {
+                    Ok(metrics) => {
+                        update_history_with_metrics(history, pipeline, metrics);
+                        update_status(status, None, None);
+                        previous_end = Some(end);
+                    }
+                    Err(err) => {
+                        log::error!("Not able to query {}: {:#}", options.server, err);
+                        update_status(status, Some(format!("{:#}", err)), None);
+                    }
+                }
+            }
+        }
+        tokio::select! {
+            _ = sleep(Duration::from_secs(step)) => {}
+            Ok(()) = scrape_interval.changed() => {}
+            _ = scrape_now.notified() => {}
+        }
+    }
+}
+
+/// Replace `targets` with the ones currently `found` in DNS or Kubernetes,
+/// keeping the known ones if the lookup failed
+fn discover_targets(source: &str, found: anyhow::Result<Vec<Target>>, targets: &mut Vec<Target>) {
+    match found {
+        Ok(resolved) if resolved != *targets => {
+            log::info!("Found {} targets for {}", resolved.len(), source);
+            *targets = resolved;
+        }
+        Ok(_) => {}
+        Err(err) => log::error!(
+            "Looking up {} failed, keeping {} targets: {:#}",
+            source,
+            targets.len(),
+            err
+        ),
+    }
+}
+
+/// Scrape all targets at once, merging their metrics, and update their
+/// `statuses`. Targets rate limiting are skipped until the delay they asked
+/// for passed. Fails only if every target fails, otherwise the errors of the
+/// failed ones are returned along the metrics of the others.
+async fn scrape_targets(
+    client: &Client,
+    options: &ScrapeOptions,
+    statuses: &mut Vec<TargetStatus>,
+) -> anyhow::Result<(Vec<SingleScrapeMetric>, Option<String>)> {
+    // the targets may have changed since the previous scrape, e.g. by DNS SD
+    let mut previous = std::mem::take(statuses);
+    *statuses = options
+        .targets
+        .iter()
+        .map(
+            |target| match previous.iter().position(|status| status.target == *target) {
+                Some(index) => previous.swap_remove(index),
+                None => TargetStatus::new(target.clone()),
+            },
+        )
+        .collect();
     let timestamp = get_timestamp_unix_epoch();
-    for part in splitted_metrics {
-        let single_scrape_metric = decode_single_scrape_metric(part, timestamp);
-        let metric_to_update_option = history_guard.metrics.get_mut(&single_scrape_metric.name);
-        match metric_to_update_option {
-            Some(metric_to_update) => {
-                log::debug!("updating metric: {}", metric_to_update.details.name);
-                metric_to_update.update_time_series(single_scrape_metric.value_per_labels);
-            }
-            None => {
-                let metric = single_scrape_metric.into_metric();
-                log::debug!(
-                    "add metric '{}' for the first time to the history.",
-                    metric.details.name
+    let now = Instant::now();
+    let mut tasks = JoinSet::new();
+    let mut errors = vec![];
+    for (index, status) in statuses.iter().enumerate() {
+        if let Some(until) = status.rate_limited_until.filter(|until| *until > now) {
+            let err = anyhow::anyhow!("rate limiting, next scrape in {}s", (until - now).as_secs());
+            errors.push((index, status.name().to_string(), err));
+            continue;
+        }
+        let target = status.target.clone();
+        let client = client.clone();
+        let auth_command = options.auth_command.clone();
+        tasks.spawn(async move {
+            let result =
+                get_splitted_metrics_from_endpoint(&client, &target.url, auth_command.as_ref())
+                    .await;
+            (index, target, result)
+        });
+    }
+    let mut scrapes = vec![];
+    while let Some(joined) = tasks.join_next().await {
+        let (index, target, result) = joined?;
+        let status = &mut statuses[index];
+        match result {
+            Ok(splitted_metrics) => {
+                status.error = None;
+                status.rate_limited_until = None;
+                let metrics = decode_scrape(splitted_metrics, timestamp);
+                scrapes.push((index, target.instance, metrics));
+            }
+            Err(err) => {
+                let name = target.instance.unwrap_or(target.url);
+                log::error!("Not able to scrape {}: {}", name, err);
+                status.error = Some(err.to_string());
+                status.rate_limited_until = retry_after(&err).map(|delay| Instant::now() + delay);
+                errors.push((index, name, err));
+            }
+        }
+    }
+    errors.sort_by_key(|(index, _, _)| *index);
+    if scrapes.is_empty() {
+        return match errors.into_iter().next() {
+            Some((_, _, err)) if options.targets.len() == 1 => Err(err),
+            Some((_, name, err)) => {
+                let message = format!(
+                    "all {} targets failed, {}: {}",
+                    options.targets.len(),
+                    name,
+                    err
                 );
-                history_guard
-                    .metrics
-                    .insert(metric.details.name.clone(), metric);
+                Err(err.context(message))
             }
+            None => Err(anyhow::anyhow!("no targets to scrape")),
+        };
+    }
+    let failed = errors.first().map(|(_, name, err)| {
+        format!(
+            "{} of {} targets failed, {}: {}",
+            errors.len(),
+            options.targets.len(),
+            name,
+            err
+        )
+    });
+    scrapes.sort_by_key(|(index, _, _)| *index);
+    let scrapes = scrapes
+        .into_iter()
+        .map(|(_, instance, metrics)| (instance, metrics))
+        .collect();
+    let metrics = targets::combine(scrapes, options.conflict_policy);
+    Ok((metrics, failed))
+}
+
+/// When the next scrape is due, given the `deadline` of the next tick and
+/// the time `now` the previous scrape finished, and how many ticks are
+/// skipped to get there. Scrapes never overlap, as the loop only continues
+/// once a scrape is done.
+fn schedule_next(
+    policy: OverlapPolicy,
+    deadline: Instant,
+    now: Instant,
+    tick_rate: Duration,
+) -> (Instant, u64) {
+    if now <= deadline {
+        return (deadline, 0);
+    }
+    match policy {
+        OverlapPolicy::Queue => (now, 0),
+        OverlapPolicy::Skip => {
+            let overrun = (now - deadline).as_nanos();
+            let skipped = (overrun / tick_rate.as_nanos().max(1)) as u64 + 1;
+            (deadline + tick_rate * skipped as u32, skipped)
         }
     }
 }
 
-fn update_error_status(error_msg: &Arc<RwLock<Option<String>>>, error_message: Option<String>) {
-    let mut has_error_guard = error_msg
+/// When the next scrape is due if every target is rate limiting: as soon as
+/// the first of them may be scraped again
+fn rate_limited(statuses: &[TargetStatus]) -> Option<Instant> {
+    statuses
+        .iter()
+        .map(|status| status.rate_limited_until)
+        .collect::<Option<Vec<_>>>()?
+        .into_iter()
+        .min()
+}
+
+/// Delay a rate limited endpoint asked for before the next scrape, if `err`
+/// is a rate limit response with a Retry-After header
+fn retry_after(err: &anyhow::Error) -> Option<Duration> {
+    err.downcast_ref::<RateLimited>()?
+        .retry_after
+        .map(|delay| delay.min(MAX_RETRY_AFTER))
+}
+
+/// Exponential backoff before retry number `attempt + 1`: 1s, 2s, 4s, ...
+fn retry_delay(attempt: u32) -> Duration {
+    Duration::from_secs(1u64 << attempt.min(16)).min(MAX_RETRY_DELAY)
+}
+
+async fn receive_otlp_metrics(
+    listener: TcpListener,
+    pipeline: Pipeline,
+    history: MetricHistoryArc,
+    status: ScrapeStatusArc,
+) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, peer)) => {
+                log::debug!("OTLP connection from {}", peer);
+                let pipeline = pipeline.clone();
+                let history = Arc::clone(&history);
+                let status = Arc::clone(&status);
+                task::spawn(async move {
+                    if let Err(err) =
+                        handle_otlp_connection(stream, &pipeline, &history, &status).await
+                    {
+                        log::error!("OTLP connection from {} failed: {}", peer, err);
+                        update_status(&status, Some(err.to_string()), None);
+                    }
+                });
+            }
+            Err(err) => {
+                log::error!("Failed to accept OTLP connection: {}", err);
+                update_status(&status, Some(err.to_string()), None);
+            }
+        }
+    }
+}
+
+async fn handle_otlp_connection(
+    stream: TcpStream,
+    pipeline: &Pipeline,
+    history: &MetricHistoryArc,
+    status: &ScrapeStatusArc,
+) -> anyhow::Result<()> {
+    let mut reader = BufReader::new(stream);
+    loop {
+        let Some(request) = http_server::read_request(&mut reader).await? else {
+            return Ok(());
+        };
+        if request.path != "/v1/metrics" {
+            http_server::write_response(reader.get_mut(), "404 Not Found", "{}").await?;
+            continue;
+        }
+        if !request.content_type.starts_with("application/json") {
+            let msg = format!(
+                "unsupported OTLP content type '{}', use the http/json protocol",
+                request.content_type
+            );
+            log::error!("{}", msg);
+            update_status(status, Some(msg), None);
+            http_server::write_response(reader.get_mut(), "415 Unsupported Media Type", "{}")
+                .await?;
+            continue;
+        }
+        match otlp::decode_export_request(&request.body, get_timestamp_unix_epoch()) {
+            // accept pushes while paused, but don't store them
+            Ok(_) if is_paused(status) => {
+                http_server::write_response(reader.get_mut(), "200 OK", "{}").await?;
+            }
+            Ok(metrics) => {
+                update_history_with_metrics(history, pipeline, metrics);
+                update_status(status, None, None);
+                http_server::write_response(reader.get_mut(), "200 OK", "{}").await?;
+            }
+            Err(err) => {
+                log::error!("Failed to decode OTLP request: {:#}", err);
+                update_status(status, Some(format!("{:#}", err)), None);
+                http_server::write_response(reader.get_mut(), "400 Bad Request", "{}").await?;
+            }
+        }
+    }
+}
+
+fn update_history_with_metrics(
+    history: &MetricHistoryArc,
+    pipeline: &Pipeline,
+    metrics: Vec<SingleScrapeMetric>,
+) {
+    let mut history_guard = history
+        .write()
+        .expect("to acquire write lock of metrics history");
+    for metric in pipeline.process(metrics) {
+        history_guard.insert_scrape(metric);
+    }
+}
+
+/// Process a scrape and buffer it until the next `deliver_batch`, or store
+/// the buffered scrapes right away once `MAX_BATCH` are waiting, e.g. while
+/// the tab of the scraper isn't shown
+fn buffer_scrape(
+    history: &MetricHistoryArc,
+    batch: &BatchArc,
+    pipeline: &Pipeline,
+    metrics: Vec<SingleScrapeMetric>,
+) {
+    let metrics = pipeline.process(metrics);
+    let full = {
+        let mut batch_guard = batch.lock().expect("to acquire lock of scrape batch");
+        batch_guard.push(metrics);
+        batch_guard.len() >= MAX_BATCH
+    };
+    if full {
+        store_batch(history, batch);
+    }
+}
+
+fn store_batch(history: &MetricHistoryArc, batch: &BatchArc) -> usize {
+    let scrapes = std::mem::take(&mut *batch.lock().expect("to acquire lock of scrape batch"));
+    if scrapes.is_empty() {
+        return 0;
+    }
+    let count = scrapes.len();
+    let mut history_guard = history
+        .write()
+        .expect("to acquire write lock of metrics history");
+    for metric in scrapes.into_iter().flatten() {
+        history_guard.insert_scrape(metric);
+    }
+    count
+}
+
+fn update_history_with_new_scrape(
+    history: &MetricHistoryArc,
+    pipeline: &Pipeline,
+    splitted_metrics: Vec<Vec<String>>,
+) {
+    let timestamp = get_timestamp_unix_epoch();
+    let metrics = decode_scrape(splitted_metrics, timestamp);
+    update_history_with_metrics(history, pipeline, metrics);
+}
+
+fn update_status(status: &ScrapeStatusArc, error_msg: Option<String>, retry: Option<RetryState>) {
+    let mut status_guard = status
+        .write()
+        .expect("to acquire write lock of scrape status");
+    if error_msg.is_some() {
+        status_guard.failure_count += 1;
+    } else {
+        status_guard.scrape_count += 1;
+    }
+    status_guard.error_msg = error_msg;
+    status_guard.retry = retry;
+}
+
+fn spill_oldest(history: &MetricHistoryArc, status: &ScrapeStatusArc, spill_file: &SpillFile) {
+    let mut history_guard = history
+        .write()
+        .expect("to acquire write lock of metrics history");
+    let spilled = history_guard.take_oldest();
+    let count: usize = spilled.iter().map(|series| series.samples.len()).sum();
+    if count == 0 {
+        return;
+    }
+    if let Err(err) = spill_file.append(&spilled) {
+        // keep the samples rather than losing them
+        log::error!("Failed to spill samples: {:#}", err);
+        history_guard.restore(spilled);
+        return;
+    }
+    drop(history_guard);
+    log::warn!(
+        "Memory limit reached, spilled {} samples to {}",
+        count,
+        spill_file.path.display()
+    );
+    let mut status_guard = status
+        .write()
+        .expect("to acquire write lock of scrape status");
+    status_guard.spilled_samples += count;
+    status_guard.spill_path = Some(spill_file.path.clone());
+}
+
+fn update_rate_limit(status: &ScrapeStatusArc, until: Option<Instant>) {
+    let mut status_guard = status
         .write()
-        .expect("to acquire write lock of has_error");
-    *has_error_guard = error_message;
+        .expect("to acquire write lock of scrape status");
+    status_guard.rate_limited_until = until;
+}
+
+fn update_targets(status: &ScrapeStatusArc, targets: &[TargetStatus]) {
+    let mut status_guard = status
+        .write()
+        .expect("to acquire write lock of scrape status");
+    status_guard.targets = targets.to_vec();
+}
+
+fn is_paused(status: &ScrapeStatusArc) -> bool {
+    status
+        .read()
+        .expect("to acquire read lock of scrape status")
+        .paused
+}
+
+fn update_timing(status: &ScrapeStatusArc, duration: Duration, skipped: u64) {
+    let mut status_guard = status
+        .write()
+        .expect("to acquire write lock of scrape status");
+    status_guard.last_duration = Some(duration);
+    status_guard.skipped_count += skipped;
 }
 
 fn get_timestamp_unix_epoch() -> u64 {
@@ -131,22 +1042,165 @@ fn get_timestamp_unix_epoch() -> u64 {
 }
 
 // TODO handle error when scraping endpoint is down and make app surviving connection issues.
-async fn get_splitted_metrics_from_endpoint(url: &str) -> anyhow::Result<Vec<Vec<String>>> {
-    let resp = reqwest::get(url).await?.text().await?;
-    let lines = resp.split('\n').map(String::from).collect::<Vec<String>>();
-    Ok(split_metric_lines(lines))
+async fn get_splitted_metrics_from_endpoint(
+    client: &Client,
+    url: &str,
+    auth_command: Option<&AuthCommand>,
+) -> anyhow::Result<Vec<Vec<String>>> {
+    let text = fetch_metrics_text(client, url, auth_command).await?;
+    Ok(split_text(&text))
+}
+
+/// Fetch the metrics text of an endpoint, or of a file given as file:// url
+pub async fn fetch_metrics_text(
+    client: &Client,
+    url: &str,
+    auth_command: Option<&AuthCommand>,
+) -> anyhow::Result<String> {
+    let (status, content_type, resp) = match url.strip_prefix("file://") {
+        Some(path) => {
+            let text = tokio::fs::read_to_string(path)
+                .await
+                .map_err(|err| anyhow::anyhow!("failed to read {}: {}", path, err))?;
+            (None, None, text)
+        }
+        None => {
+            let mut request = client.get(url);
+            if let Some(auth_command) = auth_command {
+                let mut value = HeaderValue::from_str(&auth_command.authorization().await?)
+                    .context("invalid characters in the auth command output")?;
+                value.set_sensitive(true);
+                request = request.header(AUTHORIZATION, value);
+            }
+            let resp = request.send().await?;
+            let status = resp.status();
+            if let (Some(auth_command), StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) =
+                (auth_command, status)
+            {
+                // expired credentials, run the helper again on the next attempt
+                auth_command.invalidate();
+                anyhow::bail!("endpoint rejected the credentials ({})", status);
+            }
+            if matches!(
+                status,
+                StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE
+            ) {
+                let retry_after = resp
+                    .headers()
+                    .get(RETRY_AFTER)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| parse_retry_after(value, chrono::Utc::now()));
+                return Err(RateLimited {
+                    status,
+                    retry_after,
+                }
+                .into());
+            }
+            let content_type = resp
+                .headers()
+                .get(reqwest::header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .map(String::from);
+            (Some(status), content_type, resp.text().await?)
+        }
+    };
+    check_metrics_response(status, content_type.as_deref(), &resp)?;
+    Ok(resp)
+}
+
+/// A 429 or 503 response, asking to scrape less often
+#[derive(Debug)]
+pub struct RateLimited {
+    pub status: StatusCode,
+    /// Delay asked for by the Retry-After header, if any
+    pub retry_after: Option<Duration>,
+}
+
+impl std::fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "endpoint is rate limiting ({})", self.status)?;
+        if let Some(retry_after) = self.retry_after {
+            write!(f, ", retry after {}s", retry_after.as_secs())?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for RateLimited {}
+
+/// Delay of a Retry-After header, either in seconds or as an HTTP date
+/// relative to `now`
+fn parse_retry_after(value: &str, now: chrono::DateTime<chrono::Utc>) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (date.with_timezone(&chrono::Utc) - now)
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+/// Reject responses that are obviously not Prometheus metrics, like a login
+/// or error page, instead of feeding them to the parser
+fn check_metrics_response(
+    status: Option<StatusCode>,
+    content_type: Option<&str>,
+    body: &str,
+) -> anyhow::Result<()> {
+    let content_type = content_type.unwrap_or_default().to_lowercase();
+    let got = match status {
+        Some(status) if !status.is_success() => status.to_string(),
+        _ => {
+            let is_markup = ["html", "xml", "json"]
+                .iter()
+                .any(|kind| content_type.contains(kind));
+            let looks_like_markup = body.trim_start().starts_with('<');
+            if !is_markup && !looks_like_markup {
+                return Ok(());
+            }
+            match content_type.as_str() {
+                "" => "markup".to_string(),
+                content_type => content_type.to_string(),
+            }
+        }
+    };
+    let snippet: String = body
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .take(SNIPPET_LEN)
+        .collect();
+    anyhow::bail!(
+        "endpoint did not return Prometheus metrics (got {}): {}",
+        got,
+        snippet
+    )
+}
+
+fn split_text(text: &str) -> Vec<Vec<String>> {
+    let lines = text.split('\n').map(String::from).collect::<Vec<String>>();
+    split_metric_lines(lines)
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::prom::{parser::split_metric_lines, test_data::generate_metric_lines};
+    use crate::prom::{
+        parser::split_metric_lines,
+        test_data::{gauge, generate_metric_lines, memory_store, scrape_options},
+        MetricFilter,
+    };
+    use regex::Regex;
 
     use super::*;
 
     #[test]
     fn test_update_history_with_new_scrape() {
         // initialize data structure
-        let metric_history = MetricHistoryArc::new(RwLock::new(MetricHistory::new()));
+        let metric_history = MetricHistoryArc::new(RwLock::new(memory_store()));
         // simulate first scrape
         let lines = split_metric_lines(generate_metric_lines());
         let history = Arc::clone(&metric_history);
@@ -158,24 +1212,512 @@ mod tests {
         update_and_assert(history, lines, 2);
     }
 
+    #[tokio::test]
+    async fn test_scrape_gzip_encoded_response() {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder
+            .write_all(generate_metric_lines().join("\n").as_bytes())
+            .unwrap();
+        let body = encoder.finish().unwrap();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/metrics", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = vec![0; 4096];
+            let len = socket.read(&mut request).await.unwrap();
+            let request = String::from_utf8_lossy(&request[..len]).to_lowercase();
+            assert!(request.contains("accept-encoding: gzip"));
+            let header = format!(
+                "HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\nContent-Length: {}\r\n\r\n",
+                body.len()
+            );
+            socket.write_all(header.as_bytes()).await.unwrap();
+            socket.write_all(&body).await.unwrap();
+        });
+
+        let client = crate::prom::HttpClientConfig::default().build().unwrap();
+        let metrics = get_splitted_metrics_from_endpoint(&client, &url, None)
+            .await
+            .unwrap();
+        server.await.unwrap();
+        assert_eq!(metrics.len(), 6);
+    }
+
+    #[tokio::test]
+    async fn test_scrape_local_file() {
+        let path = std::env::temp_dir().join(format!("promviz-test-{}.txt", std::process::id()));
+        std::fs::write(&path, generate_metric_lines().join("\n")).unwrap();
+        let url = format!("file://{}", path.display());
+
+        let client = crate::prom::HttpClientConfig::default().build().unwrap();
+        let metrics = get_splitted_metrics_from_endpoint(&client, &url, None).await;
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(metrics.unwrap().len(), 6);
+
+        assert!(get_splitted_metrics_from_endpoint(&client, &url, None)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_change_scrape_interval() {
+        let path =
+            std::env::temp_dir().join(format!("promviz-interval-{}.txt", std::process::id()));
+        std::fs::write(&path, generate_metric_lines().join("\n")).unwrap();
+        let url = format!("file://{}", path.display());
+        let options = ScrapeOptions {
+            targets: Target::expand(&url, &[]).unwrap(),
+            url,
+            scrape_interval: 3600,
+            fast_interval: None,
+            retries: 0,
+            overlap_policy: OverlapPolicy::Skip,
+            pipeline: Pipeline::default(),
+            auth_command: None,
+            dns_sd: None,
+            k8s: None,
+            conflict_policy: ConflictPolicy::Label,
+            grpc_health: None,
+        };
+        let client = crate::prom::HttpClientConfig::default().build().unwrap();
+        let scraper = MetricScraper::new(options, client, memory_store());
+        let scrape_count = || scraper.get_status_read_guard().unwrap().scrape_count;
+
+        sleep(Duration::from_millis(200)).await;
+        assert_eq!(scrape_count(), 1);
+        assert!(scraper.set_scrape_interval(1));
+        sleep(Duration::from_millis(1500)).await;
+        std::fs::remove_file(&path).unwrap();
+        assert!(scrape_count() >= 2);
+    }
+
+    #[tokio::test]
+    async fn test_retarget() {
+        let dir = std::env::temp_dir().join(format!("promviz-retarget-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("node1.txt"), "# TYPE up gauge\nup 1\n").unwrap();
+        std::fs::write(dir.join("node2.txt"), "# TYPE load gauge\nload 2\n").unwrap();
+        let url = |host: &str| format!("file://{}/{}.txt", dir.display(), host);
+        let client = crate::prom::HttpClientConfig::default().build().unwrap();
+        let mut scraper = MetricScraper::new(scrape_options(&url("node1")), client, memory_store());
+        assert!(scraper.set_scrape_interval(60));
+        sleep(Duration::from_millis(200)).await;
+        assert_eq!(
+            scraper.get_history_lock().unwrap().get_metrics_headers(),
+            vec!["up"]
+        );
+
+        assert!(scraper.retarget(&url("node2")).unwrap());
+        sleep(Duration::from_millis(200)).await;
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(scraper.url(), Some(url("node2").as_str()));
+        assert_eq!(scraper.scrape_interval(), Some(Duration::from_secs(60)));
+        assert_eq!(
+            scraper.get_history_lock().unwrap().get_metrics_headers(),
+            vec!["load"]
+        );
+
+        let mut piped =
+            MetricScraper::with_metrics_text("up 1\n", Pipeline::default(), memory_store());
+        assert!(!piped.retarget(&url("node2")).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_fast_interval_batches() {
+        let path = std::env::temp_dir().join(format!("promviz-batch-{}.txt", std::process::id()));
+        std::fs::write(&path, "# TYPE up gauge\nup 1\n").unwrap();
+        let url = format!("file://{}", path.display());
+        let options = ScrapeOptions {
+            targets: Target::expand(&url, &[]).unwrap(),
+            url,
+            scrape_interval: 3600,
+            fast_interval: Some(Duration::from_millis(20)),
+            retries: 0,
+            overlap_policy: OverlapPolicy::Skip,
+            pipeline: Pipeline::default(),
+            auth_command: None,
+            dns_sd: None,
+            k8s: None,
+            conflict_policy: ConflictPolicy::Label,
+            grpc_health: None,
+        };
+        let client = crate::prom::HttpClientConfig::default().build().unwrap();
+        let scraper = MetricScraper::new(options, client, memory_store());
+        assert_eq!(scraper.scrape_interval(), Some(Duration::from_millis(20)));
+
+        sleep(Duration::from_millis(200)).await;
+        std::fs::remove_file(&path).unwrap();
+        // buffered until delivered
+        assert!(scraper.get_history_lock().unwrap().is_empty());
+        let delivered = scraper.deliver_batch();
+        assert!(delivered >= 2, "{}", delivered);
+        let history = scraper.get_history_lock().unwrap();
+        let metric = history.get_metric("up").unwrap();
+        let samples = metric.time_series.values().next().unwrap().samples.len();
+        assert!(samples >= delivered);
+        drop(history);
+
+        assert!(scraper.set_scrape_interval(1));
+        assert_eq!(scraper.scrape_interval(), Some(Duration::from_secs(1)));
+    }
+
+    #[tokio::test]
+    async fn test_scrape_targets() {
+        let dir = std::env::temp_dir().join(format!("promviz-targets-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("node1.txt"), "# TYPE up gauge\nup 1\n").unwrap();
+        std::fs::write(dir.join("node2.txt"), "# TYPE up gauge\nup 0\n").unwrap();
+        let url = format!("file://{}/{{host}}.txt", dir.display());
+        let hosts = ["node1", "node2", "node3"].map(String::from);
+        let options = ScrapeOptions {
+            targets: Target::expand(&url, &hosts).unwrap(),
+            url,
+            scrape_interval: 1,
+            fast_interval: None,
+            retries: 0,
+            overlap_policy: OverlapPolicy::Skip,
+            pipeline: Pipeline::default(),
+            auth_command: None,
+            dns_sd: None,
+            k8s: None,
+            conflict_policy: ConflictPolicy::Label,
+            grpc_health: None,
+        };
+        let client = crate::prom::HttpClientConfig::default().build().unwrap();
+        let mut statuses = vec![];
+        let (metrics, failed) = scrape_targets(&client, &options, &mut statuses)
+            .await
+            .unwrap();
+        assert_eq!(metrics.len(), 1);
+        assert_eq!(metrics[0].value_per_labels.len(), 2);
+        assert!(metrics[0]
+            .value_per_labels
+            .contains_key("instance=\"node2\""));
+        assert!(failed
+            .unwrap()
+            .starts_with("1 of 3 targets failed, node3: "));
+        assert_eq!(statuses.len(), 3);
+        assert_eq!(statuses[2].name(), "node3");
+        assert!(statuses[0].error.is_none());
+        assert!(statuses[2].error.is_some());
+
+        // a rate limiting target is skipped until its delay passed
+        statuses[1].rate_limited_until = Some(Instant::now() + Duration::from_secs(60));
+        let (metrics, failed) = scrape_targets(&client, &options, &mut statuses)
+            .await
+            .unwrap();
+        assert_eq!(metrics[0].value_per_labels.len(), 1);
+        assert!(failed
+            .unwrap()
+            .starts_with("2 of 3 targets failed, node2: rate limiting, next scrape in "));
+        assert!(statuses[1].rate_limited_until.is_some());
+        assert_eq!(rate_limited(&statuses), None);
+        statuses[1].rate_limited_until = None;
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        let Err(err) = scrape_targets(&client, &options, &mut statuses).await else {
+            panic!("expected all targets to fail");
+        };
+        assert!(err.to_string().starts_with("all 3 targets failed, node1: "));
+    }
+
+    #[test]
+    fn test_with_metrics_text() {
+        let text = generate_metric_lines().join("\n");
+        let scraper = MetricScraper::with_metrics_text(&text, Pipeline::default(), memory_store());
+        let history = scraper.get_history_lock().unwrap();
+        assert_eq!(history.get_metrics_headers().len(), 6);
+        assert_eq!(scraper.get_status_read_guard().unwrap().scrape_count, 1);
+    }
+
+    #[test]
+    fn test_restore_spilled_per_tab() {
+        let scrapers: Vec<MetricScraper> = ["queue_a", "queue_b"]
+            .into_iter()
+            .enumerate()
+            .map(|(tab, name)| {
+                let mut store = memory_store();
+                for timestamp in 1..=200 {
+                    store.insert_scrape(gauge(name, &[("", 1.0)], timestamp));
+                }
+                let scraper = MetricScraper::with_metrics_text("", Pipeline::default(), store);
+                let spill_file = SpillFile::new(100 + tab);
+                spill_oldest(&scraper.metrics_history, &scraper.status, &spill_file);
+                scraper
+            })
+            .collect();
+        let samples = |scraper: &MetricScraper, name: &str| {
+            scraper
+                .get_history_lock()
+                .unwrap()
+                .get_metric(name)
+                .and_then(|metric| Some(metric.time_series.values().next()?.samples.len()))
+        };
+        assert_eq!(samples(&scrapers[0], "queue_a"), Some(100));
+
+        // each tab gets back its own samples only
+        assert_eq!(scrapers[0].restore_spilled().unwrap(), 100);
+        assert_eq!(samples(&scrapers[0], "queue_a"), Some(200));
+        assert_eq!(samples(&scrapers[0], "queue_b"), None);
+        assert!(scrapers[1]
+            .get_status_read_guard()
+            .unwrap()
+            .spill_path
+            .is_some());
+        assert_eq!(scrapers[1].restore_spilled().unwrap(), 100);
+        assert_eq!(samples(&scrapers[1], "queue_b"), Some(200));
+        assert_eq!(scrapers[1].restore_spilled().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_filter_before_storing() {
+        let text = generate_metric_lines().join("\n");
+        let filter = MetricFilter::new(
+            vec![Regex::new("^response_time").unwrap()],
+            vec![Regex::new("no_labels").unwrap()],
+        );
+        let scraper = MetricScraper::with_metrics_text(&text, filter.into(), memory_store());
+        let history = scraper.get_history_lock().unwrap();
+        assert_eq!(history.get_metrics_headers(), vec!["response_time"]);
+    }
+
+    #[test]
+    fn test_check_metrics_response() {
+        let metrics = generate_metric_lines().join("\n");
+        let ok = Some(StatusCode::OK);
+        assert!(check_metrics_response(None, None, &metrics).is_ok());
+        assert!(check_metrics_response(ok, Some("text/plain; version=0.0.4"), &metrics).is_ok());
+
+        let page = "<!DOCTYPE html>\n<html>\n  <title>Login</title>";
+        let err = check_metrics_response(ok, Some("text/html; charset=utf-8"), page).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "endpoint did not return Prometheus metrics (got text/html; charset=utf-8): \
+             <!DOCTYPE html> <html> <title>Login</title>"
+        );
+        assert!(check_metrics_response(ok, Some("text/plain"), page).is_err());
+        assert!(check_metrics_response(ok, Some("application/json"), "{}").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_scrape_plain_text_not_found() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/metrics", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = vec![0; 4096];
+            let len = socket.read(&mut request).await.unwrap();
+            assert!(String::from_utf8_lossy(&request[..len]).starts_with("GET /metrics"));
+            let body = "404 page not found\n";
+            let response = format!(
+                "HTTP/1.1 404 Not Found\r\nContent-Type: text/plain; charset=utf-8\r\n\
+                 Content-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+        });
+
+        let client = crate::prom::HttpClientConfig::default().build().unwrap();
+        let err = get_splitted_metrics_from_endpoint(&client, &url, None)
+            .await
+            .unwrap_err();
+        server.await.unwrap();
+        assert_eq!(
+            err.to_string(),
+            "endpoint did not return Prometheus metrics (got 404 Not Found): 404 page not found"
+        );
+        // the same text from stdin or a file is skipped by the parser
+        assert!(decode_scrape(split_text("404 page not found\n"), 1).is_empty());
+    }
+
+    #[test]
+    fn test_schedule_next() {
+        let tick = Duration::from_secs(10);
+        let deadline = Instant::now();
+        let early = deadline - Duration::from_secs(3);
+        let late = deadline + Duration::from_secs(25);
+        assert_eq!(
+            schedule_next(OverlapPolicy::Skip, deadline, early, tick),
+            (deadline, 0)
+        );
+        assert_eq!(
+            schedule_next(OverlapPolicy::Skip, deadline, late, tick),
+            (deadline + Duration::from_secs(30), 3)
+        );
+        assert_eq!(
+            schedule_next(OverlapPolicy::Queue, deadline, late, tick),
+            (late, 0)
+        );
+    }
+
+    #[test]
+    fn test_discover_targets() {
+        let target = |host: &str| Target {
+            url: format!("http://{}/metrics", host),
+            instance: Some(host.to_string()),
+        };
+        let mut targets = vec![target("web-0")];
+        discover_targets(
+            "Kubernetes",
+            Ok(vec![target("web-0"), target("web-1")]),
+            &mut targets,
+        );
+        assert_eq!(targets, vec![target("web-0"), target("web-1")]);
+        // a failed lookup keeps the known targets
+        discover_targets("Kubernetes", Err(anyhow::anyhow!("timeout")), &mut targets);
+        assert_eq!(targets.len(), 2);
+    }
+
+    #[test]
+    fn test_rate_limited() {
+        let now = Instant::now();
+        let status = |host: &str, delay: Option<u64>| TargetStatus {
+            rate_limited_until: delay.map(|delay| now + Duration::from_secs(delay)),
+            ..TargetStatus::new(Target {
+                url: format!("http://{}/metrics", host),
+                instance: Some(host.to_string()),
+            })
+        };
+        let mut statuses = vec![status("node1", Some(30)), status("node2", None)];
+        assert_eq!(rate_limited(&statuses), None);
+        statuses[1] = status("node2", Some(10));
+        assert_eq!(rate_limited(&statuses), Some(now + Duration::from_secs(10)));
+        assert_eq!(rate_limited(&[]), None);
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        assert_eq!(retry_delay(0), Duration::from_secs(1));
+        assert_eq!(retry_delay(2), Duration::from_secs(4));
+        assert_eq!(retry_delay(10), MAX_RETRY_DELAY);
+        assert_eq!(retry_delay(u32::MAX), MAX_RETRY_DELAY);
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        let now = chrono::DateTime::parse_from_rfc3339("2015-10-21T07:28:00Z")
+            .unwrap()
+            .with_timezone(&chrono::Utc);
+        assert_eq!(
+            parse_retry_after("120", now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:30:00 GMT", now),
+            Some(Duration::from_secs(120))
+        );
+        // a date in the past is no delay
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:00:00 GMT", now),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+
+    #[tokio::test]
+    async fn test_scrape_rate_limited() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/metrics", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = vec![0; 4096];
+            let len = socket.read(&mut request).await.unwrap();
+            assert!(len > 0);
+            let response =
+                "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 7200\r\nContent-Length: 0\r\n\r\n";
+            socket.write_all(response.as_bytes()).await.unwrap();
+        });
+
+        let client = crate::prom::HttpClientConfig::default().build().unwrap();
+        let err = get_splitted_metrics_from_endpoint(&client, &url, None)
+            .await
+            .unwrap_err();
+        server.await.unwrap();
+        assert_eq!(
+            err.to_string(),
+            "endpoint is rate limiting (429 Too Many Requests), retry after 7200s"
+        );
+        assert_eq!(retry_after(&err), Some(MAX_RETRY_AFTER));
+        let err = err.context("all 2 targets failed");
+        assert_eq!(retry_after(&err), Some(MAX_RETRY_AFTER));
+    }
+
+    #[tokio::test]
+    async fn test_receive_otlp_metrics() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/v1/metrics", listener.local_addr().unwrap());
+        let scraper =
+            MetricScraper::with_otlp_receiver(listener, Pipeline::default(), memory_store());
+
+        let client = reqwest::Client::new();
+        let body = r#"{"resourceMetrics": [{"scopeMetrics": [{"metrics": [
+            {"name": "queue_size", "gauge": {"dataPoints": [{"asDouble": 3}]}}]}]}]}"#;
+        let paused_body = body.replace("queue_size", "paused_size");
+        assert!(scraper.toggle_pause().unwrap());
+        let response = client
+            .post(&url)
+            .header("Content-Type", "application/json")
+            .body(paused_body)
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status().is_success());
+        assert!(!scraper.toggle_pause().unwrap());
+
+        let response = client
+            .post(&url)
+            .header("Content-Type", "application/json")
+            .body(body)
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status().is_success());
+
+        let response = client
+            .post(&url)
+            .header("Content-Type", "application/x-protobuf")
+            .body(vec![0u8; 4])
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(
+            response.status(),
+            reqwest::StatusCode::UNSUPPORTED_MEDIA_TYPE
+        );
+
+        let history = scraper.get_history_lock().unwrap();
+        assert_eq!(history.get_metrics_headers(), vec!["queue_size"]);
+    }
+
     fn update_and_assert(
         history: MetricHistoryArc,
         lines: Vec<Vec<String>>,
         expected_length: usize,
     ) {
         // update history
-        update_history_with_new_scrape(&history, lines);
+        update_history_with_new_scrape(&history, &Pipeline::default(), lines);
 
         // assert results
         let history_read_guard = history
             .read()
             .map_err(|err| anyhow::anyhow!("failed to aquire lock of metric history: {}", err));
+        let history_read_guard = history_read_guard.expect("to access the history");
         history_read_guard
-            .expect("to access the history")
-            .metrics
+            .get_metrics_headers()
             .iter()
+            .filter_map(|name| history_read_guard.get_metric(name))
             .for_each(|m| {
-                m.1.time_series.values().for_each(|time_series| {
+                m.time_series.values().for_each(|time_series| {
                     assert_eq!(time_series.samples.len(), expected_length);
                 });
             });
@@ -1,13 +1,86 @@
 mod model;
 pub use self::model::HistogramValueSample;
+pub use self::model::MetadataMismatch;
 pub use self::model::Metric;
+pub use self::model::MetricDetails;
 pub use self::model::MetricType;
+pub use self::model::Retention;
 pub use self::model::Sample;
 pub use self::model::SingleValueSample;
 pub use self::model::SummaryValueSample;
+pub use self::model::TimeSeries;
+pub use self::model::Trend;
 pub(crate) mod parser;
 
+mod http_client;
+pub use self::http_client::HttpClientConfig;
+
+mod auth_command;
+pub use self::auth_command::AuthCommand;
+
+pub mod export;
+
+pub mod expr;
+
+pub mod grpc_health;
+
+pub mod k8s;
+
+mod spill;
+
+mod store;
+pub use self::store::HistoryStore;
+pub use self::store::StorageCapacity;
+pub use self::store::StorageKind;
+
+mod otlp;
+
+pub mod http_server;
+
+mod import;
+
+mod query;
+pub use self::query::QueryOptions;
+
+pub mod lint;
+
+mod filter;
+pub use self::filter::MetricFilter;
+
+pub mod pipeline;
+pub use self::pipeline::Pipeline;
+
+mod remote_write;
+pub use self::remote_write::RemoteWrite;
+
+mod sink;
+pub use self::sink::SampleSink;
+pub use self::sink::SinkFormat;
+
+mod push;
+pub use self::push::grouping_url;
+pub use self::push::Push;
+
+mod self_metrics;
+pub use self::self_metrics::SelfMetrics;
+
+mod dns_sd;
+pub use self::dns_sd::DnsSd;
+pub use self::dns_sd::RecordType;
+
+mod targets;
+pub use self::targets::read_hosts;
+pub use self::targets::ConflictPolicy;
+pub use self::targets::Target;
+
 mod metric_scraper;
+pub use self::metric_scraper::fetch_metrics_text;
+pub use self::metric_scraper::MetricHistoryArc;
 pub use self::metric_scraper::MetricScraper;
+pub use self::metric_scraper::OverlapPolicy;
+pub use self::metric_scraper::ScrapeOptions;
+pub use self::metric_scraper::ScrapeStatusArc;
+pub use self::metric_scraper::TargetStatus;
 
-mod test_data;
+#[cfg(test)]
+pub(crate) mod test_data;
@@ -1,29 +1,47 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::time::Duration;
 
-use chrono::{DateTime, Local};
-use ratatui::widgets::{Bar, BarGroup};
-
-use crate::interactive::format_value;
-
-use super::parser::extract_labels_key_and_map;
+use super::parser::{decode_labels, extract_labels_key_and_map};
+use chrono::{DateTime, Local, TimeZone};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 #[derive(Debug)]
 pub struct MetricHistory {
     pub metrics: HashMap<String, Metric>,
+    pub retention: Retention,
+}
+
+/// Age limits of the samples and series kept by every storage. The number
+/// of samples is bounded by the ring buffers of `BoundedMetricHistory`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Retention {
+    /// Maximum age relative to the newest sample of the series
+    pub max_age: Option<Duration>,
+    /// Remove series missing from this many scrapes of their metric in a row
+    pub expire_after: Option<u32>,
+}
+
+impl Retention {
+    fn trim(&self, samples: &mut VecDeque<Sample>) {
+        if let (Some(max_age), Some(newest)) = (self.max_age, samples.back()) {
+            let oldest_kept = newest.timestamp().saturating_sub(max_age.as_secs());
+            let expired = samples
+                .iter()
+                .take_while(|sample| sample.timestamp() < oldest_kept)
+                .count();
+            samples.drain(..expired);
+        }
+    }
 }
 
 impl MetricHistory {
-    pub fn new() -> Self {
+    pub fn with_retention(retention: Retention) -> Self {
         Self {
             metrics: HashMap::new(),
+            retention,
         }
     }
 
-    #[allow(dead_code)]
-    pub fn is_empty(&self) -> bool {
-        self.metrics.len() == 0
-    }
-
     pub fn get_metrics_headers(&self) -> Vec<String> {
         let mut headers: Vec<String> = self.metrics.keys().cloned().collect();
         headers.sort();
@@ -35,7 +53,8 @@ impl MetricHistory {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum MetricType {
     Gauge,
     Counter,
@@ -43,11 +62,21 @@ pub enum MetricType {
     Summary,
 }
 
+/// A series of the exposition format: its sorted labels, including
+/// `__name__`, and its value at unix milliseconds
+pub type Point = (BTreeMap<String, String>, f64, i64);
+
+#[derive(Debug)]
 pub struct SingleScrapeMetric {
     pub name: String,
     pub docstring: String,
     pub metric_type: MetricType,
+    /// Unit of an OpenMetrics `# UNIT` line
+    pub unit: Option<String>,
     pub value_per_labels: HashMap<String, Sample>,
+    /// Bytes of the family in the text exposition format, including its
+    /// comments, 0 for metrics received in other formats
+    pub payload_bytes: usize,
 }
 
 impl SingleScrapeMetric {
@@ -57,8 +86,12 @@ impl SingleScrapeMetric {
                 name: self.name,
                 docstring: self.docstring,
                 metric_type: self.metric_type,
+                unit: self.unit,
+                mismatch: None,
+                payload_bytes: self.payload_bytes,
             },
             time_series: HashMap::new(),
+            trend: Trend::Unchanged,
         };
         self.value_per_labels
             .into_iter()
@@ -67,35 +100,229 @@ impl SingleScrapeMetric {
             });
         metric
     }
+
+    /// The series a scrape is exposed as, histograms and summaries as their
+    /// `_bucket` series or quantiles, `_sum` and `_count`, ordered by their
+    /// labels key
+    pub fn points(&self) -> Vec<Point> {
+        let mut points = vec![];
+        let mut series: Vec<(&String, &Sample)> = self.value_per_labels.iter().collect();
+        series.sort_by_key(|(key, _)| *key);
+        for (key, sample) in series {
+            // series without labels carry a placeholder key
+            let labels: BTreeMap<String, String> = if key.contains('=') {
+                decode_labels(key).into_iter().collect()
+            } else {
+                BTreeMap::new()
+            };
+            let mut push =
+                |suffix: &str, label: Option<(&str, &str)>, value: f64, timestamp: u64| {
+                    let mut labels = labels.clone();
+                    labels.insert("__name__".to_string(), format!("{}{}", self.name, suffix));
+                    if let Some((label, label_value)) = label {
+                        labels.insert(label.to_string(), label_value.to_string());
+                    }
+                    points.push((labels, value, timestamp as i64 * 1000));
+                };
+            match sample {
+                Sample::GaugeSample(sample) | Sample::CounterSample(sample) => {
+                    push("", None, sample.value, sample.timestamp)
+                }
+                Sample::HistogramSample(sample) => {
+                    for bucket in &sample.bucket_values {
+                        push(
+                            "_bucket",
+                            Some(("le", &bucket.name)),
+                            bucket.value as f64,
+                            sample.timestamp,
+                        );
+                    }
+                    push("_sum", None, sample.sum, sample.timestamp);
+                    push("_count", None, sample.count as f64, sample.timestamp);
+                }
+                Sample::SummarySample(sample) => {
+                    let timestamp = sample.time.timestamp() as u64;
+                    for quantile in &sample.quantiles {
+                        push(
+                            "",
+                            Some(("quantile", &quantile.name)),
+                            quantile.value,
+                            timestamp,
+                        );
+                    }
+                    push("_sum", None, sample.sum, timestamp);
+                    push("_count", None, sample.count as f64, timestamp);
+                }
+            }
+        }
+        points
+    }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct Metric {
     pub details: MetricDetails,
     pub time_series: HashMap<String, TimeSeries>,
+    /// How the series changed in the latest scrape
+    #[serde(skip)]
+    pub trend: Trend,
 }
 
-#[derive(Clone, Debug)]
+/// Direction the values of a metric moved in between the last two scrapes.
+/// Gauges and counters compare their value, histograms and summaries their
+/// count.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Trend {
+    #[default]
+    Unchanged,
+    Increased,
+    Decreased,
+    /// Some series increased and others decreased, or became NaN
+    Mixed,
+}
+
+impl Trend {
+    fn between(previous: &Sample, latest: &Sample) -> Self {
+        let (previous, latest) = (previous.comparable(), latest.comparable());
+        match previous.partial_cmp(&latest) {
+            Some(std::cmp::Ordering::Less) => Trend::Increased,
+            Some(std::cmp::Ordering::Greater) => Trend::Decreased,
+            Some(std::cmp::Ordering::Equal) => Trend::Unchanged,
+            None if previous.is_nan() && latest.is_nan() => Trend::Unchanged,
+            None => Trend::Mixed,
+        }
+    }
+
+    fn combine(self, other: Self) -> Self {
+        match (self, other) {
+            (Trend::Unchanged, trend) | (trend, Trend::Unchanged) => trend,
+            (a, b) if a == b => a,
+            _ => Trend::Mixed,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
 pub struct MetricDetails {
     pub name: String,
-    #[allow(dead_code)]
     pub docstring: String,
     pub metric_type: MetricType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+    /// Last differing TYPE and HELP seen after the first scrape, e.g. while
+    /// instances of two versions are scraped during a rolling deploy
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mismatch: Option<MetadataMismatch>,
+    /// Bytes of the family in the latest scrape, see
+    /// `SingleScrapeMetric::payload_bytes`
+    #[serde(skip)]
+    pub payload_bytes: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct MetadataMismatch {
+    pub docstring: String,
+    pub metric_type: MetricType,
+    /// Scrapes with this version
+    pub count: u64,
+}
+
+impl MetricDetails {
+    /// Record the TYPE and HELP of a new scrape if they differ from the first one
+    pub fn check_metadata(&mut self, docstring: &str, metric_type: &MetricType) {
+        if self.docstring == docstring && self.metric_type == *metric_type {
+            return;
+        }
+        match &mut self.mismatch {
+            Some(mismatch)
+                if mismatch.docstring == docstring && mismatch.metric_type == *metric_type =>
+            {
+                mismatch.count += 1;
+            }
+            mismatch => {
+                log::warn!(
+                    "Metadata of {} changed from {:?} '{}' to {:?} '{}'",
+                    self.name,
+                    self.metric_type,
+                    self.docstring,
+                    metric_type,
+                    docstring
+                );
+                *mismatch = Some(MetadataMismatch {
+                    docstring: docstring.to_string(),
+                    metric_type: metric_type.clone(),
+                    count: 1,
+                });
+            }
+        }
+    }
 }
 
 impl Metric {
-    pub fn update_time_series(&mut self, value_per_labels: HashMap<String, Sample>) {
+    /// Append the samples of a new scrape, dropping the ones `retention` no
+    /// longer keeps
+    pub fn update_time_series(
+        &mut self,
+        value_per_labels: HashMap<String, Sample>,
+        retention: &Retention,
+    ) {
+        for time_series in self.time_series.values_mut() {
+            time_series.missed_scrapes += 1;
+        }
+        self.trend = Trend::Unchanged;
         value_per_labels.into_iter().for_each(|(key, value)| {
-            if self.time_series.contains_key(&key) {
-                self.time_series
-                    .get_mut(&key)
-                    .expect("should contain the value")
-                    .samples
-                    .push(value);
+            if let Some(time_series) = self.time_series.get_mut(&key) {
+                if let Some(previous) = time_series.samples.back() {
+                    self.trend = self.trend.combine(Trend::between(previous, &value));
+                }
+                time_series.samples.push_back(value);
+                time_series.missed_scrapes = 0;
+                retention.trim(&mut time_series.samples);
             } else {
                 add_time_series_into_metric(key, &mut self.time_series, value);
             }
-        })
+        });
+        if let Some(expire_after) = retention.expire_after {
+            self.time_series
+                .retain(|_, time_series| time_series.missed_scrapes < expire_after.max(1));
+        }
+    }
+
+    /// Labels of the series missing from the latest scrape of the metric,
+    /// e.g. of a restarted pod
+    pub fn stale_labels(&self) -> HashSet<String> {
+        self.time_series
+            .iter()
+            .filter(|(_, time_series)| time_series.missed_scrapes > 0)
+            .map(|(labels, _)| labels.clone())
+            .collect()
+    }
+
+    /// Largest latest value of the series, the count for histograms and
+    /// summaries
+    pub fn latest_value(&self) -> Option<f64> {
+        self.time_series
+            .values()
+            .filter_map(|time_series| time_series.samples.back())
+            .map(Sample::comparable)
+            .filter(|value| !value.is_nan())
+            .max_by(f64::total_cmp)
+    }
+
+    /// Fastest change per second of a series between its last two samples,
+    /// in either direction
+    pub fn rate_of_change(&self) -> Option<f64> {
+        self.time_series
+            .values()
+            .filter_map(|time_series| {
+                let mut samples = time_series.samples.iter().rev();
+                let (latest, previous) = (samples.next()?, samples.next()?);
+                let seconds = latest.timestamp().checked_sub(previous.timestamp())?;
+                let change = (latest.comparable() - previous.comparable()).abs();
+                (seconds > 0).then(|| change / seconds as f64)
+            })
+            .filter(|rate| !rate.is_nan())
+            .max_by(f64::total_cmp)
     }
 
     pub fn get_labels(&self) -> Vec<&String> {
@@ -105,14 +332,20 @@ impl Metric {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct TimeSeries {
     #[allow(dead_code)]
     pub labels: HashMap<String, String>,
-    pub samples: Vec<Sample>,
+    /// Oldest first. A ring buffer, so bounded stores drop the oldest sample
+    /// without moving the others.
+    pub samples: VecDeque<Sample>,
+    /// Scrapes of the metric in a row that didn't include the series
+    #[serde(skip)]
+    pub missed_scrapes: u32,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
+#[serde(untagged)]
 #[allow(clippy::enum_variant_names)]
 pub enum Sample {
     GaugeSample(SingleValueSample),
@@ -121,19 +354,58 @@ pub enum Sample {
     SummarySample(SummaryValueSample),
 }
 
-#[derive(Clone, Debug)]
+impl Sample {
+    /// Approximate bytes used by the sample, including its buckets or
+    /// quantiles
+    pub fn size(&self) -> usize {
+        let heap = match self {
+            Sample::GaugeSample(_) | Sample::CounterSample(_) => 0,
+            Sample::HistogramSample(sample) => sample
+                .bucket_values
+                .iter()
+                .map(|bucket| std::mem::size_of::<Bucket>() + bucket.name.capacity())
+                .sum(),
+            Sample::SummarySample(sample) => sample
+                .quantiles
+                .iter()
+                .map(|quantile| std::mem::size_of::<Quantil>() + quantile.name.capacity())
+                .sum(),
+        };
+        std::mem::size_of::<Sample>() + heap
+    }
+
+    /// The value of gauges and counters, the count of histograms and summaries
+    pub fn comparable(&self) -> f64 {
+        match self {
+            Sample::GaugeSample(sample) | Sample::CounterSample(sample) => sample.value,
+            Sample::HistogramSample(sample) => sample.count as f64,
+            Sample::SummarySample(sample) => sample.count as f64,
+        }
+    }
+
+    /// Unix seconds of the scrape
+    pub fn timestamp(&self) -> u64 {
+        match self {
+            Sample::GaugeSample(sample) | Sample::CounterSample(sample) => sample.timestamp,
+            Sample::HistogramSample(sample) => sample.timestamp,
+            Sample::SummarySample(sample) => sample.time.timestamp() as u64,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct SingleValueSample {
     pub timestamp: u64,
     pub value: f64,
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Bucket {
     pub name: String,
     pub value: u64,
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Quantil {
     pub name: String,
     pub value: f64,
@@ -145,7 +417,7 @@ impl Bucket {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct HistogramValueSample {
     pub timestamp: u64,
     pub bucket_values: Vec<Bucket>,
@@ -153,49 +425,34 @@ pub struct HistogramValueSample {
     pub count: u64,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct SummaryValueSample {
+    #[serde(
+        rename = "timestamp",
+        serialize_with = "serialize_unix_timestamp",
+        deserialize_with = "deserialize_unix_timestamp"
+    )]
     pub time: DateTime<Local>,
     pub quantiles: Vec<Quantil>,
     pub sum: f64,
     pub count: u64,
 }
 
-impl<'a> From<&SummaryValueSample> for BarGroup<'a> {
-    fn from(val: &SummaryValueSample) -> Self {
-        let mut min = f64::MAX;
-        let mut max = f64::MIN;
-        val.quantiles.iter().for_each(|data_point| {
-            if data_point.value > max {
-                max = data_point.value;
-            }
-            if data_point.value < min {
-                min = data_point.value;
-            }
-        });
-        //Low level is the bar height for the min
-        //value. For non-zero values we want a
-        //small bar to be displayed.
-        let low_level = if min != 0.0 { 5.0 } else { 0.0 };
-
-        //Scale so we have at least 100 steps
-        let scale_span = 100.0 - low_level;
+fn serialize_unix_timestamp<S: Serializer>(
+    time: &DateTime<Local>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_i64(time.timestamp())
+}
 
-        //3.25, 2.25
-        let bars: Vec<Bar> = val
-            .quantiles
-            .iter()
-            .map(|m| {
-                let percent = (m.value - min) / (max - min);
-                let new_val = (percent * scale_span) + low_level;
-                Bar::default()
-                    .value(new_val.round() as u64)
-                    .text_value(format_value(m.value))
-                    .label(m.name.clone().into())
-            })
-            .collect();
-        BarGroup::default().bars(&bars)
-    }
+fn deserialize_unix_timestamp<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<DateTime<Local>, D::Error> {
+    let timestamp = i64::deserialize(deserializer)?;
+    Local
+        .timestamp_opt(timestamp, 0)
+        .single()
+        .ok_or_else(|| serde::de::Error::custom(format!("invalid timestamp {}", timestamp)))
 }
 
 fn add_time_series_into_metric(
@@ -216,7 +473,8 @@ fn add_time_series_into_metric(
         key,
         TimeSeries {
             labels: labels_map,
-            samples: vec![sample],
+            samples: VecDeque::from([sample]),
+            missed_scrapes: 0,
         },
     );
 }
@@ -225,7 +483,7 @@ fn add_time_series_into_metric(
 mod tests {
     use crate::prom::{
         parser::{decode_single_scrape_metric, split_metric_lines},
-        test_data::generate_metric_lines,
+        test_data::{gauge, generate_metric_lines},
     };
 
     use super::*;
@@ -245,7 +503,8 @@ mod tests {
                     .duration_since(UNIX_EPOCH)
                     .unwrap()
                     .as_secs(),
-            );
+            )
+            .unwrap();
             let name_to_test = single_scrape_metric.name.clone();
             let labels_to_test = match single_scrape_metric.value_per_labels.keys().next() {
                 Some(key) => key.clone(),
@@ -253,7 +512,7 @@ mod tests {
             };
             let metric = single_scrape_metric.into_metric();
             assert_eq!(metric.details.name, name_to_test);
-            assert_eq!(metric.time_series.contains_key(&labels_to_test), true);
+            assert!(metric.time_series.contains_key(&labels_to_test));
             metrics.push(metric);
         }
         // simulate second scrape
@@ -265,14 +524,18 @@ mod tests {
                     .duration_since(UNIX_EPOCH)
                     .unwrap()
                     .as_secs(),
-            );
+            )
+            .unwrap();
             // update existing metrics
             let metric_to_update_option = metrics
                 .iter_mut()
                 .find(|m| m.details.name == single_scrape_metric.name);
             match metric_to_update_option {
                 Some(metric_to_update) => {
-                    metric_to_update.update_time_series(single_scrape_metric.value_per_labels);
+                    metric_to_update.update_time_series(
+                        single_scrape_metric.value_per_labels,
+                        &Retention::default(),
+                    );
                     metric_to_update
                         .time_series
                         .values()
@@ -286,4 +549,72 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_retention_drops_old_samples() {
+        let sample = |timestamp| {
+            Sample::GaugeSample(SingleValueSample {
+                timestamp,
+                value: 1.0,
+            })
+        };
+        let timestamps =
+            |samples: &VecDeque<Sample>| samples.iter().map(Sample::timestamp).collect::<Vec<_>>();
+        let mut samples: VecDeque<Sample> = (0..10).map(|i| sample(i * 10)).collect();
+        Retention::default().trim(&mut samples);
+        assert_eq!(samples.len(), 10);
+        Retention {
+            max_age: Some(Duration::from_secs(20)),
+            ..Default::default()
+        }
+        .trim(&mut samples);
+        assert_eq!(timestamps(&samples), vec![70, 80, 90]);
+    }
+
+    #[test]
+    fn test_stale_series_expire() {
+        let scrape = |shards: &[&str], timestamp| {
+            let labels: Vec<String> = shards
+                .iter()
+                .map(|shard| format!("shard=\"{}\"", shard))
+                .collect();
+            let series: Vec<(&str, f64)> =
+                labels.iter().map(|labels| (labels.as_str(), 1.0)).collect();
+            gauge("queue_size", &series, timestamp)
+        };
+        let retention = Retention {
+            expire_after: Some(2),
+            ..Default::default()
+        };
+        let mut metric = scrape(&["a", "b"], 10).into_metric();
+        metric.update_time_series(scrape(&["a"], 20).value_per_labels, &retention);
+        assert_eq!(
+            metric.stale_labels(),
+            HashSet::from(["shard=\"b\"".to_string()])
+        );
+        metric.update_time_series(scrape(&["a", "c"], 30).value_per_labels, &retention);
+        assert!(metric.stale_labels().is_empty());
+        assert_eq!(metric.get_labels(), vec!["shard=\"a\"", "shard=\"c\""]);
+    }
+
+    #[test]
+    fn test_latest_value_and_rate_of_change() {
+        let scrape = |values: [f64; 2], timestamp| {
+            gauge(
+                "queue_size",
+                &[("queue=\"a\"", values[0]), ("queue=\"b\"", values[1])],
+                timestamp,
+            )
+        };
+        let mut metric = scrape([5.0, f64::NAN], 10).into_metric();
+        assert_eq!(metric.latest_value(), Some(5.0));
+        assert_eq!(metric.rate_of_change(), None);
+
+        metric.update_time_series(
+            scrape([1.0, f64::NAN], 20).value_per_labels,
+            &Retention::default(),
+        );
+        assert_eq!(metric.latest_value(), Some(1.0));
+        assert_eq!(metric.rate_of_change(), Some(0.4));
+    }
 }
@@ -0,0 +1,433 @@
+//! Receiver for metrics pushed with the OpenTelemetry protocol (OTLP/HTTP
+//! using the JSON encoding), mapped into the same model as scraped metrics.
+use std::collections::HashMap;
+
+use anyhow::Context;
+use chrono::{Local, TimeZone};
+use serde::Deserialize;
+
+use super::model::{Bucket, MetricType, Quantil, SingleScrapeMetric, SummaryValueSample};
+use super::pipeline::labels_key;
+use super::{HistogramValueSample, Sample, SingleValueSample};
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct ExportMetricsServiceRequest {
+    resource_metrics: Vec<ResourceMetrics>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct ResourceMetrics {
+    resource: Resource,
+    scope_metrics: Vec<ScopeMetrics>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Resource {
+    attributes: Vec<KeyValue>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct ScopeMetrics {
+    metrics: Vec<OtlpMetric>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct OtlpMetric {
+    name: String,
+    description: String,
+    gauge: Option<DataPoints<NumberDataPoint>>,
+    sum: Option<Sum>,
+    histogram: Option<DataPoints<HistogramDataPoint>>,
+    summary: Option<DataPoints<SummaryDataPoint>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DataPoints<T> {
+    data_points: Vec<T>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Sum {
+    data_points: Vec<NumberDataPoint>,
+    #[serde(default)]
+    is_monotonic: bool,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct NumberDataPoint {
+    attributes: Vec<KeyValue>,
+    time_unix_nano: Option<Int64>,
+    as_double: Option<f64>,
+    as_int: Option<Int64>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct HistogramDataPoint {
+    attributes: Vec<KeyValue>,
+    time_unix_nano: Option<Int64>,
+    count: Option<Int64>,
+    sum: Option<f64>,
+    bucket_counts: Vec<Int64>,
+    explicit_bounds: Vec<f64>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct SummaryDataPoint {
+    attributes: Vec<KeyValue>,
+    time_unix_nano: Option<Int64>,
+    count: Option<Int64>,
+    sum: Option<f64>,
+    quantile_values: Vec<ValueAtQuantile>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct ValueAtQuantile {
+    quantile: f64,
+    value: f64,
+}
+
+#[derive(Deserialize)]
+struct KeyValue {
+    key: String,
+    value: AnyValue,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum AnyValue {
+    StringValue(String),
+    BoolValue(bool),
+    IntValue(Int64),
+    DoubleValue(f64),
+    #[serde(other)]
+    Unsupported,
+}
+
+/// 64 bit integers are strings in the protobuf JSON mapping, but some
+/// exporters send plain numbers.
+#[derive(Deserialize, Clone, Copy)]
+#[serde(untagged)]
+enum Int64 {
+    Number(u64),
+    #[serde(deserialize_with = "deserialize_int_string")]
+    String(u64),
+}
+
+fn deserialize_int_string<'de, D: serde::Deserializer<'de>>(d: D) -> Result<u64, D::Error> {
+    let value = String::deserialize(d)?;
+    value.parse().map_err(serde::de::Error::custom)
+}
+
+impl Int64 {
+    fn value(self) -> u64 {
+        match self {
+            Int64::Number(value) | Int64::String(value) => value,
+        }
+    }
+}
+
+impl AnyValue {
+    fn to_label_value(&self) -> Option<String> {
+        match self {
+            AnyValue::StringValue(value) => Some(value.clone()),
+            AnyValue::BoolValue(value) => Some(value.to_string()),
+            AnyValue::IntValue(value) => Some(value.value().to_string()),
+            AnyValue::DoubleValue(value) => Some(value.to_string()),
+            AnyValue::Unsupported => None,
+        }
+    }
+}
+
+/// Replace characters not allowed in Prometheus names, e.g. the dots of
+/// OTel semantic convention names.
+pub(super) fn sanitize_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == ':' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// The labels of a point, its attributes and the job, sorted by name
+fn point_labels(job: Option<&String>, attributes: &[KeyValue]) -> Vec<(String, String)> {
+    let mut labels: Vec<(String, String)> = attributes
+        .iter()
+        .filter_map(|kv| {
+            kv.value
+                .to_label_value()
+                .map(|value| (sanitize_name(&kv.key), value))
+        })
+        .collect();
+    if let Some(job) = job {
+        labels.push(("job".to_string(), job.clone()));
+    }
+    labels.sort();
+    labels
+}
+
+fn point_timestamp(time_unix_nano: Option<Int64>, received_at: u64) -> u64 {
+    match time_unix_nano.map(Int64::value) {
+        Some(nanos) if nanos > 0 => nanos / 1_000_000_000,
+        _ => received_at,
+    }
+}
+
+/// Decode an OTLP/JSON `ExportMetricsServiceRequest` into metrics.
+/// Points without a timestamp get `received_at` (unix seconds).
+pub fn decode_export_request(
+    body: &[u8],
+    received_at: u64,
+) -> anyhow::Result<Vec<SingleScrapeMetric>> {
+    let request: ExportMetricsServiceRequest =
+        serde_json::from_slice(body).context("invalid OTLP JSON payload")?;
+    let mut metrics: HashMap<String, SingleScrapeMetric> = HashMap::new();
+
+    for resource_metrics in request.resource_metrics {
+        let job = resource_metrics
+            .resource
+            .attributes
+            .iter()
+            .find(|kv| kv.key == "service.name")
+            .and_then(|kv| kv.value.to_label_value());
+        for metric in resource_metrics
+            .scope_metrics
+            .into_iter()
+            .flat_map(|scope| scope.metrics)
+        {
+            let name = sanitize_name(&metric.name);
+            let (metric_type, samples) = decode_metric(&metric, job.as_ref(), received_at);
+            let Some(metric_type) = metric_type else {
+                log::warn!("unsupported OTLP metric data for '{}'", metric.name);
+                continue;
+            };
+            let entry = metrics
+                .entry(name.clone())
+                .or_insert_with(|| SingleScrapeMetric {
+                    name,
+                    docstring: metric.description.clone(),
+                    metric_type,
+                    unit: None,
+                    value_per_labels: HashMap::new(),
+                    payload_bytes: 0,
+                });
+            entry.value_per_labels.extend(samples);
+        }
+    }
+    Ok(metrics.into_values().collect())
+}
+
+fn decode_metric(
+    metric: &OtlpMetric,
+    job: Option<&String>,
+    received_at: u64,
+) -> (Option<MetricType>, Vec<(String, Sample)>) {
+    let key = |attributes: &[KeyValue]| {
+        let labels = point_labels(job, attributes);
+        labels_key(
+            labels
+                .iter()
+                .map(|(key, value)| (key.as_str(), value.as_str())),
+        )
+    };
+    let number = |point: &NumberDataPoint| {
+        let value = point
+            .as_double
+            .or(point.as_int.map(|v| v.value() as f64))
+            .unwrap_or_default();
+        SingleValueSample {
+            timestamp: point_timestamp(point.time_unix_nano, received_at),
+            value,
+        }
+    };
+
+    if let Some(gauge) = &metric.gauge {
+        let samples = gauge
+            .data_points
+            .iter()
+            .map(|p| (key(&p.attributes), Sample::GaugeSample(number(p))))
+            .collect();
+        (Some(MetricType::Gauge), samples)
+    } else if let Some(sum) = &metric.sum {
+        let counter = sum.is_monotonic;
+        let samples = sum
+            .data_points
+            .iter()
+            .map(|p| {
+                let sample = if counter {
+                    Sample::CounterSample(number(p))
+                } else {
+                    Sample::GaugeSample(number(p))
+                };
+                (key(&p.attributes), sample)
+            })
+            .collect();
+        let metric_type = if counter {
+            MetricType::Counter
+        } else {
+            MetricType::Gauge
+        };
+        (Some(metric_type), samples)
+    } else if let Some(histogram) = &metric.histogram {
+        let samples = histogram
+            .data_points
+            .iter()
+            .map(|p| {
+                // OTLP bucket counts are per bucket, Prometheus buckets are cumulative
+                let mut cumulative = 0;
+                let mut bucket_values: Vec<Bucket> = p
+                    .bucket_counts
+                    .iter()
+                    .zip(p.explicit_bounds.iter())
+                    .map(|(count, bound)| {
+                        cumulative += count.value();
+                        Bucket::new(bound.to_string(), cumulative)
+                    })
+                    .collect();
+                let count = p.count.map(Int64::value).unwrap_or(cumulative);
+                bucket_values.push(Bucket::new("+Inf".to_string(), count));
+                let sample = HistogramValueSample {
+                    timestamp: point_timestamp(p.time_unix_nano, received_at),
+                    bucket_values,
+                    sum: p.sum.unwrap_or_default(),
+                    count,
+                };
+                (key(&p.attributes), Sample::HistogramSample(sample))
+            })
+            .collect();
+        (Some(MetricType::Histogram), samples)
+    } else if let Some(summary) = &metric.summary {
+        let samples = summary
+            .data_points
+            .iter()
+            .map(|p| {
+                let timestamp = point_timestamp(p.time_unix_nano, received_at);
+                let sample = SummaryValueSample {
+                    time: Local.timestamp_opt(timestamp as i64, 0).unwrap(),
+                    quantiles: p
+                        .quantile_values
+                        .iter()
+                        .map(|q| Quantil {
+                            name: q.quantile.to_string(),
+                            value: q.value,
+                        })
+                        .collect(),
+                    sum: p.sum.unwrap_or_default(),
+                    count: p.count.map(Int64::value).unwrap_or_default(),
+                };
+                (key(&p.attributes), Sample::SummarySample(sample))
+            })
+            .collect();
+        (Some(MetricType::Summary), samples)
+    } else {
+        (None, vec![])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXPORT_REQUEST: &str = r#"{
+      "resourceMetrics": [{
+        "resource": {"attributes": [{"key": "service.name", "value": {"stringValue": "checkout"}}]},
+        "scopeMetrics": [{
+          "scope": {"name": "test"},
+          "metrics": [
+            {"name": "queue.size", "description": "Queue size",
+             "gauge": {"dataPoints": [{"asInt": "7", "timeUnixNano": "1700000000000000000",
+               "attributes": [{"key": "queue", "value": {"stringValue": "orders"}}]}]}},
+            {"name": "http.requests", "sum": {"isMonotonic": true, "aggregationTemporality": 2,
+             "dataPoints": [{"asDouble": 12.5}]}},
+            {"name": "latency", "histogram": {"aggregationTemporality": 2, "dataPoints": [
+              {"count": "6", "sum": 1.5, "bucketCounts": ["1", "2", "3"], "explicitBounds": [0.1, 1]}]}},
+            {"name": "rpc_duration", "summary": {"dataPoints": [
+              {"count": 4, "sum": 2.0, "quantileValues": [{"quantile": 0.5, "value": 0.4}, {"quantile": 0.99, "value": 1.2}]}]}}
+          ]
+        }]
+      }]
+    }"#;
+
+    fn metric<'a>(metrics: &'a [SingleScrapeMetric], name: &str) -> &'a SingleScrapeMetric {
+        metrics
+            .iter()
+            .find(|m| m.name == name)
+            .expect("metric to be decoded")
+    }
+
+    #[test]
+    fn test_decode_export_request() {
+        let metrics = decode_export_request(EXPORT_REQUEST.as_bytes(), 42).unwrap();
+        assert_eq!(metrics.len(), 4);
+
+        let gauge = metric(&metrics, "queue_size");
+        assert_eq!(gauge.docstring, "Queue size");
+        match gauge
+            .value_per_labels
+            .get("job=\"checkout\",queue=\"orders\"")
+        {
+            Some(Sample::GaugeSample(sample)) => {
+                assert_eq!(sample.value, 7.0);
+                assert_eq!(sample.timestamp, 1700000000);
+            }
+            _ => panic!("expected gauge sample"),
+        }
+
+        let counter = metric(&metrics, "http_requests");
+        assert!(matches!(counter.metric_type, MetricType::Counter));
+        match counter.value_per_labels.get("job=\"checkout\"") {
+            Some(Sample::CounterSample(sample)) => {
+                assert_eq!(sample.value, 12.5);
+                assert_eq!(sample.timestamp, 42);
+            }
+            _ => panic!("expected counter sample"),
+        }
+
+        match metric(&metrics, "latency").value_per_labels.values().next() {
+            Some(Sample::HistogramSample(sample)) => {
+                assert_eq!(
+                    sample.bucket_values,
+                    vec![
+                        Bucket::new("0.1".to_string(), 1),
+                        Bucket::new("1".to_string(), 3),
+                        Bucket::new("+Inf".to_string(), 6),
+                    ]
+                );
+                assert_eq!(sample.count, 6);
+            }
+            _ => panic!("expected histogram sample"),
+        }
+
+        match metric(&metrics, "rpc_duration")
+            .value_per_labels
+            .values()
+            .next()
+        {
+            Some(Sample::SummarySample(sample)) => {
+                assert_eq!(sample.quantiles.len(), 2);
+                assert_eq!(sample.quantiles[1].name, "0.99");
+                assert_eq!(sample.count, 4);
+            }
+            _ => panic!("expected summary sample"),
+        }
+    }
+
+    #[test]
+    fn test_decode_invalid_request() {
+        assert!(decode_export_request(b"<html></html>", 0).is_err());
+    }
+}
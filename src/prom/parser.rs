@@ -7,15 +7,29 @@ use super::{HistogramValueSample, SingleValueSample};
 use log::error;
 use std::collections::HashMap;
 use std::slice::Iter;
+use std::sync::atomic::{AtomicU64, Ordering};
 
-pub fn decode_single_scrape_metric(lines: Vec<String>, timestamp: u64) -> SingleScrapeMetric {
-    let mut lines_iter = lines.iter();
+/// Values the parser failed to decode since the start, for
+/// `promviz_parse_errors_total`
+pub static PARSE_ERRORS: AtomicU64 = AtomicU64::new(0);
+
+/// Decode the lines of a metric family. Fails if the family has no TYPE,
+/// samples with values that don't parse are skipped.
+pub fn decode_single_scrape_metric(
+    lines: Vec<String>,
+    timestamp: u64,
+) -> anyhow::Result<SingleScrapeMetric> {
     let mut doc_name: Option<String> = None;
     let mut docstring: Option<String> = None;
     let mut type_name: Option<String> = None;
     let mut metric_type: Option<String> = None;
-    #[allow(clippy::while_let_on_iterator)]
-    while let Some(line) = lines_iter.next() {
+    let mut unit: Option<String> = None;
+    // HELP, TYPE and UNIT come in any order before the samples
+    let header_len = lines
+        .iter()
+        .take_while(|line| line.starts_with('#'))
+        .count();
+    for line in &lines[..header_len] {
         if line.starts_with("# HELP ") {
             if let Some((name, docstr)) = extract_name_docstring(line) {
                 doc_name = Some(name);
@@ -26,19 +40,28 @@ pub fn decode_single_scrape_metric(lines: Vec<String>, timestamp: u64) -> Single
                 type_name = Some(tmp_name);
                 metric_type = Some(tmp_type);
             }
-            break;
-        } else if !line.starts_with('#') {
-            panic!("Invalid metric data, TYPE must be present");
+        } else if let Some((_, tmp_unit)) = extract_unit(line) {
+            unit = Some(tmp_unit);
         }
     }
-    let name = doc_name.unwrap_or(type_name.expect("No name found for metric"));
-    let metric_type = metric_type.expect("TYPE not set for metric");
+    let (Some(metric_type), Some(type_name)) = (metric_type, type_name) else {
+        let first_line = lines.iter().find(|line| !line.is_empty());
+        anyhow::bail!(
+            "invalid metric data, TYPE must be present: {}",
+            first_line.map_or("", String::as_str)
+        );
+    };
+    let lines_iter = lines[header_len..].iter();
+    let name = doc_name.unwrap_or(type_name);
     let docstring = docstring.unwrap_or_default();
     let mut single_scrape_metric = SingleScrapeMetric {
         name,
         docstring,
         metric_type: MetricType::Gauge,
+        unit,
         value_per_labels: HashMap::new(),
+        // the newlines were split off
+        payload_bytes: lines.iter().map(|line| line.len() + 1).sum(),
     };
     match metric_type.as_str() {
         "gauge" => {
@@ -46,9 +69,11 @@ pub fn decode_single_scrape_metric(lines: Vec<String>, timestamp: u64) -> Single
                 if line.is_empty() {
                     continue;
                 }
+                let Some(value) = extract_value(line) else {
+                    continue;
+                };
                 let labels = extract_labels(line);
                 let (_, key) = extract_labels_key_and_map(labels);
-                let value = extract_value(line);
                 single_scrape_metric.value_per_labels.insert(
                     key,
                     Sample::GaugeSample(SingleValueSample { timestamp, value }),
@@ -56,14 +81,16 @@ pub fn decode_single_scrape_metric(lines: Vec<String>, timestamp: u64) -> Single
             }
         }
         "counter" => {
+            single_scrape_metric.metric_type = MetricType::Counter;
             for line in lines_iter {
                 if line.is_empty() {
                     continue;
                 }
+                let Some(value) = extract_value(line) else {
+                    continue;
+                };
                 let labels = extract_labels(line);
                 let (_, key) = extract_labels_key_and_map(labels);
-                let value = extract_value(line);
-                single_scrape_metric.metric_type = MetricType::Counter;
                 single_scrape_metric.value_per_labels.insert(
                     key,
                     Sample::CounterSample(SingleValueSample { timestamp, value }),
@@ -72,59 +99,76 @@ pub fn decode_single_scrape_metric(lines: Vec<String>, timestamp: u64) -> Single
         }
         // TODO handle also histogram with no additional labels
         "histogram" => {
+            single_scrape_metric.metric_type = MetricType::Histogram;
             let splitted_lines_for_histogram = further_split_metric_lines_for_histogram(lines_iter);
-            for group_lines in splitted_lines_for_histogram.iter() {
+            // every group ends with the _sum and _count lines
+            for group_lines in splitted_lines_for_histogram.iter().filter(|g| g.len() >= 2) {
                 let mut bucket_values = Vec::new();
                 // retrieve buckets values
                 for line in group_lines.iter().take(group_lines.len() - 2) {
                     let labels = extract_labels(line);
                     let (labels_map, _) = extract_labels_key_and_map(labels);
-                    let bucket_value = labels_map.get("le").unwrap();
-                    let value = extract_value(line);
+                    let Some(value) = extract_value(line) else {
+                        continue;
+                    };
+                    let Some(bucket_value) = labels_map.get("le") else {
+                        parse_error(line);
+                        continue;
+                    };
                     bucket_values.push(Bucket::new(bucket_value.clone(), value as u64));
                 }
-                // retrieve sum value
-                let sum = extract_value(&group_lines[group_lines.len() - 2]);
-                // retrieve count value and labels
-                let count_line = group_lines[group_lines.len() - 1].clone();
-                let labels = extract_labels(&count_line);
+                // retrieve sum and count value and labels
+                let count_line = &group_lines[group_lines.len() - 1];
+                let (Some(sum), Some(count)) = (
+                    extract_value(&group_lines[group_lines.len() - 2]),
+                    extract_value(count_line),
+                ) else {
+                    continue;
+                };
+                let labels = extract_labels(count_line);
                 let (_, key) = extract_labels_key_and_map(labels);
-                let count = extract_value(&count_line) as u64;
-                single_scrape_metric.metric_type = MetricType::Histogram;
                 single_scrape_metric.value_per_labels.insert(
                     key,
                     Sample::HistogramSample(HistogramValueSample {
                         timestamp,
                         bucket_values,
                         sum,
-                        count,
+                        count: count as u64,
                     }),
                 );
             }
         }
         "summary" => {
+            single_scrape_metric.metric_type = MetricType::Summary;
             let splitted_lines_for_histogram = further_split_metric_lines_for_histogram(lines_iter);
-            for group_lines in splitted_lines_for_histogram.iter() {
+            for group_lines in splitted_lines_for_histogram.iter().filter(|g| g.len() >= 2) {
                 let mut quantiles = Vec::new();
                 // retrieve buckets values
                 for line in group_lines.iter().take(group_lines.len() - 2) {
                     let labels = extract_labels(line);
                     let (labels_map, _) = extract_labels_key_and_map(labels);
-                    let bucket_value = labels_map.get("quantile").unwrap();
-                    let value = extract_value(line);
+                    let Some(value) = extract_value(line) else {
+                        continue;
+                    };
+                    let Some(bucket_value) = labels_map.get("quantile") else {
+                        parse_error(line);
+                        continue;
+                    };
                     quantiles.push(Quantil {
                         name: bucket_value.clone(),
                         value,
                     });
                 }
-                // retrieve sum value
-                let sum = extract_value(&group_lines[group_lines.len() - 2]);
-                // retrieve count value and labels
-                let count_line = group_lines[group_lines.len() - 1].clone();
-                let labels = extract_labels(&count_line);
+                // retrieve sum and count value and labels
+                let count_line = &group_lines[group_lines.len() - 1];
+                let (Some(sum), Some(count)) = (
+                    extract_value(&group_lines[group_lines.len() - 2]),
+                    extract_value(count_line),
+                ) else {
+                    continue;
+                };
+                let labels = extract_labels(count_line);
                 let (_, key) = extract_labels_key_and_map(labels);
-                let count = extract_value(&count_line) as u64;
-                single_scrape_metric.metric_type = MetricType::Summary;
                 let time = Local.timestamp_opt(timestamp as i64, 0).unwrap();
                 single_scrape_metric.value_per_labels.insert(
                     key,
@@ -132,16 +176,40 @@ pub fn decode_single_scrape_metric(lines: Vec<String>, timestamp: u64) -> Single
                         time,
                         quantiles,
                         sum,
-                        count,
+                        count: count as u64,
                     }),
                 );
             }
         }
         _ => {
             error!("invalid metric type: {}", metric_type);
+            PARSE_ERRORS.fetch_add(1, Ordering::Relaxed);
         }
     }
-    single_scrape_metric
+    Ok(single_scrape_metric)
+}
+
+/// Decode the metric families of a scrape, skipping the ones that fail
+pub fn decode_scrape(
+    splitted_metrics: Vec<Vec<String>>,
+    timestamp: u64,
+) -> Vec<SingleScrapeMetric> {
+    splitted_metrics
+        .into_iter()
+        .filter_map(|part| match decode_single_scrape_metric(part, timestamp) {
+            Ok(metric) => Some(metric),
+            Err(err) => {
+                error!("skipping metric family: {}", err);
+                PARSE_ERRORS.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        })
+        .collect()
+}
+
+fn parse_error(line: &str) {
+    error!("failed to parse this line: {:?}", line);
+    PARSE_ERRORS.fetch_add(1, Ordering::Relaxed);
 }
 
 pub fn extract_labels_key_and_map(labels: Option<String>) -> (HashMap<String, String>, String) {
@@ -190,7 +258,7 @@ pub fn further_split_metric_lines_for_histogram(lines_iter: Iter<'_, String>) ->
     metrics
 }
 
-fn extract_name_docstring(line: &str) -> Option<(String, String)> {
+pub(super) fn extract_name_docstring(line: &str) -> Option<(String, String)> {
     if let Some(name_desc) = line.strip_prefix("# HELP ") {
         name_desc
             .split_once(' ')
@@ -200,7 +268,7 @@ fn extract_name_docstring(line: &str) -> Option<(String, String)> {
     }
 }
 
-fn extract_type(line: &str) -> Option<(String, String)> {
+pub(super) fn extract_type(line: &str) -> Option<(String, String)> {
     if let Some(line) = line.strip_prefix("# TYPE ") {
         line.rsplit_once(' ')
             .map(|(name, metric_type)| (name.to_string(), metric_type.trim().to_string()))
@@ -209,6 +277,12 @@ fn extract_type(line: &str) -> Option<(String, String)> {
     }
 }
 
+pub(super) fn extract_unit(line: &str) -> Option<(String, String)> {
+    let (name, unit) = line.strip_prefix("# UNIT ")?.split_once(' ')?;
+    let unit = unit.trim();
+    (!unit.is_empty()).then(|| (name.to_string(), unit.to_string()))
+}
+
 pub fn extract_labels(line: &str) -> Option<String> {
     match line.find('{') {
         Some(firs_index) => match line.find('}') {
@@ -246,6 +320,7 @@ pub fn decode_labels(labels: &str) -> HashMap<String, String> {
         let split: Vec<&str> = label.split('=').collect();
         if split.len() != 2 {
             error!("failed to split this value: {:?}", split);
+            PARSE_ERRORS.fetch_add(1, Ordering::Relaxed);
             continue;
         }
 
@@ -270,15 +345,20 @@ pub fn decode_labels_with_rgx(labels_to_split: &str) -> HashMap<String, String>
     labels
 }
 
-fn extract_value(line: &str) -> f64 {
-    line.split_whitespace()
+/// The value of a sample line, counting a parse error if it has none
+fn extract_value(line: &str) -> Option<f64> {
+    let value = line
+        .split_whitespace()
         .last()
-        .unwrap()
-        .parse::<f64>()
-        .unwrap()
+        .and_then(|value| value.parse::<f64>().ok());
+    if value.is_none() {
+        parse_error(line);
+    }
+    value
 }
 
 #[cfg(test)]
+#[allow(clippy::vec_init_then_push)]
 mod tests {
     use crate::prom::test_data::generate_metric_lines;
 
@@ -312,6 +392,22 @@ mod tests {
         assert_eq!(metric_type, "histogram");
     }
 
+    #[test]
+    fn test_decode_unit() {
+        let lines = vec![
+            String::from("# TYPE request_duration_seconds gauge"),
+            String::from("# UNIT request_duration_seconds seconds"),
+            String::from("# HELP request_duration_seconds Duration of the last request"),
+            String::from("request_duration_seconds 0.35"),
+        ];
+        let metric = decode_single_scrape_metric(lines, 1).unwrap();
+        assert_eq!(metric.name, "request_duration_seconds");
+        assert_eq!(metric.docstring, "Duration of the last request");
+        assert_eq!(metric.unit.as_deref(), Some("seconds"));
+        assert_eq!(metric.value_per_labels.len(), 1);
+        assert_eq!(extract_unit("# UNIT up "), None);
+    }
+
     #[test]
     fn test_split_metric_lines() {
         let lines = generate_metric_lines();
@@ -347,7 +443,7 @@ mod tests {
         lines.push(String::from("metric_2{shard=\"0\",label1=\"test1\"} 5"));
         lines.push(String::from("incoming_requests 10"));
         let line = &lines[0];
-        let labels = extract_labels(&line);
+        let labels = extract_labels(line);
         match labels {
             Some(labels) => {
                 assert_eq!(labels, "shard=\"0\"");
@@ -355,7 +451,7 @@ mod tests {
             None => panic!("Failed to extract labels"),
         }
         let line = &lines[1];
-        let labels = extract_labels(&line);
+        let labels = extract_labels(line);
         match labels {
             Some(labels) => {
                 assert_eq!(labels, "shard=\"0\",label1=\"test1\"");
@@ -363,12 +459,9 @@ mod tests {
             None => panic!("Failed to extract labels"),
         }
         let line = &lines[2];
-        let labels = extract_labels(&line);
-        match labels {
-            Some(_) => {
-                panic!("Should have not extracted any label");
-            }
-            None => (),
+        let labels = extract_labels(line);
+        if labels.is_some() {
+            panic!("Should have not extracted any label");
         }
     }
 
@@ -387,7 +480,8 @@ mod tests {
                 .duration_since(UNIX_EPOCH)
                 .unwrap()
                 .as_secs(),
-        );
+        )
+        .unwrap();
         assert_eq!(metric.name, "metric_1");
     }
 
@@ -406,7 +500,8 @@ mod tests {
                 .duration_since(UNIX_EPOCH)
                 .unwrap()
                 .as_secs(),
-        );
+        )
+        .unwrap();
         assert_eq!(metric.name, "metric_1");
     }
     #[test]
@@ -455,7 +550,8 @@ mod tests {
                 .duration_since(UNIX_EPOCH)
                 .unwrap()
                 .as_secs(),
-        );
+        )
+        .unwrap();
         assert_eq!(metric.name, "response_time");
         let metric_hist_1 = metric.value_per_labels.get("env=\"production\"").unwrap();
         let expected_1 = Vec::from([
@@ -508,7 +604,8 @@ mod tests {
                 .duration_since(UNIX_EPOCH)
                 .unwrap()
                 .as_secs(),
-        );
+        )
+        .unwrap();
         assert_eq!(metric.name, "response_time");
         let metric_hist_1 = metric
             .value_per_labels
@@ -529,4 +626,24 @@ mod tests {
             _ => panic!("Failed to decode histogram"),
         }
     }
+
+    #[test]
+    fn test_decode_summary_without_quantiles() {
+        let lines = vec![
+            String::from("# HELP rpc_duration_seconds RPC latency"),
+            String::from("# TYPE rpc_duration_seconds summary"),
+            String::from("rpc_duration_seconds_sum{method=\"get\"} 12.5"),
+            String::from("rpc_duration_seconds_count{method=\"get\"} 50"),
+            String::from("rpc_duration_seconds_count{method=\"put\"} 3"),
+        ];
+        let metric = decode_single_scrape_metric(lines, 1700000000).unwrap();
+        assert_eq!(metric.value_per_labels.len(), 1);
+        match metric.value_per_labels.get("method=\"get\"") {
+            Some(Sample::SummarySample(summary)) => {
+                assert!(summary.quantiles.is_empty());
+                assert_eq!((summary.sum, summary.count), (12.5, 50));
+            }
+            _ => panic!("Failed to decode summary"),
+        }
+    }
 }
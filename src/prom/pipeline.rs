@@ -0,0 +1,327 @@
+//! Processing of every parsed scrape before it is stored. A pipeline is a
+//! list of stages, each taking the metric families of a scrape and returning
+//! the ones to pass on, so families can be dropped, renamed or derived.
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::bail;
+use regex::Regex;
+
+use super::expr::{self, Expr, Matcher};
+use super::filter::MetricFilter;
+use super::model::{MetricType, Sample, SingleScrapeMetric, SingleValueSample};
+use super::parser::decode_labels;
+
+/// Label key of a series without labels, see `extract_labels_key_and_map`
+pub(crate) const NO_LABELS: &str = "single-value-with-no-labels";
+
+/// Label key of a series with the sorted `labels`, in the same
+/// `key="value",...` form as the exposition format
+pub(crate) fn labels_key<'a>(labels: impl IntoIterator<Item = (&'a str, &'a str)>) -> String {
+    let key = labels
+        .into_iter()
+        .map(|(key, value)| format!("{}=\"{}\"", key, value.replace('"', "\\\"")))
+        .collect::<Vec<String>>()
+        .join(",");
+    if key.is_empty() {
+        NO_LABELS.to_string()
+    } else {
+        key
+    }
+}
+
+/// A step of the pipeline. Implement it to add custom processing, and add
+/// the stage to the pipeline built in `main`.
+pub trait Stage: Debug + Send + Sync {
+    fn process(&self, metrics: Vec<SingleScrapeMetric>) -> Vec<SingleScrapeMetric>;
+
+    /// Whether the work the stage does in the background for the processed
+    /// scrapes is done, waited for before the headless mode exits
+    fn flushed(&self) -> bool {
+        true
+    }
+}
+
+/// Stages applied in order to every scrape
+#[derive(Clone, Debug, Default)]
+pub struct Pipeline {
+    stages: Vec<Arc<dyn Stage>>,
+}
+
+impl Pipeline {
+    pub fn with_stage(mut self, stage: impl Stage + 'static) -> Self {
+        self.stages.push(Arc::new(stage));
+        self
+    }
+
+    pub fn process(&self, metrics: Vec<SingleScrapeMetric>) -> Vec<SingleScrapeMetric> {
+        self.stages
+            .iter()
+            .fold(metrics, |metrics, stage| stage.process(metrics))
+    }
+
+    pub fn flushed(&self) -> bool {
+        self.stages.iter().all(|stage| stage.flushed())
+    }
+}
+
+impl From<MetricFilter> for Pipeline {
+    fn from(filter: MetricFilter) -> Self {
+        Pipeline::default().with_stage(filter)
+    }
+}
+
+impl Stage for MetricFilter {
+    fn process(&self, metrics: Vec<SingleScrapeMetric>) -> Vec<SingleScrapeMetric> {
+        metrics
+            .into_iter()
+            .filter(|metric| self.matches(&metric.name))
+            .collect()
+    }
+}
+
+/// Series selector like `http_requests_total{method="GET"}`, in the syntax of
+/// the expressions
+#[derive(Clone, Debug)]
+pub struct Selector {
+    pub metric: String,
+    pub matchers: Vec<Matcher>,
+}
+
+impl FromStr for Selector {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match expr::parse(s)? {
+            Expr::Selector { metric, matchers } => Ok(Self { metric, matchers }),
+            _ => bail!(
+                "expected a selector like name{{label=\"value\"}}, got \"{}\"",
+                s
+            ),
+        }
+    }
+}
+
+impl Selector {
+    /// Whether a series of the selected metric with the label key `key` matches
+    fn matches(&self, key: &str) -> bool {
+        let labels = if key.contains('=') {
+            decode_labels(key)
+        } else {
+            HashMap::new()
+        };
+        self.matchers.iter().all(|matcher| {
+            let value = labels.get(&matcher.label).map_or("", String::as_str);
+            (value == matcher.value) != matcher.negated
+        })
+    }
+}
+
+/// Keep only the series matching one of the selectors, dropping the metric
+/// families none of them selects
+#[derive(Debug)]
+pub struct Select {
+    pub selectors: Vec<Selector>,
+}
+
+impl Stage for Select {
+    fn process(&self, metrics: Vec<SingleScrapeMetric>) -> Vec<SingleScrapeMetric> {
+        metrics
+            .into_iter()
+            .filter_map(|mut metric| {
+                let selectors: Vec<&Selector> = self
+                    .selectors
+                    .iter()
+                    .filter(|selector| selector.metric == metric.name)
+                    .collect();
+                metric
+                    .value_per_labels
+                    .retain(|key, _| selectors.iter().any(|selector| selector.matches(key)));
+                (!metric.value_per_labels.is_empty()).then_some(metric)
+            })
+            .collect()
+    }
+}
+
+/// Rename the metric families matching `pattern`, e.g. `^node_` to `""` to
+/// strip a prefix. `replacement` may refer to capture groups as `$1`.
+#[derive(Debug)]
+pub struct Rename {
+    pub pattern: Regex,
+    pub replacement: String,
+}
+
+impl Stage for Rename {
+    fn process(&self, mut metrics: Vec<SingleScrapeMetric>) -> Vec<SingleScrapeMetric> {
+        for metric in &mut metrics {
+            metric.name = self
+                .pattern
+                .replace(&metric.name, self.replacement.as_str())
+                .into_owned();
+        }
+        metrics
+    }
+}
+
+/// Remove labels from all series, e.g. an ever changing pod hash. Of the
+/// series that become equal only the last one is kept.
+#[derive(Debug)]
+pub struct DropLabels {
+    pub labels: Vec<String>,
+}
+
+impl DropLabels {
+    fn drop_from(&self, key: &str) -> String {
+        let key = key
+            .split(',')
+            .filter(|label| {
+                let name = label.split('=').next().unwrap_or_default().trim();
+                !self.labels.iter().any(|dropped| dropped == name)
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        if key.is_empty() {
+            NO_LABELS.to_string()
+        } else {
+            key
+        }
+    }
+}
+
+impl Stage for DropLabels {
+    fn process(&self, mut metrics: Vec<SingleScrapeMetric>) -> Vec<SingleScrapeMetric> {
+        for metric in &mut metrics {
+            metric.value_per_labels = std::mem::take(&mut metric.value_per_labels)
+                .into_iter()
+                .map(|(key, sample)| (self.drop_from(&key), sample))
+                .collect();
+        }
+        metrics
+    }
+}
+
+/// Derive a gauge `name` with the sum of all series of the gauge or counter
+/// `source`
+#[derive(Debug)]
+pub struct Sum {
+    pub name: String,
+    pub source: String,
+}
+
+impl Stage for Sum {
+    fn process(&self, mut metrics: Vec<SingleScrapeMetric>) -> Vec<SingleScrapeMetric> {
+        let Some(source) = metrics.iter().find(|metric| metric.name == self.source) else {
+            return metrics;
+        };
+        let values: Vec<&SingleValueSample> = source
+            .value_per_labels
+            .values()
+            .filter_map(|sample| match sample {
+                Sample::GaugeSample(sample) | Sample::CounterSample(sample) => Some(sample),
+                _ => None,
+            })
+            .collect();
+        let Some(timestamp) = values.first().map(|sample| sample.timestamp) else {
+            return metrics;
+        };
+        let sum = SingleValueSample {
+            timestamp,
+            value: values.iter().map(|sample| sample.value).sum(),
+        };
+        metrics.push(SingleScrapeMetric {
+            name: self.name.clone(),
+            docstring: format!("Sum of {}", self.source),
+            metric_type: MetricType::Gauge,
+            unit: None,
+            value_per_labels: HashMap::from([(NO_LABELS.to_string(), Sample::GaugeSample(sum))]),
+            payload_bytes: 0,
+        });
+        metrics
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prom::test_data::scrape_all;
+
+    fn scrape() -> Vec<SingleScrapeMetric> {
+        let text = "# TYPE node_queue_size gauge
+node_queue_size{queue=\"a\",pod=\"x1\"} 1
+node_queue_size{queue=\"b\",pod=\"x2\"} 2
+# TYPE go_goroutines gauge
+go_goroutines 8
+";
+        scrape_all(text, 10)
+    }
+
+    fn names(metrics: &[SingleScrapeMetric]) -> Vec<&str> {
+        metrics.iter().map(|metric| metric.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_pipeline_stages() {
+        let pipeline = Pipeline::from(MetricFilter::new(vec![], vec![Regex::new("^go_").unwrap()]))
+            .with_stage(Rename {
+                pattern: Regex::new("^node_").unwrap(),
+                replacement: String::new(),
+            })
+            .with_stage(DropLabels {
+                labels: vec!["pod".to_string()],
+            })
+            .with_stage(Sum {
+                name: "queue_size_sum".to_string(),
+                source: "queue_size".to_string(),
+            });
+        let metrics = pipeline.process(scrape());
+        assert_eq!(names(&metrics), vec!["queue_size", "queue_size_sum"]);
+        let mut keys: Vec<&String> = metrics[0].value_per_labels.keys().collect();
+        keys.sort();
+        assert_eq!(keys, vec!["queue=\"a\"", "queue=\"b\""]);
+        match &metrics[1].value_per_labels[NO_LABELS] {
+            Sample::GaugeSample(sample) => assert_eq!((sample.timestamp, sample.value), (10, 3.0)),
+            sample => panic!("unexpected sample {:?}", sample),
+        }
+    }
+
+    #[test]
+    fn test_select() {
+        let selectors = ["node_queue_size{queue=\"a\"}", "go_goroutines"]
+            .iter()
+            .map(|selector| selector.parse().unwrap())
+            .collect();
+        let metrics = Select { selectors }.process(scrape());
+        assert_eq!(names(&metrics), vec!["node_queue_size", "go_goroutines"]);
+        let keys: Vec<&String> = metrics[0].value_per_labels.keys().collect();
+        assert_eq!(keys, vec!["queue=\"a\",pod=\"x1\""]);
+
+        let selectors = vec!["node_queue_size{pod!=\"x1\"}".parse().unwrap()];
+        let metrics = Select { selectors }.process(scrape());
+        assert_eq!(names(&metrics), vec!["node_queue_size"]);
+        assert!(metrics[0]
+            .value_per_labels
+            .contains_key("queue=\"b\",pod=\"x2\""));
+
+        assert!("rate(go_goroutines)".parse::<Selector>().is_err());
+    }
+
+    #[test]
+    fn test_drop_all_labels() {
+        let stage = DropLabels {
+            labels: vec!["queue".to_string()],
+        };
+        assert_eq!(stage.drop_from("queue=\"a\""), NO_LABELS);
+        assert_eq!(stage.drop_from(NO_LABELS), NO_LABELS);
+    }
+
+    #[test]
+    fn test_labels_key() {
+        assert_eq!(labels_key([]), NO_LABELS);
+        assert_eq!(
+            labels_key([("job", "api"), ("path", "/say \"hi\"")]),
+            "job=\"api\",path=\"/say \\\"hi\\\"\""
+        );
+    }
+}
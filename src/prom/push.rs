@@ -0,0 +1,188 @@
+//! Re-publishing of the latest scrape to a Pushgateway, for targets only
+//! reachable from where promviz runs
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use base64::{engine::general_purpose::URL_SAFE, Engine};
+use reqwest::Client;
+use tokio::sync::watch;
+
+use super::model::{MetricType, SingleScrapeMetric};
+use super::pipeline::Stage;
+
+/// Pipeline stage passing every scrape on unchanged, and replacing the group
+/// at the Pushgateway with it in the background
+#[derive(Debug)]
+pub struct Push {
+    /// Number and text of the latest scrape
+    latest: watch::Sender<Option<(u64, String)>>,
+    /// Number of the latest scrape pushed, successfully or not
+    pushed: Arc<AtomicU64>,
+}
+
+impl Push {
+    /// Start pushing to the grouping key URL `url`, see [`grouping_url`].
+    /// Scrapes arriving while a push is still running replace each other,
+    /// only the latest one is pushed. Must be called within the tokio runtime.
+    pub fn start(url: String, client: Client) -> Self {
+        let (latest, mut scrapes) = watch::channel(None);
+        let pushed = Arc::new(AtomicU64::new(0));
+        let done = pushed.clone();
+        tokio::spawn(async move {
+            while scrapes.changed().await.is_ok() {
+                let Some((number, body)) = scrapes.borrow_and_update().clone() else {
+                    continue;
+                };
+                if let Err(err) = send(&client, &url, body).await {
+                    log::error!("Push to {} failed: {:#}", url, err);
+                }
+                done.store(number, Ordering::SeqCst);
+            }
+        });
+        Self { latest, pushed }
+    }
+}
+
+impl Stage for Push {
+    fn process(&self, metrics: Vec<SingleScrapeMetric>) -> Vec<SingleScrapeMetric> {
+        let text = encode_text(&metrics);
+        self.latest.send_modify(|latest| {
+            let number = latest.as_ref().map_or(1, |(number, _)| number + 1);
+            *latest = Some((number, text));
+        });
+        metrics
+    }
+
+    fn flushed(&self) -> bool {
+        let latest = self
+            .latest
+            .borrow()
+            .as_ref()
+            .map_or(0, |(number, _)| *number);
+        self.pushed.load(Ordering::SeqCst) == latest
+    }
+}
+
+async fn send(client: &Client, url: &str, body: String) -> anyhow::Result<()> {
+    let resp = client
+        .put(url)
+        .header("Content-Type", "text/plain; version=0.0.4")
+        .body(body)
+        .send()
+        .await?;
+    if !resp.status().is_success() {
+        let status = resp.status();
+        anyhow::bail!(
+            "{}: {}",
+            status,
+            resp.text().await.unwrap_or_default().trim()
+        );
+    }
+    Ok(())
+}
+
+/// URL of the group with the labels `job` and `instance` at the Pushgateway
+/// `gateway`, values that can't be a path segment are base64 encoded
+pub fn grouping_url(gateway: &str, job: &str, instance: &str) -> String {
+    let mut url = format!("{}/metrics", gateway.trim_end_matches('/'));
+    for (label, value) in [("job", job), ("instance", instance)] {
+        let plain = !value.is_empty()
+            && value
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-' | ':'));
+        if plain {
+            write!(url, "/{}/{}", label, value).unwrap();
+        } else {
+            // an empty value is encoded as a lone padding character
+            let encoded = match URL_SAFE.encode(value) {
+                encoded if encoded.is_empty() => "=".to_string(),
+                encoded => encoded,
+            };
+            write!(url, "/{}@base64/{}", label, encoded).unwrap();
+        }
+    }
+    url
+}
+
+/// `metrics` in the text exposition format, without timestamps, which the
+/// Pushgateway rejects
+fn encode_text(metrics: &[SingleScrapeMetric]) -> String {
+    let mut text = String::new();
+    for metric in metrics {
+        if !metric.docstring.is_empty() {
+            let docstring = metric.docstring.replace('\\', "\\\\").replace('\n', "\\n");
+            writeln!(text, "# HELP {} {}", metric.name, docstring).unwrap();
+        }
+        let metric_type = match metric.metric_type {
+            MetricType::Gauge => "gauge",
+            MetricType::Counter => "counter",
+            MetricType::Histogram => "histogram",
+            MetricType::Summary => "summary",
+        };
+        writeln!(text, "# TYPE {} {}", metric.name, metric_type).unwrap();
+        for (mut labels, value, _) in metric.points() {
+            let name = labels.remove("__name__").unwrap_or_default();
+            text.push_str(&name);
+            if !labels.is_empty() {
+                let labels: Vec<String> = labels
+                    .iter()
+                    .map(|(label, value)| {
+                        let value = value
+                            .replace('\\', "\\\\")
+                            .replace('"', "\\\"")
+                            .replace('\n', "\\n");
+                        format!("{}=\"{}\"", label, value)
+                    })
+                    .collect();
+                write!(text, "{{{}}}", labels.join(",")).unwrap();
+            }
+            writeln!(text, " {}", format_float(value)).unwrap();
+        }
+    }
+    text
+}
+
+fn format_float(value: f64) -> String {
+    match value {
+        value if value.is_nan() => "NaN".to_string(),
+        f64::INFINITY => "+Inf".to_string(),
+        f64::NEG_INFINITY => "-Inf".to_string(),
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prom::test_data::scrape_all;
+
+    #[test]
+    fn test_encode_text() {
+        let text = "# HELP requests_total Handled requests
+# TYPE requests_total counter
+requests_total{code=\"200\"} 10
+requests_total{code=\"500\"} 2
+# TYPE latency_seconds histogram
+latency_seconds_bucket{le=\"0.5\"} 3
+latency_seconds_bucket{le=\"+Inf\"} 4
+latency_seconds_sum 1.5
+latency_seconds_count 4
+# TYPE temperature gauge
+temperature NaN
+";
+        assert_eq!(encode_text(&scrape_all(text, 1700000000)), text);
+    }
+
+    #[test]
+    fn test_grouping_url() {
+        assert_eq!(
+            grouping_url("http://pushgateway:9091/", "promviz", "node1:9100"),
+            "http://pushgateway:9091/metrics/job/promviz/instance/node1:9100"
+        );
+        assert_eq!(
+            grouping_url("http://pushgateway:9091", "a/b", ""),
+            "http://pushgateway:9091/metrics/job@base64/YS9i/instance@base64/="
+        );
+    }
+}
@@ -0,0 +1,125 @@
+//! Query mode: the history of PromQL queries read from the `query_range` API
+//! of a Prometheus server, instead of the samples of scraped exporters.
+use std::time::Duration;
+
+use anyhow::Context;
+use reqwest::{
+    header::{HeaderValue, AUTHORIZATION},
+    Client,
+};
+
+use super::{auth_command::AuthCommand, import, model::SingleScrapeMetric};
+
+/// Most points Prometheus returns per series of a `query_range` call
+const MAX_POINTS: u64 = 11_000;
+
+/// Settings of the query loop
+#[derive(Clone, Debug)]
+pub struct QueryOptions {
+    /// Base URL of the Prometheus server, e.g. `http://prometheus:9090`
+    pub server: String,
+    /// PromQL expressions, each giving one or more series
+    pub queries: Vec<String>,
+    /// History read by the first query, later ones only read the new points
+    pub range: Duration,
+    pub auth_command: Option<AuthCommand>,
+}
+
+impl QueryOptions {
+    /// `query_range` URL of the server
+    fn url(&self) -> String {
+        format!("{}/api/v1/query_range", self.server.trim_end_matches('/'))
+    }
+
+    /// Read the points of all queries from `start` to `end` unix seconds,
+    /// `step` seconds apart, ordered by time
+    pub async fn query_range(
+        &self,
+        client: &Client,
+        start: u64,
+        end: u64,
+        step: u64,
+    ) -> anyhow::Result<Vec<SingleScrapeMetric>> {
+        let step = step.max(1);
+        // stay below the limit of the server, skipping the oldest points
+        let start = start.max(end.saturating_sub(step * (MAX_POINTS - 1)));
+        let mut responses = vec![];
+        for query in &self.queries {
+            let mut request = client.get(self.url()).query(&[
+                ("query", query.as_str()),
+                ("start", &start.to_string()),
+                ("end", &end.to_string()),
+                ("step", &step.to_string()),
+            ]);
+            if let Some(auth_command) = &self.auth_command {
+                let mut value = HeaderValue::from_str(&auth_command.authorization().await?)
+                    .context("invalid characters in the auth command output")?;
+                value.set_sensitive(true);
+                request = request.header(AUTHORIZATION, value);
+            }
+            // Prometheus answers bad queries with a 400 and the error in the body
+            let body = request.send().await?.bytes().await?;
+            responses.push((query.clone(), body.to_vec()));
+        }
+        import::decode_query_responses(&responses)
+    }
+}
+
+/// Start of the next `query_range` call, given the end of the previous one:
+/// the first call reads `range` of history, the later ones only new points.
+/// The ends are aligned to the steps, as the points of the server are.
+pub fn next_start(previous_end: Option<u64>, now: u64, range: Duration, step: u64) -> u64 {
+    match previous_end {
+        Some(previous_end) => previous_end + step.max(1),
+        None => now.saturating_sub(range.as_secs()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_start() {
+        let range = Duration::from_secs(3600);
+        assert_eq!(next_start(None, 10_000, range, 15), 6400);
+        assert_eq!(next_start(Some(10_000), 10_015, range, 15), 10_015);
+        assert_eq!(next_start(Some(10_000), 10_015, range, 0), 10_001);
+    }
+
+    #[tokio::test]
+    async fn test_query_range() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let body = r#"{"status": "success", "data": {"resultType": "matrix", "result": [
+            {"metric": {"__name__": "up", "job": "node"}, "values": [[100, "1"], [115, "0"]]}]}}"#;
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let options = QueryOptions {
+            server: format!("http://{}/", listener.local_addr().unwrap()),
+            queries: vec!["up{job=\"node\"}".to_string()],
+            range: Duration::from_secs(3600),
+            auth_command: None,
+        };
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = vec![0; 4096];
+            let len = socket.read(&mut request).await.unwrap();
+            let request = String::from_utf8_lossy(&request[..len]).to_string();
+            assert!(request.starts_with(
+                "GET /api/v1/query_range?query=up%7Bjob%3D%22node%22%7D&start=100&end=115&step=15 "
+            ));
+            let header = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n",
+                body.len()
+            );
+            socket.write_all(header.as_bytes()).await.unwrap();
+            socket.write_all(body.as_bytes()).await.unwrap();
+        });
+
+        let client = crate::prom::HttpClientConfig::default().build().unwrap();
+        let metrics = options.query_range(&client, 100, 115, 15).await.unwrap();
+        server.await.unwrap();
+        assert_eq!(metrics.len(), 2);
+        assert!(metrics[1].value_per_labels.contains_key("job=\"node\""));
+    }
+}
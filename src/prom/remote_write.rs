@@ -0,0 +1,190 @@
+//! Forwarding of every scrape to a Prometheus remote-write receiver, e.g. a
+//! Prometheus with `--web.enable-remote-write-receiver`, Mimir or
+//! VictoriaMetrics, so promviz doubles as a one-target agent.
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use reqwest::Client;
+use tokio::sync::mpsc;
+
+use super::grpc_health::encode_varint;
+use super::model::SingleScrapeMetric;
+use super::pipeline::Stage;
+
+/// Write requests waiting to be sent, newer ones are dropped beyond this
+const QUEUE_LEN: usize = 100;
+
+/// Pipeline stage passing every scrape on unchanged, and sending it to `url`
+/// in the background
+#[derive(Debug)]
+pub struct RemoteWrite {
+    queue: mpsc::Sender<Vec<u8>>,
+    /// Requests queued or being sent
+    pending: Arc<AtomicUsize>,
+}
+
+impl RemoteWrite {
+    /// Start sending to `url`, one request at a time in the order of the
+    /// scrapes. Must be called within the tokio runtime.
+    pub fn start(url: String, client: Client) -> Self {
+        let (queue, mut requests) = mpsc::channel::<Vec<u8>>(QUEUE_LEN);
+        let pending = Arc::new(AtomicUsize::new(0));
+        let sent = pending.clone();
+        tokio::spawn(async move {
+            while let Some(body) = requests.recv().await {
+                if let Err(err) = send(&client, &url, body).await {
+                    log::error!("Remote write to {} failed: {:#}", url, err);
+                }
+                sent.fetch_sub(1, Ordering::SeqCst);
+            }
+        });
+        Self { queue, pending }
+    }
+}
+
+impl Stage for RemoteWrite {
+    fn process(&self, metrics: Vec<SingleScrapeMetric>) -> Vec<SingleScrapeMetric> {
+        let request = encode_write_request(&metrics);
+        self.pending.fetch_add(1, Ordering::SeqCst);
+        if let Err(err) = self.queue.try_send(snappy_literal(&request)) {
+            self.pending.fetch_sub(1, Ordering::SeqCst);
+            log::warn!("Dropping a remote write request: {}", err);
+        }
+        metrics
+    }
+
+    fn flushed(&self) -> bool {
+        self.pending.load(Ordering::SeqCst) == 0
+    }
+}
+
+async fn send(client: &Client, url: &str, body: Vec<u8>) -> anyhow::Result<()> {
+    let resp = client
+        .post(url)
+        .header("Content-Encoding", "snappy")
+        .header("Content-Type", "application/x-protobuf")
+        .header("X-Prometheus-Remote-Write-Version", "0.1.0")
+        .body(body)
+        .send()
+        .await?;
+    if !resp.status().is_success() {
+        let status = resp.status();
+        anyhow::bail!(
+            "{}: {}",
+            status,
+            resp.text().await.unwrap_or_default().trim()
+        );
+    }
+    Ok(())
+}
+
+/// Append field `field` holding the length-delimited `bytes`
+fn encode_bytes(field: u8, bytes: &[u8], buf: &mut Vec<u8>) {
+    buf.push(field << 3 | 2);
+    encode_varint(bytes.len() as u64, buf);
+    buf.extend_from_slice(bytes);
+}
+
+/// A `WriteRequest { repeated TimeSeries timeseries = 1; }` message with a
+/// `TimeSeries { repeated Label labels = 1; repeated Sample samples = 2; }`
+/// per series of `metrics`
+fn encode_write_request(metrics: &[SingleScrapeMetric]) -> Vec<u8> {
+    let mut request = vec![];
+    for (labels, value, timestamp) in metrics.iter().flat_map(SingleScrapeMetric::points) {
+        let mut series = vec![];
+        for (name, label_value) in &labels {
+            // Label { string name = 1; string value = 2; }
+            let mut label = vec![];
+            encode_bytes(1, name.as_bytes(), &mut label);
+            encode_bytes(2, label_value.as_bytes(), &mut label);
+            encode_bytes(1, &label, &mut series);
+        }
+        // Sample { double value = 1; int64 timestamp = 2; }
+        let mut sample = vec![0x09];
+        sample.extend_from_slice(&value.to_le_bytes());
+        sample.push(0x10);
+        encode_varint(timestamp as u64, &mut sample);
+        encode_bytes(2, &sample, &mut series);
+        encode_bytes(1, &series, &mut request);
+    }
+    request
+}
+
+/// `data` in the snappy block format, as literals only. Not compressed, but
+/// readable by every snappy decoder, which remote write requires.
+fn snappy_literal(data: &[u8]) -> Vec<u8> {
+    let mut block = Vec::with_capacity(data.len() + data.len() / 65536 * 3 + 8);
+    encode_varint(data.len() as u64, &mut block);
+    for chunk in data.chunks(65536) {
+        let len = chunk.len() - 1;
+        if len < 60 {
+            block.push((len as u8) << 2);
+        } else if len < 256 {
+            block.push(60 << 2);
+            block.push(len as u8);
+        } else {
+            block.push(61 << 2);
+            block.extend_from_slice(&(len as u16).to_le_bytes());
+        }
+        block.extend_from_slice(chunk);
+    }
+    block
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prom::test_data::scrape;
+
+    #[test]
+    fn test_encode_write_request() {
+        let metric = scrape("# TYPE up gauge\nup{job=\"node\"} 1\n", 2);
+        let request = encode_write_request(&[metric]);
+        let mut expected = vec![0x0a, 0x2b, 0x0a, 0x0e];
+        expected.extend_from_slice(b"\x0a\x08__name__\x12\x02up");
+        expected.extend_from_slice(b"\x0a\x0b\x0a\x03job\x12\x04node");
+        expected.extend_from_slice(&[0x12, 0x0c, 0x09]);
+        expected.extend_from_slice(&1f64.to_le_bytes());
+        expected.extend_from_slice(&[0x10, 0xd0, 0x0f]);
+        assert_eq!(request, expected);
+    }
+
+    #[test]
+    fn test_histogram_points() {
+        let text = "# TYPE latency_seconds histogram
+latency_seconds_bucket{le=\"0.5\"} 3
+latency_seconds_bucket{le=\"+Inf\"} 4
+latency_seconds_sum 1.5
+latency_seconds_count 4
+";
+        let metric = scrape(text, 1);
+        let mut names: Vec<String> = metric
+            .points()
+            .into_iter()
+            .map(|(labels, _, _)| {
+                let le = labels.get("le").map(String::as_str).unwrap_or_default();
+                format!("{}{}", labels["__name__"], le)
+            })
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            vec![
+                "latency_seconds_bucket+Inf",
+                "latency_seconds_bucket0.5",
+                "latency_seconds_count",
+                "latency_seconds_sum"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_snappy_literal() {
+        assert_eq!(snappy_literal(b"abc"), b"\x03\x08abc");
+        let data = vec![7; 300];
+        let block = snappy_literal(&data);
+        // varint 300, a literal with a 2 byte length of 299
+        assert_eq!(&block[..5], &[0xac, 0x02, 0xf4, 0x2b, 0x01]);
+        assert_eq!(block.len(), 305);
+    }
+}
@@ -0,0 +1,201 @@
+//! Metrics of promviz itself in the text exposition format, to monitor long
+//! running sessions, e.g. headless ones
+use std::fmt::Write;
+use std::sync::atomic::Ordering;
+
+use tokio::{
+    io::BufReader,
+    net::{TcpListener, TcpStream},
+    task,
+};
+
+use super::metric_scraper::{MetricHistoryArc, ScrapeStatusArc};
+use super::parser::PARSE_ERRORS;
+use super::{http_server, spill, MetricScraper};
+
+/// The history and status of the scraper exported
+#[derive(Clone)]
+pub struct SelfMetrics {
+    history: MetricHistoryArc,
+    status: ScrapeStatusArc,
+}
+
+impl SelfMetrics {
+    pub fn new(scraper: &MetricScraper) -> Self {
+        let (history, status) = scraper.shared();
+        Self { history, status }
+    }
+
+    /// The current values in the text exposition format
+    pub fn encode(&self) -> anyhow::Result<String> {
+        let (metrics, series, usage) = {
+            let history = self
+                .history
+                .read()
+                .map_err(|err| anyhow::anyhow!("failed to aquire lock: {}", err))?;
+            let headers = history.get_metrics_headers();
+            let series: usize = headers
+                .iter()
+                .filter_map(|name| history.get_metric(name))
+                .map(|metric| metric.time_series.len())
+                .sum();
+            (headers.len(), series, history.memory_usage())
+        };
+        let status = self
+            .status
+            .read()
+            .map_err(|err| anyhow::anyhow!("failed to aquire lock: {}", err))?;
+
+        let mut text = String::new();
+        let mut metric = |name: &str, metric_type: &str, help: &str, value: f64| {
+            writeln!(text, "# HELP {} {}", name, help).unwrap();
+            writeln!(text, "# TYPE {} {}", name, metric_type).unwrap();
+            writeln!(text, "{} {}", name, value).unwrap();
+        };
+        metric(
+            "promviz_scrapes_total",
+            "counter",
+            "Successful scrapes since the start.",
+            status.scrape_count as f64,
+        );
+        metric(
+            "promviz_scrape_failures_total",
+            "counter",
+            "Failed scrapes and retries since the start.",
+            status.failure_count as f64,
+        );
+        metric(
+            "promviz_scrapes_skipped_total",
+            "counter",
+            "Scrapes skipped because the previous one was still running.",
+            status.skipped_count as f64,
+        );
+        if let Some(duration) = status.last_duration {
+            metric(
+                "promviz_scrape_duration_seconds",
+                "gauge",
+                "Time taken by the latest scrape, including retries.",
+                duration.as_secs_f64(),
+            );
+        }
+        metric(
+            "promviz_parse_errors_total",
+            "counter",
+            "Values that failed to parse since the start.",
+            PARSE_ERRORS.load(Ordering::Relaxed) as f64,
+        );
+        metric(
+            "promviz_metrics",
+            "gauge",
+            "Metric families in the history.",
+            metrics as f64,
+        );
+        metric(
+            "promviz_series",
+            "gauge",
+            "Series in the history.",
+            series as f64,
+        );
+        metric(
+            "promviz_history_samples",
+            "gauge",
+            "Samples in the history.",
+            usage.samples as f64,
+        );
+        metric(
+            "promviz_history_bytes",
+            "gauge",
+            "Approximate bytes used by the samples in the history.",
+            usage.bytes as f64,
+        );
+        metric(
+            "promviz_spilled_samples_total",
+            "counter",
+            "Samples moved to the spill file because of the memory limit.",
+            status.spilled_samples as f64,
+        );
+        if let Some(resident) = spill::resident_memory() {
+            metric(
+                "promviz_resident_memory_bytes",
+                "gauge",
+                "Resident memory of the promviz process.",
+                resident as f64,
+            );
+        }
+        Ok(text)
+    }
+
+    /// Serve the metrics at `/metrics`
+    pub async fn serve(self, listener: TcpListener) {
+        loop {
+            match listener.accept().await {
+                Ok((stream, peer)) => {
+                    let exporter = self.clone();
+                    task::spawn(async move {
+                        if let Err(err) = exporter.handle_connection(stream).await {
+                            log::error!("Metrics connection from {} failed: {}", peer, err);
+                        }
+                    });
+                }
+                Err(err) => log::error!("Failed to accept metrics connection: {}", err),
+            }
+        }
+    }
+
+    async fn handle_connection(&self, stream: TcpStream) -> anyhow::Result<()> {
+        let mut reader = BufReader::new(stream);
+        loop {
+            let Some(request) = http_server::read_request(&mut reader).await? else {
+                return Ok(());
+            };
+            if request.method != "GET" || request.path.split('?').next() != Some("/metrics") {
+                http_server::write_response(reader.get_mut(), "404 Not Found", "{}").await?;
+                continue;
+            }
+            let (status, content_type, body) = match self.encode() {
+                Ok(text) => ("200 OK", "text/plain; version=0.0.4", text),
+                Err(err) => ("500 Internal Server Error", "text/plain", err.to_string()),
+            };
+            http_server::write_typed_response(reader.get_mut(), status, content_type, &body)
+                .await?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, RwLock};
+
+    use super::*;
+    use crate::prom::{
+        parser::split_metric_lines,
+        test_data::{memory_store, scrape_all},
+    };
+
+    #[test]
+    fn test_encode() {
+        let text = "# TYPE requests_total counter
+requests_total{code=\"200\"} 10
+requests_total{code=\"500\"} 2
+# TYPE up gauge
+up 1
+";
+        let mut history = memory_store();
+        for metric in scrape_all(text, 1) {
+            history.insert_scrape(metric);
+        }
+        let exporter = SelfMetrics {
+            history: Arc::new(RwLock::new(history)),
+            status: Default::default(),
+        };
+        let encoded = exporter.encode().unwrap();
+        let lines: Vec<&str> = encoded.lines().collect();
+        assert!(lines.contains(&"promviz_scrapes_total 0"), "{}", encoded);
+        assert!(lines.contains(&"promviz_metrics 2"), "{}", encoded);
+        assert!(lines.contains(&"promviz_series 3"), "{}", encoded);
+        assert!(lines.contains(&"promviz_history_samples 3"), "{}", encoded);
+        // the exporter's own text parses like any other endpoint
+        let families = split_metric_lines(encoded.lines().map(String::from).collect());
+        assert!(families.len() >= 9);
+    }
+}
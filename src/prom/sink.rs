@@ -0,0 +1,67 @@
+//! Headless mode: every scrape written to a sink instead of shown in the UI
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::Mutex;
+
+use super::export::{write_scrape_csv, write_scrape_json};
+use super::model::SingleScrapeMetric;
+use super::pipeline::Stage;
+
+/// Where --headless writes the samples
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum SinkFormat {
+    /// One row per sample, like the CSV export
+    Csv,
+    /// One JSON object per sample and line
+    Json,
+    /// Only the requests of --remote-write
+    RemoteWrite,
+}
+
+/// Pipeline stage passing every scrape on unchanged, and writing its samples
+/// to `writer`
+pub struct SampleSink {
+    format: SinkFormat,
+    writer: Mutex<Box<dyn Write + Send>>,
+}
+
+impl SampleSink {
+    /// Start writing `format` to `writer`, beginning with the CSV header
+    pub fn new(format: SinkFormat, mut writer: Box<dyn Write + Send>) -> io::Result<Self> {
+        if format == SinkFormat::Csv {
+            writeln!(writer, "metric,labels,timestamp,value")?;
+            writer.flush()?;
+        }
+        Ok(Self {
+            format,
+            writer: Mutex::new(writer),
+        })
+    }
+}
+
+impl fmt::Debug for SampleSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SampleSink")
+            .field("format", &self.format)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Stage for SampleSink {
+    fn process(&self, metrics: Vec<SingleScrapeMetric>) -> Vec<SingleScrapeMetric> {
+        let Ok(mut writer) = self.writer.lock() else {
+            return metrics;
+        };
+        let written = match self.format {
+            SinkFormat::Csv => write_scrape_csv(&mut *writer, &metrics),
+            SinkFormat::Json => write_scrape_json(&mut *writer, &metrics),
+            // sent by the RemoteWrite stage
+            SinkFormat::RemoteWrite => Ok(()),
+        };
+        // every scrape is complete when a reader of a pipe sees it
+        if let Err(err) = written.and_then(|_| writer.flush()) {
+            log::error!("Writing the scrape to the sink failed: {}", err);
+        }
+        metrics
+    }
+}
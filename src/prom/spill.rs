@@ -0,0 +1,204 @@
+//! Spilling of old samples to a temporary file when the process uses too
+//! much memory, so very long sessions are not killed for running out of it.
+//! The file has one JSON line per spilled chunk of a time series, and is read
+//! back when the user restores the history.
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::PathBuf;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use super::model::{HistogramValueSample, Sample, SingleValueSample, SummaryValueSample};
+
+/// Oldest samples of one time series, taken out of the history
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SpilledSeries {
+    pub metric: String,
+    /// Label key of the series, as used by `Metric::time_series`
+    pub labels: String,
+    pub samples: SpilledSamples,
+}
+
+/// Samples of a series, tagged with their type to be able to read them back
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", content = "samples", rename_all = "lowercase")]
+pub enum SpilledSamples {
+    Gauge(Vec<SingleValueSample>),
+    Counter(Vec<SingleValueSample>),
+    Histogram(Vec<HistogramValueSample>),
+    Summary(Vec<SummaryValueSample>),
+}
+
+impl SpilledSamples {
+    /// Group samples of a series, which all have the same type. `None` if
+    /// there are no samples.
+    pub fn from_samples(samples: Vec<Sample>) -> Option<Self> {
+        let spilled = match samples.first()? {
+            Sample::GaugeSample(_) => SpilledSamples::Gauge(Vec::new()),
+            Sample::CounterSample(_) => SpilledSamples::Counter(Vec::new()),
+            Sample::HistogramSample(_) => SpilledSamples::Histogram(Vec::new()),
+            Sample::SummarySample(_) => SpilledSamples::Summary(Vec::new()),
+        };
+        Some(samples.into_iter().fold(spilled, |mut spilled, sample| {
+            match (&mut spilled, sample) {
+                (SpilledSamples::Gauge(samples), Sample::GaugeSample(sample))
+                | (SpilledSamples::Counter(samples), Sample::CounterSample(sample)) => {
+                    samples.push(sample)
+                }
+                (SpilledSamples::Histogram(samples), Sample::HistogramSample(sample)) => {
+                    samples.push(sample)
+                }
+                (SpilledSamples::Summary(samples), Sample::SummarySample(sample)) => {
+                    samples.push(sample)
+                }
+                (_, sample) => log::warn!("dropping sample of another type: {:?}", sample),
+            }
+            spilled
+        }))
+    }
+
+    pub fn into_samples(self) -> Vec<Sample> {
+        match self {
+            SpilledSamples::Gauge(samples) => {
+                samples.into_iter().map(Sample::GaugeSample).collect()
+            }
+            SpilledSamples::Counter(samples) => {
+                samples.into_iter().map(Sample::CounterSample).collect()
+            }
+            SpilledSamples::Histogram(samples) => {
+                samples.into_iter().map(Sample::HistogramSample).collect()
+            }
+            SpilledSamples::Summary(samples) => {
+                samples.into_iter().map(Sample::SummarySample).collect()
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            SpilledSamples::Gauge(samples) | SpilledSamples::Counter(samples) => samples.len(),
+            SpilledSamples::Histogram(samples) => samples.len(),
+            SpilledSamples::Summary(samples) => samples.len(),
+        }
+    }
+}
+
+/// The temporary file holding the spilled samples of a store of this process
+#[derive(Debug)]
+pub struct SpillFile {
+    pub path: PathBuf,
+}
+
+impl SpillFile {
+    /// The spill file of the store shown in tab `tab`, each store spilling to
+    /// its own file so its samples are restored to it
+    pub fn new(tab: usize) -> Self {
+        Self {
+            path: std::env::temp_dir().join(format!(
+                "promviz-{}-{}.spill.jsonl",
+                std::process::id(),
+                tab
+            )),
+        }
+    }
+
+    /// Add spilled series after the ones spilled before
+    pub fn append(&self, spilled: &[SpilledSeries]) -> anyhow::Result<()> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("failed to open {}", self.path.display()))?;
+        let mut writer = BufWriter::new(file);
+        for series in spilled {
+            serde_json::to_writer(&mut writer, series)?;
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// All spilled series, oldest first. Empty if nothing was spilled.
+    pub fn read(&self) -> anyhow::Result<Vec<SpilledSeries>> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => {
+                return Err(err).with_context(|| format!("failed to open {}", self.path.display()))
+            }
+        };
+        BufReader::new(file)
+            .lines()
+            .map(|line| {
+                serde_json::from_str(&line?)
+                    .with_context(|| format!("invalid line in {}", self.path.display()))
+            })
+            .collect()
+    }
+
+    pub fn remove(&self) -> anyhow::Result<()> {
+        match fs::remove_file(&self.path) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                Err(err).with_context(|| format!("failed to remove {}", self.path.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Resident memory of this process in bytes, `None` where it isn't known
+pub fn resident_memory() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    // e.g. "VmRSS:	   12345 kB"
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prom::test_data::scrape;
+
+    #[test]
+    fn test_spill_file_round_trip() {
+        let text = "# TYPE rpc_duration_seconds summary
+rpc_duration_seconds{quantile=\"0.5\"} 0.2
+rpc_duration_seconds_sum 10
+rpc_duration_seconds_count 40
+";
+        let metric = scrape(text, 1_700_000_000);
+        let (labels, sample) = metric.value_per_labels.into_iter().next().unwrap();
+        let file = SpillFile {
+            path: std::env::temp_dir().join(format!("promviz-test-{}.jsonl", std::process::id())),
+        };
+        let spilled = SpilledSeries {
+            metric: metric.name,
+            labels,
+            samples: SpilledSamples::from_samples(vec![sample.clone(), sample]).unwrap(),
+        };
+        file.append(&[spilled]).unwrap();
+        let read = file.read().unwrap();
+        file.remove().unwrap();
+
+        assert_eq!(read.len(), 1);
+        assert_eq!(read[0].metric, "rpc_duration_seconds");
+        match read
+            .into_iter()
+            .next()
+            .unwrap()
+            .samples
+            .into_samples()
+            .as_slice()
+        {
+            [Sample::SummarySample(first), Sample::SummarySample(_)] => {
+                assert_eq!(first.time.timestamp(), 1_700_000_000);
+                assert_eq!((first.sum, first.count), (10.0, 40));
+                assert_eq!(first.quantiles[0].value, 0.2);
+            }
+            samples => panic!("unexpected samples {:?}", samples),
+        }
+        assert!(file.read().unwrap().is_empty());
+    }
+}
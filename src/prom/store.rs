@@ -0,0 +1,455 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Debug;
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+use super::model::{Metric, MetricHistory, Retention, Sample, SingleScrapeMetric};
+use super::spill::{SpilledSamples, SpilledSeries};
+
+/// Samples per series that are never spilled, so graphs stay useful
+pub const MIN_KEPT_SAMPLES: usize = 100;
+
+/// Storage of the scraped metrics and their sample history.
+pub trait HistoryStore: Debug + Send + Sync {
+    /// Add the samples of a freshly scraped metric to the history
+    fn insert_scrape(&mut self, metric: SingleScrapeMetric);
+
+    /// Sorted names of all stored metrics
+    fn get_metrics_headers(&self) -> Vec<String>;
+
+    fn get_metric(&self, metric_name: &str) -> Option<&Metric>;
+
+    /// Take out the older half of the samples of every series, keeping at
+    /// least `MIN_KEPT_SAMPLES`, to spill them to disk
+    fn take_oldest(&mut self) -> Vec<SpilledSeries>;
+
+    /// Put spilled samples back in front of the stored ones. `spilled` is
+    /// oldest first.
+    fn restore(&mut self, spilled: Vec<SpilledSeries>) -> usize;
+
+    fn memory_usage(&self) -> MemoryUsage;
+
+    /// An empty store of the same kind and limits, e.g. for another endpoint
+    fn new_empty(&self) -> Box<dyn HistoryStore>;
+
+    #[allow(dead_code)]
+    fn is_empty(&self) -> bool {
+        self.get_metrics_headers().is_empty()
+    }
+}
+
+/// Available `HistoryStore` implementations. Both keep the history in memory,
+/// SQLite and memory-mapped file backends are deferred.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageKind {
+    /// Keep every sample in memory, growing without limit
+    #[default]
+    Memory,
+    /// Keep the samples of every series in a fixed-size ring buffer, dropping the oldest
+    Bounded,
+}
+
+impl StorageKind {
+    /// Create the store. `capacity` is the size of the ring buffers of
+    /// bounded stores, `retention` applies to all of them.
+    pub fn create(self, capacity: StorageCapacity, retention: Retention) -> Box<dyn HistoryStore> {
+        match self {
+            StorageKind::Memory => Box::new(MetricHistory::with_retention(retention)),
+            StorageKind::Bounded => Box::new(BoundedMetricHistory::new(capacity, retention)),
+        }
+    }
+}
+
+/// Size of the ring buffers of a bounded store
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "CapacityValue")]
+pub enum StorageCapacity {
+    /// Samples per series
+    Samples(usize),
+    /// Bytes for the whole history, shared equally by all series
+    Bytes(u64),
+}
+
+impl StorageCapacity {
+    /// Samples kept per series, given the number of stored series and the
+    /// size of a sample of the series
+    fn samples_per_series(self, series_count: usize, sample_size: usize) -> usize {
+        match self {
+            StorageCapacity::Samples(samples) => samples,
+            StorageCapacity::Bytes(bytes) => {
+                (bytes / (series_count.max(1) * sample_size.max(1)) as u64) as usize
+            }
+        }
+        .max(1)
+    }
+}
+
+impl FromStr for StorageCapacity {
+    type Err = String;
+
+    /// A number of samples, e.g. "1000", or bytes with a unit, e.g. "256MiB"
+    fn from_str(capacity: &str) -> Result<Self, Self::Err> {
+        let capacity = capacity.trim();
+        let (number, unit) = capacity.split_at(
+            capacity
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(capacity.len()),
+        );
+        let number: u64 = number.parse().map_err(|_| {
+            format!(
+                "expected samples or bytes like 256MiB, got \"{}\"",
+                capacity
+            )
+        })?;
+        let factor = match unit.trim() {
+            "" => return Ok(StorageCapacity::Samples(number as usize)),
+            "B" => 1,
+            "KiB" => 1 << 10,
+            "MiB" => 1 << 20,
+            "GiB" => 1 << 30,
+            unit => return Err(format!("unknown unit \"{}\", use B, KiB, MiB or GiB", unit)),
+        };
+        Ok(StorageCapacity::Bytes(number * factor))
+    }
+}
+
+/// A capacity in the config file, either samples as a number or a string
+/// parsed like the CLI flag
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum CapacityValue {
+    Samples(usize),
+    Text(String),
+}
+
+impl TryFrom<CapacityValue> for StorageCapacity {
+    type Error = String;
+
+    fn try_from(value: CapacityValue) -> Result<Self, Self::Error> {
+        match value {
+            CapacityValue::Samples(samples) => Ok(StorageCapacity::Samples(samples)),
+            CapacityValue::Text(text) => text.parse(),
+        }
+    }
+}
+
+/// Approximate size of the stored history
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    pub samples: usize,
+    pub bytes: usize,
+}
+
+impl HistoryStore for MetricHistory {
+    fn insert_scrape(&mut self, single_scrape_metric: SingleScrapeMetric) {
+        match self.metrics.get_mut(&single_scrape_metric.name) {
+            Some(metric_to_update) => {
+                log::debug!("updating metric: {}", metric_to_update.details.name);
+                metric_to_update.details.check_metadata(
+                    &single_scrape_metric.docstring,
+                    &single_scrape_metric.metric_type,
+                );
+                metric_to_update.details.payload_bytes = single_scrape_metric.payload_bytes;
+                metric_to_update
+                    .update_time_series(single_scrape_metric.value_per_labels, &self.retention);
+            }
+            None => {
+                let metric = single_scrape_metric.into_metric();
+                log::debug!(
+                    "add metric '{}' for the first time to the history.",
+                    metric.details.name
+                );
+                self.metrics.insert(metric.details.name.clone(), metric);
+            }
+        }
+    }
+
+    fn get_metrics_headers(&self) -> Vec<String> {
+        MetricHistory::get_metrics_headers(self)
+    }
+
+    fn get_metric(&self, metric_name: &str) -> Option<&Metric> {
+        MetricHistory::get_metric(self, metric_name)
+    }
+
+    fn take_oldest(&mut self) -> Vec<SpilledSeries> {
+        let mut spilled = Vec::new();
+        for (name, metric) in &mut self.metrics {
+            for (labels, time_series) in &mut metric.time_series {
+                let count = (time_series.samples.len() / 2)
+                    .min(time_series.samples.len().saturating_sub(MIN_KEPT_SAMPLES));
+                let samples = time_series.samples.drain(..count).collect();
+                time_series.samples.shrink_to_fit();
+                if let Some(samples) = SpilledSamples::from_samples(samples) {
+                    spilled.push(SpilledSeries {
+                        metric: name.clone(),
+                        labels: labels.clone(),
+                        samples,
+                    });
+                }
+            }
+        }
+        spilled
+    }
+
+    fn restore(&mut self, spilled: Vec<SpilledSeries>) -> usize {
+        let mut restored: HashMap<(String, String), VecDeque<Sample>> = HashMap::new();
+        for series in spilled {
+            restored
+                .entry((series.metric, series.labels))
+                .or_default()
+                .extend(series.samples.into_samples());
+        }
+        let mut count = 0;
+        for ((name, labels), mut samples) in restored {
+            let Some(time_series) = self
+                .metrics
+                .get_mut(&name)
+                .and_then(|metric| metric.time_series.get_mut(&labels))
+            else {
+                log::warn!(
+                    "dropping spilled samples of unknown series {} {}",
+                    name,
+                    labels
+                );
+                continue;
+            };
+            count += samples.len();
+            samples.append(&mut time_series.samples);
+            time_series.samples = samples;
+        }
+        count
+    }
+
+    fn memory_usage(&self) -> MemoryUsage {
+        self.metrics
+            .values()
+            .flat_map(|metric| metric.time_series.values())
+            .fold(MemoryUsage::default(), |usage, time_series| {
+                let sample_size = time_series.samples.back().map_or(0, Sample::size);
+                MemoryUsage {
+                    samples: usage.samples + time_series.samples.len(),
+                    bytes: usage.bytes + time_series.samples.len() * sample_size,
+                }
+            })
+    }
+
+    fn new_empty(&self) -> Box<dyn HistoryStore> {
+        Box::new(MetricHistory::with_retention(self.retention))
+    }
+}
+
+/// In-memory history keeping the samples of every time series in a ring
+/// buffer of `capacity`.
+#[derive(Debug)]
+pub struct BoundedMetricHistory {
+    history: MetricHistory,
+    capacity: StorageCapacity,
+    /// Number of stored series, sharing a capacity given in bytes
+    series_count: usize,
+}
+
+impl BoundedMetricHistory {
+    pub fn new(capacity: StorageCapacity, retention: Retention) -> Self {
+        Self {
+            history: MetricHistory::with_retention(retention),
+            capacity,
+            series_count: 0,
+        }
+    }
+}
+
+impl HistoryStore for BoundedMetricHistory {
+    fn insert_scrape(&mut self, single_scrape_metric: SingleScrapeMetric) {
+        let name = single_scrape_metric.name.clone();
+        let (capacity, series_count) = (self.capacity, self.series_count);
+        let mut known_series = 0;
+        if let Some(metric) = self.history.metrics.get_mut(&name) {
+            known_series = metric.time_series.len();
+            // drop the oldest samples before adding the new ones, so full
+            // ring buffers don't grow
+            for (labels, sample) in &single_scrape_metric.value_per_labels {
+                if let Some(time_series) = metric.time_series.get_mut(labels) {
+                    let capacity = capacity.samples_per_series(series_count, sample.size());
+                    let excess = (time_series.samples.len() + 1).saturating_sub(capacity);
+                    time_series.samples.drain(..excess);
+                }
+            }
+        }
+        self.history.insert_scrape(single_scrape_metric);
+        if let Some(metric) = self.history.metrics.get(&name) {
+            // series may have expired as well
+            self.series_count = (self.series_count + metric.time_series.len()) - known_series;
+        }
+    }
+
+    fn get_metrics_headers(&self) -> Vec<String> {
+        self.history.get_metrics_headers()
+    }
+
+    fn get_metric(&self, metric_name: &str) -> Option<&Metric> {
+        self.history.get_metric(metric_name)
+    }
+
+    fn take_oldest(&mut self) -> Vec<SpilledSeries> {
+        self.history.take_oldest()
+    }
+
+    fn restore(&mut self, spilled: Vec<SpilledSeries>) -> usize {
+        self.history.restore(spilled)
+    }
+
+    fn memory_usage(&self) -> MemoryUsage {
+        self.history.memory_usage()
+    }
+
+    fn new_empty(&self) -> Box<dyn HistoryStore> {
+        Box::new(BoundedMetricHistory::new(
+            self.capacity,
+            self.history.retention,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prom::{
+        test_data::{generate_metric_lines, memory_store, scrape, scrape_all},
+        MetricType,
+    };
+
+    use super::*;
+
+    fn scrape_into(store: &mut dyn HistoryStore, timestamp: u64) {
+        for metric in scrape_all(&generate_metric_lines().join("\n"), timestamp) {
+            store.insert_scrape(metric);
+        }
+    }
+
+    fn sample_counts(store: &dyn HistoryStore) -> Vec<usize> {
+        store
+            .get_metrics_headers()
+            .iter()
+            .flat_map(|name| {
+                store
+                    .get_metric(name)
+                    .expect("listed metric to exist")
+                    .time_series
+                    .values()
+                    .map(|time_series| time_series.samples.len())
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_memory_store_keeps_all_samples() {
+        let mut store =
+            StorageKind::Memory.create(StorageCapacity::Samples(2), Retention::default());
+        assert!(store.is_empty());
+        for timestamp in 0..5 {
+            scrape_into(store.as_mut(), timestamp);
+        }
+        assert!(!store.is_empty());
+        assert!(sample_counts(store.as_ref())
+            .iter()
+            .all(|count| *count == 5));
+    }
+
+    #[test]
+    fn test_bounded_store_drops_oldest_samples() {
+        let mut store =
+            StorageKind::Bounded.create(StorageCapacity::Samples(2), Retention::default());
+        for timestamp in 0..5 {
+            scrape_into(store.as_mut(), timestamp);
+        }
+        assert!(sample_counts(store.as_ref())
+            .iter()
+            .all(|count| *count == 2));
+
+        let metric = store.get_metric("metric_1").unwrap();
+        match metric.time_series.values().next().unwrap().samples.front() {
+            Some(crate::prom::Sample::GaugeSample(sample)) => assert_eq!(sample.timestamp, 3),
+            _ => panic!("expected a gauge sample"),
+        }
+    }
+
+    #[test]
+    fn test_bounded_store_shares_a_byte_capacity() {
+        let mut store = memory_store();
+        scrape_into(store.as_mut(), 0);
+        let usage = store.memory_usage();
+        let series = sample_counts(store.as_ref()).len();
+        assert_eq!(usage.samples, series);
+
+        // room for about three samples per series
+        let budget = 3 * usage.bytes as u64;
+        let mut store =
+            StorageKind::Bounded.create(StorageCapacity::Bytes(budget), Retention::default());
+        for timestamp in 0..10 {
+            scrape_into(store.as_mut(), timestamp);
+        }
+        let usage = store.memory_usage();
+        assert!(usage.bytes as u64 <= budget);
+        assert!(usage.samples > 2 * series);
+    }
+
+    #[test]
+    fn test_parse_storage_capacity() {
+        assert_eq!("1000".parse(), Ok(StorageCapacity::Samples(1000)));
+        assert_eq!("256MiB".parse(), Ok(StorageCapacity::Bytes(256 << 20)));
+        assert_eq!("2 GiB".parse(), Ok(StorageCapacity::Bytes(2 << 30)));
+        assert!("1TB".parse::<StorageCapacity>().is_err());
+        assert!("MiB".parse::<StorageCapacity>().is_err());
+    }
+
+    #[test]
+    fn test_spill_and_restore_oldest_samples() {
+        let mut store = memory_store();
+        for timestamp in 0..(MIN_KEPT_SAMPLES as u64 + 50) {
+            scrape_into(store.as_mut(), timestamp);
+        }
+        let spilled = store.take_oldest();
+        assert!(sample_counts(store.as_ref())
+            .iter()
+            .all(|count| *count == MIN_KEPT_SAMPLES));
+        assert!(spilled.iter().all(|series| series.samples.len() == 50));
+        assert!(store.take_oldest().is_empty());
+
+        let spilled_count: usize = spilled.iter().map(|series| series.samples.len()).sum();
+        assert_eq!(store.restore(spilled), spilled_count);
+        let metric = store.get_metric("metric_1").unwrap();
+        let samples = &metric.time_series.values().next().unwrap().samples;
+        assert_eq!(samples.len(), MIN_KEPT_SAMPLES + 50);
+        match samples.front() {
+            Some(crate::prom::Sample::GaugeSample(sample)) => assert_eq!(sample.timestamp, 0),
+            _ => panic!("expected a gauge sample"),
+        }
+    }
+
+    #[test]
+    fn test_metadata_mismatch_is_recorded() {
+        let mut store = memory_store();
+        scrape_into(store.as_mut(), 0);
+        let metric_1 = |help: &str, metric_type: &str| {
+            let text = format!(
+                "# HELP metric_1 {}\n# TYPE metric_1 {}\nmetric_1{{shard=\"0\"}} 11.0\n",
+                help, metric_type
+            );
+            scrape(&text, 1)
+        };
+        store.insert_scrape(metric_1("Description of the metric", "gauge"));
+        assert_eq!(store.get_metric("metric_1").unwrap().details.mismatch, None);
+
+        store.insert_scrape(metric_1("Renamed", "counter"));
+        store.insert_scrape(metric_1("Renamed", "counter"));
+        let details = &store.get_metric("metric_1").unwrap().details;
+        assert_eq!(details.docstring, "Description of the metric");
+        let mismatch = details.mismatch.as_ref().unwrap();
+        assert_eq!(mismatch.docstring, "Renamed");
+        assert_eq!(mismatch.metric_type, MetricType::Counter);
+        assert_eq!(mismatch.count, 2);
+    }
+}
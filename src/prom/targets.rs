@@ -0,0 +1,352 @@
+//! Scraping a fleet of targets from an endpoint template like
+//! `http://{host}:9100/metrics` and a list of hosts. The series of every
+//! target get an `instance` label with its host, and the scrapes of all
+//! targets are merged into one, see `ConflictPolicy`.
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::Context;
+
+use super::model::{Sample, SingleScrapeMetric};
+
+/// Replaced by every host of the host list
+const HOST_PLACEHOLDER: &str = "{host}";
+
+/// A scraped endpoint
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Target {
+    pub url: String,
+    /// Value of the `instance` label added to the series of the target, if
+    /// it is one of many
+    pub instance: Option<String>,
+}
+
+impl Target {
+    /// The targets of `url`, one per host with `{host}` replaced if hosts
+    /// are given
+    pub fn expand(url: &str, hosts: &[String]) -> anyhow::Result<Vec<Target>> {
+        if hosts.is_empty() {
+            return Ok(vec![Target {
+                url: url.to_string(),
+                instance: None,
+            }]);
+        }
+        if !url.contains(HOST_PLACEHOLDER) {
+            anyhow::bail!(
+                "a host list needs an endpoint with {}, e.g. http://{}:9100/metrics",
+                HOST_PLACEHOLDER,
+                HOST_PLACEHOLDER
+            );
+        }
+        Ok(hosts
+            .iter()
+            .map(|host| Target {
+                url: url.replace(HOST_PLACEHOLDER, host),
+                instance: Some(host.clone()),
+            })
+            .collect())
+    }
+}
+
+/// Hosts listed one per line, skipping blank lines and `#` comments
+pub fn read_hosts(path: &Path) -> anyhow::Result<Vec<String>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read the host list {}", path.display()))?;
+    let hosts: Vec<String> = text
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|line| !line.is_empty())
+        .map(String::from)
+        .collect();
+    if hosts.is_empty() {
+        anyhow::bail!("the host list {} is empty", path.display());
+    }
+    Ok(hosts)
+}
+
+/// How series of several targets with the same name and labels are told
+/// apart
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ConflictPolicy {
+    /// Every series gets an `instance` label with its target. An `instance`
+    /// label of the target is kept as `exported_instance`.
+    #[default]
+    Label,
+    /// Only series exposed by several targets get the `instance` label
+    Suffix,
+    /// Series exposed by several targets are added up into one
+    Merge,
+}
+
+/// Label of the target of a series
+const INSTANCE_LABEL: &str = "instance";
+
+/// An `instance` label exposed by a target is renamed to this
+const EXPORTED_INSTANCE_LABEL: &str = "exported_instance";
+
+/// `key` with the `instance` label of `instance` in front
+fn with_instance(key: &str, instance: &str) -> String {
+    let label = format!("{}=\"{}\"", INSTANCE_LABEL, instance);
+    // see `extract_labels_key_and_map` for the key of series without labels
+    if !key.contains('=') {
+        return label;
+    }
+    // keep an instance label of the target instead of repeating the label
+    let prefix = format!("{}=\"", INSTANCE_LABEL);
+    let key = match key.starts_with(&prefix) {
+        true => format!(
+            "{}{}",
+            EXPORTED_INSTANCE_LABEL,
+            &key[INSTANCE_LABEL.len()..]
+        ),
+        false => key.replace(
+            &format!(",{}", prefix),
+            &format!(",{}=\"", EXPORTED_INSTANCE_LABEL),
+        ),
+    };
+    format!("{},{}", label, key)
+}
+
+/// Add the `instance` label to all series of a scrape
+pub fn label_instance(metrics: &mut [SingleScrapeMetric], instance: &str) {
+    for metric in metrics {
+        metric.value_per_labels = std::mem::take(&mut metric.value_per_labels)
+            .into_iter()
+            .map(|(key, sample)| (with_instance(&key, instance), sample))
+            .collect();
+    }
+}
+
+/// Merge the scrapes of several targets, joining the series of metric
+/// families with the same name
+pub fn merge(scrapes: Vec<Vec<SingleScrapeMetric>>) -> Vec<SingleScrapeMetric> {
+    let mut merged: Vec<SingleScrapeMetric> = vec![];
+    let mut index_by_name: HashMap<String, usize> = HashMap::new();
+    for metric in scrapes.into_iter().flatten() {
+        match index_by_name.get(&metric.name) {
+            Some(&index) => {
+                merged[index].payload_bytes += metric.payload_bytes;
+                let series = &mut merged[index].value_per_labels;
+                for (key, sample) in metric.value_per_labels {
+                    match series.entry(key) {
+                        Entry::Occupied(mut entry) => {
+                            if !add_sample(entry.get_mut(), sample) {
+                                log::debug!(
+                                    "{}{{{}}} can't be added up, keeping the first one",
+                                    metric.name,
+                                    entry.key()
+                                );
+                            }
+                        }
+                        Entry::Vacant(entry) => {
+                            entry.insert(sample);
+                        }
+                    }
+                }
+            }
+            None => {
+                index_by_name.insert(metric.name.clone(), merged.len());
+                merged.push(metric);
+            }
+        }
+    }
+    merged
+}
+
+/// Add `sample` to `total`, returning false if they can't be added up, like
+/// the quantiles of summaries or histograms with different buckets
+fn add_sample(total: &mut Sample, sample: Sample) -> bool {
+    match (total, sample) {
+        (Sample::GaugeSample(total), Sample::GaugeSample(sample))
+        | (Sample::CounterSample(total), Sample::CounterSample(sample)) => {
+            total.value += sample.value;
+            true
+        }
+        (Sample::HistogramSample(total), Sample::HistogramSample(sample))
+            if total
+                .bucket_values
+                .iter()
+                .map(|bucket| &bucket.name)
+                .eq(sample.bucket_values.iter().map(|bucket| &bucket.name)) =>
+        {
+            for (bucket, added) in total.bucket_values.iter_mut().zip(sample.bucket_values) {
+                bucket.value += added.value;
+            }
+            total.sum += sample.sum;
+            total.count += sample.count;
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Merge the scrapes of the targets with their `instance`, resolving series
+/// exposed by several of them with `policy`
+pub fn combine(
+    scrapes: Vec<(Option<String>, Vec<SingleScrapeMetric>)>,
+    policy: ConflictPolicy,
+) -> Vec<SingleScrapeMetric> {
+    let scrapes = match policy {
+        ConflictPolicy::Label => scrapes
+            .into_iter()
+            .map(|(instance, mut metrics)| {
+                if let Some(instance) = instance {
+                    label_instance(&mut metrics, &instance);
+                }
+                metrics
+            })
+            .collect(),
+        ConflictPolicy::Suffix => {
+            // number of targets exposing every series
+            let mut exposed: HashMap<(String, String), usize> = HashMap::new();
+            for (_, metrics) in &scrapes {
+                for metric in metrics {
+                    for key in metric.value_per_labels.keys() {
+                        *exposed
+                            .entry((metric.name.clone(), key.clone()))
+                            .or_default() += 1;
+                    }
+                }
+            }
+            scrapes
+                .into_iter()
+                .map(|(instance, mut metrics)| {
+                    let Some(instance) = instance else {
+                        return metrics;
+                    };
+                    for metric in &mut metrics {
+                        let name = metric.name.clone();
+                        metric.value_per_labels = std::mem::take(&mut metric.value_per_labels)
+                            .into_iter()
+                            .map(
+                                |(key, sample)| match exposed[&(name.clone(), key.clone())] {
+                                    1 => (key, sample),
+                                    _ => (with_instance(&key, &instance), sample),
+                                },
+                            )
+                            .collect();
+                    }
+                    metrics
+                })
+                .collect()
+        }
+        ConflictPolicy::Merge => scrapes.into_iter().map(|(_, metrics)| metrics).collect(),
+    };
+    merge(scrapes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prom::test_data::scrape_all;
+
+    #[test]
+    fn test_expand_targets() {
+        let hosts = vec!["node1".to_string(), "node2".to_string()];
+        let targets = Target::expand("http://{host}:9100/metrics", &hosts).unwrap();
+        assert_eq!(targets[1].url, "http://node2:9100/metrics");
+        assert_eq!(targets[1].instance.as_deref(), Some("node2"));
+        assert!(Target::expand("http://node1:9100/metrics", &hosts).is_err());
+        assert_eq!(
+            Target::expand("http://node1:9100/metrics", &[]).unwrap()[0].instance,
+            None
+        );
+    }
+
+    #[test]
+    fn test_label_and_merge_scrapes() {
+        let scrape = |host: &str| {
+            let mut metrics = scrape_all(
+                "# TYPE up gauge\nup 1\n# TYPE load gauge\nload{cpu=\"0\"} 2\n",
+                10,
+            );
+            label_instance(&mut metrics, host);
+            metrics
+        };
+        let merged = merge(vec![scrape("node1"), scrape("node2")]);
+        assert_eq!(merged.len(), 2);
+        let mut keys: Vec<&String> = merged[1].value_per_labels.keys().collect();
+        keys.sort();
+        assert_eq!(
+            keys,
+            vec![
+                "instance=\"node1\",cpu=\"0\"",
+                "instance=\"node2\",cpu=\"0\""
+            ]
+        );
+        assert!(merged[0]
+            .value_per_labels
+            .contains_key("instance=\"node1\""));
+    }
+
+    #[test]
+    fn test_combine_conflicting_series() {
+        let scrape = |host: &str, cpu: u32, jobs: u32| {
+            let text = format!(
+                "# TYPE up gauge\nup 1\n# TYPE load gauge\nload{{cpu=\"{}\"}} 2\n\
+                 # TYPE jobs gauge\njobs{{instance=\"a\",queue=\"mail\"}} {}\n",
+                cpu, jobs
+            );
+            (Some(host.to_string()), scrape_all(&text, 10))
+        };
+        let combined = |policy| {
+            let metrics = combine(vec![scrape("node1", 0, 5), scrape("node2", 1, 7)], policy);
+            metrics
+                .into_iter()
+                .map(|metric| {
+                    let mut series: Vec<(String, f64)> = metric
+                        .value_per_labels
+                        .into_iter()
+                        .map(|(key, sample)| match sample {
+                            Sample::GaugeSample(sample) => (key, sample.value),
+                            sample => panic!("unexpected {:?}", sample),
+                        })
+                        .collect();
+                    series.sort_by(|a, b| a.0.cmp(&b.0));
+                    (metric.name, series)
+                })
+                .collect::<Vec<_>>()
+        };
+        let series = |key: &str, value: f64| (key.to_string(), value);
+
+        let labeled = combined(ConflictPolicy::Label);
+        assert_eq!(
+            labeled[2].1,
+            vec![
+                series(
+                    "instance=\"node1\",exported_instance=\"a\",queue=\"mail\"",
+                    5.0
+                ),
+                series(
+                    "instance=\"node2\",exported_instance=\"a\",queue=\"mail\"",
+                    7.0
+                ),
+            ]
+        );
+
+        let suffixed = combined(ConflictPolicy::Suffix);
+        assert_eq!(
+            suffixed[0].1,
+            vec![
+                series("instance=\"node1\"", 1.0),
+                series("instance=\"node2\"", 1.0)
+            ]
+        );
+        // only exposed by one target each
+        assert_eq!(
+            suffixed[1].1,
+            vec![series("cpu=\"0\"", 2.0), series("cpu=\"1\"", 2.0)]
+        );
+
+        let merged = combined(ConflictPolicy::Merge);
+        assert_eq!(
+            merged[0].1,
+            vec![series("single-value-with-no-labels", 2.0)]
+        );
+        assert_eq!(
+            merged[2].1,
+            vec![series("instance=\"a\",queue=\"mail\"", 12.0)]
+        );
+    }
+}
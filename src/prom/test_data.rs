@@ -1,4 +1,78 @@
-#[allow(dead_code)]
+use std::fmt::Write;
+
+use super::{
+    model::SingleScrapeMetric,
+    parser::{decode_single_scrape_metric, split_metric_lines},
+    ConflictPolicy, HistoryStore, OverlapPolicy, Pipeline, Retention, ScrapeOptions,
+    StorageCapacity, StorageKind, Target, TargetStatus,
+};
+
+/// The metric family of the exposition `text`, scraped at `timestamp`
+pub fn scrape(text: &str, timestamp: u64) -> SingleScrapeMetric {
+    decode_single_scrape_metric(text.lines().map(String::from).collect(), timestamp).unwrap()
+}
+
+/// Every metric family of the exposition `text`, scraped at `timestamp`
+pub fn scrape_all(text: &str, timestamp: u64) -> Vec<SingleScrapeMetric> {
+    split_metric_lines(text.lines().map(String::from).collect())
+        .into_iter()
+        .map(|lines| decode_single_scrape_metric(lines, timestamp).unwrap())
+        .collect()
+}
+
+/// The gauge `name` with a series per labels and value, e.g.
+/// `("queue=\"a\"", 1.0)`, scraped at `timestamp`
+pub fn gauge(name: &str, series: &[(&str, f64)], timestamp: u64) -> SingleScrapeMetric {
+    let mut text = format!("# TYPE {} gauge\n", name);
+    for (labels, value) in series {
+        if labels.is_empty() {
+            writeln!(text, "{} {}", name, value).unwrap();
+        } else {
+            writeln!(text, "{}{{{}}} {}", name, labels, value).unwrap();
+        }
+    }
+    scrape(&text, timestamp)
+}
+
+/// Options scraping the single endpoint `url` once an hour, without retries
+pub fn scrape_options(url: &str) -> ScrapeOptions {
+    ScrapeOptions {
+        targets: Target::expand(url, &[]).unwrap(),
+        url: url.to_string(),
+        scrape_interval: 3600,
+        fast_interval: None,
+        retries: 0,
+        overlap_policy: OverlapPolicy::Skip,
+        pipeline: Pipeline::default(),
+        auth_command: None,
+        dns_sd: None,
+        k8s: None,
+        conflict_policy: ConflictPolicy::Label,
+        grpc_health: None,
+    }
+}
+
+/// Targets named by their hosts, with the error of their latest scrape, if
+/// they failed
+pub fn target_statuses(targets: &[(&str, Option<&str>)]) -> Vec<TargetStatus> {
+    targets
+        .iter()
+        .map(|(host, error)| TargetStatus {
+            target: Target {
+                url: format!("http://{}:9100/metrics", host),
+                instance: Some(host.to_string()),
+            },
+            error: error.map(String::from),
+            rate_limited_until: None,
+        })
+        .collect()
+}
+
+/// A memory store keeping every sample
+pub fn memory_store() -> Box<dyn HistoryStore> {
+    StorageKind::Memory.create(StorageCapacity::Samples(0), Retention::default())
+}
+
 pub fn generate_metric_lines() -> Vec<String> {
     vec![
         "# HELP metric_1 Description of the metric".to_string(),
@@ -0,0 +1,271 @@
+//! Read-only HTTP dashboard of the scraped history, so others can peek at a
+//! session from a browser: a JSON API and a page charting it. Requests read
+//! the history directly, they don't go through the render loop.
+use std::sync::PoisonError;
+
+use serde_json::{json, Value};
+use tokio::{
+    io::BufReader,
+    net::{TcpListener, TcpStream},
+    task,
+};
+
+use crate::prom::{http_server, MetricHistoryArc, MetricScraper, ScrapeStatusArc};
+
+/// The page of `GET /`, drawing the JSON API
+const INDEX_HTML: &str = include_str!("index.html");
+
+/// Status line, content type and body of a response
+type Response = (&'static str, &'static str, String);
+
+#[derive(Debug, PartialEq)]
+pub enum Page {
+    Index,
+    /// The source and the scrape status
+    Status,
+    /// Name, type and number of series of every metric
+    Metrics,
+    /// The samples of every series of a metric
+    Series {
+        metric: String,
+    },
+}
+
+/// What the dashboard shows, shared with the scraper
+#[derive(Clone)]
+pub struct Dashboard {
+    source: String,
+    history: MetricHistoryArc,
+    status: ScrapeStatusArc,
+}
+
+impl Dashboard {
+    pub fn new(source: String, scraper: &MetricScraper) -> Self {
+        let (history, status) = scraper.shared();
+        Self {
+            source,
+            history,
+            status,
+        }
+    }
+
+    /// Answer a request for `page`
+    pub fn respond(&self, page: Page) -> Response {
+        match self.render(page) {
+            Ok(response) => response,
+            Err(err) => {
+                log::error!("Web request failed: {}", err);
+                error("500 Internal Server Error", err.to_string())
+            }
+        }
+    }
+
+    fn render(&self, page: Page) -> anyhow::Result<Response> {
+        let body = match page {
+            Page::Index => return Ok(("200 OK", "text/html; charset=utf-8", INDEX_HTML.into())),
+            Page::Status => {
+                let status = self.status.read().map_err(lock_error)?;
+                json!({
+                    "source": self.source,
+                    "scrapes": status.scrape_count,
+                    "failures": status.failure_count,
+                    "paused": status.paused,
+                    "error": status.error_msg,
+                })
+            }
+            Page::Metrics => {
+                let history = self.history.read().map_err(lock_error)?;
+                let metrics: Vec<Value> = history
+                    .get_metrics_headers()
+                    .iter()
+                    .filter_map(|name| history.get_metric(name))
+                    .map(|metric| {
+                        json!({
+                            "name": metric.details.name,
+                            "type": metric.details.metric_type,
+                            "help": metric.details.docstring,
+                            "unit": metric.details.unit,
+                            "series": metric.time_series.len(),
+                        })
+                    })
+                    .collect();
+                json!({ "metrics": metrics })
+            }
+            Page::Series { metric: name } => {
+                let history = self.history.read().map_err(lock_error)?;
+                let Some(metric) = history.get_metric(&name) else {
+                    return Ok(error("404 Not Found", format!("no metric {}", name)));
+                };
+                let mut labels: Vec<&String> = metric.time_series.keys().collect();
+                labels.sort();
+                // histograms and summaries are charted by their count
+                let series: Vec<Value> = labels
+                    .into_iter()
+                    .map(|labels| {
+                        let samples: Vec<(u64, f64)> = metric.time_series[labels]
+                            .samples
+                            .iter()
+                            .map(|sample| (sample.timestamp(), sample.comparable()))
+                            .collect();
+                        json!({ "labels": labels, "samples": samples })
+                    })
+                    .collect();
+                json!({
+                    "metric": name,
+                    "type": metric.details.metric_type,
+                    "series": series,
+                })
+            }
+        };
+        Ok(("200 OK", "application/json", body.to_string()))
+    }
+}
+
+/// The page of a request, or the status line and message of the error
+pub fn route(method: &str, path: &str) -> Result<Page, (&'static str, String)> {
+    let url = reqwest::Url::parse("http://promviz")
+        .and_then(|base| base.join(path))
+        .map_err(|err| ("400 Bad Request", err.to_string()))?;
+    let page = match url.path() {
+        "/" => Page::Index,
+        "/api/v1/status" => Page::Status,
+        "/api/v1/metrics" => Page::Metrics,
+        "/api/v1/series" => {
+            let metric = url
+                .query_pairs()
+                .find(|(key, _)| key == "metric")
+                .map(|(_, metric)| metric.into_owned())
+                .ok_or(("400 Bad Request", "missing ?metric=".to_string()))?;
+            Page::Series { metric }
+        }
+        _ => return Err(("404 Not Found", format!("no such page {}", url.path()))),
+    };
+    match method {
+        "GET" => Ok(page),
+        _ => Err((
+            "405 Method Not Allowed",
+            format!("{} is not supported by {}", method, url.path()),
+        )),
+    }
+}
+
+fn lock_error<T>(err: PoisonError<T>) -> anyhow::Error {
+    anyhow::anyhow!("failed to aquire lock: {}", err)
+}
+
+fn error(status: &'static str, message: String) -> Response {
+    let body = json!({ "error": message });
+    (status, "application/json", body.to_string())
+}
+
+/// Accept connections to the dashboard
+pub async fn serve(listener: TcpListener, dashboard: Dashboard) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, peer)) => {
+                log::debug!("Web connection from {}", peer);
+                let dashboard = dashboard.clone();
+                task::spawn(async move {
+                    if let Err(err) = handle_connection(stream, &dashboard).await {
+                        log::error!("Web connection from {} failed: {}", peer, err);
+                    }
+                });
+            }
+            Err(err) => log::error!("Failed to accept web connection: {}", err),
+        }
+    }
+}
+
+async fn handle_connection(stream: TcpStream, dashboard: &Dashboard) -> anyhow::Result<()> {
+    let mut reader = BufReader::new(stream);
+    loop {
+        let Some(request) = http_server::read_request(&mut reader).await? else {
+            return Ok(());
+        };
+        let (status, content_type, body) = match route(&request.method, &request.path) {
+            Ok(page) => dashboard.respond(page),
+            Err((status, message)) => error(status, message),
+        };
+        http_server::write_typed_response(reader.get_mut(), status, content_type, &body).await?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, RwLock};
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+    use crate::prom::test_data::{memory_store, scrape_all};
+
+    #[test]
+    fn test_route() {
+        assert_eq!(route("GET", "/"), Ok(Page::Index));
+        assert_eq!(route("GET", "/api/v1/metrics"), Ok(Page::Metrics));
+        assert_eq!(
+            route("GET", "/api/v1/series?metric=http_requests_total&x=1"),
+            Ok(Page::Series {
+                metric: "http_requests_total".to_string()
+            })
+        );
+        assert_eq!(
+            route("GET", "/api/v1/series?metric=a%20b"),
+            Ok(Page::Series {
+                metric: "a b".to_string()
+            })
+        );
+        assert_eq!(
+            route("GET", "/api/v1/series").unwrap_err().0,
+            "400 Bad Request"
+        );
+        assert_eq!(
+            route("POST", "/api/v1/status").unwrap_err().0,
+            "405 Method Not Allowed"
+        );
+        assert_eq!(route("GET", "/admin").unwrap_err().0, "404 Not Found");
+    }
+
+    #[tokio::test]
+    async fn test_serve() {
+        let text = "# TYPE requests_total counter
+requests_total{code=\"200\"} 10
+requests_total{code=\"500\"} 2
+";
+        let mut history = memory_store();
+        for metric in scrape_all(text, 1700000000) {
+            history.insert_scrape(metric);
+        }
+        let dashboard = Dashboard {
+            source: "test".to_string(),
+            history: Arc::new(RwLock::new(history)),
+            status: Default::default(),
+        };
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        task::spawn(serve(listener, dashboard));
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET /api/v1/series?metric=requests_total HTTP/1.1\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = vec![0; 1024];
+        let read = stream.read(&mut response).await.unwrap();
+        let response = String::from_utf8_lossy(&response[..read]);
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+        let body = response.split("\r\n\r\n").nth(1).unwrap();
+        let body: Value = serde_json::from_str(body).unwrap();
+        assert_eq!(
+            body,
+            json!({
+                "metric": "requests_total",
+                "type": "counter",
+                "series": [
+                    { "labels": "code=\"200\"", "samples": [[1700000000, 10.0]] },
+                    { "labels": "code=\"500\"", "samples": [[1700000000, 2.0]] },
+                ],
+            })
+        );
+    }
+}